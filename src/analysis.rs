@@ -0,0 +1,904 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use log::{debug, trace};
+use num_format::{Locale, ToFormattedString};
+use regex::Regex;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::cluster::Clusterer;
+use crate::columns::{compare_cells, ColumnMode};
+use crate::common::{ANALYSIS_BATCH_SIZE, MAX_CLUSTER_ROWS, MAX_TOP_VALUES};
+use crate::ffile::{FFReq, FFReqSender};
+use crate::ifile::{FileReq, FileReqSender, IFResp, LinesRespSender};
+use crate::query::{LineField, Query};
+use crate::timestamp::{GapDetector, TimestampParser};
+
+pub type AnalysisSender = mpsc::Sender<AnalysisResult>;
+pub type AnalysisReceiver = mpsc::Receiver<AnalysisResult>;
+
+/// Why a set of lines was fetched in the background.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinesPurpose {
+    ColumnHeader,
+    Pinned,
+    // A line cut short, loaded in full to show or copy.
+    Detail,
+    Yank,
+    Sampled,
+}
+
+/// Results of the background analyses, delivered back to the TUI.
+#[derive(Debug)]
+pub enum AnalysisResult {
+    Lines {
+        purpose: LinesPurpose,
+        lines: Vec<(usize, String)>,
+    },
+    Aggregate {
+        pattern: String,
+        lines_scanned: usize,
+        stats: Option<AggregateStats>,
+    },
+    Table(ResultTable),
+    Groups(LineGroups),
+    Gaps(GapScan),
+    Clusters(ClusterScan),
+    TimeFound {
+        target: String,
+        line_no: Option<usize>,
+    },
+    TimeFraction {
+        line_no: usize,
+        fraction: Option<f64>,
+    },
+    // Lines found at byte offsets, as offset, line number and line.
+    LinesAt(Vec<(u64, usize, String)>),
+    LineOffset {
+        line_no: usize,
+        offset: Option<u64>,
+    },
+    Failed {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateStats {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub p95: f64,
+}
+
+impl AggregateStats {
+    pub fn from_values(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        values.sort_by(|a, b| a.total_cmp(b));
+
+        let count = values.len();
+        let sum: f64 = values.iter().sum();
+
+        // Nearest rank percentile.
+        let p95_rank = ((count as f64) * 0.95).ceil() as usize;
+
+        Some(AggregateStats {
+            count,
+            min: values[0],
+            max: values[count - 1],
+            avg: sum / count as f64,
+            p95: values[p95_rank.saturating_sub(1)],
+        })
+    }
+}
+
+/// A table of results, each row optionally linked back to a file line for syncing, or to a regex
+/// filter to apply when chosen.
+#[derive(Debug, Clone, Default)]
+pub struct ResultTable {
+    pub title: String,
+    pub header: Vec<String>,
+    pub rows: Vec<ResultRow>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultRow {
+    pub line_no: Option<usize>,
+    pub cells: Vec<String>,
+    pub filter: Option<String>,
+}
+
+/// Lines grouped by the value of a field, the groups in order of their first line. Groups are shown
+/// collapsed to a row each, and can be expanded to show their lines.
+#[derive(Debug, Clone, Default)]
+pub struct LineGroups {
+    pub title: String,
+    pub groups: Vec<LineGroup>,
+    index: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineGroup {
+    pub value: String,
+    pub lines: Vec<(usize, String)>,
+    pub expanded: bool,
+}
+
+impl LineGroups {
+    pub fn add(&mut self, value: String, line_no: usize, line: &str) {
+        let i = *self.index.entry(value.clone()).or_insert_with(|| {
+            self.groups.push(LineGroup {
+                value,
+                lines: Vec::new(),
+                expanded: false,
+            });
+            self.groups.len() - 1
+        });
+        self.groups[i].lines.push((line_no, line.to_owned()));
+    }
+
+    /// The groups as a table, each followed by its lines if expanded, with the group of each row.
+    pub fn table(&self) -> (ResultTable, Vec<usize>) {
+        let mut rows = Vec::new();
+        let mut row_groups = Vec::new();
+        for (i, group) in self.groups.iter().enumerate() {
+            rows.push(ResultRow {
+                line_no: group.lines.first().map(|(line_no, _)| *line_no),
+                cells: vec![
+                    format!("{} {}", if group.expanded { "▾" } else { "▸" }, group.value),
+                    format!(
+                        "{} lines",
+                        group.lines.len().to_formatted_string(&Locale::en)
+                    ),
+                ],
+                filter: None,
+            });
+            row_groups.push(i);
+            if group.expanded {
+                for (line_no, line) in &group.lines {
+                    rows.push(ResultRow {
+                        line_no: Some(*line_no),
+                        cells: vec![String::new(), line.clone()],
+                        filter: None,
+                    });
+                    row_groups.push(i);
+                }
+            }
+        }
+
+        (
+            ResultTable {
+                title: self.title.clone(),
+                header: vec!["Group".to_owned(), "Lines".to_owned()],
+                rows,
+            },
+            row_groups,
+        )
+    }
+}
+
+/// Time gaps found in a range of the file. The detector carries on from the end of the range.
+#[derive(Debug)]
+pub struct GapScan {
+    pub generation: usize,
+    pub end: usize,
+    pub gaps: Vec<(usize, TimeDelta)>,
+    pub detector: GapDetector,
+}
+
+/// The clusters of a range of file lines. The clusterer carries on from the end of the range.
+#[derive(Debug)]
+pub struct ClusterScan {
+    pub generation: usize,
+    pub end: usize,
+    pub clusters: Vec<(usize, usize)>,
+    pub clusterer: Clusterer,
+}
+
+/// Fetch lines from the IFile in batches, visiting each in order. Returns the number of lines
+/// visited, unknown lines are skipped.
+async fn fetch_lines(
+    ifreq_sender: &FileReqSender<IFResp<String>>,
+    line_nos: &[usize],
+    mut visit: impl FnMut(usize, &str),
+) -> Result<usize> {
+    let mut visited = 0;
+    for batch in line_nos.chunks(ANALYSIS_BATCH_SIZE) {
+        let (resp, recv) = oneshot::channel();
+        ifreq_sender
+            .send(FileReq::GetLines {
+                line_nos: batch.to_vec(),
+                resp,
+            })
+            .await?;
+
+        let lines = recv
+            .await
+            .map_err(|_| anyhow!("File went away whilst fetching lines"))?;
+        for (line_no, line) in lines {
+            visit(line_no, &line);
+            visited += 1;
+        }
+    }
+
+    Ok(visited)
+}
+
+/// Where an analysis gets its lines from: the current filter matches, or the whole file if there
+/// is no filter.
+///
+/// Lines are fetched from the IFile in batches from a separate task, so the TUI keeps draining its
+/// channels whilst the analysis runs.
+#[derive(Debug, Clone)]
+pub struct AnalysisSource {
+    pub ifreq_sender: FileReqSender<IFResp<String>>,
+    pub ff_sender: FFReqSender,
+    pub file_lines: usize,
+}
+
+impl AnalysisSource {
+    async fn line_nos(&self) -> Result<Vec<usize>> {
+        let (resp, recv) = oneshot::channel();
+        self.ff_sender.send(FFReq::GetMatches { resp }).await?;
+
+        let matches = recv
+            .await
+            .map_err(|_| anyhow!("Filter went away before reporting matches"))?;
+
+        Ok(matches.unwrap_or_else(|| (0..self.file_lines).collect()))
+    }
+
+    /// Visit each line of the source in order, returning the number of lines visited.
+    pub async fn for_each_line(&self, visit: impl FnMut(usize, &str)) -> Result<usize> {
+        let line_nos = self.line_nos().await?;
+        trace!("Analysing {} lines", line_nos.len());
+
+        fetch_lines(&self.ifreq_sender, &line_nos, visit).await
+    }
+}
+
+/// Extract a number from the first capture group of the regex, or the whole match if there is no
+/// group.
+pub fn extract_number(regex: &Regex, line: &str) -> Option<f64> {
+    let captures = regex.captures(line)?;
+    let m = captures.get(1).or_else(|| captures.get(0))?;
+
+    m.as_str().trim().parse::<f64>().ok()
+}
+
+/// Fetch specific lines from the IFile without going through a View.
+pub fn spawn_fetch_lines(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    line_nos: Vec<usize>,
+    purpose: LinesPurpose,
+    result_sender: AnalysisSender,
+) {
+    spawn_lines_request(
+        ifreq_sender,
+        |resp| FileReq::GetLines { line_nos, resp },
+        purpose,
+        result_sender,
+    );
+}
+
+/// Fetch a line in full, even if it is longer than the cap on lines.
+pub fn spawn_fetch_full_line(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    line_no: usize,
+    purpose: LinesPurpose,
+    result_sender: AnalysisSender,
+) {
+    spawn_lines_request(
+        ifreq_sender,
+        move |resp| FileReq::GetFullLine { line_no, resp },
+        purpose,
+        result_sender,
+    );
+}
+
+fn spawn_lines_request(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    request: impl FnOnce(LinesRespSender) -> FileReq<IFResp<String>> + Send + 'static,
+    purpose: LinesPurpose,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let (resp, recv) = oneshot::channel();
+        let result = match ifreq_sender.send(request(resp)).await {
+            Ok(_) => match recv.await {
+                Ok(lines) => AnalysisResult::Lines { purpose, lines },
+                Err(_) => AnalysisResult::Failed {
+                    reason: "File went away whilst fetching lines".to_owned(),
+                },
+            },
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Failed to request lines: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before lines were fetched");
+        }
+    });
+}
+
+/// Find the first line at or after a time using the IFile's timestamp index.
+pub fn spawn_find_time(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    time: DateTime<FixedOffset>,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let (resp, recv) = oneshot::channel();
+        let result = match ifreq_sender.send(FileReq::FindTime { time, resp }).await {
+            Ok(_) => match recv.await {
+                Ok(line_no) => AnalysisResult::TimeFound {
+                    target: time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    line_no,
+                },
+                Err(_) => AnalysisResult::Failed {
+                    reason: "File went away whilst finding time".to_owned(),
+                },
+            },
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Failed to request time: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before time was found");
+        }
+    });
+}
+
+/// Find the lines starting at byte offsets, e.g. to place notes saved against them.
+pub fn spawn_lines_at(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    offsets: Vec<u64>,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let (resp, recv) = oneshot::channel();
+        let result = match ifreq_sender
+            .send(FileReq::GetLinesAt { offsets, resp })
+            .await
+        {
+            Ok(_) => match recv.await {
+                Ok(lines) => AnalysisResult::LinesAt(lines),
+                Err(_) => AnalysisResult::Failed {
+                    reason: "File went away whilst finding lines at offsets".to_owned(),
+                },
+            },
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Failed to request lines at offsets: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before lines at offsets were found");
+        }
+    });
+}
+
+/// Find where a line starts in the file.
+pub fn spawn_line_offset(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    line_no: usize,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let (resp, recv) = oneshot::channel();
+        let result = match ifreq_sender
+            .send(FileReq::LineOffset { line_no, resp })
+            .await
+        {
+            Ok(_) => match recv.await {
+                Ok(offset) => AnalysisResult::LineOffset { line_no, offset },
+                Err(_) => AnalysisResult::Failed {
+                    reason: "File went away whilst finding line offset".to_owned(),
+                },
+            },
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Failed to request line offset: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before line offset was found");
+        }
+    });
+}
+
+/// Find how far through the file's time span a line is, for placing the scrollbar by time.
+pub fn spawn_time_fraction(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    line_no: usize,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let (resp, recv) = oneshot::channel();
+        let result = match ifreq_sender
+            .send(FileReq::TimeFraction { line_no, resp })
+            .await
+        {
+            Ok(_) => match recv.await {
+                Ok(fraction) => AnalysisResult::TimeFraction { line_no, fraction },
+                Err(_) => AnalysisResult::Failed {
+                    reason: "File went away whilst finding time fraction".to_owned(),
+                },
+            },
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Failed to request time fraction: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before time fraction was found");
+        }
+    });
+}
+
+pub fn spawn_aggregate(source: AnalysisSource, regex: Regex, result_sender: AnalysisSender) {
+    tokio::spawn(async move {
+        let mut values = Vec::new();
+        let result = source
+            .for_each_line(|_, line| {
+                if let Some(v) = extract_number(&regex, line) {
+                    values.push(v);
+                }
+            })
+            .await;
+
+        let result = match result {
+            Ok(lines_scanned) => AnalysisResult::Aggregate {
+                pattern: regex.as_str().to_owned(),
+                lines_scanned,
+                stats: AggregateStats::from_values(values),
+            },
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Aggregate failed: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before aggregate completed");
+        }
+    });
+}
+
+/// Look for time gaps in a range of file lines, carrying on from the detector's last timestamp.
+pub fn spawn_gap_scan(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    lines: Range<usize>,
+    generation: usize,
+    parser: TimestampParser,
+    mut detector: GapDetector,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let mut gaps = Vec::new();
+        let line_nos = lines.clone().collect::<Vec<_>>();
+        let result = fetch_lines(&ifreq_sender, &line_nos, |line_no, line| {
+            if let Some(gap) = detector.check(&parser, line) {
+                gaps.push((line_no, gap));
+            }
+        })
+        .await;
+
+        let result = match result {
+            Ok(_) => AnalysisResult::Gaps(GapScan {
+                generation,
+                end: lines.end,
+                gaps,
+                detector,
+            }),
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Time gap scan failed: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before time gap scan completed");
+        }
+    });
+}
+
+/// Group the source lines into message templates, most common first. Choosing a row filters on
+/// its template.
+pub fn spawn_clusters(source: AnalysisSource, result_sender: AnalysisSender) {
+    tokio::spawn(async move {
+        let mut clusterer = Clusterer::new();
+        let result = source
+            .for_each_line(|line_no, line| {
+                clusterer.add(line_no, line);
+            })
+            .await;
+
+        let result = match result {
+            Ok(lines_scanned) => {
+                let title = format!(
+                    "Message templates from {} lines",
+                    lines_scanned.to_formatted_string(&Locale::en)
+                );
+                let rows = clusterer
+                    .into_clusters()
+                    .into_iter()
+                    .take(MAX_CLUSTER_ROWS)
+                    .map(|c| ResultRow {
+                        line_no: Some(c.first_line_no),
+                        cells: vec![
+                            c.count.to_formatted_string(&Locale::en),
+                            c.template_string(),
+                        ],
+                        filter: Some(c.filter_regex()),
+                    })
+                    .collect();
+
+                AnalysisResult::Table(ResultTable {
+                    title,
+                    header: vec!["Count".to_owned(), "Template".to_owned()],
+                    rows,
+                })
+            }
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Clustering failed: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before clustering completed");
+        }
+    });
+}
+
+/// Cluster a range of file lines, carrying on with the clusterer from earlier ranges.
+pub fn spawn_cluster_scan(
+    ifreq_sender: FileReqSender<IFResp<String>>,
+    lines: Range<usize>,
+    generation: usize,
+    mut clusterer: Clusterer,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let mut clusters = Vec::new();
+        let line_nos = lines.clone().collect::<Vec<_>>();
+        let result = fetch_lines(&ifreq_sender, &line_nos, |line_no, line| {
+            clusters.push((line_no, clusterer.add(line_no, line)));
+        })
+        .await;
+
+        let result = match result {
+            Ok(_) => AnalysisResult::Clusters(ClusterScan {
+                generation,
+                end: lines.end,
+                clusters,
+                clusterer,
+            }),
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Rare message scan failed: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before rare message scan completed");
+        }
+    });
+}
+
+/// Sort rows by a column, numerically where both cells are numbers.
+pub fn sort_rows(mut rows: Vec<ResultRow>, column: usize, descending: bool) -> Vec<ResultRow> {
+    // Stable sort, so equal values stay in file order.
+    rows.sort_by(|a, b| {
+        let ordering = compare_cells(
+            a.cells.get(column).map(|c| c.as_str()).unwrap_or(""),
+            b.cells.get(column).map(|c| c.as_str()).unwrap_or(""),
+        );
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+
+    rows
+}
+
+/// Sort the source lines by a column. In delimited mode the first line is the header, so is left
+/// out.
+pub fn spawn_sort(
+    source: AnalysisSource,
+    mode: ColumnMode,
+    header: Vec<String>,
+    column: usize,
+    descending: bool,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let skip_header = matches!(mode, ColumnMode::Delimited { .. });
+
+        let mut rows = Vec::new();
+        let result = source
+            .for_each_line(|line_no, line| {
+                if skip_header && line_no == 0 {
+                    return;
+                }
+
+                if let Some(cells) = mode.split(line) {
+                    rows.push(ResultRow {
+                        line_no: Some(line_no),
+                        cells,
+                        filter: None,
+                    });
+                }
+            })
+            .await;
+
+        let result = match result {
+            Ok(_) => AnalysisResult::Table(ResultTable {
+                title: format!(
+                    "Sorted by {} {}",
+                    header.get(column).map(|h| h.as_str()).unwrap_or("?"),
+                    if descending {
+                        "descending"
+                    } else {
+                        "ascending"
+                    }
+                ),
+                header,
+                rows: sort_rows(rows, column, descending),
+            }),
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Sort failed: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before sort completed");
+        }
+    });
+}
+
+/// Group the source lines by the value of a field, leaving out lines without one.
+pub fn spawn_groups(
+    source: AnalysisSource,
+    field: LineField,
+    name: String,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        let mut groups = LineGroups::default();
+        let result = source
+            .for_each_line(|line_no, line| {
+                if let Some(value) = field.value(line) {
+                    groups.add(value, line_no, line);
+                }
+            })
+            .await;
+
+        let result = match result {
+            Ok(lines_scanned) => {
+                groups.title = format!(
+                    "{} groups by {} from {} lines",
+                    groups.groups.len().to_formatted_string(&Locale::en),
+                    name,
+                    lines_scanned.to_formatted_string(&Locale::en)
+                );
+                AnalysisResult::Groups(groups)
+            }
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Grouping failed: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before grouping completed");
+        }
+    });
+}
+
+/// Count the values of a field in the source lines, most frequent first, each row filtering for
+/// its value when chosen.
+pub fn spawn_top_values(
+    source: AnalysisSource,
+    field: LineField,
+    name: String,
+    result_sender: AnalysisSender,
+) {
+    tokio::spawn(async move {
+        // Each value's count and first line.
+        let mut values: HashMap<String, (usize, usize)> = HashMap::new();
+        let result = source
+            .for_each_line(|line_no, line| {
+                if let Some(value) = field.value(line) {
+                    values.entry(value).or_insert((0, line_no)).0 += 1;
+                }
+            })
+            .await;
+
+        let result = match result {
+            Ok(lines_scanned) => {
+                AnalysisResult::Table(top_values_table(&field, &name, values, lines_scanned))
+            }
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Counting values failed: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before values were counted");
+        }
+    });
+}
+
+fn top_values_table(
+    field: &LineField,
+    name: &str,
+    values: HashMap<String, (usize, usize)>,
+    lines_scanned: usize,
+) -> ResultTable {
+    let distinct = values.len();
+    let mut values = values.into_iter().collect::<Vec<_>>();
+    values.sort_by(|(_, (a_count, a_line)), (_, (b_count, b_line))| {
+        b_count.cmp(a_count).then(a_line.cmp(b_line))
+    });
+
+    let rows = values
+        .into_iter()
+        .take(MAX_TOP_VALUES)
+        .map(|(value, (count, line_no))| ResultRow {
+            line_no: Some(line_no),
+            cells: vec![
+                count.to_formatted_string(&Locale::en),
+                format!("{:.1}", 100.0 * count as f64 / lines_scanned.max(1) as f64),
+                value.clone(),
+            ],
+            filter: Some(field.filter_regex(&value)),
+        })
+        .collect();
+
+    ResultTable {
+        title: format!(
+            "{} values of {} in {} lines",
+            distinct.to_formatted_string(&Locale::en),
+            name,
+            lines_scanned.to_formatted_string(&Locale::en)
+        ),
+        header: vec!["Count".to_owned(), "%".to_owned(), "Value".to_owned()],
+        rows,
+    }
+}
+
+/// Run a query over the source lines, giving a table of the matching lines' fields.
+pub fn spawn_query(source: AnalysisSource, query: Query, result_sender: AnalysisSender) {
+    tokio::spawn(async move {
+        let mut records = Vec::new();
+        let result = source
+            .for_each_line(|line_no, line| {
+                if query.is_full(records.len()) {
+                    return;
+                }
+                if let Some(record) = query.matching(line) {
+                    records.push((line_no, record));
+                }
+            })
+            .await;
+
+        let result = match result {
+            Ok(_) => AnalysisResult::Table(query.table(records)),
+            Err(e) => AnalysisResult::Failed {
+                reason: format!("Query failed: {}", e),
+            },
+        };
+
+        if result_sender.send(result).await.is_err() {
+            debug!("TUI went away before query completed");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_stats() {
+        let values = (1..=100).map(|v| v as f64).rev().collect::<Vec<_>>();
+        let stats = AggregateStats::from_values(values).expect("Expected stats");
+
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 100.0);
+        assert_eq!(stats.avg, 50.5);
+        assert_eq!(stats.p95, 95.0);
+
+        assert_eq!(AggregateStats::from_values(vec![]), None);
+    }
+
+    #[test]
+    fn test_extract_number() {
+        let with_group = Regex::new(r"took (\d+(\.\d+)?)ms").unwrap();
+        assert_eq!(
+            extract_number(&with_group, "request took 12.5ms ok"),
+            Some(12.5)
+        );
+        assert_eq!(extract_number(&with_group, "no timing here"), None);
+
+        let whole_match = Regex::new(r"\d+").unwrap();
+        assert_eq!(extract_number(&whole_match, "status 503"), Some(503.0));
+    }
+
+    #[test]
+    fn test_line_groups() {
+        let mut groups = LineGroups::default();
+        groups.add("a".to_owned(), 1, "a start");
+        groups.add("b".to_owned(), 2, "b start");
+        groups.add("a".to_owned(), 5, "a done");
+
+        let (table, row_groups) = groups.table();
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[0].cells, vec!["▸ a", "2 lines"]);
+        assert_eq!(table.rows[1].line_no, Some(2));
+        assert_eq!(row_groups, vec![0, 1]);
+
+        groups.groups[0].expanded = true;
+        let (table, row_groups) = groups.table();
+        let line_nos = table.rows.iter().map(|r| r.line_no).collect::<Vec<_>>();
+        assert_eq!(line_nos, vec![Some(1), Some(1), Some(5), Some(2)]);
+        assert_eq!(table.rows[2].cells, vec!["", "a done"]);
+        assert_eq!(row_groups, vec![0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_top_values_table() {
+        let field = LineField::parse("level").unwrap();
+        let values = HashMap::from([
+            ("info".to_owned(), (2, 0)),
+            ("error".to_owned(), (5, 3)),
+            ("warn".to_owned(), (2, 1)),
+        ]);
+        let table = top_values_table(&field, "level", values, 10);
+
+        assert_eq!(table.title, "3 values of level in 10 lines");
+        let cells = table
+            .rows
+            .iter()
+            .map(|r| r.cells.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            vec![
+                vec!["5", "50.0", "error"],
+                vec!["2", "20.0", "info"],
+                vec!["2", "20.0", "warn"],
+            ]
+        );
+        assert_eq!(table.rows[0].line_no, Some(3));
+        assert_eq!(table.rows[0].filter, Some(field.filter_regex("error")));
+    }
+
+    #[test]
+    fn test_sort_rows() {
+        let row = |line_no, duration: &str| ResultRow {
+            line_no: Some(line_no),
+            cells: vec!["x".to_owned(), duration.to_owned()],
+            filter: None,
+        };
+        let rows = vec![row(1, "9"), row(2, "120"), row(3, "9"), row(4, "15")];
+
+        let line_nos = |rows: Vec<ResultRow>| rows.iter().map(|r| r.line_no).collect::<Vec<_>>();
+        assert_eq!(
+            line_nos(sort_rows(rows.clone(), 1, true)),
+            vec![Some(2), Some(4), Some(1), Some(3)]
+        );
+        assert_eq!(
+            line_nos(sort_rows(rows, 1, false)),
+            vec![Some(1), Some(3), Some(4), Some(2)]
+        );
+    }
+}
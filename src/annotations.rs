@@ -0,0 +1,212 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+// The notes on a log are kept beside it, e.g. `app.log.notes.yaml`.
+const SIDECAR_SUFFIX: &str = ".notes.yaml";
+
+/// A note on a line, keyed by where the line starts in the file and a hash of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub offset: u64,
+    pub hash: String,
+    pub note: String,
+    // The line noted, so the file can be read on its own.
+    pub line: String,
+    // Where the line is in the file as viewed, once found.
+    #[serde(skip)]
+    pub line_no: Option<usize>,
+}
+
+/// Notes on the lines of a log, saved to a sidecar file beside it so they can be committed or
+/// shared. A note is only shown on a line starting at its offset with the same content, so notes
+/// are kept but not shown if the log is replaced.
+#[derive(Debug, Clone)]
+pub struct Annotations {
+    path: PathBuf,
+    notes: Vec<Annotation>,
+}
+
+impl Annotations {
+    /// The notes on a log, none if it has no sidecar file yet.
+    pub fn load(log_path: &str) -> Result<Self> {
+        let path = PathBuf::from(format!("{}{}", log_path, SIDECAR_SUFFIX));
+        let notes = match fs::read_to_string(&path) {
+            Ok(yaml) => serde_yaml::from_str(&yaml)
+                .map_err(|e| anyhow!("Bad notes file {}: {}", path.display(), e))?,
+            Err(_) => Vec::new(),
+        };
+        debug!("Loaded {} notes from {:?}", notes.len(), path);
+
+        Ok(Annotations { path, notes })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Write the notes to the sidecar file, removing it when there are none.
+    pub fn save(&self) -> Result<()> {
+        if self.notes.is_empty() {
+            if self.path.exists() {
+                fs::remove_file(&self.path)?;
+            }
+            return Ok(());
+        }
+
+        fs::write(&self.path, serde_yaml::to_string(&self.notes)?)?;
+        debug!("Saved {} notes to {:?}", self.notes.len(), self.path);
+
+        Ok(())
+    }
+
+    /// The offsets of the notes not yet found in the file.
+    pub fn unresolved(&self) -> Vec<u64> {
+        self.notes
+            .iter()
+            .filter(|a| a.line_no.is_none())
+            .map(|a| a.offset)
+            .collect()
+    }
+
+    /// Place notes on the lines starting at their offsets, given as offset, line number and line,
+    /// where the lines are the ones noted. Returns whether any were placed.
+    pub fn resolve(&mut self, lines: &[(u64, usize, String)]) -> bool {
+        let mut resolved = false;
+        for annotation in self.notes.iter_mut().filter(|a| a.line_no.is_none()) {
+            if let Some((_, line_no, _)) = lines.iter().find(|(offset, _, line)| {
+                *offset == annotation.offset && line_hash(line) == annotation.hash
+            }) {
+                annotation.line_no = Some(*line_no);
+                resolved = true;
+            }
+        }
+
+        resolved
+    }
+
+    /// Note a line starting at the offset, replacing any note on it. An empty note removes it.
+    pub fn set(&mut self, offset: u64, line_no: usize, line: &str, note: &str) {
+        self.notes.retain(|a| a.line_no != Some(line_no));
+        if !note.is_empty() {
+            self.notes.push(Annotation {
+                offset,
+                hash: line_hash(line),
+                note: note.to_owned(),
+                line: line.to_owned(),
+                line_no: Some(line_no),
+            });
+            self.notes.sort_by_key(|a| a.offset);
+        }
+    }
+
+    /// Where a noted line starts.
+    pub fn offset(&self, line_no: usize) -> Option<u64> {
+        self.notes
+            .iter()
+            .find(|a| a.line_no == Some(line_no))
+            .map(|a| a.offset)
+    }
+
+    pub fn note(&self, line_no: usize) -> Option<&str> {
+        self.notes
+            .iter()
+            .find(|a| a.line_no == Some(line_no))
+            .map(|a| a.note.as_str())
+    }
+
+    /// Add notes made elsewhere, e.g. on another machine, that aren't already here. They are placed
+    /// once found in the file. Returns how many were added.
+    pub fn merge(&mut self, notes: &[Annotation]) -> usize {
+        let mut added = 0;
+        for annotation in notes {
+            if !self
+                .notes
+                .iter()
+                .any(|a| a.offset == annotation.offset && a.hash == annotation.hash)
+            {
+                self.notes.push(Annotation {
+                    line_no: None,
+                    ..annotation.clone()
+                });
+                added += 1;
+            }
+        }
+        self.notes.sort_by_key(|a| a.offset);
+
+        added
+    }
+
+    pub fn notes(&self) -> &[Annotation] {
+        &self.notes
+    }
+
+    /// The notes found in the file, in file order.
+    pub fn placed(&self) -> impl Iterator<Item = &Annotation> {
+        self.notes.iter().filter(|a| a.line_no.is_some())
+    }
+
+    pub fn line_nos(&self) -> BTreeSet<usize> {
+        self.notes.iter().filter_map(|a| a.line_no).collect()
+    }
+}
+
+// A hash of a line that stays the same across machines and versions, unlike std's hashers: 64 bit
+// FNV-1a.
+fn line_hash(line: &str) -> String {
+    let hash = line.bytes().fold(0xcbf29ce484222325_u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotations() {
+        let dir = std::env::temp_dir().join(format!("otail-test-notes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log").to_string_lossy().into_owned();
+
+        let mut annotations = Annotations::load(&log).unwrap();
+        annotations.set(20, 2, "ERROR disk full", "Root cause");
+        annotations.set(10, 1, "WARN disk nearly full", "First sign");
+        annotations.set(10, 1, "WARN disk nearly full", "First warning");
+        annotations.save().unwrap();
+        assert!(annotations.path().exists());
+
+        // Only found again on the same lines at the same offsets.
+        let mut annotations = Annotations::load(&log).unwrap();
+        assert_eq!(annotations.unresolved(), vec![10, 20]);
+        assert!(annotations.resolve(&[
+            (10, 5, "WARN disk nearly full".to_owned()),
+            (20, 6, "ERROR something else".to_owned()),
+        ]));
+        assert_eq!(annotations.note(5), Some("First warning"));
+        assert_eq!(annotations.note(6), None);
+        assert_eq!(annotations.unresolved(), vec![20]);
+        assert_eq!(annotations.line_nos(), BTreeSet::from([5]));
+
+        // Notes not found are kept.
+        annotations.set(10, 5, "WARN disk nearly full", "");
+        annotations.save().unwrap();
+        let mut annotations = Annotations::load(&log).unwrap();
+        assert_eq!(annotations.unresolved(), vec![20]);
+
+        // Notes from elsewhere are added unless already here.
+        let mut other = Annotations::load(&dir.join("other.log").to_string_lossy()).unwrap();
+        other.set(10, 1, "WARN disk nearly full", "Seen elsewhere");
+        other.set(20, 2, "ERROR disk full", "Duplicate");
+        assert_eq!(annotations.merge(other.notes()), 1);
+        assert_eq!(annotations.unresolved(), vec![10, 20]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
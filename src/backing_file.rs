@@ -1,22 +1,293 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Seek};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
 
-#[cfg(test)]
-use mockall::automock;
+// What files are read with: io_uring when built with it on Linux, otherwise the file itself.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+type DiskFile = crate::uring::UringFile;
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+type DiskFile = File;
 
-#[cfg_attr(test, automock)]
+/// Reads the lines of a file, in order or from where they start.
 pub trait BackingFile {
     fn read_line(&mut self, offset: Option<u64>) -> Result<String>;
+    // The start of the line at the offset, no more than so many bytes of it, and whether it was cut
+    // short. Only that much is read, however long the line.
+    fn read_line_start(&mut self, offset: u64, max_bytes: usize) -> Result<(String, bool)>;
     fn seek(&mut self, offset: u64) -> Result<()>;
     fn incremental_read(&mut self, line: &mut String) -> Result<(usize, bool)>;
+    // Read from another file, e.g. when a followed symlink is re-pointed.
+    fn reopen(&mut self, path: &Path) -> Result<()>;
+}
+
+/// What ends a line: a newline, with any carriage return before it, or other bytes such as the NUL
+/// from `find -print0`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum LineDelimiter {
+    #[default]
+    Newline,
+    Bytes(Vec<u8>),
+}
+
+impl LineDelimiter {
+    /// `nul`, `newline`, or the delimiter itself with escapes such as `\0`, `\t`, `\r\n` and
+    /// `\x1e`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec {
+            "newline" | "\\n" => return Ok(LineDelimiter::Newline),
+            "nul" | "null" => return Ok(LineDelimiter::Bytes(vec![0])),
+            _ => {}
+        }
+
+        let mut bytes = Vec::new();
+        let mut chars = spec.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+            bytes.push(match chars.next() {
+                Some('0') => 0,
+                Some('n') => b'\n',
+                Some('r') => b'\r',
+                Some('t') => b'\t',
+                Some('\\') => b'\\',
+                Some('x') => {
+                    let hex = chars.by_ref().take(2).collect::<String>();
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(byte) if hex.len() == 2 => byte,
+                        _ => bail!("Bad \\x escape in line delimiter: {}", spec),
+                    }
+                }
+                _ => bail!("Bad escape in line delimiter: {}", spec),
+            });
+        }
+        if bytes.is_empty() {
+            bail!("The line delimiter can't be empty");
+        }
+
+        Ok(LineDelimiter::Bytes(bytes))
+    }
+}
+
+/// How a file is compressed, decompressed with the matching command line tool, which needs to be
+/// installed. The tools can't start part way through a file, so compressed files are decompressed
+/// to a file to be read at random.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    const ALL: [Compression; 3] = [Compression::Gzip, Compression::Zstd, Compression::Bzip2];
+
+    /// How a file is compressed, by its name or else its first bytes, None if it isn't.
+    pub fn detect(path: &str) -> Option<Self> {
+        Compression::from_name(path).or_else(|| {
+            let mut magic = [0; 4];
+            let read = File::open(path)
+                .and_then(|mut file| file.read(&mut magic))
+                .ok()?;
+            Compression::ALL
+                .into_iter()
+                .find(|c| magic[..read].starts_with(c.magic()))
+        })
+    }
+
+    /// How a file is compressed, by its name alone.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Compression::ALL
+            .into_iter()
+            .find(|c| c.extensions().iter().any(|e| name.ends_with(e)))
+    }
+
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Compression::Gzip => &[".gz"],
+            Compression::Zstd => &[".zst", ".zstd"],
+            Compression::Bzip2 => &[".bz2"],
+        }
+    }
+
+    fn magic(&self) -> &'static [u8] {
+        match self {
+            Compression::Gzip => &[0x1f, 0x8b],
+            Compression::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+            Compression::Bzip2 => b"BZh",
+        }
+    }
+
+    /// The tool that decompresses to standard output, reading a file given to it or else its input.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+
+    /// Start decompressing the file into the output, or what is written to the decompressor's
+    /// input if no file is given.
+    pub fn decompress(&self, compressed: Option<&Path>, output: File) -> Result<Decompressor> {
+        let mut command = Command::new(self.command());
+        command.arg("-dc");
+        match compressed {
+            Some(compressed) => command.arg(compressed).stdin(Stdio::null()),
+            None => command.stdin(Stdio::piped()),
+        };
+        let mut child = command
+            .stdout(output)
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.not_run(e))?;
+
+        // Read what the tool reports as it goes, so it isn't held up by a full pipe.
+        let errors = child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut errors = String::new();
+                stderr.read_to_string(&mut errors).ok();
+                errors
+            })
+        });
+
+        Ok(Decompressor {
+            compression: *self,
+            child,
+            errors,
+        })
+    }
+
+    /// Decompress the whole file.
+    pub fn decompress_all(&self, compressed: &Path) -> Result<Vec<u8>> {
+        let output = Command::new(self.command())
+            .arg("-dc")
+            .arg(compressed)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| self.not_run(e))?;
+        if !output.status.success() {
+            bail!(
+                "{} failed to decompress {:?}: {}",
+                self.command(),
+                compressed,
+                describe_failure(output.status, &String::from_utf8_lossy(&output.stderr))
+            );
+        }
+
+        Ok(output.stdout)
+    }
+
+    /// Fail if the tool can't be run, e.g. isn't installed.
+    pub fn check_installed(&self) -> Result<()> {
+        Command::new(self.command())
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| self.not_run(e))?;
+
+        Ok(())
+    }
+
+    fn not_run(&self, e: io::Error) -> anyhow::Error {
+        match e.kind() {
+            io::ErrorKind::NotFound => anyhow!(
+                "{} isn't installed, it's needed to decompress {} files",
+                self.command(),
+                self.extensions()[0]
+            ),
+            _ => anyhow!("Failed to run {}: {}", self.command(), e),
+        }
+    }
+
+    /// The name without the compression extension, e.g. `app.log.1` for `app.log.1.zst`.
+    pub fn strip<'a>(&self, name: &'a str) -> &'a str {
+        self.extensions()
+            .iter()
+            .find_map(|e| name.strip_suffix(e))
+            .unwrap_or(name)
+    }
+}
+
+/// A compressed file, or what is written to its input, being decompressed by its tool. The tool is
+/// stopped if still running when this is dropped.
+#[derive(Debug)]
+pub struct Decompressor {
+    compression: Compression,
+    child: Child,
+    // What the tool reports, read until it exits, to say why it failed.
+    errors: Option<JoinHandle<String>>,
+}
+
+impl Decompressor {
+    /// The tool's input, when decompressing what is written to it. Dropping it lets the tool finish.
+    pub fn input(&mut self) -> Option<ChildStdin> {
+        self.child.stdin.take()
+    }
+
+    /// Whether decompressing has finished, None whilst it hasn't, failing with what the tool reported
+    /// if it failed, e.g. for a corrupt file.
+    pub fn try_finish(&mut self) -> Option<Result<()>> {
+        match self.child.try_wait() {
+            Ok(None) => None,
+            Ok(Some(status)) => Some(self.finished(status)),
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+
+    /// Wait for decompressing to finish.
+    pub fn finish(&mut self) -> Result<()> {
+        let status = self.child.wait()?;
+        self.finished(status)
+    }
+
+    fn finished(&mut self, status: ExitStatus) -> Result<()> {
+        if status.success() {
+            return Ok(());
+        }
+        let errors = self
+            .errors
+            .take()
+            .and_then(|errors| errors.join().ok())
+            .unwrap_or_default();
+        bail!(
+            "{} failed: {}",
+            self.compression.command(),
+            describe_failure(status, &errors)
+        )
+    }
+}
+
+impl Drop for Decompressor {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            self.child.kill().ok();
+        }
+        self.child.wait().ok();
+    }
+}
+
+// Why a tool failed: what it reported, else how it exited.
+fn describe_failure(status: ExitStatus, errors: &str) -> String {
+    match errors.trim() {
+        "" => status.to_string(),
+        errors => errors.lines().collect::<Vec<_>>().join("; "),
+    }
 }
 
 /// Provide random access to a file on disk.
 #[derive(Debug)]
 pub struct FileBackingFile {
-    br: BufReader<File>,
+    // None until the file waited for is created.
+    br: Option<BufReader<DiskFile>>,
+    delimiter: LineDelimiter,
 }
 
 impl FileBackingFile {
@@ -28,14 +299,67 @@ impl FileBackingFile {
     }
 
     pub fn new(path: &PathBuf) -> Result<Self> {
-        let file = File::open(path.clone())?;
+        let file = DiskFile::open(path)?;
         let bf = Self {
-            br: BufReader::new(file),
+            br: Some(BufReader::new(file)),
+            delimiter: LineDelimiter::Newline,
         };
 
         Ok(bf)
     }
 
+    /// A file that doesn't exist yet, read once reopened when it has been created.
+    pub fn pending() -> Self {
+        Self {
+            br: None,
+            delimiter: LineDelimiter::Newline,
+        }
+    }
+
+    pub fn with_delimiter(mut self, delimiter: LineDelimiter) -> Self {
+        self.delimiter = delimiter;
+
+        self
+    }
+
+    fn opened(br: &mut Option<BufReader<DiskFile>>) -> Result<&mut BufReader<DiskFile>> {
+        br.as_mut()
+            .ok_or_else(|| anyhow!("The file hasn't been created yet"))
+    }
+
+    // Read a line up to the delimiter, or what there is of it. Gives the bytes read and whether it
+    // is partial, i.e. the delimiter hasn't been written yet.
+    fn read_delimited(&mut self, line: &mut String) -> Result<(usize, bool)> {
+        let delimiter = match &self.delimiter {
+            LineDelimiter::Newline => {
+                let bytes = Self::opened(&mut self.br)?.read_line(line)?;
+                return Ok((bytes, FileBackingFile::trim_line_end(line)));
+            }
+            LineDelimiter::Bytes(delimiter) => delimiter,
+        };
+
+        // Read to the last byte of the delimiter until the whole delimiter has been read.
+        let last = delimiter[delimiter.len() - 1];
+        let br = Self::opened(&mut self.br)?;
+        let mut record = Vec::new();
+        loop {
+            let bytes = br.read_until(last, &mut record)?;
+            if bytes == 0 || record.ends_with(delimiter) {
+                break;
+            }
+        }
+
+        let bytes = record.len();
+        let partial = !record.ends_with(delimiter);
+        if !partial {
+            record.truncate(bytes - delimiter.len());
+        }
+        // Records such as file names needn't be UTF-8.
+        line.push_str(&String::from_utf8_lossy(&record));
+
+        Ok((bytes, partial))
+    }
+
     fn trim_line_end(line: &mut String) -> bool {
         if line.ends_with('\n') {
             line.pop();
@@ -57,25 +381,218 @@ impl BackingFile for FileBackingFile {
         }
 
         let mut line = String::new();
-        self.br.read_line(&mut line)?;
-
-        // Remove trailing newline if present
-        FileBackingFile::trim_line_end(&mut line);
+        self.read_delimited(&mut line)?;
 
         Ok(line)
     }
 
+    fn read_line_start(&mut self, offset: u64, max_bytes: usize) -> Result<(String, bool)> {
+        self.seek(offset)?;
+
+        // Reading as far as a delimiter after the bytes wanted tells whether there is more to the
+        // line.
+        let delimiter_bytes = match &self.delimiter {
+            LineDelimiter::Newline => 2,
+            LineDelimiter::Bytes(delimiter) => delimiter.len(),
+        };
+        let mut start = Vec::new();
+        Self::opened(&mut self.br)?
+            .take((max_bytes + delimiter_bytes) as u64)
+            .read_to_end(&mut start)?;
+        let end = match &self.delimiter {
+            LineDelimiter::Newline => start.iter().position(|b| *b == b'\n').map(|end| {
+                if end > 0 && start[end - 1] == b'\r' {
+                    end - 1
+                } else {
+                    end
+                }
+            }),
+            LineDelimiter::Bytes(delimiter) => start
+                .windows(delimiter.len())
+                .position(|window| window == delimiter.as_slice()),
+        };
+        let cut = match end {
+            Some(end) if end <= max_bytes => {
+                start.truncate(end);
+                false
+            }
+            _ => start.len() > max_bytes,
+        };
+        start.truncate(max_bytes);
+        // A character split by the cut is left off.
+        if let Err(e) = std::str::from_utf8(&start) {
+            if e.error_len().is_none() {
+                start.truncate(e.valid_up_to());
+            }
+        }
+
+        Ok((String::from_utf8_lossy(&start).into_owned(), cut))
+    }
+
     fn seek(&mut self, offset: u64) -> Result<()> {
-        self.br.seek(io::SeekFrom::Start(offset))?;
+        Self::opened(&mut self.br)?.seek(io::SeekFrom::Start(offset))?;
 
         Ok(())
     }
 
     fn incremental_read(&mut self, line: &mut String) -> Result<(usize, bool)> {
-        let bytes = self.br.read_line(line)?;
+        self.read_delimited(line)
+    }
 
-        let partial = FileBackingFile::trim_line_end(line);
+    fn reopen(&mut self, path: &Path) -> Result<()> {
+        *self = Self::new(&path.to_path_buf())?.with_delimiter(self.delimiter.clone());
 
-        Ok((bytes, partial))
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        assert_eq!(
+            Compression::from_name("app.log.2.zst"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::from_name("app.log.bz2"),
+            Some(Compression::Bzip2)
+        );
+        assert_eq!(Compression::from_name("app.log"), None);
+        assert_eq!(Compression::Zstd.strip("app.log.1.zstd"), "app.log.1");
+
+        let path = std::env::temp_dir().join(format!("otail-compressed-{}", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        for (bytes, compression) in [
+            (&[0x1f, 0x8b, 0x08][..], Some(Compression::Gzip)),
+            (&[0x28, 0xb5, 0x2f, 0xfd, 0x04][..], Some(Compression::Zstd)),
+            (&b"BZh91AY"[..], Some(Compression::Bzip2)),
+            (&b"plain text\n"[..], None),
+            (&b""[..], None),
+        ] {
+            std::fs::write(&path, bytes).unwrap();
+            assert_eq!(Compression::detect(&path), compression);
+        }
+        assert_eq!(Compression::detect("/nonexistent/app.log"), None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_decompress() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("otail-decompress-test-{}", std::process::id()));
+        let compressed = dir.join(format!("otail-decompress-test-{}.gz", std::process::id()));
+        std::fs::write(&path, "one\ntwo\n").unwrap();
+        let status = Command::new("gzip")
+            .arg("-c")
+            .arg(&path)
+            .stdout(File::create(&compressed).unwrap())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut decompressor = Compression::Gzip
+            .decompress(Some(&compressed), File::create(&path).unwrap())
+            .unwrap();
+        decompressor.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        assert_eq!(
+            Compression::Gzip.decompress_all(&compressed).unwrap(),
+            b"one\ntwo\n"
+        );
+
+        // A corrupt file fails with what the tool reported.
+        let bytes = std::fs::read(&compressed).unwrap();
+        std::fs::write(&compressed, &bytes[..bytes.len() / 2]).unwrap();
+        let mut decompressor = Compression::Gzip
+            .decompress(Some(&compressed), File::create(&path).unwrap())
+            .unwrap();
+        let error = decompressor.finish().unwrap_err().to_string();
+        assert!(error.starts_with("gzip failed: gzip:"), "{}", error);
+        assert!(Compression::Gzip.decompress_all(&compressed).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&compressed).unwrap();
+    }
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(
+            LineDelimiter::parse("newline").unwrap(),
+            LineDelimiter::Newline
+        );
+        assert_eq!(
+            LineDelimiter::parse("nul").unwrap(),
+            LineDelimiter::Bytes(vec![0])
+        );
+        assert_eq!(
+            LineDelimiter::parse("\\r\\n--\\x1e").unwrap(),
+            LineDelimiter::Bytes(b"\r\n--\x1e".to_vec())
+        );
+        assert!(LineDelimiter::parse("").is_err());
+        assert!(LineDelimiter::parse("\\x1").is_err());
+        assert!(LineDelimiter::parse("\\q").is_err());
+    }
+
+    #[test]
+    fn test_read_delimited() {
+        let path = std::env::temp_dir().join(format!("otail-delimited-{}", std::process::id()));
+        std::fs::write(&path, b"one;;two;three;;fo").unwrap();
+        let mut file = FileBackingFile::new_from_path(path.to_str().unwrap())
+            .unwrap()
+            .with_delimiter(LineDelimiter::parse(";;").unwrap());
+
+        let mut read = || {
+            let mut line = String::new();
+            let (bytes, partial) = file.incremental_read(&mut line).unwrap();
+            (line, bytes, partial)
+        };
+        assert_eq!(read(), ("one".to_owned(), 5, false));
+        assert_eq!(read(), ("two;three".to_owned(), 11, false));
+        assert_eq!(read(), ("fo".to_owned(), 2, true));
+        assert_eq!(read(), (String::new(), 0, true));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_line_start() {
+        let path = std::env::temp_dir().join(format!("otail-line-start-{}", std::process::id()));
+        std::fs::write(&path, "short\r\nab日本語\nfour\n").unwrap();
+        let mut file = FileBackingFile::new_from_path(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            file.read_line_start(0, 5).unwrap(),
+            ("short".to_owned(), false)
+        );
+        // Never split a character.
+        assert_eq!(
+            file.read_line_start(7, 6).unwrap(),
+            ("ab日".to_owned(), true)
+        );
+        assert_eq!(
+            file.read_line_start(7, 11).unwrap(),
+            ("ab日本語".to_owned(), false)
+        );
+        assert_eq!(
+            file.read_line_start(19, 4).unwrap(),
+            ("four".to_owned(), false)
+        );
+
+        let mut file = file.with_delimiter(LineDelimiter::parse(";;").unwrap());
+        std::fs::write(&path, b"one;;two;three;;").unwrap();
+        assert_eq!(
+            file.read_line_start(5, 9).unwrap(),
+            ("two;three".to_owned(), false)
+        );
+        assert_eq!(
+            file.read_line_start(5, 4).unwrap(),
+            ("two;".to_owned(), true)
+        );
+
+        std::fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+/// Pressed before a chord unless the config says otherwise.
+pub const DEFAULT_LEADER: char = ',';
+
+// The chords there are without any config, as keys and the command they run. A command ending in
+// a space is put in the command prompt to finish.
+const DEFAULT_CHORDS: [(&str, &str); 10] = [
+    ("f f", "filter"),
+    ("f o", "e "),
+    ("h h", "history"),
+    ("h l", "hl "),
+    ("h c", "nohl"),
+    ("b l", "bookmarks"),
+    ("b e", "bookmarks "),
+    ("s s", "s"),
+    ("p p", "pin "),
+    ("p u", "unpin"),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum ChordMatch {
+    // The keys finish a chord, running this command.
+    Command(String),
+    // The keys start chords, with the keys that can follow and the commands they run, or None if
+    // more keys are needed.
+    Prefix(Vec<(char, Option<String>)>),
+    None,
+}
+
+/// Sequences of keys pressed after the leader key, e.g. `f o`, each running a command as typed at
+/// the `:` prompt. They make room for commands without a key of their own.
+#[derive(Debug, Clone)]
+pub struct Chords {
+    bindings: BTreeMap<String, String>,
+}
+
+impl Chords {
+    /// The default chords, changed by the config's. A chord with an empty command is removed.
+    pub fn new(config: &BTreeMap<String, String>) -> Self {
+        let mut bindings = DEFAULT_CHORDS
+            .iter()
+            .map(|(keys, command)| (keys.to_string(), command.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        for (keys, command) in config {
+            let keys = normalise(keys);
+            if command.trim().is_empty() {
+                bindings.remove(&keys);
+            } else {
+                bindings.insert(keys, command.clone());
+            }
+        }
+
+        Chords { bindings }
+    }
+
+    /// What the keys pressed so far, separated by spaces, do.
+    pub fn lookup(&self, keys: &str) -> ChordMatch {
+        if let Some(command) = self.bindings.get(keys) {
+            return ChordMatch::Command(command.clone());
+        }
+
+        let prefix = if keys.is_empty() {
+            String::new()
+        } else {
+            format!("{} ", keys)
+        };
+        let mut next: Vec<(char, Option<String>)> = Vec::new();
+        for (chord, command) in self.bindings.range(prefix.clone()..) {
+            let Some(rest) = chord.strip_prefix(&prefix) else {
+                break;
+            };
+            let Some(key) = rest.chars().next() else {
+                continue;
+            };
+            let command = (rest.chars().count() == 1).then(|| command.clone());
+            match next.iter_mut().find(|(k, _)| *k == key) {
+                Some(existing) if command.is_some() => existing.1 = command,
+                Some(_) => {}
+                None => next.push((key, command)),
+            }
+        }
+
+        if next.is_empty() {
+            ChordMatch::None
+        } else {
+            ChordMatch::Prefix(next)
+        }
+    }
+}
+
+// Chords written with any spacing, e.g. `fo` or `f  o`, as `f o`.
+fn normalise(keys: &str) -> String {
+    keys.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(String::from)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chords() {
+        let config = BTreeMap::from([
+            ("fs".to_owned(), "filter-save".to_owned()),
+            ("h c".to_owned(), "".to_owned()),
+            ("g x y".to_owned(), "e /var/log/syslog".to_owned()),
+        ]);
+        let chords = Chords::new(&config);
+
+        assert_eq!(
+            chords.lookup("f s"),
+            ChordMatch::Command("filter-save".to_owned())
+        );
+        assert_eq!(chords.lookup("h c"), ChordMatch::None);
+        assert_eq!(
+            chords.lookup("h"),
+            ChordMatch::Prefix(vec![
+                ('h', Some("history".to_owned())),
+                ('l', Some("hl ".to_owned())),
+            ])
+        );
+        assert_eq!(chords.lookup("g"), ChordMatch::Prefix(vec![('x', None)]));
+        assert_eq!(
+            chords.lookup("g x"),
+            ChordMatch::Prefix(vec![('y', Some("e /var/log/syslog".to_owned()))])
+        );
+        let ChordMatch::Prefix(first) = chords.lookup("") else {
+            panic!("No chords");
+        };
+        assert_eq!(
+            first.iter().map(|(key, _)| *key).collect::<String>(),
+            "bfghps"
+        );
+        assert_eq!(chords.lookup("q"), ChordMatch::None);
+        assert_eq!(chords.lookup("f s x"), ChordMatch::None);
+    }
+}
@@ -0,0 +1,141 @@
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// How text is copied to the clipboard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardMode {
+    // A local clipboard utility, falling back to OSC52 over SSH or if there isn't one.
+    #[default]
+    Auto,
+    // Only a local clipboard utility.
+    Local,
+    // Only OSC52, where the terminal sets its clipboard. Works over SSH and in tmux.
+    Osc52,
+}
+
+// Local clipboard utilities, with their arguments and the variable set when they can be used.
+const UTILITIES: &[(&str, &[&str], Option<&str>)] = &[
+    ("pbcopy", &[], None),
+    ("wl-copy", &[], Some("WAYLAND_DISPLAY")),
+    ("xclip", &["-selection", "clipboard"], Some("DISPLAY")),
+    ("xsel", &["--clipboard", "--input"], Some("DISPLAY")),
+];
+
+/// Copy text to the clipboard, returning how it was copied.
+pub fn copy(text: &str, mode: ClipboardMode) -> Result<&'static str> {
+    // A local utility would copy to the remote machine's clipboard.
+    let over_ssh = env::var_os("SSH_TTY").is_some() || env::var_os("SSH_CONNECTION").is_some();
+
+    if mode == ClipboardMode::Local || (mode == ClipboardMode::Auto && !over_ssh) {
+        match local_utility() {
+            Some((name, args)) => match copy_with(name, args, text) {
+                Ok(()) => return Ok(name),
+                Err(e) if mode == ClipboardMode::Auto => {
+                    warn!("Failed to copy with {}, using OSC52: {:?}", name, e);
+                }
+                Err(e) => return Err(e),
+            },
+            None if mode == ClipboardMode::Local => {
+                bail!("No clipboard utility found: pbcopy, wl-copy, xclip or xsel")
+            }
+            None => {}
+        }
+    }
+
+    let mut stdout = io::stdout();
+    stdout.write_all(osc52(text, env::var_os("TMUX").is_some()).as_bytes())?;
+    stdout.flush()?;
+
+    Ok("OSC52")
+}
+
+fn local_utility() -> Option<(&'static str, &'static [&'static str])> {
+    let path = env::var_os("PATH")?;
+
+    UTILITIES
+        .iter()
+        .filter(|(_, _, needs)| needs.is_none_or(|var| env::var_os(var).is_some()))
+        .find(|(name, _, _)| env::split_paths(&path).any(|dir| dir.join(name).is_file()))
+        .map(|(name, args, _)| (*name, *args))
+}
+
+fn copy_with(name: &str, args: &[&str], text: &str) -> Result<()> {
+    debug!("Copying {} bytes with {}", text.len(), name);
+    let mut child = Command::new(name)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Closing stdin ends the input.
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("No stdin for {}", name))?
+        .write_all(text.as_bytes())?;
+
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{} failed: {}", name, status);
+    }
+
+    Ok(())
+}
+
+// The escape sequence asking the terminal to set its clipboard. Inside tmux it is wrapped to pass
+// through to the outer terminal, which needs tmux's `allow-passthrough` option.
+fn osc52(text: &str, tmux: bool) -> String {
+    let sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_CHARS[(n >> (18 - 6 * i)) & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("héllo wörld".as_bytes()), "aMOpbGxvIHfDtnJsZA==");
+
+        assert_eq!(osc52("foo", false), "\x1b]52;c;Zm9v\x07");
+        assert_eq!(osc52("foo", true), "\x1bPtmux;\x1b\x1b]52;c;Zm9v\x07\x1b\\");
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+// Lines need at least this fraction of their tokens in common with a template to join it.
+const SIMILARITY_THRESHOLD: f64 = 0.5;
+
+const WILDCARD: &str = "<*>";
+
+// Templates seen fewer times than this are rare.
+pub const DEFAULT_RARE_THRESHOLD: usize = 3;
+
+/// A group of similar lines, e.g. `Request to <*> failed after <*> ms`. Variable tokens are None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster {
+    pub template: Vec<Option<String>>,
+    pub count: usize,
+    pub first_line_no: usize,
+}
+
+impl Cluster {
+    pub fn template_string(&self) -> String {
+        self.template
+            .iter()
+            .map(|t| t.as_deref().unwrap_or(WILDCARD))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// A regex matching the lines of this cluster, for use as a filter.
+    pub fn filter_regex(&self) -> String {
+        let tokens = self
+            .template
+            .iter()
+            .map(|t| match t {
+                Some(t) => regex::escape(t),
+                None => r"\S+".to_owned(),
+            })
+            .collect::<Vec<_>>();
+
+        format!(r"^\s*{}\s*$", tokens.join(r"\s+"))
+    }
+
+    // Fraction of the tokens matching the fixed parts of the template.
+    fn similarity(&self, tokens: &[Option<&str>]) -> f64 {
+        if tokens.is_empty() {
+            return 1.0;
+        }
+
+        let same = self
+            .template
+            .iter()
+            .zip(tokens)
+            .filter(|(t, token)| t.is_some() && t.as_deref() == **token)
+            .count();
+
+        same as f64 / tokens.len() as f64
+    }
+
+    fn merge(&mut self, tokens: &[Option<&str>]) {
+        for (t, token) in self.template.iter_mut().zip(tokens) {
+            if t.as_deref() != *token {
+                *t = None;
+            }
+        }
+        self.count += 1;
+    }
+}
+
+/// A regex for lines of the same template as this one, taking tokens with digits to vary as
+/// clustering does.
+pub fn line_filter_regex(line: &str) -> String {
+    let mut clusterer = Clusterer::new();
+    let id = clusterer.add(0, line);
+
+    clusterer.clusters[id].filter_regex()
+}
+
+/// Groups lines into message templates, after the Drain algorithm: lines are bucketed by their
+/// number of tokens and first token, then join the most similar cluster in the bucket, turning
+/// tokens that differ into wildcards. Tokens containing digits are taken to be variable up front.
+#[derive(Debug, Default)]
+pub struct Clusterer {
+    clusters: Vec<Cluster>,
+    buckets: HashMap<(usize, String), Vec<usize>>,
+}
+
+impl Clusterer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a line, returning the index of the cluster it joined.
+    pub fn add(&mut self, line_no: usize, line: &str) -> usize {
+        let tokens = line
+            .split_whitespace()
+            .map(|t| {
+                if t.chars().any(|c| c.is_ascii_digit()) {
+                    None
+                } else {
+                    Some(t)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let first = tokens
+            .first()
+            .map(|t| t.unwrap_or(WILDCARD).to_owned())
+            .unwrap_or_default();
+        let bucket = self.buckets.entry((tokens.len(), first)).or_default();
+
+        let best = bucket
+            .iter()
+            .map(|id| (*id, self.clusters[*id].similarity(&tokens)))
+            .filter(|(_, similarity)| *similarity >= SIMILARITY_THRESHOLD)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        match best {
+            Some((id, _)) => {
+                self.clusters[id].merge(&tokens);
+                id
+            }
+            None => {
+                let id = self.clusters.len();
+                self.clusters.push(Cluster {
+                    template: tokens.iter().map(|t| t.map(|t| t.to_owned())).collect(),
+                    count: 1,
+                    first_line_no: line_no,
+                });
+                bucket.push(id);
+                id
+            }
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Cluster> {
+        self.clusters.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.clusters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clusters.is_empty()
+    }
+
+    /// Number of lines in each cluster, by index.
+    pub fn counts(&self) -> Vec<usize> {
+        self.clusters.iter().map(|c| c.count).collect()
+    }
+
+    /// The clusters, most common first.
+    pub fn into_clusters(mut self) -> Vec<Cluster> {
+        self.clusters.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(a.first_line_no.cmp(&b.first_line_no))
+        });
+
+        self.clusters
+    }
+}
+
+/// The cluster of each file line, for spotting lines from rarely seen templates. Counts keep
+/// changing as lines arrive, so a line may stop being rare.
+#[derive(Debug)]
+pub struct RareLines {
+    threshold: usize,
+    line_clusters: Vec<usize>,
+    counts: Vec<usize>,
+}
+
+impl RareLines {
+    pub fn new(threshold: usize) -> Self {
+        RareLines {
+            threshold,
+            line_clusters: Vec::new(),
+            counts: Vec::new(),
+        }
+    }
+
+    /// Lines clustered so far.
+    pub fn scanned_to(&self) -> usize {
+        self.line_clusters.len()
+    }
+
+    /// Record the clusters of newly scanned lines, up to the end line, and the latest counts.
+    pub fn update(&mut self, end: usize, clusters: Vec<(usize, usize)>, counts: Vec<usize>) {
+        self.line_clusters.resize(end, usize::MAX);
+        for (line_no, id) in clusters {
+            if let Some(c) = self.line_clusters.get_mut(line_no) {
+                *c = id;
+            }
+        }
+        self.counts = counts;
+    }
+
+    pub fn is_rare(&self, line_no: usize) -> bool {
+        self.line_clusters
+            .get(line_no)
+            .and_then(|id| self.counts.get(*id))
+            .is_some_and(|count| *count < self.threshold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    #[test]
+    fn test_line_filter_regex() {
+        let regex = Regex::new(&line_filter_regex("10:01:02 GET /users took 12ms")).unwrap();
+        assert!(regex.is_match("11:00:00 GET /users took 250ms"));
+        assert!(!regex.is_match("11:00:00 GET /orders took 250ms"));
+        assert!(!regex.is_match("11:00:00 GET /users"));
+    }
+
+    #[test]
+    fn test_clusters() {
+        let mut clusterer = Clusterer::new();
+        let lines = [
+            "Request to /api/users failed after 120 ms",
+            "Connected to db",
+            "Request to /api/orders failed after 3 ms",
+            "Request to /api/items failed after 55 ms",
+            "Connected to cache",
+            "Shutting down",
+        ];
+        let ids = lines
+            .iter()
+            .enumerate()
+            .map(|(i, l)| clusterer.add(i, l))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 1, 0, 0, 1, 2]);
+
+        let clusters = clusterer.into_clusters();
+        let summary = clusters
+            .iter()
+            .map(|c| (c.count, c.first_line_no, c.template_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (3, 0, "Request to <*> failed after <*> ms".to_owned()),
+                (2, 1, "Connected to <*>".to_owned()),
+                (1, 5, "Shutting down".to_owned()),
+            ]
+        );
+
+        let regex = Regex::new(&clusters[0].filter_regex()).unwrap();
+        assert!(regex.is_match("Request  to /x failed after 9 ms\n"));
+        assert!(!regex.is_match("Request to /x failed after 9 ms again"));
+    }
+
+    #[test]
+    fn test_rare_lines() {
+        let mut clusterer = Clusterer::new();
+        let mut rare = RareLines::new(2);
+
+        let ids = ["Connected to db", "Disk full", "Connected to cache"]
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (i, clusterer.add(i, l)))
+            .collect();
+        rare.update(3, ids, clusterer.counts());
+
+        assert_eq!(rare.scanned_to(), 3);
+        assert_eq!(
+            (0..4).map(|i| rare.is_rare(i)).collect::<Vec<_>>(),
+            vec![false, true, false, false]
+        );
+
+        let id = clusterer.add(3, "Disk full");
+        rare.update(4, vec![(3, id)], clusterer.counts());
+        assert!(!rare.is_rare(1));
+    }
+}
@@ -1,3 +1,4 @@
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString, VariantArray};
 
@@ -18,12 +19,37 @@ pub enum Colour {
     White,
 }
 
+/// The panes a colouring rule applies to, e.g. heavy backgrounds only in the filtered pane.
+#[derive(Display, Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ColouringScope {
+    #[default]
+    Both,
+    Content,
+    Filter,
+}
+
+impl ColouringScope {
+    pub fn next(self) -> Self {
+        match self {
+            ColouringScope::Both => ColouringScope::Content,
+            ColouringScope::Content => ColouringScope::Filter,
+            ColouringScope::Filter => ColouringScope::Both,
+        }
+    }
+
+    fn is_both(&self) -> bool {
+        *self == ColouringScope::Both
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ColouringRule {
     pub enabled: bool,
     pub filter_spec: FilterSpec,
     pub fg_colour: Option<Colour>,
     pub bg_colour: Option<Colour>,
+    #[serde(default, skip_serializing_if = "ColouringScope::is_both")]
+    pub scope: ColouringScope,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,6 +73,7 @@ impl ColouringRule {
                 }),
             fg_colour: None,
             bg_colour: None,
+            scope: ColouringScope::Both,
         }
     }
 }
@@ -63,6 +90,7 @@ impl ColouringSpec {
                 .expect("Failed to build sample filter spec"),
             fg_colour: Some(Colour::Red),
             bg_colour: None,
+            scope: ColouringScope::Both,
         }])
     }
 
@@ -118,6 +146,34 @@ impl ColouringSpec {
         }
     }
 
+    /// The rules with their filters built, as needed after loading them from the config.
+    pub fn compiled(&self) -> Result<Self> {
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| {
+                Ok(ColouringRule {
+                    filter_spec: rule.filter_spec.compiled()?,
+                    ..rule.clone()
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(ColouringSpec { rules })
+    }
+
+    /// The rules applying to a pane, `Content` or `Filter`.
+    pub fn for_pane(&self, pane: ColouringScope) -> Self {
+        let rules = self
+            .rules
+            .iter()
+            .filter(|rule| rule.scope == ColouringScope::Both || rule.scope == pane)
+            .cloned()
+            .collect();
+
+        ColouringSpec { rules }
+    }
+
     pub fn maybe_colour(&self, line: &str) -> Option<Colours> {
         for r in &self.rules {
             if r.filter_spec.matches(line) {
@@ -128,3 +184,44 @@ impl ColouringSpec {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_pane() {
+        let spec: ColouringSpec = serde_yaml::from_str(
+            r#"
+rules:
+- enabled: true
+  filter_spec: { filter_type: SimpleCaseInsensitive, filter_pattern: error }
+  fg_colour: Red
+  bg_colour: null
+- enabled: true
+  filter_spec: { filter_type: SimpleCaseInsensitive, filter_pattern: warn }
+  fg_colour: null
+  bg_colour: Yellow
+  scope: Filter
+"#,
+        )
+        .unwrap();
+        let spec = spec.compiled().unwrap();
+
+        let content = spec.for_pane(ColouringScope::Content);
+        let filter = spec.for_pane(ColouringScope::Filter);
+        assert_eq!(content.maybe_colour("a warning"), None);
+        assert_eq!(
+            filter.maybe_colour("a warning"),
+            Some((None, Some(Colour::Yellow)))
+        );
+        assert_eq!(
+            content.maybe_colour("an error"),
+            Some((Some(Colour::Red), None))
+        );
+
+        // Rules for both panes are saved as they were.
+        let yaml = serde_yaml::to_string(&spec).unwrap();
+        assert_eq!(yaml.matches("scope").count(), 1);
+    }
+}
@@ -0,0 +1,328 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+// Keep very wide columns from pushing everything else off screen.
+pub const MAX_COLUMN_WIDTH: usize = 40;
+
+const COLUMN_SEPARATOR: &str = " │ ";
+const ELLIPSIS: char = '…';
+
+const CANDIDATE_DELIMITERS: [char; 4] = [',', '\t', ';', '|'];
+
+pub const DEFAULT_JSON_FIELDS: &str = "ts,level,logger,msg";
+
+/// How to break a line into columns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnMode {
+    Delimited { delimiter: char },
+    Json { fields: Vec<String> },
+}
+
+impl ColumnMode {
+    /// Parse a delimiter given on the command line: `auto`, `csv`, `tsv`, `tab` or a single
+    /// character. Returns None for `auto`, meaning detect from the header.
+    pub fn parse_delimiter(spec: &str) -> Result<Option<char>> {
+        match spec {
+            "auto" => Ok(None),
+            "csv" => Ok(Some(',')),
+            "tsv" | "tab" | "\\t" => Ok(Some('\t')),
+            _ => {
+                let mut chars = spec.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Some(c)),
+                    _ => Err(anyhow!("Invalid column delimiter: {}", spec)),
+                }
+            }
+        }
+    }
+
+    /// Parse a comma separated list of JSON fields. Nested fields use dots, e.g. `log.level`.
+    pub fn parse_json_fields(spec: &str) -> Vec<String> {
+        spec.split(',')
+            .map(|f| f.trim())
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_owned())
+            .collect()
+    }
+
+    /// Split a line into its columns. None if the line does not fit the mode (e.g. it is not a
+    /// JSON object), in which case it is shown raw.
+    pub fn split(&self, line: &str) -> Option<Vec<String>> {
+        match self {
+            ColumnMode::Delimited { delimiter } => Some(split_delimited(line, *delimiter)),
+            ColumnMode::Json { fields } => project_json(line, fields),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            ColumnMode::Json { .. } => "JSON".to_owned(),
+            ColumnMode::Delimited { delimiter: '\t' } => "TSV".to_owned(),
+            ColumnMode::Delimited { delimiter: ',' } => "CSV".to_owned(),
+            ColumnMode::Delimited { delimiter } => format!("'{}' delimited", delimiter),
+        }
+    }
+}
+
+/// Pick the most frequent candidate delimiter in the (header) line.
+pub fn detect_delimiter(line: &str) -> Option<char> {
+    CANDIDATE_DELIMITERS
+        .iter()
+        .map(|d| (*d, line.matches(*d).count()))
+        .filter(|(_, count)| *count > 0)
+        .max_by_key(|(_, count)| *count)
+        .map(|(d, _)| d)
+}
+
+/// Split a delimited line, honouring double quoted fields (with `""` as an escaped quote).
+pub fn split_delimited(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field.is_empty() => in_quotes = true,
+            c if c == delimiter && !in_quotes => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+/// Project the named fields out of a JSON object line. Missing fields are left empty, strings are
+/// shown without their quotes.
+pub fn project_json(line: &str, fields: &[String]) -> Option<Vec<String>> {
+    let value = serde_json::from_str::<Value>(line).ok()?;
+    if !value.is_object() {
+        return None;
+    }
+
+    let projected = fields
+        .iter()
+        .map(
+            |field| match field.split('.').try_fold(&value, |v, name| v.get(name)) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(v) => v.to_string(),
+            },
+        )
+        .collect();
+
+    Some(projected)
+}
+
+/// Pretty print a JSON line for the detail popup, or None if it is not JSON.
+pub fn pretty_json(line: &str) -> Option<Vec<String>> {
+    let value = serde_json::from_str::<Value>(line).ok()?;
+    let pretty = serde_json::to_string_pretty(&value).ok()?;
+
+    Some(pretty.lines().map(|l| l.to_owned()).collect())
+}
+
+/// Compare two cells, numerically if both are numbers.
+pub fn compare_cells(a: &str, b: &str) -> Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// Parse a sort request: a column (header name or 1 based number) optionally followed by `asc` or
+/// `desc`. Returns the column index and if the sort is descending.
+pub fn parse_sort_spec(spec: &str, header: &[String]) -> Result<(usize, bool)> {
+    let mut words = spec.split_whitespace();
+    let column = words.next().ok_or_else(|| anyhow!("No column given"))?;
+    let descending = match words.next() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(order) => return Err(anyhow!("Unknown sort order: {}", order)),
+    };
+
+    let index = match column.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= header.len() => n - 1,
+        Ok(n) => return Err(anyhow!("No column number {}", n)),
+        Err(_) => header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(column))
+            .ok_or_else(|| anyhow!("No column named {}", column))?,
+    };
+
+    Ok((index, descending))
+}
+
+/// Column layout state for a pane. Widths only grow so columns don't jump around whilst
+/// scrolling.
+#[derive(Debug, Clone)]
+pub struct ColumnState {
+    pub mode: ColumnMode,
+    pub header: Option<Vec<String>>,
+    pub widths: Vec<usize>,
+    pub start_column: usize,
+}
+
+impl ColumnState {
+    pub fn new(mode: ColumnMode) -> Self {
+        ColumnState {
+            mode,
+            header: None,
+            widths: Vec::new(),
+            start_column: 0,
+        }
+    }
+
+    /// Take the header from the first line of a delimited file.
+    pub fn set_header(&mut self, header: &str) {
+        if let Some(fields) = self.mode.split(header) {
+            self.set_header_fields(fields);
+        }
+    }
+
+    pub fn set_header_fields(&mut self, fields: Vec<String>) {
+        self.fit(&fields);
+        self.header = Some(fields);
+    }
+
+    pub fn fit(&mut self, fields: &[String]) {
+        if self.widths.len() < fields.len() {
+            self.widths.resize(fields.len(), 0);
+        }
+
+        for (i, f) in fields.iter().enumerate() {
+            let len = f.chars().count().min(MAX_COLUMN_WIDTH);
+            if len > self.widths[i] {
+                self.widths[i] = len;
+            }
+        }
+    }
+
+    pub fn num_columns(&self) -> usize {
+        self.widths.len()
+    }
+
+    pub fn pan(&mut self, delta: isize) {
+        let max = self.num_columns().saturating_sub(1);
+        self.start_column = crate::common::clamped_add(self.start_column, delta, 0, max);
+    }
+
+    /// Render the fields aligned to the current widths, starting at the first visible column.
+    pub fn render(&self, fields: &[String]) -> String {
+        fields
+            .iter()
+            .enumerate()
+            .skip(self.start_column)
+            .map(|(i, f)| {
+                let width = self.widths.get(i).copied().unwrap_or(0);
+                pad_or_truncate(f, width)
+            })
+            .collect::<Vec<_>>()
+            .join(COLUMN_SEPARATOR)
+    }
+}
+
+fn pad_or_truncate(field: &str, width: usize) -> String {
+    let len = field.chars().count();
+    if len > width {
+        let mut s = field
+            .chars()
+            .take(width.saturating_sub(1))
+            .collect::<String>();
+        s.push(ELLIPSIS);
+        s
+    } else {
+        format!("{:<width$}", field, width = width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_delimited() {
+        assert_eq!(
+            split_delimited(r#"a,"b,c",,"say ""hi""""#, ','),
+            vec!["a", "b,c", "", r#"say "hi""#]
+        );
+        assert_eq!(split_delimited("x\ty", '\t'), vec!["x", "y"]);
+    }
+
+    #[test]
+    fn test_detect_delimiter() {
+        assert_eq!(detect_delimiter("ts\tlevel\tmsg"), Some('\t'));
+        assert_eq!(detect_delimiter("ts,level,msg"), Some(','));
+        assert_eq!(detect_delimiter("plain text"), None);
+    }
+
+    #[test]
+    fn test_render_aligned() {
+        let mut state = ColumnState::new(ColumnMode::Delimited { delimiter: ',' });
+        state.set_header("id,name");
+        state.fit(&split_delimited("1000,x", ','));
+
+        assert_eq!(state.render(&split_delimited("1,bob", ',')), "1    │ bob ");
+
+        state.pan(1);
+        assert_eq!(state.render(&split_delimited("1,bob", ',')), "bob ");
+    }
+
+    #[test]
+    fn test_parse_sort_spec() {
+        let header = vec!["ts".to_owned(), "Duration".to_owned()];
+
+        assert_eq!(
+            parse_sort_spec("duration desc", &header).unwrap(),
+            (1, true)
+        );
+        assert_eq!(parse_sort_spec("1", &header).unwrap(), (0, false));
+        assert!(parse_sort_spec("3", &header).is_err());
+        assert!(parse_sort_spec("size", &header).is_err());
+        assert!(parse_sort_spec("ts sideways", &header).is_err());
+
+        assert_eq!(compare_cells("9", "10"), Ordering::Less);
+        assert_eq!(compare_cells("b", "a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_project_json() {
+        let fields = ColumnMode::parse_json_fields("ts, level,,log.logger,n");
+        assert_eq!(fields, vec!["ts", "level", "log.logger", "n"]);
+
+        assert_eq!(
+            project_json(
+                r#"{"ts":"10:00","level":"INFO","log":{"logger":"app"},"n":3,"msg":"hi"}"#,
+                &fields
+            ),
+            Some(vec![
+                "10:00".to_owned(),
+                "INFO".to_owned(),
+                "app".to_owned(),
+                "3".to_owned()
+            ])
+        );
+        assert_eq!(
+            project_json(r#"{"level":"WARN"}"#, &fields),
+            Some(vec![
+                "".to_owned(),
+                "WARN".to_owned(),
+                "".to_owned(),
+                "".to_owned()
+            ])
+        );
+        assert_eq!(project_json("not json", &fields), None);
+        assert_eq!(project_json("[1, 2]", &fields), None);
+    }
+}
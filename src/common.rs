@@ -1,23 +1,93 @@
 pub const CHANNEL_BUFFER: usize = 1000;
 
-pub const FPS: u64 = 20;
-pub const MS_PER_FRAME: u64 = 2_000 / FPS;
+// Frames per second whilst things are changing, and once they have been still for a while.
+pub const FPS: u64 = 10;
+pub const IDLE_FPS: u64 = 2;
+pub const IDLE_AFTER_MS: u64 = 2_000;
 
 pub const FILTER_SPOOLING_BATCH_SIZE: usize = 10;
 
+// Ripgrep matches passed back to the filter at a time.
+pub const RIPGREP_BATCH_SIZE: usize = 1000;
+
+pub const ANALYSIS_BATCH_SIZE: usize = 1000;
+
+// Lines scanned for time gaps per background request.
+pub const GAP_SCAN_LINES: usize = 50_000;
+
+// Message templates shown in the cluster table.
+pub const MAX_CLUSTER_ROWS: usize = 1000;
+
+// Values shown in the top values table.
+pub const MAX_TOP_VALUES: usize = 1000;
+
+// Lines longer than this are cut down to the part on screen before rendering.
+pub const LONG_LINE_BYTES: usize = 64 * 1024;
+
+// Lines longer than this are cut short when read, unless the config says otherwise.
+pub const DEFAULT_MAX_LINE_BYTES: usize = 1024 * 1024;
+
+// Ends a line that was cut short.
+const CAPPED_LINE_SUFFIX: &str = " line – press Enter to load]";
+
+// How often the file list sidebar checks the files for changes.
+pub const FILE_LIST_REFRESH_MS: u64 = 1000;
+
+// How often a followed symlink is checked for being re-pointed.
+pub const LINK_CHECK_MS: u64 = 1000;
+
+// Filters over at least this many lines have their matches cached, unless the config says otherwise.
+pub const DEFAULT_FILTER_CACHE_LINES: usize = 10_000;
+
+// How often the reader is checked for having died or stopped seeing the file grow.
+pub const READER_WATCHDOG_MS: u64 = 5000;
+
+// A reader that fails is retried after this long, doubling with each failure in a row up to the
+// longest delay, giving up after so many failures, unless the config says otherwise.
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 250;
+pub const DEFAULT_RETRY_MAX_DELAY_MS: u64 = 30_000;
+pub const DEFAULT_RETRY_ATTEMPTS: u32 = 10;
+
+// The file's growth rate is averaged over this long.
+pub const GROWTH_WINDOW_MS: u64 = 5000;
+
+// Lines a stream can have waiting to be spooled unless the config says otherwise.
+pub const DEFAULT_STREAM_BUFFER_LINES: usize = 100_000;
+
+// Whilst sampling, show one line in this many unless the config says otherwise.
+pub const DEFAULT_SAMPLE_EVERY: usize = 50;
+
+use fmtsize::{Conventional, FmtSize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthChar;
+
 pub trait LineContent {
-    fn len(&self) -> usize;
+    // Width of the line on screen, in display columns.
+    fn width(&self) -> usize;
     fn render(&self) -> String; // TODO: Return structure for better display
+
+    // The line as read, e.g. with any tabs.
+    fn content(&self) -> &str;
+
+    // The line number in the file, if it differs from the line's position in the view.
+    fn file_line_no(&self) -> Option<usize> {
+        None
+    }
 }
 
 impl LineContent for String {
-    fn len(&self) -> usize {
-        self.len()
+    fn width(&self) -> usize {
+        display_width(self)
     }
 
     fn render(&self) -> String {
         replace_for_view(self)
     }
+
+    fn content(&self) -> &str {
+        self
+    }
 }
 
 pub fn clamped_sub(a: usize, b: usize) -> usize {
@@ -49,3 +119,306 @@ pub fn count_digits(n: usize) -> usize {
 pub fn replace_for_view(line: &String) -> String {
     line.replace("\t", " ")
 }
+
+// Width of a character on screen. Tabs are shown as a single space, other control characters
+// take no space.
+fn char_width(c: char) -> usize {
+    if c == '\t' {
+        1
+    } else {
+        c.width().unwrap_or(0)
+    }
+}
+
+pub fn display_width(line: &str) -> usize {
+    line.chars().map(char_width).sum()
+}
+
+/// Cut a line longer than the cap short, at a character boundary, noting its full size so it can
+/// be loaded whole when wanted. Returns whether it was cut.
+pub fn cap_line(line: &mut String, max_bytes: usize) -> bool {
+    if line.len() <= max_bytes {
+        return false;
+    }
+
+    let bytes = line.len();
+    cut_line(line, max_bytes, bytes);
+
+    true
+}
+
+/// Cut a line short at the cap, noting its full size, e.g. when only the start of it was read and
+/// its size is known from where it ends.
+pub fn cut_line(line: &mut String, max_bytes: usize, bytes: usize) {
+    let mut end = max_bytes.min(line.len());
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    line.truncate(end);
+    line.push_str(&format!(
+        "… [{}{}",
+        (bytes as u64).fmt_size(Conventional),
+        CAPPED_LINE_SUFFIX
+    ));
+}
+
+/// Was the line cut short by `cap_line`?
+pub fn is_capped(line: &str) -> bool {
+    line.ends_with(CAPPED_LINE_SUFFIX)
+}
+
+/// The part of a line seen through a window starting at a display column. A wide character cut by
+/// either edge of the window is shown as spaces, so characters are never split and the rest of the
+/// line stays aligned.
+pub fn display_window(line: &str, start: usize, width: usize) -> String {
+    let end = start + width;
+    let mut window = String::new();
+    let mut column = 0;
+    // Zero width characters, e.g. combining accents, go with the character before them.
+    let mut last_shown = start == 0;
+    for c in line.chars() {
+        let width = char_width(c);
+        if width == 0 {
+            if last_shown {
+                window.push(c);
+            }
+            continue;
+        }
+        if column >= end {
+            break;
+        }
+
+        let next = column + width;
+        last_shown = column >= start && next <= end;
+        if last_shown {
+            window.push(c);
+        } else if next > start {
+            let shown = next.min(end) - column.max(start);
+            window.extend(std::iter::repeat_n(' ', shown));
+        }
+        column = next;
+    }
+
+    window
+}
+
+/// The part of a line covering a window of display columns, without copying it. Returns the
+/// slice, the column within it where the window starts (non zero when a wide character is cut)
+/// and whether the slice reaches the end of the line.
+pub fn display_slice(line: &str, start: usize, width: usize) -> (&str, usize, bool) {
+    let end = start + width;
+    let mut column = 0;
+    let mut from = None;
+    for (i, c) in line.char_indices() {
+        let next = column + char_width(c);
+        if from.is_none() && next > start {
+            from = Some((i, start - column));
+        }
+        if column >= end {
+            let (from, offset) = from.unwrap_or((i, 0));
+            return (&line[from..i], offset, false);
+        }
+        column = next;
+    }
+
+    let (from, offset) = from.unwrap_or((line.len(), 0));
+    (&line[from..], offset, true)
+}
+
+/// Fill in the `{name}` placeholders in a template, leaving those without a value as they are.
+pub fn fill_template(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        filled.push_str(&rest[..open]);
+        let placeholder = &rest[open..];
+        match placeholder
+            .find('}')
+            .and_then(|close| Some((close, value(&placeholder[1..close])?)))
+        {
+            Some((close, value)) => {
+                filled.push_str(&value);
+                rest = &placeholder[close + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &placeholder[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+
+    filled
+}
+
+/// How fast a file is growing, from its size sampled over a recent window.
+#[derive(Debug)]
+pub struct GrowthRate {
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>,
+    // Sizes below this are the file being loaded, not it growing.
+    loading_to: u64,
+}
+
+impl GrowthRate {
+    pub fn new(window: Duration) -> Self {
+        GrowthRate {
+            window,
+            samples: VecDeque::new(),
+            loading_to: 0,
+        }
+    }
+
+    pub fn loading_to(mut self, bytes: u64) -> Self {
+        self.loading_to = bytes;
+
+        self
+    }
+
+    pub fn sample(&mut self, now: Instant, bytes: u64) {
+        // A smaller file has been truncated, so its earlier sizes mean nothing.
+        if self.samples.back().is_some_and(|&(_, last)| bytes < last) {
+            self.samples.clear();
+            self.loading_to = 0;
+        }
+        if bytes < self.loading_to {
+            return;
+        }
+        self.samples.push_back((now, bytes));
+        while self
+            .samples
+            .front()
+            .is_some_and(|&(at, _)| now.duration_since(at) > self.window)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    // Bytes per second over the samples in the window.
+    pub fn bytes_per_sec(&self) -> u64 {
+        let (Some(&(first_at, first)), Some(&(last_at, last))) =
+            (self.samples.front(), self.samples.back())
+        else {
+            return 0;
+        };
+        let secs = last_at.duration_since(first_at).as_secs_f64();
+        if secs <= 0.0 {
+            return 0;
+        }
+
+        ((last - first) as f64 / secs) as u64
+    }
+
+    pub fn describe(&self) -> String {
+        match self.bytes_per_sec() {
+            0 => "→".to_owned(),
+            rate => format!("↑ {}/s", rate.fmt_size(Conventional)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_window() {
+        let line = "ab日本c\td";
+        assert_eq!(display_width(line), 9);
+
+        assert_eq!(display_window(line, 0, 9), line);
+        assert_eq!(display_window(line, 2, 4), "日本");
+        // Wide characters cut by the edges become spaces.
+        assert_eq!(display_window(line, 3, 3), " 本");
+        assert_eq!(display_window(line, 1, 2), "b ");
+        assert_eq!(display_window(line, 6, 10), "c\td");
+        assert_eq!(display_window(line, 20, 10), "");
+
+        // A slice shows the same window.
+        for (start, width) in [(0, 9), (2, 4), (3, 3), (1, 2), (6, 10), (20, 10)] {
+            let (slice, offset, _) = display_slice(line, start, width);
+            assert_eq!(
+                display_window(slice, offset, width),
+                display_window(line, start, width)
+            );
+        }
+        assert_eq!(display_slice(line, 3, 3), ("日本", 1, false));
+        assert_eq!(display_slice(line, 6, 3), ("c\td", 0, true));
+
+        // Combining characters stay with their base character.
+        assert_eq!(display_window("e\u{301}x", 1, 1), "x");
+        assert_eq!(display_window("e\u{301}x", 0, 1), "e\u{301}");
+    }
+
+    #[test]
+    fn test_cap_line() {
+        let mut line = "short".to_owned();
+        assert!(!cap_line(&mut line, 5));
+        assert_eq!(line, "short");
+        assert!(!is_capped(&line));
+
+        // Never split a character.
+        let mut line = "ab日本".repeat(100);
+        assert!(cap_line(&mut line, 4));
+        assert!(line.starts_with("ab… ["));
+        assert!(is_capped(&line));
+
+        // The size noted is the line's, not the start read.
+        let mut line = "ab".to_owned();
+        cut_line(&mut line, 4, 2048);
+        assert_eq!(
+            line,
+            format!(
+                "ab… [{}{}",
+                2048u64.fmt_size(Conventional),
+                CAPPED_LINE_SUFFIX
+            )
+        );
+    }
+
+    #[test]
+    fn test_fill_template() {
+        let value = |name: &str| match name {
+            "path" => Some("app.log".to_owned()),
+            "lines" => Some("1,234".to_owned()),
+            _ => None,
+        };
+        assert_eq!(
+            fill_template("{path}: {lines} lines", value),
+            "app.log: 1,234 lines"
+        );
+        assert_eq!(fill_template("{nope} {path", value), "{nope} {path");
+        assert_eq!(fill_template("{{path}}", value), "{app.log}");
+        assert_eq!(fill_template("", value), "");
+    }
+
+    #[test]
+    fn test_growth_rate() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut growth = GrowthRate::new(Duration::from_secs(5));
+        assert_eq!(growth.bytes_per_sec(), 0);
+
+        growth.sample(at(0), 1000);
+        growth.sample(at(1000), 1500);
+        growth.sample(at(2000), 3000);
+        assert_eq!(growth.bytes_per_sec(), 1000);
+
+        // Samples older than the window are forgotten, so a file that stops growing goes flat.
+        growth.sample(at(8000), 3000);
+        assert_eq!(growth.bytes_per_sec(), 0);
+        assert_eq!(growth.describe(), "→");
+
+        // Truncation starts again.
+        growth.sample(at(9000), 100);
+        growth.sample(at(10000), 300);
+        assert_eq!(growth.bytes_per_sec(), 200);
+
+        // Loading the file isn't growth.
+        let mut growth = GrowthRate::new(Duration::from_secs(5)).loading_to(5000);
+        growth.sample(at(0), 0);
+        growth.sample(at(1000), 5000);
+        growth.sample(at(2000), 5000);
+        assert_eq!(growth.bytes_per_sec(), 0);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::read_to_string;
 use std::path::Path;
@@ -5,8 +6,13 @@ use std::path::Path;
 use anyhow::Result;
 use log::{info, trace, warn};
 use serde::{Deserialize, Serialize};
+use strum::EnumString;
 
-use crate::colour_spec::ColouringSpec;
+use crate::clipboard::ClipboardMode;
+use crate::colour_spec::{Colour, ColouringSpec};
+use crate::filter_spec::FilterSpec;
+use crate::retry::RetryPolicy;
+use crate::spool::DropPolicy;
 
 const CONFIG_FILENAME: &str = "otail.yaml";
 
@@ -15,6 +21,302 @@ pub struct OtailConfig {
     #[serde(default)]
     pub readonly: bool,
     pub colouring: ColouringSpec,
+
+    // Extra strftime timestamp formats, tried before the built in ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timestamp_formats: Vec<String>,
+
+    // Mark lines more than this many seconds after the previous timestamp. 0 disables.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_gap_secs: Option<f64>,
+
+    // Templates seen fewer times than this are highlighted as rare.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rare_message_threshold: Option<usize>,
+
+    // How the current line is shown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_line: Option<CurrentLineStyle>,
+
+    // Whether the panes are stacked or side by side.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<PaneLayout>,
+
+    // Percentage of the screen for the content pane, updated as the panes are resized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_percent: Option<usize>,
+
+    // Templates for the title line and the status line under the content pane, with placeholders
+    // such as `{path}` and `{matches}`, in place of the built in layouts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_format: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_format: Option<String>,
+
+    // Frames per second to render at whilst things are changing, and once they have been idle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fps: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_fps: Option<u64>,
+
+    // Rows of context kept above and below the current line when scrolling.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrolloff: Option<usize>,
+
+    // Start with the content scrollbar placing lines by their time rather than their number.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub time_scrollbar: bool,
+
+    // Whilst tailing a file growing faster than this many lines a second, show only every
+    // `sample_every`th line in the content pane. Filters still see every line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_lines_per_sec: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_every: Option<usize>,
+
+    // New filters are smart case: insensitive unless the pattern has an uppercase letter.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub smart_case: bool,
+
+    // Lines longer than this many bytes are cut short, so a gigantic line doesn't hold everything
+    // up. 0 for no limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_line_bytes: Option<usize>,
+
+    // When the file is a symlink, read the file it points to when it is re-pointed, as on rotation.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub follow_links: bool,
+
+    // Follow the file by name, as `tail -F` does: when it is moved away or removed and another
+    // created in its place, as on rotation, read the new one.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub follow_name: bool,
+
+    // Start tailing these panes, as with --tail: `both`, `content` or `filter`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail: Option<TailPanes>,
+
+    // Draw for screen readers and braille displays, as with --accessible.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub accessible: bool,
+
+    // Line delimiters other than newline for files with names matching a glob, e.g. `"*.nul": nul`
+    // or `"*.dat": "\x1e"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub line_delimiters: BTreeMap<String, String>,
+
+    // Lines matching any of these regexes are left out as the file is read, e.g. health checks,
+    // so they take no memory or filter time. `:ignore` or `--no-ignore` shows them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore_patterns: Vec<String>,
+
+    // Buffer piped input and streams (`--listen`, WebSocket and Kafka), dropping lines as the policy
+    // says once otail falls too far behind, rather than spooling everything.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_buffer: Option<StreamBufferConfig>,
+
+    // How the file is reopened when reading it fails: `delay_ms` before the first attempt,
+    // doubling up to `max_delay_ms`, giving up after `attempts` failures in a row (0 never).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reader_retry: Option<RetryPolicy>,
+
+    // Use ripgrep, if installed, for the first pass of a filter over the file.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ripgrep: bool,
+
+    // Filters over at least this many lines of a file have their matches kept in the cache
+    // directory, so filtering it again after a restart only looks at new lines. 0 keeps none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_cache_lines: Option<usize>,
+
+    // Filters and searches kept in the history, which is saved in the state directory. 0 keeps
+    // none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_size: Option<usize>,
+
+    // Named regex fragments, used in regex filters as `{{name}}`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub regex_macros: BTreeMap<String, String>,
+
+    // Rewrites of lines for display only, as sed expressions such as `s/^\S+ //`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub substitutions: Vec<String>,
+
+    // A base16 scheme or terminal.sexy palette whose colours are used for otail's, e.g.
+    // `~/.config/base16/gruvbox-dark.yaml`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+
+    // The key pressed before a chord, `,` unless given. Space can be used, leaving PgDn to page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leader: Option<char>,
+
+    // Chords, as the keys pressed after the leader and the `:` command they run, e.g.
+    // `"f o": "e /var/log/syslog"`. They are added to the default chords, and an empty command
+    // removes one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub chords: BTreeMap<String, String>,
+
+    // How lines are copied to the clipboard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<ClipboardMode>,
+
+    // Named bundles of settings for an environment, chosen with `--profile` or `P`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings used together, e.g. for a `prod-errors` profile. Anything not given is left as it is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_spec: Option<FilterSpec>,
+    // Replaces the colouring rules whilst the profile is in use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colouring: Option<ColouringSpec>,
+    // Tail both panes, or stop tailing them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail: Option<bool>,
+}
+
+impl Profile {
+    /// The profile with its filters built, as needed after loading it from the config.
+    pub fn compiled(&self) -> Result<Self> {
+        Ok(Profile {
+            filter_spec: self
+                .filter_spec
+                .as_ref()
+                .map(|f| f.compiled())
+                .transpose()?,
+            colouring: self.colouring.as_ref().map(|c| c.compiled()).transpose()?,
+            tail: self.tail,
+        })
+    }
+}
+
+/// The panes tailed from the start, as `tail -f` does.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum TailPanes {
+    Both,
+    Content,
+    Filter,
+}
+
+impl TailPanes {
+    pub fn content(&self) -> bool {
+        *self != TailPanes::Filter
+    }
+
+    pub fn filter(&self) -> bool {
+        *self != TailPanes::Content
+    }
+}
+
+/// How many lines a stream can have waiting, and what happens to more.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamBufferConfig {
+    pub policy: DropPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<usize>,
+}
+
+/// Style of the current line. It is applied over any colouring rule, with the rule's foreground
+/// colour kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CurrentLineStyle {
+    #[serde(default = "default_bold")]
+    pub bold: bool,
+    #[serde(default)]
+    pub reverse: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bg_colour: Option<Colour>,
+    // Shown in the margin next to the line number.
+    #[serde(default = "default_arrow")]
+    pub arrow: char,
+}
+
+/// How the content and filtered panes share the screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaneLayout {
+    // The content pane above the filtered pane.
+    #[default]
+    Stacked,
+    // The content pane beside the filtered pane, for wide screens.
+    SideBySide,
+}
+
+impl PaneLayout {
+    pub fn toggle(self) -> Self {
+        match self {
+            PaneLayout::Stacked => PaneLayout::SideBySide,
+            PaneLayout::SideBySide => PaneLayout::Stacked,
+        }
+    }
+}
+
+fn default_bold() -> bool {
+    true
+}
+
+fn default_arrow() -> char {
+    '>'
+}
+
+impl Default for CurrentLineStyle {
+    fn default() -> Self {
+        CurrentLineStyle {
+            bold: default_bold(),
+            reverse: false,
+            bg_colour: None,
+            arrow: default_arrow(),
+        }
+    }
+}
+
+impl Default for OtailConfig {
+    fn default() -> Self {
+        OtailConfig {
+            readonly: false,
+            colouring: ColouringSpec::default(),
+            timestamp_formats: Vec::new(),
+            time_gap_secs: None,
+            rare_message_threshold: None,
+            current_line: None,
+            layout: None,
+            content_percent: None,
+            title_format: None,
+            status_format: None,
+            fps: None,
+            idle_fps: None,
+            scrolloff: None,
+            time_scrollbar: false,
+            sample_lines_per_sec: None,
+            sample_every: None,
+            smart_case: false,
+            max_line_bytes: None,
+            follow_links: false,
+            follow_name: false,
+            tail: None,
+            accessible: false,
+            line_delimiters: BTreeMap::new(),
+            ignore_patterns: Vec::new(),
+            stream_buffer: None,
+            reader_retry: None,
+            ripgrep: false,
+            filter_cache_lines: None,
+            history_size: None,
+            regex_macros: BTreeMap::new(),
+            substitutions: Vec::new(),
+            theme: None,
+            leader: None,
+            chords: BTreeMap::new(),
+            clipboard: None,
+            profiles: BTreeMap::new(),
+        }
+    }
 }
 
 pub struct LocatedConfig {
@@ -51,7 +353,7 @@ pub fn load_config() -> LocatedConfig {
         path: None,
         config: OtailConfig {
             readonly: true,
-            colouring: ColouringSpec::default(),
+            ..Default::default()
         },
     })
 }
@@ -84,7 +386,7 @@ pub fn load_config_from(config_path: Option<String>) -> Result<LocatedConfig> {
             info!("Empty config found, initialising: {}", path);
             OtailConfig {
                 readonly: false,
-                colouring: ColouringSpec::default(),
+                ..Default::default()
             }
         } else {
             info!("Loading config from: {}", path);
@@ -94,7 +396,7 @@ pub fn load_config_from(config_path: Option<String>) -> Result<LocatedConfig> {
                     warn!("Failed to parse config from {}: {}", path, e);
                     OtailConfig {
                         readonly: true,
-                        colouring: ColouringSpec::default(),
+                        ..Default::default()
                     }
                 }
             }
@@ -102,7 +404,7 @@ pub fn load_config_from(config_path: Option<String>) -> Result<LocatedConfig> {
     } else {
         OtailConfig {
             readonly: true,
-            colouring: ColouringSpec::default(),
+            ..Default::default()
         }
     };
 
@@ -135,3 +437,53 @@ pub fn maybe_save_config(located_config: &LocatedConfig) {
         trace!("No file to save config.");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let config: OtailConfig = serde_yaml::from_str(
+            r#"
+colouring:
+  rules: []
+profiles:
+  prod-errors:
+    filter_spec:
+      filter_type: Regex
+      filter_pattern: "ERROR|FATAL"
+    colouring:
+      rules:
+        - enabled: true
+          filter_spec:
+            filter_type: Level
+            filter_pattern: ">= ERROR"
+          fg_colour: Red
+          bg_colour: null
+    tail: true
+"#,
+        )
+        .unwrap();
+
+        // Only the type and pattern are loaded, the filters need building.
+        let profile = &config.profiles["prod-errors"];
+        assert!(!profile
+            .filter_spec
+            .as_ref()
+            .unwrap()
+            .matches("ERROR failed"));
+
+        let profile = profile.compiled().unwrap();
+        assert!(profile
+            .filter_spec
+            .as_ref()
+            .unwrap()
+            .matches("ERROR failed"));
+        assert_eq!(
+            profile.colouring.unwrap().maybe_colour("FATAL gone"),
+            Some((Some(Colour::Red), None))
+        );
+        assert_eq!(profile.tail, Some(true));
+    }
+}
@@ -2,12 +2,20 @@ use anyhow::{anyhow, Result};
 use log::{debug, trace, warn};
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
 use tokio::select;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 
-use crate::common::{replace_for_view, LineContent, CHANNEL_BUFFER, FILTER_SPOOLING_BATCH_SIZE};
-use crate::filter_spec::FilterSpec;
+use crate::common::{
+    display_width, replace_for_view, LineContent, CHANNEL_BUFFER, FILTER_SPOOLING_BATCH_SIZE,
+    RIPGREP_BATCH_SIZE,
+};
+use crate::filter_cache::FilterCache;
+use crate::filter_spec::{Context, FilterSpec, FilterType};
 use crate::ifile::{
     FileReq, FileReqReceiver, FileReqSender, FileResp, FileRespReceiver, FileRespSender, IFResp,
 };
@@ -20,6 +28,9 @@ pub type FFReqReceiver = mpsc::Receiver<FFReq>;
 pub type FilterReqRespSender = oneshot::Sender<FFReqResp>;
 pub type FilterReqRespReceiver = oneshot::Receiver<FFReqResp>;
 
+pub type MatchesRespSender = oneshot::Sender<Option<Vec<usize>>>;
+pub type MatchesRespReceiver = oneshot::Receiver<Option<Vec<usize>>>;
+
 #[derive(Debug)]
 pub enum FFResp {
     ViewUpdate { update: FileResp<FilterLine> },
@@ -34,7 +45,38 @@ pub enum FFReqResp {
 
 #[derive(Debug)]
 pub enum FFReq {
-    SetFilter { filter_spec: Option<FilterSpec> },
+    // Filter the lines from a file line on, 0 for the whole file, up to an optional end line.
+    SetFilter {
+        filter_spec: Option<FilterSpec>,
+        from: LineNo,
+        to: Option<LineNo>,
+    },
+    // Snapshot of the file line numbers matched so far, None if there is no filter.
+    GetMatches {
+        resp: MatchesRespSender,
+    },
+}
+
+// Where a message for the filter comes from, when stepping it in a simulation.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FFSource {
+    Views,
+    Filter,
+    IFile,
+}
+
+// Progress of a ripgrep pass, tagged with the filter generation it was started for.
+#[derive(Debug)]
+enum RipgrepUpdate {
+    Matches {
+        generation: usize,
+        line_nos: Vec<LineNo>,
+    },
+    Done {
+        generation: usize,
+        result: Result<(), String>,
+    },
 }
 
 #[derive(Debug)]
@@ -54,6 +96,16 @@ struct FilterState {
     line_to_match: HashMap<usize, usize>,
     next_line_expected: LineNo,
     next_line_to_request: LineNo,
+    // Lines still to show after the last match.
+    after_remaining: usize,
+
+    // Whilst ripgrep is finding the matches in the lines before this, spooling waits.
+    ripgrep_to: Option<LineNo>,
+
+    // The first line filtered, skipping those before.
+    from: LineNo,
+    // The line filtering stops before, if any.
+    to: Option<LineNo>,
 }
 
 impl FilterState {
@@ -65,8 +117,37 @@ impl FilterState {
             num_matches: 0,
             next_line_expected: 0,
             next_line_to_request: 0,
+            after_remaining: 0,
+            ripgrep_to: None,
+            from: 0,
+            to: None,
         })
     }
+
+    fn starting_at(mut self, from: LineNo) -> Self {
+        self.from = from;
+        self.next_line_expected = from;
+        self.next_line_to_request = from;
+
+        self
+    }
+
+    fn ending_before(mut self, to: Option<LineNo>) -> Self {
+        self.to = to;
+
+        self
+    }
+
+    // Whether the line is past the end of the range being filtered.
+    fn past_end(&self, line_no: LineNo) -> bool {
+        self.to.is_some_and(|to| line_no >= to)
+    }
+
+    // Whether the matches can be cached: for the whole file, without context lines that could
+    // carry on past the lines cached.
+    fn cacheable(&self) -> bool {
+        self.from == 0 && self.to.is_none() && self.filter_spec.context() == Context::default()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -76,13 +157,21 @@ pub struct FilterLine {
 }
 
 impl LineContent for FilterLine {
-    fn len(&self) -> usize {
-        self.line.len()
+    fn width(&self) -> usize {
+        display_width(&self.line)
     }
 
     fn render(&self) -> String {
         replace_for_view(&self.line)
     }
+
+    fn content(&self) -> &str {
+        &self.line
+    }
+
+    fn file_line_no(&self) -> Option<usize> {
+        Some(self.line_no)
+    }
 }
 
 pub struct FFile {
@@ -105,6 +194,30 @@ pub struct FFile {
     clients: HashMap<String, Client>,
 
     filter_state: Option<FilterState>,
+
+    // Lines known to the IFile, and the bytes they came from.
+    file_lines: usize,
+    file_bytes: u64,
+
+    // Matches saved from earlier runs, to start filtering from where they got to.
+    cache: Option<FilterCache>,
+
+    // Use ripgrep for the initial pass over the file. The generation discards updates from passes
+    // for earlier filters.
+    ripgrep: bool,
+    ripgrep_generation: usize,
+    ripgrep_task: Option<JoinHandle<()>>,
+    ripgrep_sender: mpsc::Sender<RipgrepUpdate>,
+    ripgrep_receiver: mpsc::Receiver<RipgrepUpdate>,
+}
+
+/// Is ripgrep installed?
+pub fn ripgrep_available() -> bool {
+    std::process::Command::new("rg")
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
 }
 
 impl FFile {
@@ -115,6 +228,7 @@ impl FFile {
         let (view_req_sender, view_req_receiver) = mpsc::channel(CHANNEL_BUFFER);
         let (ff_req_sender, ff_req_receiver) = mpsc::channel(CHANNEL_BUFFER);
         let (if_resp_sender, if_resp_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        let (ripgrep_sender, ripgrep_receiver) = mpsc::channel(CHANNEL_BUFFER);
         FFile {
             id,
             path: pb,
@@ -133,9 +247,34 @@ impl FFile {
             clients: HashMap::new(),
 
             filter_state: None,
+
+            file_lines: 0,
+            file_bytes: 0,
+
+            cache: None,
+
+            ripgrep: false,
+            ripgrep_generation: 0,
+            ripgrep_task: None,
+            ripgrep_sender,
+            ripgrep_receiver,
         }
     }
 
+    /// Find the matches in the existing file with ripgrep, before filtering new lines as usual.
+    pub fn with_ripgrep(mut self) -> Self {
+        self.ripgrep = true;
+
+        self
+    }
+
+    /// Start filters from their cached matches, and cache them when they change.
+    pub fn with_cache(mut self, cache: FilterCache) -> Self {
+        self.cache = Some(cache);
+
+        self
+    }
+
     pub fn get_view_sender(&self) -> FileReqSender<FFResp> {
         self.view_req_sender.clone()
     }
@@ -147,14 +286,7 @@ impl FFile {
     pub async fn run(&mut self) -> Result<()> {
         debug!("FFile starting: {:?}", self.path);
 
-        trace!("Sending RegisterClient to IFile: id={}", self.id);
-        self.if_req_sender
-            .send(crate::ifile::FileReq::RegisterClient {
-                id: self.id.clone(),
-                client_sender: self.if_resp_sender.clone(),
-            })
-            .await?;
-        trace!("RegisterClient sent successfully to IFile: id={}", self.id);
+        self.register().await?;
 
         loop {
             trace!("Select...");
@@ -183,6 +315,9 @@ impl FFile {
                         }
                     }
                 }
+                Some(update) = self.ripgrep_receiver.recv() => {
+                    self.handle_ripgrep_update(update).await?;
+                }
                 update = self.if_resp_receiver.recv() => {
                     match update {
                         Some(update) => {
@@ -203,10 +338,54 @@ impl FFile {
         Ok(())
     }
 
+    pub(crate) async fn register(&self) -> Result<()> {
+        trace!("Sending RegisterClient to IFile: id={}", self.id);
+        self.if_req_sender
+            .send(crate::ifile::FileReq::RegisterClient {
+                id: self.id.clone(),
+                client_sender: self.if_resp_sender.clone(),
+            })
+            .await?;
+        trace!("RegisterClient sent successfully to IFile: id={}", self.id);
+
+        Ok(())
+    }
+
+    /// Handle a message waiting from the source, for stepping the filter deterministically in a
+    /// simulation rather than with `run`. Returns whether there was one.
+    #[cfg(test)]
+    pub(crate) async fn step(&mut self, source: FFSource) -> Result<bool> {
+        match source {
+            FFSource::Views => match self.view_req_receiver.try_recv() {
+                Ok(cmd) => self.handle_client_command(cmd).await?,
+                Err(_) => return Ok(false),
+            },
+            FFSource::Filter => match self.ff_req_receiver.try_recv() {
+                Ok(cmd) => self.handle_ff_command(cmd).await?,
+                Err(_) => return Ok(false),
+            },
+            FFSource::IFile => match self.if_resp_receiver.try_recv() {
+                Ok(update) => self.handle_ifile_update(update).await?,
+                Err(_) => return Ok(false),
+            },
+        }
+
+        Ok(true)
+    }
+
     async fn handle_ff_command(&mut self, cmd: FFReq) -> Result<()> {
         match cmd {
-            FFReq::SetFilter { filter_spec } => {
-                trace!("Setting filter: {:?}", filter_spec);
+            FFReq::SetFilter {
+                filter_spec,
+                from,
+                to,
+            } => {
+                trace!(
+                    "Setting filter: {:?} from {} to {:?}",
+                    filter_spec,
+                    from,
+                    to
+                );
 
                 let Some(filter_spec) = filter_spec else {
                     trace!("Removing filter");
@@ -214,21 +393,50 @@ impl FFile {
                 };
 
                 if let Some(filter_state) = &self.filter_state {
-                    if filter_state.filter_spec == filter_spec {
+                    if filter_state.filter_spec == filter_spec
+                        && filter_state.from == from
+                        && filter_state.to == to
+                    {
                         trace!("Filter unchanged, no change.");
                         return Ok(());
                     }
                 }
 
-                self.set_filter_state(Some(FilterState::make(filter_spec)?))
-                    .await
+                self.set_filter_state(Some(
+                    FilterState::make(filter_spec)?
+                        .starting_at(from)
+                        .ending_before(to),
+                ))
+                .await
+            }
+            FFReq::GetMatches { resp } => {
+                let matches = self
+                    .filter_state
+                    .as_ref()
+                    .map(|filter_state| filter_state.matches.clone());
+                trace!(
+                    "Sending matches snapshot: {:?}",
+                    matches.as_ref().map(|m| m.len())
+                );
+
+                if resp.send(matches).is_err() {
+                    trace!("Matches requester went away");
+                }
+                Ok(())
             }
         }
     }
 
     async fn set_filter_state(&mut self, filter_state: Option<FilterState>) -> Result<()> {
+        self.save_matches();
         self.filter_state = filter_state;
 
+        self.ripgrep_generation += 1;
+        if let Some(task) = self.ripgrep_task.take() {
+            trace!("Stopping ripgrep for the previous filter");
+            task.abort();
+        }
+
         for (client_id, client) in self.clients.iter() {
             trace!("Sending Clear command to client: id={}", client_id);
             client.channel.send(FFResp::Clear).await?;
@@ -238,12 +446,229 @@ impl FFile {
             );
         }
 
-        if self.filter_state.is_some() {
+        if self.restore_matches().await? {
             self.start_spooling().await?;
+        } else if self.filter_state.is_some() {
+            // Ripgrep can only search for text, without context lines.
+            let searchable = self.filter_state.as_ref().is_some_and(|f| {
+                f.filter_spec.filter_type.is_text_search()
+                    && f.filter_spec.context() == Context::default()
+                    && f.from == 0
+                    && f.to.is_none()
+            });
+            if self.ripgrep && searchable && self.file_lines > 1 {
+                self.start_ripgrep();
+            } else {
+                self.start_spooling().await?;
+            }
         }
         return Ok(());
     }
 
+    // Take the matches cached when the file was last filtered the same way, returning whether there
+    // were any. Only the lines after them need filtering.
+    async fn restore_matches(&mut self) -> Result<bool> {
+        let (Some(cache), Some(filter_state)) = (&self.cache, &mut self.filter_state) else {
+            return Ok(false);
+        };
+        if !filter_state.cacheable() {
+            return Ok(false);
+        }
+        let Some(cached) = cache.load(&self.path, &filter_state.filter_spec) else {
+            return Ok(false);
+        };
+
+        debug!(
+            "Starting filter from {} cached matches in {} lines",
+            cached.matches.len(),
+            cached.lines
+        );
+        filter_state.next_line_expected = cached.lines;
+        filter_state.next_line_to_request = cached.lines;
+        self.add_matches(cached.matches).await?;
+
+        Ok(true)
+    }
+
+    // Cache the matches in the lines filtered so far. The last line is left out, as it may not be
+    // finished.
+    fn save_matches(&self) {
+        let (Some(cache), Some(filter_state)) = (&self.cache, &self.filter_state) else {
+            return;
+        };
+        if !filter_state.cacheable() || filter_state.ripgrep_to.is_some() {
+            return;
+        }
+
+        let lines = filter_state
+            .next_line_expected
+            .min(self.file_lines.saturating_sub(1));
+        let matches = filter_state
+            .matches
+            .iter()
+            .copied()
+            .take_while(|line_no| *line_no < lines)
+            .collect::<Vec<_>>();
+        cache.save(
+            &self.path,
+            &filter_state.filter_spec,
+            lines,
+            self.file_bytes,
+            &matches,
+        );
+    }
+
+    // Find matches in the lines already read with ripgrep. The last line may still be partial, so
+    // is left for spooling.
+    fn start_ripgrep(&mut self) {
+        let Some(filter_state) = &mut self.filter_state else {
+            return;
+        };
+
+        let end = self.file_lines - 1;
+        filter_state.ripgrep_to = Some(end);
+        // Lines before the end are only fetched for matches.
+        filter_state.next_line_expected = end;
+        filter_state.next_line_to_request = end;
+
+        let mut command = Command::new("rg");
+        command
+            .args(["--no-config", "--line-number", "--no-filename", "--text"])
+            .args(["--color", "never"]);
+        match filter_state.filter_spec.filter_type {
+            FilterType::SimpleCaseSensitive => command.arg("--fixed-strings"),
+            FilterType::SimpleCaseInsensitive => command.args(["--fixed-strings", "--ignore-case"]),
+            FilterType::SmartCase => command.args(["--fixed-strings", "--smart-case"]),
+            _ => &mut command,
+        };
+        command
+            .arg("--regexp")
+            .arg(filter_state.filter_spec.expanded_pattern())
+            .arg(&self.path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        debug!(
+            "Filtering lines 0..{} with ripgrep: {:?}",
+            end, filter_state.filter_spec
+        );
+        let generation = self.ripgrep_generation;
+        let sender = self.ripgrep_sender.clone();
+        self.ripgrep_task = Some(tokio::spawn(async move {
+            let result = run_ripgrep(command, end, generation, &sender)
+                .await
+                .map_err(|e| e.to_string());
+            if sender
+                .send(RipgrepUpdate::Done { generation, result })
+                .await
+                .is_err()
+            {
+                trace!("Filter went away before ripgrep finished");
+            }
+        }));
+    }
+
+    async fn handle_ripgrep_update(&mut self, update: RipgrepUpdate) -> Result<()> {
+        match update {
+            RipgrepUpdate::Matches {
+                generation,
+                line_nos,
+            } => {
+                if generation != self.ripgrep_generation {
+                    trace!("Ignoring ripgrep matches for an old filter");
+                    return Ok(());
+                }
+                self.add_matches(line_nos).await
+            }
+            RipgrepUpdate::Done { generation, result } => {
+                if generation != self.ripgrep_generation {
+                    trace!("Ignoring ripgrep completion for an old filter");
+                    return Ok(());
+                }
+                self.ripgrep_task = None;
+
+                match result {
+                    Ok(()) => {
+                        debug!("Ripgrep finished, spooling new lines");
+                        if let Some(filter_state) = &mut self.filter_state {
+                            filter_state.ripgrep_to = None;
+                        }
+                        self.start_spooling().await
+                    }
+                    Err(e) => {
+                        warn!("Ripgrep failed, filtering without it: {}", e);
+                        self.ripgrep = false;
+                        let Some(filter_state) = &self.filter_state else {
+                            return Ok(());
+                        };
+                        let filter_spec = filter_state.filter_spec.clone();
+                        let (from, to) = (filter_state.from, filter_state.to);
+                        self.set_filter_state(Some(
+                            FilterState::make(filter_spec)?
+                                .starting_at(from)
+                                .ending_before(to),
+                        ))
+                        .await
+                    }
+                }
+            }
+        }
+    }
+
+    // Record matches found without their content, by ripgrep or as context before a match. Content
+    // is fetched from the IFile when a client wants it.
+    async fn add_matches(&mut self, line_nos: Vec<LineNo>) -> Result<()> {
+        let Some(filter_state) = &mut self.filter_state else {
+            return Ok(());
+        };
+        let Some(last) = line_nos.last().copied() else {
+            return Ok(());
+        };
+
+        for line_no in line_nos {
+            let match_no = filter_state.num_matches;
+            filter_state.matches.push(line_no);
+            filter_state.num_matches += 1;
+
+            let mut wanted = false;
+            for client in self.clients.values_mut() {
+                wanted |= client.interested.remove(&match_no) || client.tailing;
+            }
+            if wanted {
+                trace!("Requesting match line: {} / {}", line_no, match_no);
+                self.if_req_sender
+                    .send(FileReq::GetLine {
+                        id: self.id.clone(),
+                        line_no,
+                    })
+                    .await?;
+                filter_state.line_to_match.insert(line_no, match_no);
+            }
+        }
+
+        for (id, client) in self.clients.iter() {
+            trace!(
+                "Sending added match stats to client: id={}, total_matches={}",
+                id,
+                filter_state.num_matches
+            );
+            client
+                .channel
+                .send(FFResp::ViewUpdate {
+                    update: FileResp::Stats {
+                        view_lines: filter_state.num_matches,
+                        file_lines: last + 1,
+                        file_bytes: 0, // TODO: Not pretty... don't want this field.
+                    },
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+
     async fn handle_client_command(&mut self, cmd: FileReq<FFResp>) -> Result<()> {
         match cmd {
             FileReq::GetLine {
@@ -286,6 +711,51 @@ impl FFile {
                     }
                 }
             }
+            FileReq::GetLines { line_nos, .. } => {
+                // Bulk reads go straight to the IFile, dropping the responder tells the caller.
+                warn!(
+                    "Bulk line request not supported by filtered file, ignoring {} lines",
+                    line_nos.len()
+                );
+                Ok(())
+            }
+            FileReq::GetFullLine { line_no, .. } => {
+                // Full lines go straight to the IFile too.
+                warn!(
+                    "Full line request not supported by filtered file, ignoring: {}",
+                    line_no
+                );
+                Ok(())
+            }
+            FileReq::FindTime { time, .. } => {
+                // Time searches go straight to the IFile too.
+                warn!(
+                    "Time search not supported by filtered file, ignoring: {}",
+                    time
+                );
+                Ok(())
+            }
+            FileReq::GetLinesAt { offsets, .. } => {
+                warn!(
+                    "Lines at offsets not supported by filtered file, ignoring: {:?}",
+                    offsets
+                );
+                Ok(())
+            }
+            FileReq::LineOffset { line_no, .. } => {
+                warn!(
+                    "Line offset not supported by filtered file, ignoring: {}",
+                    line_no
+                );
+                Ok(())
+            }
+            FileReq::TimeFraction { line_no, .. } => {
+                warn!(
+                    "Time fraction not supported by filtered file, ignoring: {}",
+                    line_no
+                );
+                Ok(())
+            }
             FileReq::CancelLine { id, line_no } => {
                 trace!("Cancel match: {} / {:?}", id, line_no);
                 let Some(client) = self.clients.get_mut(&id) else {
@@ -412,17 +882,21 @@ impl FFile {
         };
 
         for i in 0..FILTER_SPOOLING_BATCH_SIZE {
-            trace!("Sending batch GetLine request to IFile during spooling: id={}, line_no={}, batch_position={}/{}", self.id, i, i + 1, FILTER_SPOOLING_BATCH_SIZE);
+            let line_no = filter_state.next_line_to_request;
+            if filter_state.past_end(line_no) {
+                break;
+            }
+            trace!("Sending batch GetLine request to IFile during spooling: id={}, line_no={}, batch_position={}/{}", self.id, line_no, i + 1, FILTER_SPOOLING_BATCH_SIZE);
             self.if_req_sender
                 .send(FileReq::GetLine {
                     id: self.id.clone(),
-                    line_no: i,
+                    line_no,
                 })
                 .await?;
             trace!(
                 "Batch GetLine request sent successfully: id={}, line_no={}",
                 self.id,
-                i
+                line_no
             );
 
             filter_state.next_line_to_request += 1;
@@ -443,17 +917,44 @@ impl FFile {
             return Ok(());
         };
 
+        // Lines requested for an earlier pass, before the file was truncated or the filter changed,
+        // can still arrive, and are not the next to filter.
         if line_no != filter_state.next_line_expected {
-            warn!(
-                "Next spooled line {} is not expected {}",
+            debug!(
+                "Ignoring spooled line {}, expecting {}",
                 line_no, filter_state.next_line_expected
             );
+            return Ok(());
         }
 
         filter_state.next_line_expected += 1;
         let file_lines = line_no + 1;
+        if filter_state.past_end(line_no) {
+            trace!("Line {} is past the end of the filter range", line_no);
+            return Ok(());
+        }
+
+        let context = filter_state.filter_spec.context();
+        let is_match = filter_state.filter_spec.matches(&line_content);
+        let in_context = !is_match && filter_state.after_remaining > 0;
+        if is_match {
+            filter_state.after_remaining = context.after;
+
+            // Lines before the match, that aren't already shown.
+            let first = line_no
+                .saturating_sub(context.before)
+                .max(filter_state.matches.last().map_or(0, |last| last + 1));
+            if first < line_no {
+                self.add_matches((first..line_no).collect()).await?;
+            }
+        } else if in_context {
+            filter_state.after_remaining -= 1;
+        }
+        let Some(filter_state) = &mut self.filter_state else {
+            return Ok(());
+        };
 
-        if filter_state.filter_spec.matches(&line_content) {
+        if is_match || in_context {
             trace!("Line matches...");
             // TODO: Can we be sure that the updates come in order?
             filter_state.matches.push(line_no);
@@ -540,6 +1041,11 @@ impl FFile {
             }
         }
 
+        if filter_state.past_end(filter_state.next_line_to_request) {
+            trace!("Reached the end of the filter range: {}", self.id);
+            return Ok(());
+        }
+
         trace!(
             "Sending continued spooling GetLine request to IFile: id={}, line_no={}",
             self.id,
@@ -578,7 +1084,7 @@ impl FFile {
                     return Ok(());
                 };
 
-                if line_no < filter_state.next_line_expected {
+                if line_no < filter_state.next_line_expected || filter_state.ripgrep_to.is_some() {
                     let Some(match_no) = filter_state.line_to_match.remove(&line_no) else {
                         trace!(
                             "Line delivered without a coresponding waiting match: {}",
@@ -612,7 +1118,8 @@ impl FFile {
                     self.next_spooling(line_no, line_content, partial).await?;
                 }
             }
-            IFResp::Truncated => {
+            // Lines added before those filtered renumber them, so the file is filtered again.
+            IFResp::Truncated | IFResp::Backfilled { .. } => {
                 let new_filter = match &mut self.filter_state {
                     None => {
                         trace!("Ignoring truncation, no current filter.");
@@ -624,6 +1131,17 @@ impl FFile {
                 self.set_filter_state(Some(FilterState::make(new_filter)?))
                     .await?;
             }
+            IFResp::ViewUpdate {
+                update:
+                    FileResp::Stats {
+                        file_lines,
+                        file_bytes,
+                        ..
+                    },
+            } => {
+                self.file_lines = file_lines;
+                self.file_bytes = file_bytes;
+            }
             _ => {
                 trace!("Ignoring unimportant message: {:?}", update);
             }
@@ -632,3 +1150,71 @@ impl FFile {
         Ok(())
     }
 }
+
+// Keep the matches when the file is closed.
+impl Drop for FFile {
+    fn drop(&mut self) {
+        self.save_matches();
+    }
+}
+
+// Run ripgrep, sending batches of the (0 based) matching line numbers before the end line.
+async fn run_ripgrep(
+    mut command: Command,
+    end: LineNo,
+    generation: usize,
+    sender: &mpsc::Sender<RipgrepUpdate>,
+) -> Result<()> {
+    let mut child = command.spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("No output from ripgrep"))?;
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("No errors from ripgrep"))?;
+
+    let mut lines = BufReader::new(stdout).lines();
+    let mut line_nos = Vec::with_capacity(RIPGREP_BATCH_SIZE);
+    while let Some(line) = lines.next_line().await? {
+        let number = line.split(':').next().unwrap_or_default();
+        let line_no = number
+            .parse::<usize>()
+            .map_err(|_| anyhow!("Unexpected ripgrep output: {}", line))?
+            - 1;
+        if line_no >= end {
+            break;
+        }
+
+        line_nos.push(line_no);
+        if line_nos.len() >= RIPGREP_BATCH_SIZE {
+            sender
+                .send(RipgrepUpdate::Matches {
+                    generation,
+                    line_nos: std::mem::take(&mut line_nos),
+                })
+                .await?;
+        }
+    }
+
+    if !line_nos.is_empty() {
+        sender
+            .send(RipgrepUpdate::Matches {
+                generation,
+                line_nos,
+            })
+            .await?;
+    }
+
+    // Exit code 1 is no matches. Stopping early may leave ripgrep to be killed, which is fine.
+    drop(lines);
+    let status = child.wait().await?;
+    if status.code().is_some_and(|code| code > 1) {
+        let mut errors = String::new();
+        stderr.read_to_string(&mut errors).await?;
+        return Err(anyhow!("ripgrep {}: {}", status, errors.trim()));
+    }
+
+    Ok(())
+}
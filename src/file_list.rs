@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, bail, Result};
+use log::{trace, warn};
+use regex::Regex;
+
+// Files that grew this recently are shown as growing.
+const GROWING_SECS: u64 = 5;
+
+#[derive(Debug, Clone)]
+enum FileSource {
+    // Files named on the command line, e.g. expanded from a glob by the shell.
+    Paths(Vec<String>),
+    // The files in a directory.
+    Directory(PathBuf),
+    // The files in a directory with names matching a glob, e.g. `logs/*.log`.
+    Glob { dir: PathBuf, pattern: Regex },
+}
+
+#[derive(Debug, Clone)]
+pub struct ListedFile {
+    pub path: String,
+    pub name: String,
+    pub bytes: u64,
+    pub modified: Option<SystemTime>,
+    grew_at: Option<Instant>,
+}
+
+impl ListedFile {
+    pub fn growing(&self) -> bool {
+        self.grew_at
+            .is_some_and(|grew_at| grew_at.elapsed() < Duration::from_secs(GROWING_SECS))
+    }
+}
+
+/// The files to switch between, from the paths, directory or glob given on the command line.
+#[derive(Debug, Clone)]
+pub struct FileList {
+    source: FileSource,
+    files: Vec<ListedFile>,
+}
+
+impl FileList {
+    /// The list for the command line paths, and the file to open first: the first path given, or
+    /// the most recently modified file in a directory or matching a glob.
+    pub fn from_args(paths: &[String]) -> Result<(Self, String)> {
+        let source = match paths {
+            [] => bail!("No files given"),
+            [path] if Path::new(path).is_dir() => FileSource::Directory(PathBuf::from(path)),
+            [path] if is_glob(path) => {
+                let glob = Path::new(path);
+                let dir = match glob.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                    _ => PathBuf::from("."),
+                };
+                if is_glob(&dir.to_string_lossy()) {
+                    bail!("Only the file name can have wildcards: {}", path);
+                }
+                let name = glob.file_name().unwrap_or_default().to_string_lossy();
+
+                FileSource::Glob {
+                    dir,
+                    pattern: glob_regex(&name)?,
+                }
+            }
+            paths => FileSource::Paths(paths.to_vec()),
+        };
+
+        let mut file_list = FileList {
+            source,
+            files: Vec::new(),
+        };
+        file_list.refresh();
+
+        let first = match &file_list.source {
+            FileSource::Paths(paths) => paths[0].clone(),
+            _ => file_list
+                .newest()
+                .map(|f| f.path.clone())
+                .ok_or_else(|| anyhow!("No files found: {}", paths.join(" ")))?,
+        };
+
+        Ok((file_list, first))
+    }
+
+    pub fn files(&self) -> &[ListedFile] {
+        &self.files
+    }
+
+    /// The most recently modified file, as of the last refresh.
+    pub fn newest(&self) -> Option<&ListedFile> {
+        self.files.iter().max_by_key(|f| f.modified)
+    }
+
+    /// List the files again, noting which have grown since last time.
+    pub fn refresh(&mut self) {
+        let paths = match &self.source {
+            FileSource::Paths(paths) => paths.clone(),
+            FileSource::Directory(dir) => list_dir(dir, |_| true),
+            FileSource::Glob { dir, pattern } => list_dir(dir, |name| pattern.is_match(name)),
+        };
+        trace!("Listed {} files", paths.len());
+
+        let show_full_path = matches!(self.source, FileSource::Paths(_));
+        let previous = self
+            .files
+            .drain(..)
+            .map(|f| (f.path.clone(), f))
+            .collect::<HashMap<_, _>>();
+        let now = Instant::now();
+
+        self.files = paths
+            .into_iter()
+            .map(|path| {
+                let metadata = fs::metadata(&path).ok();
+                let bytes = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+                let grew_at = match previous.get(&path) {
+                    Some(previous) if bytes > previous.bytes => Some(now),
+                    Some(previous) => previous.grew_at,
+                    None => None,
+                };
+                let name = if show_full_path {
+                    path.clone()
+                } else {
+                    Path::new(&path)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| path.clone())
+                };
+
+                ListedFile {
+                    path,
+                    name,
+                    bytes,
+                    modified: metadata.and_then(|m| m.modified().ok()),
+                    grew_at,
+                }
+            })
+            .collect();
+    }
+}
+
+/// Expand a leading `~` to the home directory.
+pub fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home, rest)
+        }
+        _ => path.to_owned(),
+    }
+}
+
+/// Complete a partly typed path as far as it is unambiguous, ending directories with `/`. None if
+/// nothing matches.
+pub fn complete_path(partial: &str) -> Option<String> {
+    let (dir, prefix) = match partial.rfind('/') {
+        Some(i) => partial.split_at(i + 1),
+        None => ("", partial),
+    };
+    let list_dir = if dir.is_empty() { "." } else { dir };
+
+    let names = fs::read_dir(expand_home(list_dir))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            // Hidden files only when asked for.
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            if entry.path().is_dir() {
+                name.push('/');
+            }
+            Some(name)
+        });
+
+    let common = common_prefix(names)?;
+
+    Some(format!("{}{}", dir, common))
+}
+
+/// The longest start shared by all the names, None if there are none.
+pub fn common_prefix<S: AsRef<str>>(mut names: impl Iterator<Item = S>) -> Option<String> {
+    let first = names.next()?.as_ref().to_owned();
+
+    Some(names.fold(first, |common, name| {
+        common
+            .chars()
+            .zip(name.as_ref().chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect()
+    }))
+}
+
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?'])
+}
+
+/// A regex matching file names against a glob, where `*` is any characters and `?` any one.
+pub fn glob_regex(glob: &str) -> Result<Regex> {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+
+    Ok(Regex::new(&pattern)?)
+}
+
+// The files in a directory with wanted names, sorted by name.
+fn list_dir(dir: &Path, wanted: impl Fn(&str) -> bool) -> Vec<String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list {:?}: {:?}", dir, e);
+            return Vec::new();
+        }
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| wanted(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_regex() {
+        let regex = glob_regex("app-?.*log").unwrap();
+        assert!(regex.is_match("app-1.log"));
+        assert!(regex.is_match("app-2.2024.log"));
+        assert!(!regex.is_match("app-10.log"));
+        assert!(!regex.is_match("app-1.log.gz"));
+
+        assert!(is_glob("logs/*.log"));
+        assert!(!is_glob("logs/app.log"));
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let dir = std::env::temp_dir().join(format!("otail-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("beta")).unwrap();
+        for name in ["alpha.log", "alpine.log", ".hidden"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let dir = dir.to_string_lossy().into_owned();
+
+        let complete = |partial: &str| {
+            complete_path(&format!("{}/{}", dir, partial))
+                .map(|path| path[dir.len() + 1..].to_owned())
+        };
+        assert_eq!(complete("al"), Some("alp".to_owned()));
+        assert_eq!(complete("alph"), Some("alpha.log".to_owned()));
+        assert_eq!(complete("b"), Some("beta/".to_owned()));
+        assert_eq!(complete("."), Some(".hidden".to_owned()));
+        assert_eq!(complete("z"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_newest() {
+        let dir = std::env::temp_dir().join(format!("otail-newest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("app-1.log");
+        fs::write(&old, "old\n").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        fs::write(dir.join("app-2.log"), "new\n").unwrap();
+        let dir = dir.to_string_lossy().into_owned();
+
+        let (mut list, first) = FileList::from_args(std::slice::from_ref(&dir)).unwrap();
+        assert!(first.ends_with("app-2.log"));
+
+        // A file written since is the newest once listed again.
+        fs::write(&old, "old\nnewer\n").unwrap();
+        assert!(list.newest().unwrap().path.ends_with("app-2.log"));
+        list.refresh();
+        assert!(list.newest().unwrap().path.ends_with("app-1.log"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use log::{debug, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::filter_spec::FilterSpec;
+
+// The start of a file checked to tell whether it is the one the matches were found in.
+const HEAD_BYTES: u64 = 4096;
+
+// Filters kept, the least recently saved going first.
+const MAX_CACHED_FILTERS: usize = 50;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    path: String,
+    filter: String,
+    ino: u64,
+    head: u64,
+    // The file's size when the matches were saved, and the lines filtered.
+    bytes: u64,
+    lines: usize,
+    matches: Vec<usize>,
+}
+
+/// Matches for the first lines of a file, as saved when it was last filtered the same way.
+#[derive(Debug, PartialEq)]
+pub struct CachedMatches {
+    pub lines: usize,
+    pub matches: Vec<usize>,
+}
+
+/// Filter matches kept in the cache directory, by file and filter, so filtering a file again after
+/// a restart only needs to look at the lines added since. A file is only taken to be the same if
+/// it is the same inode, hasn't shrunk and starts the same way, as a growing log does.
+#[derive(Debug, Clone)]
+pub struct FilterCache {
+    dir: PathBuf,
+    min_lines: usize,
+}
+
+impl FilterCache {
+    /// Cache in the directory the matches of filters over at least this many lines.
+    pub fn new(dir: PathBuf, min_lines: usize) -> Self {
+        FilterCache { dir, min_lines }
+    }
+
+    /// Where the matches are kept: `$XDG_CACHE_HOME/otail/filters`, or `~/.cache/otail/filters`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let dir = match env::var("XDG_CACHE_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var("HOME").ok()?).join(".cache"),
+        };
+
+        Some(dir.join("otail").join("filters"))
+    }
+
+    /// The matches saved for the file and filter, if the file is still the one they were found in.
+    pub fn load(&self, path: &Path, filter_spec: &FilterSpec) -> Option<CachedMatches> {
+        let filter = filter_key(filter_spec);
+        let entry_path = self.entry_path(path, &filter);
+        let json = fs::read_to_string(&entry_path).ok()?;
+        let entry = match serde_json::from_str::<CacheEntry>(&json) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Failed to parse cached filter {:?}: {}", entry_path, e);
+                return None;
+            }
+        };
+        if entry.path != path.to_string_lossy() || entry.filter != filter {
+            return None;
+        }
+
+        let metadata = fs::metadata(path).ok()?;
+        if metadata.ino() != entry.ino || metadata.len() < entry.bytes {
+            debug!("Cached filter for {:?} is for another file", path);
+            return None;
+        }
+        if head_hash(path, entry.bytes).ok()? != entry.head {
+            debug!("Cached filter for {:?} is for other content", path);
+            return None;
+        }
+
+        debug!(
+            "Using {} cached matches in {} lines of {:?}",
+            entry.matches.len(),
+            entry.lines,
+            path
+        );
+        Some(CachedMatches {
+            lines: entry.lines,
+            matches: entry.matches,
+        })
+    }
+
+    /// Keep the matches for the first lines of the file, which was this many bytes long.
+    pub fn save(
+        &self,
+        path: &Path,
+        filter_spec: &FilterSpec,
+        lines: usize,
+        bytes: u64,
+        matches: &[usize],
+    ) {
+        if lines < self.min_lines {
+            return;
+        }
+        if let Err(e) = self.write(path, filter_spec, lines, bytes, matches) {
+            warn!("Failed to cache filter matches for {:?}: {}", path, e);
+        }
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+        filter_spec: &FilterSpec,
+        lines: usize,
+        bytes: u64,
+        matches: &[usize],
+    ) -> Result<()> {
+        // A file that has shrunk since the lines were read is no longer the one matched.
+        let metadata = fs::metadata(path)?;
+        if metadata.len() < bytes {
+            trace!("Not caching filter matches for truncated {:?}", path);
+            return Ok(());
+        }
+
+        let filter = filter_key(filter_spec);
+        let entry = CacheEntry {
+            path: path.to_string_lossy().into_owned(),
+            ino: metadata.ino(),
+            head: head_hash(path, bytes)?,
+            filter: filter.clone(),
+            bytes,
+            lines,
+            matches: matches.to_vec(),
+        };
+        fs::create_dir_all(&self.dir)?;
+        let entry_path = self.entry_path(path, &filter);
+        fs::write(&entry_path, serde_json::to_string(&entry)?)?;
+        debug!(
+            "Cached {} matches in {} lines of {:?} as {:?}",
+            matches.len(),
+            lines,
+            path,
+            entry_path
+        );
+
+        self.prune()
+    }
+
+    fn entry_path(&self, path: &Path, filter: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        filter.hash(&mut hasher);
+
+        self.dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    // Forget the least recently saved filters beyond the most kept.
+    fn prune(&self) -> Result<()> {
+        let mut entries = fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+            .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+            .collect::<Vec<_>>();
+        if entries.len() <= MAX_CACHED_FILTERS {
+            return Ok(());
+        }
+
+        entries.sort();
+        for (_, path) in &entries[..entries.len() - MAX_CACHED_FILTERS] {
+            trace!("Removing cached filter {:?}", path);
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+// What decides the matches: the filter's type, pattern with macros expanded, and context.
+fn filter_key(filter_spec: &FilterSpec) -> String {
+    format!(
+        "{:?} {:?} {}",
+        filter_spec.filter_type,
+        filter_spec.context(),
+        filter_spec.expanded_pattern()
+    )
+}
+
+// A hash of the start of the file, up to the size it was.
+fn head_hash(path: &Path, bytes: u64) -> Result<u64> {
+    let mut head = Vec::new();
+    File::open(path)?
+        .take(bytes.min(HEAD_BYTES))
+        .read_to_end(&mut head)?;
+
+    let mut hasher = DefaultHasher::new();
+    head.hash(&mut hasher);
+
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_spec::FilterType;
+    use std::io::Write;
+
+    #[test]
+    fn test_filter_cache() {
+        let dir = env::temp_dir().join(format!("otail-test-filter-cache-{}", std::process::id()));
+        let path = dir.join("app.log");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "one\nerror two\nthree\n").unwrap();
+
+        let cache = FilterCache::new(dir.join("cache"), 2);
+        let error = FilterSpec::new(FilterType::SimpleCaseInsensitive, "error").unwrap();
+        let warn = FilterSpec::new(FilterType::SimpleCaseInsensitive, "warn").unwrap();
+
+        // Too few lines to be worth keeping.
+        cache.save(&path, &error, 1, 20, &[]);
+        assert_eq!(cache.load(&path, &error), None);
+
+        cache.save(&path, &error, 3, 20, &[1]);
+        let cached = Some(CachedMatches {
+            lines: 3,
+            matches: vec![1],
+        });
+        assert_eq!(cache.load(&path, &error), cached);
+        assert_eq!(cache.load(&path, &warn), None);
+
+        // The file growing keeps the matches, being replaced loses them.
+        File::options()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"error four\n")
+            .unwrap();
+        assert_eq!(cache.load(&path, &error), cached);
+        fs::write(&path, "ONE\nerror two\nthree\n").unwrap();
+        assert_eq!(cache.load(&path, &error), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs::read_to_string;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::filter_spec::{Context, FilterExpr, FilterSpec, FilterType};
+
+// Each part of a filter is a map with one key, e.g. `regex: "time(d)? ?out"` or `not: {...}`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum ExprDef {
+    All(Vec<ExprDef>),
+    Any(Vec<ExprDef>),
+    Not(Box<ExprDef>),
+    Text(String),
+    CaseSensitive(String),
+    SmartCase(String),
+    Regex(String),
+    Level(String),
+    Json(String),
+}
+
+impl ExprDef {
+    // A single filter, or None if this combines others.
+    fn leaf(&self) -> Option<(FilterType, &str)> {
+        match self {
+            ExprDef::Text(pattern) => Some((FilterType::SimpleCaseInsensitive, pattern)),
+            ExprDef::CaseSensitive(pattern) => Some((FilterType::SimpleCaseSensitive, pattern)),
+            ExprDef::SmartCase(pattern) => Some((FilterType::SmartCase, pattern)),
+            ExprDef::Regex(pattern) => Some((FilterType::Regex, pattern)),
+            ExprDef::Level(pattern) => Some((FilterType::Level, pattern)),
+            ExprDef::Json(pattern) => Some((FilterType::Json, pattern)),
+            _ => None,
+        }
+    }
+
+    fn to_expr(&self) -> Result<FilterExpr> {
+        if let Some((filter_type, pattern)) = self.leaf() {
+            return Ok(FilterExpr::Filter(FilterSpec::new(filter_type, pattern)?));
+        }
+
+        let exprs = |defs: &[ExprDef]| defs.iter().map(|d| d.to_expr()).collect::<Result<_>>();
+        Ok(match self {
+            ExprDef::All(defs) => FilterExpr::All(exprs(defs)?),
+            ExprDef::Any(defs) => FilterExpr::Any(exprs(defs)?),
+            ExprDef::Not(def) => FilterExpr::Not(Box::new(def.to_expr()?)),
+            _ => unreachable!("Leaf filters are handled above"),
+        })
+    }
+}
+
+/// A filter kept in a YAML or JSON file, so that elaborate queries can be versioned and reused:
+///
+/// ```yaml
+/// filter:
+///   all:
+///     - level: ">= WARN"
+///     - any:
+///         - regex: "time(d)? ?out"
+///         - json: ".response.status >= 500"
+///     - not:
+///         text: healthcheck
+/// context:
+///   before: 2
+///   after: 5
+/// since: "2024-03-01 10:00"
+/// until: "2024-03-01 12:00"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterFile {
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    filter: ExprDef,
+    #[serde(default)]
+    pub context: Context,
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+impl FilterFile {
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read filter file {}: {}", path, e))?;
+
+        Self::parse(&contents).map_err(|e| anyhow!("Bad filter file {}: {}", path, e))
+    }
+
+    // JSON is also YAML, so one parser reads both.
+    fn parse(contents: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(contents)?)
+    }
+
+    /// The filter to apply. A single filter stays as is, so it can be edited in the filter
+    /// dialog. Regex macros must be set first.
+    pub fn filter_spec(&self) -> Result<FilterSpec> {
+        let filter_spec = match self.filter.leaf() {
+            Some((filter_type, pattern)) => FilterSpec::new(filter_type, pattern)?,
+            None => FilterSpec::compound(self.filter.to_expr()?),
+        };
+
+        Ok(filter_spec.with_context(self.context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_file() {
+        let filter_file = FilterFile::parse(
+            r#"
+filter:
+  all:
+    - level: ">= WARN"
+    - any:
+        - regex: "time(d)? ?out"
+        - json: ".status >= 500"
+    - not:
+        text: healthcheck
+context:
+  after: 2
+since: "10:00"
+"#,
+        )
+        .unwrap();
+        assert_eq!(filter_file.since.as_deref(), Some("10:00"));
+        assert_eq!(filter_file.until, None);
+
+        let filter_spec = filter_file.filter_spec().unwrap();
+        assert_eq!(filter_spec.filter_type, FilterType::Compound);
+        assert_eq!(
+            filter_spec.context(),
+            Context {
+                before: 0,
+                after: 2
+            }
+        );
+        assert_eq!(
+            filter_spec.render(),
+            r#"">= WARN" (Level) and ("time(d)? ?out" (Regex) or ".status >= 500" (JSON)) and not "healthcheck" (Insensitive) with 0 before/2 after"#
+        );
+
+        assert!(filter_spec.matches("WARN request timed out"));
+        assert!(filter_spec.matches(r#"{"level":"error","status":503}"#));
+        assert!(!filter_spec.matches("WARN healthcheck timeout"));
+        assert!(!filter_spec.matches("INFO request timed out"));
+
+        let filter_file = FilterFile::parse(r#"{"filter": {"regex": "a+b"}}"#).unwrap();
+        let filter_spec = filter_file.filter_spec().unwrap();
+        assert_eq!(filter_spec.filter_type, FilterType::Regex);
+        assert_eq!(filter_spec.filter_pattern, "a+b");
+
+        assert!(FilterFile::parse("filter:\n  glob: '*.log'").is_err());
+        assert!(FilterFile::parse("filter:\n  regex: '('")
+            .unwrap()
+            .filter_spec()
+            .is_err());
+    }
+}
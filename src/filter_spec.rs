@@ -1,12 +1,126 @@
-use anyhow::Result;
-use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use anyhow::{anyhow, bail, Result};
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
 
+use crate::json_query::JsonPredicate;
+use crate::level::Level;
+
+lazy_static::lazy_static! {
+    // Named regex fragments from the config, referenced in regex filters as `{{name}}`.
+    static ref REGEX_MACROS: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+    static ref MACRO_REFERENCE: Regex = Regex::new(r"\{\{(\w+)\}\}").expect("Bad macro regex");
+}
+
+pub fn set_regex_macros(macros: &BTreeMap<String, String>) {
+    if let Ok(mut m) = REGEX_MACROS.write() {
+        *m = macros.clone();
+    }
+}
+
+// Replace each `{{name}}` with its fragment, grouped so a following quantifier applies to all of it.
+fn expand_macros(pattern: &str, macros: &BTreeMap<String, String>) -> Result<String> {
+    let mut unknown = None;
+    let expanded =
+        MACRO_REFERENCE.replace_all(pattern, |caps: &Captures| match macros.get(&caps[1]) {
+            Some(fragment) => format!("(?:{})", fragment),
+            None => {
+                unknown.get_or_insert_with(|| caps[1].to_owned());
+                String::new()
+            }
+        });
+
+    match unknown {
+        Some(name) => Err(anyhow!("Unknown regex macro: {{{{{}}}}}", name)),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+/// Compile a regex, expanding any macros.
+pub fn macro_regex(pattern: &str) -> Result<Regex> {
+    let macros = REGEX_MACROS.read().map_err(|e| anyhow!("{}", e))?;
+
+    Ok(Regex::new(&expand_macros(pattern, &macros)?)?)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FilterType {
     SimpleCaseSensitive,
     SimpleCaseInsensitive,
+    // Case insensitive unless the pattern has an uppercase letter, as in ripgrep and vim.
+    SmartCase,
     Regex,
+    // Lines at or above a log level, e.g. `WARN` or `>= WARN`.
+    Level,
+    // JSON lines passing a test, e.g. `.response.status >= 500`.
+    Json,
+    // A combination of filters, loaded from a filter file.
+    Compound,
+}
+
+impl FilterType {
+    /// Whether the filter looks for text, so can be searched for by ripgrep.
+    pub fn is_text_search(&self) -> bool {
+        !matches!(
+            self,
+            FilterType::Level | FilterType::Json | FilterType::Compound
+        )
+    }
+}
+
+/// Lines around each match to show as well, like grep's `-B` and `-A`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Context {
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Filters combined with and, or and not.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    All(Vec<FilterExpr>),
+    Any(Vec<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Filter(FilterSpec),
+}
+
+impl FilterExpr {
+    pub fn matches(&self, line: &str) -> bool {
+        match self {
+            FilterExpr::All(exprs) => exprs.iter().all(|e| e.matches(line)),
+            FilterExpr::Any(exprs) => exprs.iter().any(|e| e.matches(line)),
+            FilterExpr::Not(expr) => !expr.matches(line),
+            FilterExpr::Filter(filter_spec) => filter_spec.matches(line),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let join = |exprs: &[FilterExpr], op: &str| {
+            let parts = exprs
+                .iter()
+                .map(|e| match e {
+                    FilterExpr::All(_) | FilterExpr::Any(_) if exprs.len() > 1 => {
+                        format!("({})", e.render())
+                    }
+                    _ => e.render(),
+                })
+                .collect::<Vec<_>>();
+            parts.join(op)
+        };
+
+        match self {
+            FilterExpr::All(exprs) => join(exprs, " and "),
+            FilterExpr::Any(exprs) => join(exprs, " or "),
+            FilterExpr::Not(expr) => match expr.as_ref() {
+                FilterExpr::Filter(_) | FilterExpr::Not(_) => format!("not {}", expr.render()),
+                _ => format!("not ({})", expr.render()),
+            },
+            FilterExpr::Filter(filter_spec) => filter_spec.render(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,30 +129,115 @@ pub struct FilterSpec {
     pub filter_pattern: String,
     #[serde(skip)]
     regex: Option<Regex>,
+    #[serde(skip)]
+    level: Option<Level>,
+    #[serde(skip)]
+    json: Option<JsonPredicate>,
+    #[serde(skip)]
+    compound: Option<Box<FilterExpr>>,
+    #[serde(skip)]
+    context: Context,
 }
 
 impl FilterSpec {
     pub fn new(filter_type: FilterType, filter_pattern: &str) -> Result<Self> {
+        if filter_type == FilterType::Compound {
+            bail!("Combined filters can only be loaded from a filter file");
+        }
+
         Ok(FilterSpec {
             filter_type: filter_type.clone(),
             filter_pattern: filter_pattern.to_owned(),
             regex: if filter_type == FilterType::Regex {
-                Some(Regex::new(filter_pattern)?)
+                Some(macro_regex(filter_pattern)?)
             } else {
                 None
             },
+            level: if filter_type == FilterType::Level {
+                Some(Level::parse_threshold(filter_pattern)?)
+            } else {
+                None
+            },
+            json: if filter_type == FilterType::Json {
+                Some(JsonPredicate::parse(filter_pattern)?)
+            } else {
+                None
+            },
+            compound: None,
+            context: Context::default(),
         })
     }
+
+    pub fn compound(expr: FilterExpr) -> Self {
+        FilterSpec {
+            filter_type: FilterType::Compound,
+            filter_pattern: expr.render(),
+            regex: None,
+            level: None,
+            json: None,
+            compound: Some(Box::new(expr)),
+            context: Context::default(),
+        }
+    }
+
+    /// Build the filter again from its type and pattern, e.g. after loading it from the config,
+    /// which only keeps those.
+    pub fn compiled(&self) -> Result<Self> {
+        Ok(
+            FilterSpec::new(self.filter_type.clone(), &self.filter_pattern)?
+                .with_context(self.context),
+        )
+    }
+
+    pub fn with_context(mut self, context: Context) -> Self {
+        self.context = context;
+
+        self
+    }
+
+    pub fn context(&self) -> Context {
+        self.context
+    }
+
+    /// The pattern to search for, with any regex macros expanded.
+    pub fn expanded_pattern(&self) -> &str {
+        match self.regex {
+            Some(ref regex) => regex.as_str(),
+            None => &self.filter_pattern,
+        }
+    }
+
+    pub fn regex(&self) -> Option<&Regex> {
+        self.regex.as_ref()
+    }
+
     pub fn render(&self) -> String {
-        format!(
-            "\"{}\" ({})",
-            self.filter_pattern,
-            match self.filter_type {
-                FilterType::SimpleCaseSensitive => "Sensitive",
-                FilterType::SimpleCaseInsensitive => "Insensitive",
-                FilterType::Regex => "Regex",
+        let filter = match self.filter_type {
+            FilterType::Compound => self.filter_pattern.clone(),
+            _ => format!(
+                "\"{}\" ({})",
+                self.filter_pattern,
+                match self.filter_type {
+                    FilterType::SimpleCaseSensitive => "Sensitive",
+                    FilterType::SimpleCaseInsensitive => "Insensitive",
+                    FilterType::SmartCase => "Smart case",
+                    FilterType::Regex => "Regex",
+                    FilterType::Level => "Level",
+                    FilterType::Json => "JSON",
+                    FilterType::Compound => "Combined",
+                }
+            ),
+        };
+
+        match self.context {
+            Context {
+                before: 0,
+                after: 0,
+            } => filter,
+            Context { before, after } => {
+                format!("{} with {} before/{} after", filter, before, after)
             }
-        )
+        }
     }
 
     pub fn matches(&self, line: &str) -> bool {
@@ -47,6 +246,12 @@ impl FilterSpec {
             FilterType::SimpleCaseInsensitive => line
                 .to_lowercase()
                 .contains(&self.filter_pattern.to_lowercase()),
+            FilterType::SmartCase if self.filter_pattern.chars().any(char::is_uppercase) => {
+                line.contains(&self.filter_pattern)
+            }
+            FilterType::SmartCase => line
+                .to_lowercase()
+                .contains(&self.filter_pattern.to_lowercase()),
             FilterType::Regex => {
                 if let Some(ref regex) = self.regex {
                     regex.find(line).is_some()
@@ -55,12 +260,59 @@ impl FilterSpec {
                     false
                 }
             }
+            FilterType::Level => match (self.level, Level::detect(line)) {
+                (Some(threshold), Some(level)) => level >= threshold,
+                _ => false,
+            },
+            FilterType::Json => self.json.as_ref().is_some_and(|json| json.matches(line)),
+            FilterType::Compound => self
+                .compound
+                .as_ref()
+                .is_some_and(|compound| compound.matches(line)),
         }
     }
 }
 
 impl PartialEq for FilterSpec {
     fn eq(&self, other: &Self) -> bool {
-        self.filter_type == other.filter_type && self.filter_pattern == other.filter_pattern
+        self.filter_type == other.filter_type
+            && self.filter_pattern == other.filter_pattern
+            && self.context == other.context
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_macros() {
+        let macros = BTreeMap::from([
+            ("ip".to_owned(), r"\d+\.\d+\.\d+\.\d+".to_owned()),
+            ("word".to_owned(), r"\w+".to_owned()),
+        ]);
+
+        assert_eq!(
+            expand_macros("from {{ip}} as {{word}}+", &macros).unwrap(),
+            r"from (?:\d+\.\d+\.\d+\.\d+) as (?:\w+)+"
+        );
+        assert_eq!(expand_macros("a{2}", &macros).unwrap(), "a{2}");
+        assert_eq!(
+            expand_macros("{{ip}} {{uuid}}", &macros)
+                .unwrap_err()
+                .to_string(),
+            "Unknown regex macro: {{uuid}}"
+        );
+    }
+
+    #[test]
+    fn test_smart_case() {
+        let lower = FilterSpec::new(FilterType::SmartCase, "error").unwrap();
+        assert!(lower.matches("an error here"));
+        assert!(lower.matches("an ERROR here"));
+
+        let upper = FilterSpec::new(FilterType::SmartCase, "Error").unwrap();
+        assert!(upper.matches("Error: failed"));
+        assert!(!upper.matches("an error here"));
     }
 }
@@ -0,0 +1,255 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::filter_spec::FilterType;
+
+pub const DEFAULT_HISTORY_SIZE: usize = 100;
+
+const HISTORY_FILENAME: &str = "history.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryKind {
+    // Patterns applied in the filter dialog.
+    Filter,
+    // Times searched for with go to time.
+    Time,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub kind: HistoryKind,
+    pub pattern: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_type: Option<FilterType>,
+}
+
+/// Filters and searches used before, oldest first. Kept in the state directory so they are still
+/// there after a restart.
+#[derive(Debug, Clone)]
+pub struct History {
+    path: Option<PathBuf>,
+    size: usize,
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn new(path: Option<PathBuf>, size: usize) -> Self {
+        History {
+            path,
+            size,
+            entries: Vec::new(),
+        }
+    }
+
+    /// The history saved at the path, or an empty one if there isn't one that can be read.
+    pub fn load(path: Option<PathBuf>, size: usize) -> Self {
+        let mut history = History::new(path, size);
+        if let Some(path) = &history.path {
+            match fs::read_to_string(path) {
+                Ok(json) => match serde_json::from_str::<Vec<HistoryEntry>>(&json) {
+                    Ok(entries) => history.entries = entries,
+                    Err(e) => warn!("Failed to parse history {:?}: {}", path, e),
+                },
+                Err(e) => trace!("No history {:?}: {}", path, e),
+            }
+        }
+        history.trim();
+
+        history
+    }
+
+    /// Where the history is kept: `$XDG_STATE_HOME/otail`, or `~/.local/state/otail`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var("XDG_STATE_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var("HOME").ok()?).join(".local/state"),
+        };
+
+        Some(dir.join("otail").join(HISTORY_FILENAME))
+    }
+
+    /// Add an entry as the most recent, moving it if it was used before, and save the history.
+    pub fn add(&mut self, entry: HistoryEntry) {
+        if entry.pattern.is_empty() {
+            return;
+        }
+        self.entries.retain(|e| *e != entry);
+        self.entries.push(entry);
+        self.trim();
+
+        if let Err(e) = self.save() {
+            warn!("Failed to save history {:?}: {}", self.path, e);
+        }
+    }
+
+    /// The entries of a kind, most recent first.
+    pub fn recent(&self, kind: HistoryKind) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter().rev().filter(move |e| e.kind == kind)
+    }
+
+    /// Step from the position through the entries of a kind, to older ones for Up. Gives the entry
+    /// moved to, blank when moving back past the most recent, or None if it didn't move.
+    pub fn step(
+        &self,
+        kind: HistoryKind,
+        pos: &mut Option<usize>,
+        older: bool,
+    ) -> Option<HistoryEntry> {
+        let count = self.recent(kind).count();
+        let next = match (*pos, older) {
+            (None, true) if count > 0 => Some(0),
+            (Some(p), true) if p + 1 < count => Some(p + 1),
+            (Some(0), false) => None,
+            (Some(p), false) => Some(p - 1),
+            _ => return None,
+        };
+        *pos = next;
+
+        Some(match next {
+            Some(p) => self.recent(kind).nth(p)?.clone(),
+            None => HistoryEntry {
+                kind,
+                pattern: String::new(),
+                filter_type: None,
+            },
+        })
+    }
+
+    /// The entries fuzzily matching the query, best match first and then most recent first.
+    pub fn pick(&self, query: &str) -> Vec<&HistoryEntry> {
+        let mut matches = self
+            .entries
+            .iter()
+            .rev()
+            .filter_map(|e| fuzzy_score(query, &e.pattern).map(|score| (score, e)))
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|(score, _)| *score);
+
+        matches.into_iter().map(|(_, e)| e).collect()
+    }
+
+    fn trim(&mut self) {
+        let excess = self.entries.len().saturating_sub(self.size);
+        self.entries.drain(..excess);
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&self.entries)?)?;
+
+        Ok(())
+    }
+}
+
+/// How well the query matches the text when its characters appear in order, ignoring case. Lower
+/// is better: the number of characters skipped between the first and last matched ones.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<usize> {
+    let query = query.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    // Try each start, the tightest match may not start at the first occurrence.
+    (0..text.len())
+        .filter(|&start| text[start] == query[0])
+        .filter_map(|start| {
+            let mut matched = 1;
+            for (i, c) in text.iter().enumerate().skip(start + 1) {
+                if matched == query.len() {
+                    break;
+                }
+                if *c == query[matched] {
+                    matched += 1;
+                    if matched == query.len() {
+                        return Some(i + 1 - start - query.len());
+                    }
+                }
+            }
+            (matched == query.len()).then_some(0)
+        })
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(pattern: &str) -> HistoryEntry {
+        HistoryEntry {
+            kind: HistoryKind::Filter,
+            pattern: pattern.to_owned(),
+            filter_type: Some(FilterType::Regex),
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("err", "ERROR"), Some(0));
+        assert_eq!(fuzzy_score("tmo", "timeout"), Some(2));
+        assert_eq!(fuzzy_score("ab", "a.....ab"), Some(0));
+        assert_eq!(fuzzy_score("x", "timeout"), None);
+        assert_eq!(fuzzy_score("tuoemit", "timeout"), None);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new(None, 3);
+        for pattern in ["error", "timeout", "warn", "error", "", "retry"] {
+            history.add(filter(pattern));
+        }
+        history.add(HistoryEntry {
+            kind: HistoryKind::Time,
+            pattern: "10:15".to_owned(),
+            filter_type: None,
+        });
+
+        // Repeats move to the front and the oldest go once full.
+        let recent = |history: &History, kind| {
+            history
+                .recent(kind)
+                .map(|e| e.pattern.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            recent(&history, HistoryKind::Filter),
+            vec!["retry", "error"]
+        );
+        assert_eq!(recent(&history, HistoryKind::Time), vec!["10:15"]);
+
+        let picked = |query| {
+            history
+                .pick(query)
+                .iter()
+                .map(|e| e.pattern.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(picked(""), vec!["10:15", "retry", "error"]);
+        assert_eq!(picked("rr"), vec!["error", "retry"]);
+
+        let mut pos = None;
+        let mut step = |older| {
+            history
+                .step(HistoryKind::Filter, &mut pos, older)
+                .map(|e| e.pattern)
+        };
+        assert_eq!(step(false), None);
+        assert_eq!(step(true), Some("retry".to_owned()));
+        assert_eq!(step(true), Some("error".to_owned()));
+        assert_eq!(step(true), None);
+        assert_eq!(step(false), Some("retry".to_owned()));
+        assert_eq!(step(false), Some(String::new()));
+    }
+}
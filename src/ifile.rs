@@ -1,13 +1,20 @@
 use anyhow::Result;
-use log::{debug, error, info, trace, warn};
+use chrono::{DateTime, FixedOffset};
+use log::{debug, error, trace, warn};
+use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::time::Duration;
 use tokio::select;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinError, JoinHandle};
+use tokio::time::{interval, sleep_until, Instant, MissedTickBehavior};
 
-use crate::backing_file::BackingFile;
-use crate::common::CHANNEL_BUFFER;
-use crate::reader::{Reader, ReaderUpdate, ReaderUpdateReceiver};
+use crate::common::{cap_line, cut_line, CHANNEL_BUFFER, READER_WATCHDOG_MS};
+use crate::input_source::{InputSource, SourceLines};
+use crate::reader::{ReaderUpdate, ReaderUpdateReceiver};
+use crate::retry::RetryPolicy;
+use crate::timestamp::{TimeWindow, TimestampParser};
 
 pub type FileReqSender<T> = mpsc::Sender<FileReq<T>>;
 pub type FileReqReceiver<T> = mpsc::Receiver<FileReq<T>>;
@@ -15,6 +22,15 @@ pub type FileReqReceiver<T> = mpsc::Receiver<FileReq<T>>;
 pub type FileRespSender<T> = mpsc::Sender<T>;
 pub type FileRespReceiver<T> = mpsc::Receiver<T>;
 
+pub type LinesRespSender = oneshot::Sender<Vec<(usize, String)>>;
+pub type OffsetLinesRespSender = oneshot::Sender<Vec<(u64, usize, String)>>;
+pub type OffsetRespSender = oneshot::Sender<Option<u64>>;
+pub type LinesRespReceiver = oneshot::Receiver<Vec<(usize, String)>>;
+
+pub type TimeRespSender = oneshot::Sender<Option<usize>>;
+pub type TimeFractionRespSender = oneshot::Sender<Option<f64>>;
+pub type TimeRespReceiver = oneshot::Receiver<Option<usize>>;
+
 #[derive(Debug)]
 pub enum FileReq<T> {
     GetLine {
@@ -25,6 +41,40 @@ pub enum FileReq<T> {
         id: String,
         line_no: usize,
     },
+    // Bulk fetch of lines, answered directly on the oneshot rather than through the client
+    // channel. Lines not yet known are omitted from the response.
+    GetLines {
+        line_nos: Vec<usize>,
+        resp: LinesRespSender,
+    },
+    // A line as it is in the file, even if longer than the cap on lines.
+    GetFullLine {
+        line_no: usize,
+        resp: LinesRespSender,
+    },
+    // The lines starting at byte offsets, with their offsets and line numbers, answered on the
+    // oneshot. Offsets not at the start of a whole line yet are omitted.
+    GetLinesAt {
+        offsets: Vec<u64>,
+        resp: OffsetLinesRespSender,
+    },
+    // Where a whole line starts in the file, answered on the oneshot.
+    LineOffset {
+        line_no: usize,
+        resp: OffsetRespSender,
+    },
+    // Find the first line at or after a time, answered on the oneshot. None if there is no such
+    // line or the file isn't indexed by time.
+    FindTime {
+        time: DateTime<FixedOffset>,
+        resp: TimeRespSender,
+    },
+    // How far through the file's time span a line is, from 0 for the first timestamp to 1 for
+    // the last, answered on the oneshot. None if the file isn't indexed by time or has no span.
+    TimeFraction {
+        line_no: usize,
+        resp: TimeFractionRespSender,
+    },
     RegisterClient {
         id: String,
         client_sender: mpsc::Sender<T>,
@@ -54,9 +104,40 @@ pub enum FileResp<L> {
 
 #[derive(Debug)]
 pub enum IFResp<L> {
-    ViewUpdate { update: FileResp<L> },
+    ViewUpdate {
+        update: FileResp<L>,
+    },
     Truncated,
-    FileError { reason: String },
+    // The file was replaced by another, which is read in its place, e.g. on rotation. Sent after
+    // Truncated.
+    Rotated,
+    FileError {
+        reason: String,
+    },
+    // The reader died or stopped seeing the file grow, so was started again where it left off.
+    ReaderRestarted {
+        reason: String,
+    },
+    // The reader failed, so the file is reopened after the delay. Attempts are counted from 1, up to
+    // the most made, 0 if there is no limit.
+    ReaderRetrying {
+        reason: String,
+        attempt: u32,
+        attempts: u32,
+        delay: Duration,
+    },
+    // The file doesn't exist yet, so is waited for. Also sent to clients registering whilst waiting.
+    Waiting,
+    // The file waited for has been created, and is read from the start.
+    Created,
+    // The file has been read to the end, as it was when opened. Also sent to clients registering
+    // later.
+    Spooled,
+    // The lines before those read were indexed, when starting with the last lines of the file, so
+    // every line is now this many further on. Sent before the new stats.
+    Backfilled {
+        lines: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -64,8 +145,10 @@ struct SLine {
     offset: u64,
     _line_no: usize,
     _line_chars: usize,
-    _line_bytes: usize,
+    line_bytes: usize,
     partial: bool,
+    // The line's timestamp, or that of the nearest line before with one.
+    timestamp: Option<DateTime<FixedOffset>>,
 }
 
 #[derive(Debug)]
@@ -76,38 +159,83 @@ struct Client<L> {
     interested: HashSet<usize>,
 }
 
-// Separate Clients from BackingFile to avoid overlapping references to &mut self.
+// Separate Clients from the InputSource to avoid overlapping references to &mut self.
 #[derive(Debug)]
 struct Clients {
     clients: HashMap<String, Client<String>>,
 }
 
-/// Provide access to a file on disk, for random access and watching for changes.
+/// Provide access to a file, or another input source, for random access and watching for changes.
 #[derive(Debug)]
-pub struct IFile<BF: BackingFile> {
+pub struct IFile<S: InputSource> {
     view_receiver: FileReqReceiver<IFResp<String>>,
     view_sender: FileReqSender<IFResp<String>>,
+    // Bulk requests, e.g. the filter reading every line, only served when no view is waiting so
+    // scrolling stays quick during a long filter.
+    bulk_receiver: FileReqReceiver<IFResp<String>>,
+    bulk_sender: FileReqSender<IFResp<String>>,
     path: PathBuf,
-    backing_file: BF,
+    source: S,
     lines: Vec<SLine>,
     file_lines: usize,
     file_bytes: u64,
     previous_partial: bool,
     clients: Clients,
+    reader_task: Option<JoinHandle<()>>,
+    // The reader kept failing until the retry policy gave up, so isn't restarted when it stops.
+    file_error: bool,
+    // What is being read, to tell whether it has been replaced when reopened.
+    source_identity: Option<(u64, u64)>,
+
+    // How the file is reopened when the reader fails, the failures in a row, and when the next
+    // attempt is due.
+    retry_policy: RetryPolicy,
+    retry_attempts: u32,
+    retry_at: Option<Instant>,
+
+    // Only index lines within this time window. Whether the line being read (which may be
+    // partial) is outside it, and if the last reader line was partial.
+    time_window: Option<TimeWindow>,
+    skipping_line: bool,
+    reader_partial: bool,
+
+    // Lines starting with these aren't indexed, e.g. the tags of hidden merged files.
+    hidden_prefixes: Vec<String>,
+
+    // Lines matching these aren't indexed, e.g. health checks and other known noise.
+    ignore_patterns: Vec<Regex>,
+
+    // Index each line's time to allow binary searching by time.
+    timestamps: Option<TimestampParser>,
+
+    // Lines longer than this are cut short before being passed on.
+    max_line_bytes: Option<usize>,
+
+    // Waiting for the file to be created.
+    waiting: bool,
+    // Whether the file has been read to the end, as it was when opened.
+    spooled: bool,
+
+    // Start with only the last lines of the file, indexing the lines before in the background.
+    tail_lines: Option<usize>,
+    backfill: Option<JoinHandle<Result<Vec<SLine>>>>,
 }
 
-impl<BF: BackingFile> IFile<BF> {
-    pub fn new(path: &str, backing_file: BF) -> IFile<BF> {
+impl<S: InputSource> IFile<S> {
+    pub fn new(path: &str, source: S) -> IFile<S> {
         let mut pb = PathBuf::new();
         pb.push(path);
 
         let (view_sender, view_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        let (bulk_sender, bulk_receiver) = mpsc::channel(CHANNEL_BUFFER);
 
         IFile {
             path: pb,
-            backing_file,
+            source,
             view_receiver,
             view_sender,
+            bulk_receiver,
+            bulk_sender,
             lines: vec![],
             file_lines: 0,
             file_bytes: 0,
@@ -115,36 +243,403 @@ impl<BF: BackingFile> IFile<BF> {
             clients: Clients {
                 clients: HashMap::new(),
             },
+            reader_task: None,
+            file_error: false,
+            source_identity: None,
+
+            retry_policy: RetryPolicy::default(),
+            retry_attempts: 0,
+            retry_at: None,
+
+            time_window: None,
+            skipping_line: false,
+            reader_partial: false,
+
+            hidden_prefixes: Vec::new(),
+
+            ignore_patterns: Vec::new(),
+
+            timestamps: None,
+
+            max_line_bytes: None,
+
+            waiting: false,
+            spooled: false,
+
+            tail_lines: None,
+            backfill: None,
         }
     }
 
-    fn run_reader(&mut self) -> ReaderUpdateReceiver {
-        let (reader_sender, reader_receiver) = mpsc::channel(CHANNEL_BUFFER);
-        let path = self.path.clone();
-        tokio::spawn(async move {
-            match Reader::run(path, reader_sender).await {
-                Err(err) => {
-                    error!("Reader failed: {:?}", err);
-                }
-                Ok(_) => {
-                    info!("Reader finished normally");
+    pub fn with_time_window(mut self, time_window: TimeWindow) -> Self {
+        self.time_window = Some(time_window);
+
+        self
+    }
+
+    pub fn with_hidden_prefixes(mut self, hidden_prefixes: Vec<String>) -> Self {
+        self.hidden_prefixes = hidden_prefixes;
+
+        self
+    }
+
+    pub fn with_ignore_patterns(mut self, ignore_patterns: Vec<Regex>) -> Self {
+        self.ignore_patterns = ignore_patterns;
+
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+
+        self
+    }
+
+    pub fn with_timestamps(mut self, timestamps: TimestampParser) -> Self {
+        self.timestamps = Some(timestamps);
+
+        self
+    }
+
+    pub fn with_tail_lines(mut self, lines: usize) -> Self {
+        self.tail_lines = Some(lines);
+
+        self
+    }
+
+    pub fn with_max_line_bytes(mut self, max_line_bytes: usize) -> Self {
+        self.max_line_bytes = Some(max_line_bytes);
+
+        self
+    }
+
+    // Read a line, cut short if it is longer than the cap. Only the start of a gigantic line is
+    // read, its full size being known from the index.
+    fn read_capped_line(&mut self, sl: (u64, usize)) -> Result<String> {
+        let (offset, line_bytes) = sl;
+        let Some(max_line_bytes) = self.max_line_bytes.filter(|max| line_bytes > *max) else {
+            return self.source.read_line(Some(offset));
+        };
+
+        let (mut line_content, cut) = self.source.read_line_start(offset, max_line_bytes)?;
+        if cut {
+            cut_line(&mut line_content, max_line_bytes, line_bytes);
+        }
+
+        Ok(line_content)
+    }
+
+    // The time of a line, carrying forward the time of the line before for lines without one,
+    // such as stack traces.
+    fn line_timestamp(&self, line_no: usize, line_content: &str) -> Option<DateTime<FixedOffset>> {
+        let timestamps = self.timestamps.as_ref()?;
+
+        timestamps.find(line_content).map(|ts| ts.time).or_else(|| {
+            line_no
+                .checked_sub(1)
+                .and_then(|previous| self.lines.get(previous))
+                .and_then(|sl| sl.timestamp)
+        })
+    }
+
+    // The first line at or after the time. Assumes times only go forwards, lines before the first
+    // timestamp count as earlier than any time.
+    fn find_time(&self, time: DateTime<FixedOffset>) -> Option<usize> {
+        self.timestamps.as_ref()?;
+
+        let line_no = self
+            .lines
+            .partition_point(|sl| sl.timestamp.is_none_or(|t| t < time));
+
+        (line_no < self.lines.len()).then_some(line_no)
+    }
+
+    fn time_fraction(&self, line_no: usize) -> Option<f64> {
+        self.timestamps.as_ref()?;
+
+        let first = self.lines.iter().find_map(|sl| sl.timestamp)?;
+        let last = self.lines.last()?.timestamp?;
+        let span = (last - first).num_milliseconds();
+        if span <= 0 {
+            return None;
+        }
+
+        // Lines before the first timestamp are at the start.
+        let time = self
+            .lines
+            .get(line_no.min(self.lines.len() - 1))?
+            .timestamp
+            .unwrap_or(first);
+        Some((time - first).num_milliseconds() as f64 / span as f64)
+    }
+
+    // Should this reader line be indexed? The decision is made on the first part of a line and
+    // sticks for the rest of it.
+    fn admit_line(&mut self, line_content: &str, partial: bool) -> bool {
+        if self.time_window.is_none()
+            && self.hidden_prefixes.is_empty()
+            && self.ignore_patterns.is_empty()
+        {
+            return true;
+        }
+
+        if !self.reader_partial {
+            self.skipping_line = left_out(
+                line_content,
+                &self.hidden_prefixes,
+                &self.ignore_patterns,
+                self.time_window.as_mut(),
+            );
+        }
+        self.reader_partial = partial;
+
+        !self.skipping_line
+    }
+
+    // Where reading starts: at the last lines of the file if starting with them, indexing the lines
+    // before in the background, otherwise at the start.
+    fn start_backfill(&mut self) -> u64 {
+        let Some(lines) = self.tail_lines else {
+            return 0;
+        };
+        let (start, source_lines) = match self.source.tail_offset(lines) {
+            Ok(0) => return 0,
+            Ok(start) => match self.source.lines() {
+                Ok(source_lines) => (start, source_lines),
+                Err(e) => {
+                    debug!("Reading all of {:?}: {:?}", self.path, e);
+                    return 0;
                 }
+            },
+            Err(e) => {
+                debug!("Reading all of {:?}: {:?}", self.path, e);
+                return 0;
             }
-        });
+        };
+
+        debug!(
+            "Reading {:?} from {}, indexing before it in the background",
+            self.path, start
+        );
+        self.file_bytes = start;
+        let backfill = Backfill {
+            source_lines,
+            end: start,
+            max_line_bytes: self.max_line_bytes,
+            hidden_prefixes: self.hidden_prefixes.clone(),
+            ignore_patterns: self.ignore_patterns.clone(),
+            time_window: self.time_window.clone(),
+            timestamps: self.timestamps.clone(),
+        };
+        self.backfill = Some(tokio::task::spawn_blocking(move || backfill.index()));
+
+        start
+    }
+
+    // Put the lines indexed in the background before those read since, telling the clients so they
+    // can stay on the same lines.
+    async fn backfilled(&mut self, mut lines: Vec<SLine>) -> Result<()> {
+        let backfilled = lines.len();
+        debug!("Indexed {} earlier lines of {:?}", backfilled, self.path);
+        lines.append(&mut self.lines);
+        self.lines = lines;
+        self.file_lines += backfilled;
+
+        for client in self.clients.clients.values_mut() {
+            client.interested = client
+                .interested
+                .iter()
+                .map(|line_no| line_no + backfilled)
+                .collect();
+        }
+        self.tell_clients(|| IFResp::Backfilled { lines: backfilled })
+            .await?;
+        let (file_lines, file_bytes) = (self.file_lines, self.file_bytes);
+        self.tell_clients(|| IFResp::ViewUpdate {
+            update: FileResp::Stats {
+                view_lines: file_lines,
+                file_lines,
+                file_bytes,
+            },
+        })
+        .await
+    }
+
+    fn run_reader(&mut self, start: u64) -> ReaderUpdateReceiver {
+        let (reader_sender, reader_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        self.source_identity = self.source.identity();
+        self.reader_task = Some(
+            self.source
+                .follow(start, self.max_line_bytes, reader_sender),
+        );
 
         reader_receiver
     }
 
+    // Where a restarted reader carries on from: the start of the last line if it was partial, so
+    // the whole line is read again, otherwise the end of what has been read.
+    fn resume_offset(&self) -> u64 {
+        match self.lines.last() {
+            Some(sl) if self.previous_partial => sl.offset,
+            _ => self.file_bytes,
+        }
+    }
+
+    // Why the reader needs restarting, if it does: it has stopped, or the file has grown without
+    // anything being read for a whole check. Nothing is done whilst the file can't be opened.
+    fn reader_trouble(&self, stopped: bool, watched_bytes: &mut Option<u64>) -> Option<String> {
+        let size = self.source.size().ok()?;
+        if stopped {
+            return Some("the reader stopped".to_owned());
+        }
+
+        if size <= self.file_bytes {
+            *watched_bytes = None;
+            return None;
+        }
+        if *watched_bytes == Some(self.file_bytes) {
+            *watched_bytes = None;
+            return Some("the file grew without being read".to_owned());
+        }
+        *watched_bytes = Some(self.file_bytes);
+
+        None
+    }
+
+    // Start a new reader where the last one got to, telling the clients.
+    async fn restart_reader(&mut self, reason: String) -> Result<ReaderUpdateReceiver> {
+        warn!("Restarting reader for {:?}: {}", self.path, reason);
+        if let Some(reader_task) = self.reader_task.take() {
+            reader_task.abort();
+        }
+
+        // The file may have been truncated whilst nothing was reading it, or replaced, e.g. removed
+        // and created again.
+        let replaced = self.source.identity() != self.source_identity;
+        if replaced {
+            self.source.reopen(&self.path)?;
+        }
+        let size = self.source.size().unwrap_or(0);
+        if replaced || size < self.resume_offset() {
+            self.reset().await?;
+        }
+        let reader_receiver = self.run_reader(self.resume_offset());
+
+        for (id, client) in self.clients.clients.iter_mut() {
+            trace!("Sending reader restart to client: {}", id);
+            let send_result = client
+                .channel
+                .send(IFResp::ReaderRestarted {
+                    reason: reason.clone(),
+                })
+                .await;
+            if let Err(e) = &send_result {
+                trace!("Failed to send reader restart to client {}: {:?}", id, e);
+            }
+            send_result?;
+        }
+
+        Ok(reader_receiver)
+    }
+
+    // Reopen the file after a delay when the reader fails, telling the clients, or give up once the
+    // retry policy says to.
+    async fn reader_failed(&mut self, reason: String) -> Result<()> {
+        self.retry_attempts += 1;
+        let Some(delay) = self.retry_policy.delay(self.retry_attempts) else {
+            return self.give_up(reason).await;
+        };
+        warn!(
+            "Reader failed for {:?}, reopening in {:?} (attempt {}): {}",
+            self.path, delay, self.retry_attempts, reason
+        );
+        self.retry_at = Some(Instant::now() + delay);
+
+        for (id, client) in self.clients.clients.iter_mut() {
+            trace!("Sending reader retry to client: {}", id);
+            let send_result = client
+                .channel
+                .send(IFResp::ReaderRetrying {
+                    reason: reason.clone(),
+                    attempt: self.retry_attempts,
+                    attempts: self.retry_policy.attempts,
+                    delay,
+                })
+                .await;
+            if let Err(e) = &send_result {
+                trace!("Failed to send reader retry to client {}: {:?}", id, e);
+            }
+            send_result?;
+        }
+
+        Ok(())
+    }
+
+    async fn give_up(&mut self, reason: String) -> Result<()> {
+        error!("File error: {:?}", reason);
+        self.file_error = true;
+
+        for (id, updater) in self.clients.clients.iter_mut() {
+            trace!("Forwarding error to client {}: {}", id, reason);
+            updater.interested = HashSet::new();
+            let send_result = updater
+                .channel
+                .send(IFResp::FileError {
+                    reason: reason.clone(),
+                })
+                .await;
+            if let Err(e) = &send_result {
+                trace!("Failed to send error to client {}: {:?}", id, e);
+            }
+            send_result?;
+        }
+        Ok(())
+    }
+
     pub fn get_view_sender(&self) -> FileReqSender<IFResp<String>> {
         self.view_sender.clone()
     }
 
+    pub fn get_bulk_sender(&self) -> FileReqSender<IFResp<String>> {
+        self.bulk_sender.clone()
+    }
+
+    /// Handle a request waiting from the views, or from bulk clients, for stepping the file
+    /// deterministically in a simulation rather than with `run`. Returns whether there was one.
+    #[cfg(test)]
+    pub(crate) async fn step(&mut self, bulk: bool) -> Result<bool> {
+        let receiver = if bulk {
+            &mut self.bulk_receiver
+        } else {
+            &mut self.view_receiver
+        };
+        let Ok(cmd) = receiver.try_recv() else {
+            return Ok(false);
+        };
+        self.handle_client_command(cmd).await?;
+
+        Ok(true)
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         debug!("Ifile starting: {:?}", self.path);
 
-        let mut reader_receiver = self.run_reader();
+        let start = self.start_backfill();
+        let mut reader_receiver = Some(self.run_reader(start));
+
+        // Check the reader is still going, so a long running tail never silently goes stale.
+        let mut watchdog = interval(Duration::from_millis(READER_WATCHDOG_MS));
+        watchdog.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut watched_bytes = None;
 
         loop {
+            // Serve every waiting view request first, so a view isn't kept behind a queue of bulk
+            // requests.
+            while let Ok(cmd) = self.view_receiver.try_recv() {
+                trace!("Received client command: {:?}", cmd);
+                self.handle_client_command(cmd).await?;
+            }
+
             trace!("Select...");
             select! {
                 cmd = self.view_receiver.recv() => {
@@ -159,18 +654,66 @@ impl<BF: BackingFile> IFile<BF> {
                         }
                     }
                 }
-                update = reader_receiver.recv() => {
+                cmd = self.bulk_receiver.recv() => {
+                    match cmd {
+                        Some(cmd) => {
+                            trace!("Received bulk client command: {:?}", cmd);
+                            self.handle_client_command(cmd).await?;
+                        },
+                        None => {
+                            debug!("Bulk IFR closed");
+                            break;
+                        }
+                    }
+                }
+                update = next_update(&mut reader_receiver) => {
                     match update {
                         Some(update) => {
                             trace!("Received reader update: {:?}", update);
                             self.handle_reader_update(update).await?;
                         },
                         None => {
-                            debug!("Reader update channel closed");
-                            break;
+                            reader_receiver = None;
+                            // A reader that reported failing already has its retry due.
+                            if self.retry_at.is_none() && !self.file_error {
+                                warn!("Reader stopped unexpectedly: {:?}", self.path);
+                                self.reader_failed("the reader stopped".to_owned()).await?;
+                            }
+                            if self.file_error {
+                                debug!("Reader update channel closed");
+                                break;
+                            }
+                        }
+                    }
+                }
+                _ = sleep_until(self.retry_at.unwrap_or_else(Instant::now)), if self.retry_at.is_some() => {
+                    self.retry_at = None;
+                    match self.source.size() {
+                        Ok(_) => {
+                            let reason = format!("reopened after {} failures", self.retry_attempts);
+                            reader_receiver = Some(self.restart_reader(reason).await?);
+                        }
+                        Err(e) => {
+                            self.reader_failed(format!("Failed to open: {}", e)).await?;
+                            if self.file_error {
+                                break;
+                            }
                         }
                     }
                 }
+                result = next_backfill(&mut self.backfill) => {
+                    self.backfill = None;
+                    match result {
+                        Ok(Ok(lines)) => self.backfilled(lines).await?,
+                        Ok(Err(e)) => warn!("Failed to index the start of {:?}: {:?}", self.path, e),
+                        Err(e) => warn!("Indexing the start of {:?} stopped: {:?}", self.path, e),
+                    }
+                }
+                _ = watchdog.tick(), if self.retry_at.is_none() => {
+                    if let Some(reason) = self.reader_trouble(reader_receiver.is_none(), &mut watched_bytes) {
+                        reader_receiver = Some(self.restart_reader(reason).await?);
+                    }
+                }
             }
         }
 
@@ -182,38 +725,60 @@ impl<BF: BackingFile> IFile<BF> {
     /// Handle an update from the reader.
     ///
     /// Returns boolean indicating if the file should be closed
-    async fn handle_reader_update(&mut self, update: ReaderUpdate) -> Result<()> {
+    pub(crate) async fn handle_reader_update(&mut self, update: ReaderUpdate) -> Result<()> {
         match update {
             ReaderUpdate::Line {
-                line_content,
+                mut line_content,
                 offset,
                 line_bytes,
                 partial,
                 file_bytes,
             } => {
+                // The reader is working again, so later failures are counted afresh.
+                self.retry_attempts = 0;
+
+                // Only the start of a gigantic line is passed on, or used for its timestamp. The
+                // reader sends only a little over the cap of it.
+                if let Some(max_line_bytes) =
+                    self.max_line_bytes.filter(|max| line_content.len() > *max)
+                {
+                    cut_line(&mut line_content, max_line_bytes, line_bytes);
+                    debug!("Capped a {} byte line @{}", line_bytes, offset);
+                }
+
+                if !self.admit_line(&line_content, partial) {
+                    trace!("Skipping line left out @{}", offset);
+                    self.file_bytes = file_bytes;
+                    return Ok(());
+                }
+
                 let line_chars = line_content.len();
 
                 let file_line_updated = if self.previous_partial {
                     // We know updated_line_no >= 1, as we cannot have a previous_partial before
                     // the first line comes in.
                     let file_line_updated = self.file_lines - 1;
+                    let timestamp = self.line_timestamp(file_line_updated, &line_content);
                     self.lines[file_line_updated] = SLine {
                         offset,
                         _line_no: file_line_updated,
                         _line_chars: line_content.len(),
-                        _line_bytes: line_bytes,
+                        line_bytes,
                         partial,
+                        timestamp,
                     };
 
                     file_line_updated
                 } else {
                     let file_line_updated = self.file_lines;
+                    let timestamp = self.line_timestamp(file_line_updated, &line_content);
                     self.lines.push(SLine {
                         offset,
                         _line_no: file_line_updated,
                         _line_chars: line_content.len(),
-                        _line_bytes: line_bytes,
+                        line_bytes,
                         partial,
+                        timestamp,
                     });
                     self.file_lines += 1;
 
@@ -281,43 +846,89 @@ impl<BF: BackingFile> IFile<BF> {
                 }
                 Ok(())
             }
+            ReaderUpdate::Relinked { target } => {
+                debug!("Link re-pointed to {:?}... resetting ifile", target);
+                self.source.reopen(&target)?;
+                self.source_identity = self.source.identity();
+                self.reset().await
+            }
+            ReaderUpdate::Rotated { target } => {
+                debug!("File replaced by {:?}... resetting ifile", target);
+                self.source.reopen(&target)?;
+                self.source_identity = self.source.identity();
+                self.reset().await?;
+                self.tell_clients(|| IFResp::Rotated).await
+            }
             ReaderUpdate::Truncated => {
                 trace!("File truncated... resetting ifile");
-                self.file_lines = 0;
-                self.lines = vec![];
-                self.file_bytes = 0;
-
-                for (id, client) in self.clients.clients.iter_mut() {
-                    trace!("Sending truncate to client: {}", id);
-                    client.interested = HashSet::new();
-                    let send_result = client.channel.send(IFResp::Truncated).await;
-                    if let Err(e) = &send_result {
-                        trace!("Failed to send truncate to client {}: {:?}", id, e);
-                    }
-                    send_result?;
+                self.reset().await
+            }
+            ReaderUpdate::FileError { reason } => self.reader_failed(reason).await,
+            ReaderUpdate::Failed { reason } => self.give_up(reason).await,
+            ReaderUpdate::Waiting => {
+                debug!("Waiting for {:?} to be created", self.path);
+                self.waiting = true;
+                self.tell_clients(|| IFResp::Waiting).await
+            }
+            ReaderUpdate::Created => {
+                debug!("File {:?} created... opening it", self.path);
+                self.waiting = false;
+                self.source.reopen(&self.path)?;
+                self.source_identity = self.source.identity();
+                self.tell_clients(|| IFResp::Created).await
+            }
+            ReaderUpdate::Spooled => {
+                // Only the first time matters, not when a restarted reader catches up.
+                if self.spooled {
+                    return Ok(());
                 }
-                Ok(())
+                debug!("Read {} lines of {:?}", self.file_lines, self.path);
+                self.spooled = true;
+                self.tell_clients(|| IFResp::Spooled).await
             }
-            ReaderUpdate::FileError { reason } => {
-                error!("File error: {:?}", reason);
+        }
+    }
 
-                for (id, updater) in self.clients.clients.iter_mut() {
-                    trace!("Forwarding error to client {}: {}", id, reason);
-                    updater.interested = HashSet::new();
-                    let send_result = updater
-                        .channel
-                        .send(IFResp::FileError {
-                            reason: reason.clone(),
-                        })
-                        .await;
-                    if let Err(e) = &send_result {
-                        trace!("Failed to send error to client {}: {:?}", id, e);
-                    }
-                    send_result?;
-                }
-                Ok(())
+    async fn tell_clients(&mut self, resp: impl Fn() -> IFResp<String>) -> Result<()> {
+        for (id, client) in self.clients.clients.iter_mut() {
+            let resp = resp();
+            trace!("Sending {:?} to client: {}", resp, id);
+            let send_result = client.channel.send(resp).await;
+            if let Err(e) = &send_result {
+                trace!("Failed to send to client {}: {:?}", id, e);
+            }
+            send_result?;
+        }
+        Ok(())
+    }
+
+    // Forget the lines read, as the file has been truncated or replaced, and tell the clients.
+    async fn reset(&mut self) -> Result<()> {
+        // The lines before aren't wanted now everything is read again.
+        if let Some(backfill) = self.backfill.take() {
+            backfill.abort();
+        }
+        self.file_lines = 0;
+        self.lines = vec![];
+        self.file_bytes = 0;
+        self.previous_partial = false;
+
+        self.skipping_line = false;
+        self.reader_partial = false;
+        if let Some(time_window) = &mut self.time_window {
+            time_window.reset();
+        }
+
+        for (id, client) in self.clients.clients.iter_mut() {
+            trace!("Sending truncate to client: {}", id);
+            client.interested = HashSet::new();
+            let send_result = client.channel.send(IFResp::Truncated).await;
+            if let Err(e) = &send_result {
+                trace!("Failed to send truncate to client {}: {:?}", id, e);
             }
+            send_result?;
         }
+        Ok(())
     }
 
     async fn handle_client_command(&mut self, cmd: FileReq<IFResp<String>>) -> Result<()> {
@@ -325,26 +936,36 @@ impl<BF: BackingFile> IFile<BF> {
             FileReq::GetLine { id, line_no } => {
                 trace!("Client {} requested line {}", id, line_no);
 
-                let clients = &mut self.clients;
-                let Some(client) = clients.clients.get_mut(&id) else {
+                if !self.clients.clients.contains_key(&id) {
                     warn!("Unknown client, ignoring request: {}", id);
                     return Ok(());
+                }
+
+                // Read the line before borrowing the client.
+                let line = match self
+                    .lines
+                    .get(line_no)
+                    .map(|sl| ((sl.offset, sl.line_bytes), sl.partial))
+                {
+                    Some((sl, partial)) => Some((self.read_capped_line(sl)?, partial)),
+                    None => None,
                 };
+                let client = self
+                    .clients
+                    .clients
+                    .get_mut(&id)
+                    .expect("Client checked above");
 
-                let sl = self.lines.get_mut(line_no);
-                match sl {
+                match line {
                     None => {
                         trace!("Registering interest in: {} / {:?}", id, line_no);
                         client.interested.insert(line_no);
                         Ok(())
                     }
-                    Some(sl) => {
-                        let backing_file = &mut self.backing_file;
-                        let line_content = backing_file.read_line(Some(sl.offset as u64))?.clone();
-
+                    Some((line_content, partial)) => {
                         trace!(
                             "Sending requested line to client {}: line_no={}, partial={}, content_len={}",
-                            id, line_no, sl.partial, line_content.len()
+                            id, line_no, partial, line_content.len()
                         );
                         let send_result = client
                             .channel
@@ -352,7 +973,7 @@ impl<BF: BackingFile> IFile<BF> {
                                 update: FileResp::Line {
                                     line_no,
                                     line_content,
-                                    partial: sl.partial,
+                                    partial,
                                 },
                             })
                             .await;
@@ -364,6 +985,89 @@ impl<BF: BackingFile> IFile<BF> {
                     }
                 }
             }
+            FileReq::GetLines { line_nos, resp } => {
+                trace!("Bulk request for {} lines", line_nos.len());
+
+                let mut lines = Vec::with_capacity(line_nos.len());
+                for line_no in line_nos {
+                    let Some(sl) = self.lines.get(line_no).map(|sl| (sl.offset, sl.line_bytes))
+                    else {
+                        continue;
+                    };
+
+                    let line_content = self.read_capped_line(sl)?;
+                    lines.push((line_no, line_content));
+                }
+
+                if resp.send(lines).is_err() {
+                    trace!("Bulk line requester went away");
+                }
+                Ok(())
+            }
+            FileReq::GetFullLine { line_no, resp } => {
+                trace!("Full line request: {}", line_no);
+
+                let mut lines = Vec::new();
+                if let Some(sl) = self.lines.get(line_no) {
+                    lines.push((line_no, self.source.read_line(Some(sl.offset))?));
+                }
+
+                if resp.send(lines).is_err() {
+                    trace!("Full line requester went away");
+                }
+                Ok(())
+            }
+            FileReq::GetLinesAt { offsets, resp } => {
+                trace!("Request for lines at {} offsets", offsets.len());
+
+                let mut lines = Vec::new();
+                for offset in offsets {
+                    let line_no = self.lines.partition_point(|sl| sl.offset < offset);
+                    match self.lines.get(line_no) {
+                        Some(sl) if sl.offset == offset && !sl.partial => {
+                            let sl = (sl.offset, sl.line_bytes);
+                            lines.push((offset, line_no, self.read_capped_line(sl)?));
+                        }
+                        _ => {}
+                    }
+                }
+
+                if resp.send(lines).is_err() {
+                    trace!("Lines at offsets requester went away");
+                }
+                Ok(())
+            }
+            FileReq::LineOffset { line_no, resp } => {
+                let offset = self
+                    .lines
+                    .get(line_no)
+                    .filter(|sl| !sl.partial)
+                    .map(|sl| sl.offset);
+                trace!("Offset of line {}: {:?}", line_no, offset);
+
+                if resp.send(offset).is_err() {
+                    trace!("Line offset requester went away");
+                }
+                Ok(())
+            }
+            FileReq::FindTime { time, resp } => {
+                let line_no = self.find_time(time);
+                trace!("Find time {}: {:?}", time, line_no);
+
+                if resp.send(line_no).is_err() {
+                    trace!("Time search requester went away");
+                }
+                Ok(())
+            }
+            FileReq::TimeFraction { line_no, resp } => {
+                let fraction = self.time_fraction(line_no);
+                trace!("Time fraction of line {}: {:?}", line_no, fraction);
+
+                if resp.send(fraction).is_err() {
+                    trace!("Time fraction requester went away");
+                }
+                Ok(())
+            }
             FileReq::CancelLine { id, line_no } => {
                 trace!("Cancel line: {} / {:?}", id, line_no);
                 let Some(client) = self.clients.clients.get_mut(&id) else {
@@ -407,6 +1111,12 @@ impl<BF: BackingFile> IFile<BF> {
                     trace!("Failed to send initial stats to client {}: {:?}", id, e);
                 }
                 send_result?;
+                if self.waiting {
+                    client_sender.send(IFResp::Waiting).await?;
+                }
+                if self.spooled {
+                    client_sender.send(IFResp::Spooled).await?;
+                }
                 Ok(())
             }
             FileReq::EnableTailing { id, last_seen_line } => {
@@ -418,6 +1128,7 @@ impl<BF: BackingFile> IFile<BF> {
                 };
 
                 client.tailing = true;
+                let channel = client.channel.clone();
 
                 // Determine which lines the client will not know about.
                 let missing_lines_count = self.file_lines.saturating_sub(last_seen_line);
@@ -429,29 +1140,30 @@ impl<BF: BackingFile> IFile<BF> {
                     self.file_lines
                 );
                 for i in last_seen_line..self.file_lines {
-                    let sl = self.lines.get(i);
-                    let Some(l) = sl else {
+                    let Some((sl, partial)) = self
+                        .lines
+                        .get(i)
+                        .map(|sl| ((sl.offset, sl.line_bytes), sl.partial))
+                    else {
                         warn!("Unknown line whilst sending missing tailing lines: {}", i);
                         continue;
                     };
 
-                    let backing_file = &mut self.backing_file;
-                    let line_content = backing_file.read_line(Some(l.offset as u64))?.clone();
+                    let line_content = self.read_capped_line(sl)?;
 
                     trace!(
                         "Sending missing line to client {}: line_no={}, partial={}, content_len={}",
                         id,
                         i,
-                        l.partial,
+                        partial,
                         line_content.len()
                     );
-                    let send_result = client
-                        .channel
+                    let send_result = channel
                         .send(IFResp::ViewUpdate {
                             update: FileResp::Line {
                                 line_no: i,
                                 line_content,
-                                partial: l.partial,
+                                partial,
                             },
                         })
                         .await;
@@ -477,13 +1189,112 @@ impl<BF: BackingFile> IFile<BF> {
     }
 }
 
+// The next update from the reader, waiting forever once it has stopped until it is restarted.
+async fn next_update(reader_receiver: &mut Option<ReaderUpdateReceiver>) -> Option<ReaderUpdate> {
+    match reader_receiver {
+        Some(reader_receiver) => reader_receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn next_backfill(
+    backfill: &mut Option<JoinHandle<Result<Vec<SLine>>>>,
+) -> Result<Result<Vec<SLine>>, JoinError> {
+    match backfill {
+        Some(backfill) => backfill.await,
+        None => std::future::pending().await,
+    }
+}
+
+// Whether a line is left out: a hidden merged file's, noise, or outside the time window.
+fn left_out(
+    line: &str,
+    hidden_prefixes: &[String],
+    ignore_patterns: &[Regex],
+    time_window: Option<&mut TimeWindow>,
+) -> bool {
+    hidden_prefixes
+        .iter()
+        .any(|prefix| line.starts_with(prefix.as_str()))
+        || ignore_patterns.iter().any(|regex| regex.is_match(line))
+        || time_window.is_some_and(|time_window| !time_window.admits(line))
+}
+
+// The lines of a file before where reading started, when starting with its last lines, indexed in
+// the background as they would have been read.
+struct Backfill {
+    source_lines: SourceLines,
+    end: u64,
+    max_line_bytes: Option<usize>,
+    hidden_prefixes: Vec<String>,
+    ignore_patterns: Vec<Regex>,
+    time_window: Option<TimeWindow>,
+    timestamps: Option<TimestampParser>,
+}
+
+impl Backfill {
+    fn index(mut self) -> Result<Vec<SLine>> {
+        if let Some(time_window) = &mut self.time_window {
+            time_window.reset();
+        }
+
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        let mut timestamp = None;
+        while offset < self.end {
+            let Some(read) = self.source_lines.next() else {
+                break;
+            };
+            let (mut line, line_bytes) = read?;
+            let line_offset = offset;
+            offset += line_bytes as u64;
+
+            if let Some(max_line_bytes) = self.max_line_bytes {
+                cap_line(&mut line, max_line_bytes);
+            }
+            if left_out(
+                &line,
+                &self.hidden_prefixes,
+                &self.ignore_patterns,
+                self.time_window.as_mut(),
+            ) {
+                continue;
+            }
+            if let Some(ts) = self.timestamps.as_ref().and_then(|t| t.find(&line)) {
+                timestamp = Some(ts.time);
+            }
+
+            lines.push(SLine {
+                offset: line_offset,
+                _line_no: lines.len(),
+                _line_chars: line.len(),
+                line_bytes,
+                partial: false,
+                timestamp,
+            });
+        }
+
+        Ok(lines)
+    }
+}
+
+// Stop reading when the file is closed.
+impl<S: InputSource> Drop for IFile<S> {
+    fn drop(&mut self) {
+        if let Some(reader_task) = &self.reader_task {
+            reader_task.abort();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     //use mockall::{mock, predicate::*};
 
     use super::*;
-    use crate::backing_file::MockBackingFile;
+    use crate::input_source::MockInputSource;
     use flexi_logger::{detailed_format, FileSpec};
+    use std::path::Path;
     use tokio::sync::mpsc::Receiver;
 
     fn init_test_logging() {
@@ -502,8 +1313,8 @@ mod tests {
         let line0 = "FirstFullLine".to_owned();
         let line0_len = line0.len();
 
-        let mut backing_file = MockBackingFile::new();
-        backing_file
+        let mut source = MockInputSource::new();
+        source
             .expect_read_line()
             .with(mockall::predicate::eq(Some(0u64)))
             .times(1)
@@ -512,7 +1323,7 @@ mod tests {
                 move |_| Ok(line0.clone())
             });
 
-        let mut ifile = IFile::new("test", backing_file);
+        let mut ifile = IFile::new("test", source);
 
         let client_id = "test_client".to_owned();
         let (client_sender, mut client_receiver) = mpsc::channel(CHANNEL_BUFFER);
@@ -607,6 +1418,354 @@ mod tests {
         // The mock expectations will be verified automatically when the mock is dropped
     }
 
+    #[tokio::test]
+    async fn test_ifile_find_time() {
+        let lines = [
+            "starting up",
+            "2024-03-01T10:00:00Z first",
+            "2024-03-01T10:05:00Z second",
+            "  at stack.trace()",
+            "2024-03-01T10:10:00Z third",
+        ];
+
+        let mut ifile =
+            IFile::new("test", MockInputSource::new()).with_timestamps(TimestampParser::default());
+        let mut offset = 0;
+        for line in lines {
+            offset += line.len() as u64;
+            ifile
+                .handle_reader_update(ReaderUpdate::Line {
+                    line_content: line.to_owned(),
+                    offset,
+                    line_bytes: line.len(),
+                    partial: false,
+                    file_bytes: offset,
+                })
+                .await
+                .unwrap();
+        }
+
+        let find = |time: &str| ifile.find_time(DateTime::parse_from_rfc3339(time).unwrap());
+        assert_eq!(find("2024-03-01T09:00:00Z"), Some(1));
+        assert_eq!(find("2024-03-01T10:00:00Z"), Some(1));
+        assert_eq!(find("2024-03-01T10:01:00Z"), Some(2));
+        // The stack trace line takes the time before it.
+        assert_eq!(find("2024-03-01T10:06:00Z"), Some(4));
+        assert_eq!(find("2024-03-01T11:00:00Z"), None);
+
+        // Ten minutes from the first timestamp to the last.
+        assert_eq!(ifile.time_fraction(0), Some(0.0));
+        assert_eq!(ifile.time_fraction(1), Some(0.0));
+        assert_eq!(ifile.time_fraction(2), Some(0.5));
+        assert_eq!(ifile.time_fraction(3), Some(0.5));
+        assert_eq!(ifile.time_fraction(4), Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_ifile_resume_offset() {
+        let mut ifile = IFile::new("test", MockInputSource::new());
+        let update = |line: &str, offset: u64, partial: bool| ReaderUpdate::Line {
+            line_content: line.to_owned(),
+            offset,
+            line_bytes: line.len(),
+            partial,
+            file_bytes: offset + line.len() as u64,
+        };
+
+        assert_eq!(ifile.resume_offset(), 0);
+        ifile
+            .handle_reader_update(update("first\n", 0, false))
+            .await
+            .unwrap();
+        assert_eq!(ifile.resume_offset(), 6);
+
+        // A partial line is read again from its start.
+        ifile
+            .handle_reader_update(update("seco", 6, true))
+            .await
+            .unwrap();
+        assert_eq!(ifile.resume_offset(), 6);
+        ifile
+            .handle_reader_update(update("second\n", 6, false))
+            .await
+            .unwrap();
+        assert_eq!(ifile.resume_offset(), 13);
+        assert_eq!(ifile.file_lines, 2);
+    }
+
+    #[tokio::test]
+    async fn test_ifile_ignore_patterns() {
+        let mut ifile = IFile::new("test", MockInputSource::new())
+            .with_ignore_patterns(vec![Regex::new(r"GET /health\b").unwrap()]);
+        let mut offset = 0;
+        for line in [
+            "GET /health 200\n",
+            "ERROR boom\n",
+            "GET /health 200\n",
+            "GET /healthy 200\n",
+        ] {
+            ifile
+                .handle_reader_update(ReaderUpdate::Line {
+                    line_content: line.to_owned(),
+                    offset,
+                    line_bytes: line.len(),
+                    partial: false,
+                    file_bytes: offset + line.len() as u64,
+                })
+                .await
+                .unwrap();
+            offset += line.len() as u64;
+        }
+
+        // Only the lines kept are numbered, but the whole file is read.
+        assert_eq!(ifile.file_lines, 2);
+        assert_eq!(ifile.lines[0].offset, 16);
+        assert_eq!(ifile.lines[1].offset, 43);
+        assert_eq!(ifile.file_bytes, offset);
+    }
+
+    #[tokio::test]
+    async fn test_ifile_tailing_capped() {
+        let mut source = MockInputSource::new();
+        source
+            .expect_read_line_start()
+            .with(
+                mockall::predicate::eq(0u64),
+                mockall::predicate::eq(10usize),
+            )
+            .times(1)
+            .returning(|_, _| Ok(("0123456789".to_owned(), true)));
+        let mut ifile = IFile::new("test", source).with_max_line_bytes(10);
+
+        // The reader sends only a little over the cap of a long line.
+        ifile
+            .handle_reader_update(ReaderUpdate::Line {
+                line_content: "0123456789a".to_owned(),
+                offset: 0,
+                line_bytes: 2000,
+                partial: false,
+                file_bytes: 2000,
+            })
+            .await
+            .unwrap();
+        let mut capped = "0123456789".to_owned();
+        cut_line(&mut capped, 10, 2000);
+
+        // A client starting to tail is sent the missing line capped, as when it asks for it.
+        let client_id = "test_client".to_owned();
+        let (client_sender, mut client_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        for req in [
+            FileReq::RegisterClient {
+                id: client_id.clone(),
+                client_sender,
+            },
+            FileReq::EnableTailing {
+                id: client_id.clone(),
+                last_seen_line: 0,
+            },
+        ] {
+            ifile.handle_client_command(req).await.unwrap();
+        }
+        let mut lines = Vec::new();
+        while let Ok(resp) = client_receiver.try_recv() {
+            if let IFResp::ViewUpdate {
+                update: FileResp::Line { line_content, .. },
+            } = resp
+            {
+                lines.push(line_content);
+            }
+        }
+        assert_eq!(lines, vec![capped]);
+    }
+
+    #[tokio::test]
+    async fn test_ifile_reader_retry() {
+        let mut ifile = IFile::new("test", MockInputSource::new()).with_retry_policy(RetryPolicy {
+            delay_ms: 100,
+            max_delay_ms: 1000,
+            attempts: 2,
+        });
+        let (client_sender, mut client_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        ifile
+            .handle_client_command(FileReq::RegisterClient {
+                id: "test_client".to_owned(),
+                client_sender,
+            })
+            .await
+            .unwrap();
+        check_viewupdate_fileresp_stats(&mut client_receiver, Some(0), Some(0), "Registered");
+
+        let failed = || ReaderUpdate::FileError {
+            reason: "Permission denied".to_owned(),
+        };
+        for expected_delay in [100, 200] {
+            ifile.handle_reader_update(failed()).await.unwrap();
+            match client_receiver.try_recv() {
+                Ok(IFResp::ReaderRetrying {
+                    attempt,
+                    attempts: 2,
+                    delay,
+                    ..
+                }) => {
+                    assert_eq!(delay, Duration::from_millis(expected_delay));
+                    assert_eq!(attempt, ifile.retry_attempts);
+                }
+                other => panic!("Expected a retry: {:?}", other),
+            }
+            assert!(ifile.retry_at.is_some());
+            assert!(!ifile.file_error);
+        }
+
+        // Reading again resets the count.
+        ifile
+            .handle_reader_update(ReaderUpdate::Line {
+                line_content: "back\n".to_owned(),
+                offset: 0,
+                line_bytes: 5,
+                partial: false,
+                file_bytes: 5,
+            })
+            .await
+            .unwrap();
+        assert_eq!(ifile.retry_attempts, 0);
+
+        for _ in 0..3 {
+            ifile.handle_reader_update(failed()).await.unwrap();
+        }
+        assert!(ifile.file_error);
+        let last = std::iter::from_fn(|| client_receiver.try_recv().ok()).last();
+        assert!(matches!(last, Some(IFResp::FileError { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_ifile_rotated() {
+        let mut source = MockInputSource::new();
+        source
+            .expect_reopen()
+            .withf(|path| path == Path::new("/logs/app.log"))
+            .times(1)
+            .returning(|_| Ok(()));
+        source.expect_identity().returning(|| Some((1, 2)));
+        let mut ifile = IFile::new("test", source);
+        let (client_sender, mut client_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        ifile
+            .handle_client_command(FileReq::RegisterClient {
+                id: "test_client".to_owned(),
+                client_sender,
+            })
+            .await
+            .unwrap();
+        check_viewupdate_fileresp_stats(&mut client_receiver, Some(0), Some(0), "Registered");
+
+        ifile
+            .handle_reader_update(ReaderUpdate::Line {
+                line_content: "old\n".to_owned(),
+                offset: 0,
+                line_bytes: 4,
+                partial: false,
+                file_bytes: 4,
+            })
+            .await
+            .unwrap();
+        assert_eq!(ifile.file_lines, 1);
+
+        // The new file is read from the start, after telling the clients.
+        ifile
+            .handle_reader_update(ReaderUpdate::Rotated {
+                target: PathBuf::from("/logs/app.log"),
+            })
+            .await
+            .unwrap();
+        assert_eq!(ifile.file_lines, 0);
+        assert_eq!(ifile.file_bytes, 0);
+        let resps = std::iter::from_fn(|| client_receiver.try_recv().ok())
+            .filter(|resp| !matches!(resp, IFResp::ViewUpdate { .. }))
+            .collect::<Vec<_>>();
+        assert!(matches!(resps[..], [IFResp::Truncated, IFResp::Rotated]));
+    }
+
+    #[tokio::test]
+    async fn test_ifile_waiting() {
+        let mut source = MockInputSource::new();
+        source
+            .expect_reopen()
+            .withf(|path| path == Path::new("test"))
+            .times(1)
+            .returning(|_| Ok(()));
+        source.expect_identity().returning(|| Some((1, 2)));
+        let mut ifile = IFile::new("test", source);
+        ifile
+            .handle_reader_update(ReaderUpdate::Waiting)
+            .await
+            .unwrap();
+
+        // Clients registering whilst waiting are told.
+        let (client_sender, mut client_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        ifile
+            .handle_client_command(FileReq::RegisterClient {
+                id: "test_client".to_owned(),
+                client_sender,
+            })
+            .await
+            .unwrap();
+        check_viewupdate_fileresp_stats(&mut client_receiver, Some(0), Some(0), "Registered");
+        assert!(matches!(client_receiver.try_recv(), Ok(IFResp::Waiting)));
+
+        ifile
+            .handle_reader_update(ReaderUpdate::Created)
+            .await
+            .unwrap();
+        assert!(!ifile.waiting);
+        assert!(matches!(client_receiver.try_recv(), Ok(IFResp::Created)));
+    }
+
+    #[tokio::test]
+    async fn test_ifile_backfilled() {
+        let mut ifile = IFile::new("test", MockInputSource::new());
+        let (client_sender, mut client_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        ifile
+            .handle_client_command(FileReq::RegisterClient {
+                id: "test_client".to_owned(),
+                client_sender,
+            })
+            .await
+            .unwrap();
+        check_viewupdate_fileresp_stats(&mut client_receiver, Some(0), Some(0), "Registered");
+
+        // Read from the last line, then the two before are indexed.
+        ifile
+            .handle_reader_update(ReaderUpdate::Line {
+                line_content: "last".to_owned(),
+                offset: 8,
+                line_bytes: 5,
+                partial: false,
+                file_bytes: 13,
+            })
+            .await
+            .unwrap();
+        check_viewupdate_fileresp_stats(&mut client_receiver, Some(1), Some(13), "Last line");
+        let earlier = (0..2)
+            .map(|line_no| SLine {
+                offset: line_no as u64 * 4,
+                _line_no: line_no,
+                _line_chars: 3,
+                line_bytes: 4,
+                partial: false,
+                timestamp: None,
+            })
+            .collect();
+        ifile.backfilled(earlier).await.unwrap();
+
+        assert_eq!(ifile.file_lines, 3);
+        let offsets = ifile.lines.iter().map(|sl| sl.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 4, 8]);
+        assert!(matches!(
+            client_receiver.try_recv(),
+            Ok(IFResp::Backfilled { lines: 2 })
+        ));
+        check_viewupdate_fileresp_stats(&mut client_receiver, Some(3), Some(13), "Backfilled");
+    }
+
     fn check_viewupdate_fileresp_line(
         client_receiver: &mut Receiver<IFResp<String>>,
         expected_line_no: Option<usize>,
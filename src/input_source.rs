@@ -0,0 +1,588 @@
+use std::fs::{self, File};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, error, info, warn};
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+
+#[cfg(test)]
+use mockall::automock;
+
+use crate::backing_file::{BackingFile, Compression, Decompressor, FileBackingFile, LineDelimiter};
+use crate::common::CHANNEL_BUFFER;
+use crate::reader::{tail_offset, Reader, ReaderOptions, ReaderUpdate, ReaderUpdateSender};
+use crate::spool::{create_spool_file, SpoolDone};
+
+// How often a spool file read to the end is checked for its stream having ended.
+const SPOOL_DONE_POLL_MS: u64 = 100;
+
+// How often a decompressing tool is checked for having finished.
+const DECOMPRESS_POLL_MS: u64 = 100;
+
+// Numbers the files compressed files are decompressed to, as several can be open at once.
+static DECOMPRESSED: AtomicU64 = AtomicU64::new(0);
+
+/// The lines of a source read through from the start, each with the bytes it took, delimiter
+/// included.
+pub type SourceLines = Box<dyn Iterator<Item = Result<(String, usize)>> + Send>;
+
+/// Where an IFile's lines come from. A source is read at random, at the offsets lines start at, and
+/// followed by a task sending the lines read and what happens to the source, such as truncation
+/// and rotation, as reader updates. Anything implementing this can be viewed, without the IFile
+/// knowing what it is.
+#[cfg_attr(test, automock)]
+pub trait InputSource {
+    /// The line starting at the offset, or the next line if none is given.
+    fn read_line(&mut self, offset: Option<u64>) -> Result<String>;
+
+    /// The start of the line at the offset, no more than so many bytes of it, and whether it was
+    /// cut short, e.g. for a line too long to read whole each time it is shown.
+    fn read_line_start(&mut self, offset: u64, max_bytes: usize) -> Result<(String, bool)>;
+
+    /// Read from the start again, from the path given, e.g. once rotated or created.
+    fn reopen(&mut self, path: &Path) -> Result<()>;
+
+    /// Read from the start offset to the end, then follow the source, sending what is read and
+    /// what happens to it until the task is aborted. Given a cap, only the start of longer lines is
+    /// sent.
+    fn follow(
+        &self,
+        start: u64,
+        max_line_bytes: Option<usize>,
+        sender: ReaderUpdateSender,
+    ) -> JoinHandle<()>;
+
+    /// How many bytes there are to read, failing if the source can't be opened.
+    fn size(&self) -> Result<u64>;
+
+    /// What is being read now, to tell when it has been replaced, e.g. a file's device and inode.
+    fn identity(&self) -> Option<(u64, u64)>;
+
+    /// Where the last lines start, the start if there aren't that many.
+    fn tail_offset(&self, lines: usize) -> Result<u64>;
+
+    /// Read the lines again from the start, e.g. to index those before the last in the background.
+    fn lines(&self) -> Result<SourceLines>;
+}
+
+/// Read a backing file's lines through, as a source's lines.
+pub fn read_through<B: BackingFile + Send + 'static>(file: B) -> SourceLines {
+    Box::new(ReadThrough { file })
+}
+
+struct ReadThrough<B> {
+    file: B,
+}
+
+impl<B: BackingFile> Iterator for ReadThrough<B> {
+    type Item = Result<(String, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        match self.file.incremental_read(&mut line) {
+            Ok((0, _)) => None,
+            Ok((bytes, _)) => Some(Ok((line, bytes))),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// A file on disk, followed by a Reader.
+#[derive(Debug)]
+pub struct FileSource {
+    path: PathBuf,
+    file: FileBackingFile,
+    delimiter: LineDelimiter,
+    // Read the file a symlink points to now, when it is re-pointed.
+    follow_link: bool,
+    // Read the file with the name now, when the one read is moved away or removed.
+    follow_name: bool,
+    // Wait for the file to be created if it doesn't exist yet, rather than failing.
+    wait_for_file: bool,
+}
+
+impl FileSource {
+    pub fn new(path: &str) -> Result<Self> {
+        Ok(Self::with_file(path, FileBackingFile::new_from_path(path)?))
+    }
+
+    /// A file that doesn't exist yet, read once it has been created.
+    pub fn pending(path: &str) -> Self {
+        Self::with_file(path, FileBackingFile::pending())
+    }
+
+    fn with_file(path: &str, file: FileBackingFile) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            file,
+            delimiter: LineDelimiter::Newline,
+            follow_link: false,
+            follow_name: false,
+            wait_for_file: false,
+        }
+    }
+
+    pub fn with_delimiter(mut self, delimiter: LineDelimiter) -> Self {
+        self.file = self.file.with_delimiter(delimiter.clone());
+        self.delimiter = delimiter;
+
+        self
+    }
+
+    pub fn with_follow_link(mut self) -> Self {
+        self.follow_link = true;
+
+        self
+    }
+
+    pub fn with_follow_name(mut self) -> Self {
+        self.follow_name = true;
+
+        self
+    }
+
+    pub fn with_wait_for_file(mut self) -> Self {
+        self.wait_for_file = true;
+
+        self
+    }
+}
+
+impl InputSource for FileSource {
+    fn read_line(&mut self, offset: Option<u64>) -> Result<String> {
+        self.file.read_line(offset)
+    }
+
+    fn read_line_start(&mut self, offset: u64, max_bytes: usize) -> Result<(String, bool)> {
+        self.file.read_line_start(offset, max_bytes)
+    }
+
+    fn reopen(&mut self, path: &Path) -> Result<()> {
+        self.file.reopen(path)
+    }
+
+    fn follow(
+        &self,
+        start: u64,
+        max_line_bytes: Option<usize>,
+        sender: ReaderUpdateSender,
+    ) -> JoinHandle<()> {
+        let path = self.path.clone();
+        let options = ReaderOptions {
+            follow_link: self.follow_link,
+            follow_name: self.follow_name,
+            wait_for_file: self.wait_for_file,
+            delimiter: self.delimiter.clone(),
+            max_line_bytes,
+        };
+        tokio::spawn(async move {
+            match Reader::run(path, options, start, sender).await {
+                Err(err) => {
+                    error!("Reader failed: {:?}", err);
+                }
+                Ok(_) => {
+                    info!("Reader finished normally");
+                }
+            }
+        })
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(File::open(&self.path)?.metadata()?.len())
+    }
+
+    fn identity(&self) -> Option<(u64, u64)> {
+        fs::metadata(&self.path).ok().map(|m| (m.dev(), m.ino()))
+    }
+
+    fn tail_offset(&self, lines: usize) -> Result<u64> {
+        tail_offset(&self.path, &self.delimiter, lines)
+    }
+
+    fn lines(&self) -> Result<SourceLines> {
+        Ok(read_through(
+            FileBackingFile::new(&self.path)?.with_delimiter(self.delimiter.clone()),
+        ))
+    }
+}
+
+/// A stream copied to a spool file as it arrives, e.g. piped input. It is read as the file is, but
+/// only spooled once the stream has ended and the file has been read to the end, not each time
+/// reading catches up with the stream. If the spool failed, that is reported once what it did copy
+/// has been read.
+#[derive(Debug)]
+pub struct SpoolSource {
+    file: FileSource,
+    done: SpoolDone,
+}
+
+impl SpoolSource {
+    pub fn new(file: FileSource, done: SpoolDone) -> Self {
+        Self { file, done }
+    }
+}
+
+impl InputSource for SpoolSource {
+    fn read_line(&mut self, offset: Option<u64>) -> Result<String> {
+        self.file.read_line(offset)
+    }
+
+    fn read_line_start(&mut self, offset: u64, max_bytes: usize) -> Result<(String, bool)> {
+        self.file.read_line_start(offset, max_bytes)
+    }
+
+    fn reopen(&mut self, path: &Path) -> Result<()> {
+        self.file.reopen(path)
+    }
+
+    fn follow(
+        &self,
+        start: u64,
+        max_line_bytes: Option<usize>,
+        sender: ReaderUpdateSender,
+    ) -> JoinHandle<()> {
+        let (reader_sender, mut reader_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        let reader = AbortOnDrop(self.file.follow(start, max_line_bytes, reader_sender));
+        let path = self.file.path.clone();
+        let done = self.done.clone();
+        tokio::spawn(async move {
+            let _reader = reader;
+            let mut read = start;
+            let mut spooled = false;
+            let mut check = interval(Duration::from_millis(SPOOL_DONE_POLL_MS));
+            loop {
+                let update = select! {
+                    update = reader_receiver.recv() => match update {
+                        Some(update) => update,
+                        None => break,
+                    },
+                    _ = check.tick(), if !spooled => {
+                        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                        if !done.is_done() || read < size {
+                            continue;
+                        }
+                        spooled = true;
+                        match done.failure() {
+                            Some(reason) => ReaderUpdate::Failed { reason },
+                            None => {
+                                debug!("Spooled all of {:?}", path);
+                                ReaderUpdate::Spooled
+                            }
+                        }
+                    }
+                };
+                match &update {
+                    // Reading catching up with the stream says nothing of whether it has ended.
+                    ReaderUpdate::Spooled if !spooled => continue,
+                    ReaderUpdate::Line { file_bytes, .. } => read = *file_bytes,
+                    ReaderUpdate::Truncated => read = 0,
+                    _ => {}
+                }
+                if sender.send(update).await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    fn size(&self) -> Result<u64> {
+        self.file.size()
+    }
+
+    fn identity(&self) -> Option<(u64, u64)> {
+        self.file.identity()
+    }
+
+    fn tail_offset(&self, lines: usize) -> Result<u64> {
+        self.file.tail_offset(lines)
+    }
+
+    fn lines(&self) -> Result<SourceLines> {
+        self.file.lines()
+    }
+}
+
+/// A compressed file, e.g. `app.log.gz`, decompressed by its tool to a temporary file as it is read,
+/// so the start can be browsed whilst the rest is decompressed. Lines are read at random from the
+/// decompressed file, at the offsets the IFile indexes them at. It is spooled once the tool has
+/// finished, or if the tool fails, e.g. for a corrupt file, what the tool reported is the file's
+/// error. The tool is stopped and the file removed when the source is dropped.
+#[derive(Debug)]
+pub struct DecompressSource {
+    decompressed: PathBuf,
+    spool: SpoolSource,
+    _decompressor: Arc<Mutex<Decompressor>>,
+}
+
+impl DecompressSource {
+    pub fn new(path: &str, compression: Compression, delimiter: LineDelimiter) -> Result<Self> {
+        let name = format!(
+            "decompress-{}",
+            DECOMPRESSED.fetch_add(1, Ordering::Relaxed)
+        );
+        let (decompressed, output) = create_spool_file(&name, "log")?;
+        let decompressor = compression
+            .decompress(Some(Path::new(path)), output)
+            .inspect_err(|_| {
+                fs::remove_file(&decompressed).ok();
+            })?;
+        debug!(
+            "Decompressing {} with {} to {:?}",
+            path,
+            compression.command(),
+            decompressed
+        );
+
+        // Watch for the tool finishing, until the source is dropped.
+        let decompressor = Arc::new(Mutex::new(decompressor));
+        let done = SpoolDone::default();
+        let watched = (Arc::downgrade(&decompressor), done.clone());
+        thread::spawn(move || {
+            let (decompressor, done) = watched;
+            while let Some(decompressor) = decompressor.upgrade() {
+                let finished = decompressor
+                    .lock()
+                    .ok()
+                    .and_then(|mut decompressor| decompressor.try_finish());
+                match finished {
+                    Some(Ok(())) => return done.finish(),
+                    Some(Err(e)) => return done.fail(e.to_string()),
+                    None => {}
+                }
+                drop(decompressor);
+                thread::sleep(Duration::from_millis(DECOMPRESS_POLL_MS));
+            }
+        });
+
+        let file = FileSource::new(&decompressed.to_string_lossy())?.with_delimiter(delimiter);
+        Ok(Self {
+            decompressed,
+            spool: SpoolSource::new(file, done),
+            _decompressor: decompressor,
+        })
+    }
+}
+
+impl InputSource for DecompressSource {
+    fn read_line(&mut self, offset: Option<u64>) -> Result<String> {
+        self.spool.read_line(offset)
+    }
+
+    fn read_line_start(&mut self, offset: u64, max_bytes: usize) -> Result<(String, bool)> {
+        self.spool.read_line_start(offset, max_bytes)
+    }
+
+    // Only ever the decompressed file, not the compressed file's path.
+    fn reopen(&mut self, _path: &Path) -> Result<()> {
+        self.spool.reopen(&self.decompressed)
+    }
+
+    fn follow(
+        &self,
+        start: u64,
+        max_line_bytes: Option<usize>,
+        sender: ReaderUpdateSender,
+    ) -> JoinHandle<()> {
+        self.spool.follow(start, max_line_bytes, sender)
+    }
+
+    fn size(&self) -> Result<u64> {
+        self.spool.size()
+    }
+
+    fn identity(&self) -> Option<(u64, u64)> {
+        self.spool.identity()
+    }
+
+    // Where the last lines are isn't known until it has all been decompressed.
+    fn tail_offset(&self, _lines: usize) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn lines(&self) -> Result<SourceLines> {
+        self.spool.lines()
+    }
+}
+
+impl Drop for DecompressSource {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.decompressed) {
+            warn!(
+                "Failed to remove decompressed file {:?}: {:?}",
+                self.decompressed, e
+            );
+        }
+    }
+}
+
+// Stops a task when the task depending on it is stopped.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_file_source() {
+        let path = std::env::temp_dir().join(format!("otail-test-source-{}", std::process::id()));
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let mut source = FileSource::new(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(source.size().unwrap(), 14);
+        assert_eq!(source.tail_offset(1).unwrap(), 8);
+        assert_eq!(source.read_line(Some(4)).unwrap(), "two");
+        assert!(source.identity().is_some());
+        let lines = source
+            .lines()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                ("one".to_owned(), 4),
+                ("two".to_owned(), 4),
+                ("three".to_owned(), 6)
+            ]
+        );
+
+        // Followed from the offset given, sending the lines then that they have all been read.
+        let (sender, mut receiver) = mpsc::channel(CHANNEL_BUFFER);
+        let task = source.follow(4, None, sender);
+        let mut lines = Vec::new();
+        loop {
+            match receiver.recv().await.unwrap() {
+                ReaderUpdate::Line { line_content, .. } => lines.push(line_content),
+                ReaderUpdate::Spooled => break,
+                update => panic!("Unexpected update: {:?}", update),
+            }
+        }
+        assert_eq!(lines, vec!["two", "three"]);
+        task.abort();
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    async fn next(receiver: &mut mpsc::Receiver<ReaderUpdate>) -> Option<ReaderUpdate> {
+        timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[tokio::test]
+    async fn test_spool_source() {
+        let path = std::env::temp_dir().join(format!("otail-test-spool-{}", std::process::id()));
+        fs::write(&path, "one\n").unwrap();
+        let done = SpoolDone::default();
+        let source = SpoolSource::new(
+            FileSource::new(path.to_str().unwrap()).unwrap(),
+            done.clone(),
+        );
+
+        let (sender, mut receiver) = mpsc::channel(CHANNEL_BUFFER);
+        let task = source.follow(0, None, sender);
+        assert!(
+            matches!(next(&mut receiver).await, Some(ReaderUpdate::Line { line_content, .. }) if line_content == "one")
+        );
+
+        // Not spooled whilst the stream may have more to come.
+        assert!(timeout(Duration::from_millis(300), receiver.recv())
+            .await
+            .is_err());
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"two\n")
+            .unwrap();
+        done.finish();
+        assert!(
+            matches!(next(&mut receiver).await, Some(ReaderUpdate::Line { line_content, .. }) if line_content == "two")
+        );
+        assert!(matches!(
+            next(&mut receiver).await,
+            Some(ReaderUpdate::Spooled)
+        ));
+        task.abort();
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_decompress_source() {
+        let path = std::env::temp_dir().join(format!("otail-test-gz-{}.gz", std::process::id()));
+        let gzip = std::process::Command::new("gzip")
+            .arg("-c")
+            .stdin(std::process::Stdio::piped())
+            .stdout(File::create(&path).unwrap())
+            .spawn()
+            .unwrap();
+        gzip.stdin
+            .as_ref()
+            .unwrap()
+            .write_all(b"one\ntwo\n")
+            .unwrap();
+        assert!(gzip.wait_with_output().unwrap().status.success());
+
+        // Read once decompressed, then spooled.
+        let follow = |path: &Path| {
+            let source = DecompressSource::new(
+                path.to_str().unwrap(),
+                Compression::Gzip,
+                LineDelimiter::Newline,
+            )
+            .unwrap();
+            let (sender, receiver) = mpsc::channel(CHANNEL_BUFFER);
+            let task = source.follow(0, None, sender);
+            (source, task, receiver)
+        };
+        let (mut source, task, mut receiver) = follow(&path);
+        let mut lines = Vec::new();
+        loop {
+            match next(&mut receiver).await {
+                Some(ReaderUpdate::Line { line_content, .. }) => lines.push(line_content),
+                Some(ReaderUpdate::Spooled) => break,
+                update => panic!("Unexpected update: {:?}", update),
+            }
+        }
+        assert_eq!(lines, vec!["one", "two"]);
+        assert_eq!(source.read_line(Some(4)).unwrap(), "two");
+        task.abort();
+
+        // Failing to decompress is the file's error.
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        let (_source, task, mut receiver) = follow(&path);
+        loop {
+            match next(&mut receiver).await {
+                Some(ReaderUpdate::Line { .. }) => {}
+                Some(ReaderUpdate::Failed { reason }) => {
+                    assert!(reason.starts_with("gzip failed"), "{}", reason);
+                    break;
+                }
+                update => panic!("Unexpected update: {:?}", update),
+            }
+        }
+        task.abort();
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::Annotation;
+use crate::filter_spec::{Context, FilterSpec};
+
+/// How far an investigation of a log has got, written to a file to hand over to someone else, who
+/// picks it up against the same log on their own machine.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Investigation {
+    // The log investigated, as it was named.
+    pub log: String,
+    // The filter in use, None if filtering was off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<FilterSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Context>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<SavedBookmark>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Annotation>,
+    // The current line of the content pane.
+    #[serde(default)]
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedBookmark {
+    pub line_no: usize,
+    pub note: String,
+    pub line: String,
+}
+
+impl Investigation {
+    pub fn load(path: &str) -> Result<Self> {
+        let yaml =
+            fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+
+        serde_yaml::from_str(&yaml).map_err(|e| anyhow!("Bad investigation {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_yaml::to_string(self)?)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path, e))
+    }
+
+    /// The filter with its context, built ready to use.
+    pub fn filter_spec(&self) -> Option<Result<FilterSpec>> {
+        let filter_spec = self.filter.as_ref()?;
+
+        Some(
+            filter_spec
+                .compiled()
+                .map(|f| f.with_context(self.context.unwrap_or_default())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter_spec::FilterType;
+
+    #[test]
+    fn test_investigation() {
+        let path = std::env::temp_dir().join(format!(
+            "otail-test-investigation-{}.yaml",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy().into_owned();
+
+        let investigation = Investigation {
+            log: "app.log".to_owned(),
+            filter: Some(FilterSpec::new(FilterType::Regex, r"timeout after \d+").unwrap()),
+            context: Some(Context {
+                before: 2,
+                after: 0,
+            }),
+            highlights: vec!["db-3".to_owned()],
+            bookmarks: vec![SavedBookmark {
+                line_no: 120,
+                note: "first timeout".to_owned(),
+                line: "ERROR timeout after 30s".to_owned(),
+            }],
+            notes: Vec::new(),
+            line: 118,
+        };
+        investigation.save(&path).unwrap();
+
+        let loaded = Investigation::load(&path).unwrap();
+        let filter_spec = loaded.filter_spec().unwrap().unwrap();
+        assert_eq!(filter_spec.filter_type, FilterType::Regex);
+        assert!(filter_spec.matches("ERROR timeout after 30s"));
+        assert_eq!(filter_spec.context().before, 2);
+        assert_eq!(loaded.highlights, investigation.highlights);
+        assert_eq!(loaded.bookmarks, investigation.bookmarks);
+        assert_eq!(loaded.line, 118);
+
+        assert!(Investigation::load("/nonexistent/investigation.yaml").is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
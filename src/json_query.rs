@@ -0,0 +1,368 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+
+/// A path into a JSON value, e.g. `.response.status` or `.items[0].id`. `.` alone is the whole
+/// value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Field(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self> {
+        let Some(rest) = path.strip_prefix('.') else {
+            bail!("JSON path must start with '.': {}", path);
+        };
+
+        let mut steps = Vec::new();
+        for part in rest.split('.').filter(|p| !p.is_empty()) {
+            // Any array indexes follow the field name, e.g. `items[0][1]`.
+            let (field, mut indexes) = part.split_at(part.find('[').unwrap_or(part.len()));
+            if !field.is_empty() {
+                steps.push(Step::Field(field.to_owned()));
+            }
+            while let Some(index) = indexes.strip_prefix('[') {
+                let (index, tail) = index
+                    .split_once(']')
+                    .ok_or_else(|| anyhow!("Missing ']' in JSON path: {}", path))?;
+                steps.push(Step::Index(index.trim().parse().map_err(|_| {
+                    anyhow!("Bad array index '{}' in JSON path: {}", index, path)
+                })?));
+                indexes = tail;
+            }
+            if !indexes.is_empty() {
+                bail!("Unexpected '{}' in JSON path: {}", indexes, path);
+            }
+        }
+
+        Ok(JsonPath { steps })
+    }
+
+    pub fn get<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.steps.iter().try_fold(value, |v, step| match step {
+            Step::Field(name) => v.get(name),
+            Step::Index(i) => v.get(i),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn parse(op: &str) -> Result<Self> {
+        Ok(match op {
+            "==" | "=" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            _ => bail!("Unknown operator: {}", op),
+        })
+    }
+
+    fn test(&self, ordering: Option<Ordering>) -> bool {
+        match (self, ordering) {
+            (Op::Eq, Some(o)) => o.is_eq(),
+            (Op::Ne, o) => o.is_none_or(|o| o.is_ne()),
+            (Op::Lt, Some(o)) => o.is_lt(),
+            (Op::Le, Some(o)) => o.is_le(),
+            (Op::Gt, Some(o)) => o.is_gt(),
+            (Op::Ge, Some(o)) => o.is_ge(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Path(String),
+    Op(String),
+    Literal(Value),
+    And,
+    Or,
+    Plus,
+}
+
+const OP_CHARS: &[char] = &['=', '!', '<', '>'];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c == '"' {
+            // Find the closing quote, skipping escaped characters.
+            let mut escaped = false;
+            let end = rest[1..]
+                .find(|c| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                })
+                .ok_or_else(|| anyhow!("Missing closing quote in: {}", expr))?;
+            let quoted = &rest[..end + 2];
+            tokens.push(Token::Literal(serde_json::from_str(quoted)?));
+            quoted.len()
+        } else if c == '+' {
+            tokens.push(Token::Plus);
+            1
+        } else if OP_CHARS.contains(&c) {
+            let op = &rest[..rest.find(|c| !OP_CHARS.contains(&c)).unwrap_or(rest.len())];
+            tokens.push(Token::Op(op.to_owned()));
+            op.len()
+        } else {
+            let word = &rest[..rest
+                .find(|c: char| c.is_whitespace() || c == '"' || c == '+' || OP_CHARS.contains(&c))
+                .unwrap_or(rest.len())];
+            tokens.push(match word {
+                "and" | "&&" => Token::And,
+                "or" | "||" => Token::Or,
+                _ if word.starts_with('.') => Token::Path(word.to_owned()),
+                // Numbers, true, false and null as JSON, anything else as a string.
+                _ => Token::Literal(
+                    serde_json::from_str(word).unwrap_or_else(|_| Value::String(word.to_owned())),
+                ),
+            });
+            word.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+// Numbers compare numerically, also against numbers in strings, e.g. `"500"`. Strings compare as
+// text, anything else only for equality.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    let number = |v: &Value| match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => match (number(a), number(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ if a == b => Some(Ordering::Equal),
+            _ => None,
+        },
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    path: JsonPath,
+    test: Option<(Op, Value)>,
+}
+
+impl Condition {
+    fn matches(&self, value: &Value) -> bool {
+        let found = self.path.get(value);
+        match (&self.test, found) {
+            (Some((op, expected)), Some(found)) => op.test(compare(found, expected)),
+            (Some((op, _)), None) => *op == Op::Ne,
+            // Without a test the path must be there and not null or false.
+            (None, found) => !matches!(found, None | Some(Value::Null) | Some(Value::Bool(false))),
+        }
+    }
+}
+
+/// A test on JSON lines, such as `.response.status >= 500 and .method == GET`. Conditions are a
+/// path, optionally compared with a value, joined with `and` or `or`; `and` binds tighter. Lines
+/// that aren't JSON never match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPredicate {
+    // Any of these groups of conditions, each needing all of its conditions.
+    any: Vec<Vec<Condition>>,
+}
+
+impl JsonPredicate {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let mut any = vec![Vec::new()];
+        let mut tokens = tokenize(expr)?.into_iter().peekable();
+        loop {
+            let path = match tokens.next() {
+                Some(Token::Path(path)) => JsonPath::parse(&path)?,
+                _ => bail!("Expected a JSON path such as .level in: {}", expr),
+            };
+            let test = match tokens.next_if(|t| matches!(t, Token::Op(_))) {
+                Some(Token::Op(op)) => match tokens.next() {
+                    Some(Token::Literal(value)) => Some((Op::parse(&op)?, value)),
+                    _ => bail!("Expected a value after {} in: {}", op, expr),
+                },
+                _ => None,
+            };
+            if let Some(all) = any.last_mut() {
+                all.push(Condition { path, test });
+            }
+
+            match tokens.next() {
+                None => break,
+                Some(Token::And) => {}
+                Some(Token::Or) => any.push(Vec::new()),
+                Some(_) => bail!("Expected 'and' or 'or' in: {}", expr),
+            }
+        }
+
+        Ok(JsonPredicate { any })
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        serde_json::from_str::<Value>(line).is_ok_and(|value| self.matches_value(&value))
+    }
+
+    /// Whether an already parsed value passes, e.g. the fields of a logfmt line.
+    pub fn matches_value(&self, value: &Value) -> bool {
+        self.any
+            .iter()
+            .any(|all| all.iter().all(|c| c.matches(value)))
+    }
+}
+
+/// Show a value as text, strings without their quotes.
+pub fn value_text(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(v) => v.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Path(JsonPath),
+    Literal(Value),
+}
+
+/// Reshapes a JSON line for display, e.g. `.ts + " " + .level + " " + .msg`. Terms are paths or
+/// literals joined with `+`, concatenated as text. Missing fields are empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonTransform {
+    terms: Vec<Term>,
+}
+
+impl JsonTransform {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+        let mut tokens = tokenize(expr)?.into_iter();
+        loop {
+            terms.push(match tokens.next() {
+                Some(Token::Path(path)) => Term::Path(JsonPath::parse(&path)?),
+                Some(Token::Literal(value)) => Term::Literal(value),
+                _ => bail!("Expected a JSON path or value in: {}", expr),
+            });
+
+            match tokens.next() {
+                None => break,
+                Some(Token::Plus) => {}
+                Some(_) => bail!("Expected '+' in: {}", expr),
+            }
+        }
+
+        Ok(JsonTransform { terms })
+    }
+
+    /// The transformed line, or None if it is not JSON.
+    pub fn apply(&self, line: &str) -> Option<String> {
+        let value = serde_json::from_str::<Value>(line).ok()?;
+
+        Some(
+            self.terms
+                .iter()
+                .map(|term| match term {
+                    Term::Path(path) => value_text(path.get(&value)),
+                    Term::Literal(literal) => value_text(Some(literal)),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_path() {
+        let value: Value = serde_json::from_str(r#"{"a":{"b":[10,{"c":"x"}]},"d":null}"#).unwrap();
+
+        let get = |p: &str| JsonPath::parse(p).unwrap().get(&value).cloned();
+        assert_eq!(get(".a.b[0]"), Some(Value::from(10)));
+        assert_eq!(get(".a.b[1].c"), Some(Value::from("x")));
+        assert_eq!(get(".a.z"), None);
+        assert_eq!(get("."), Some(value.clone()));
+
+        assert!(JsonPath::parse("a.b").is_err());
+        assert!(JsonPath::parse(".a[x]").is_err());
+    }
+
+    #[test]
+    fn test_json_predicate() {
+        let lines = [
+            r#"{"method":"GET","response":{"status":503},"user":"bob"}"#,
+            r#"{"method":"POST","response":{"status":"500"}}"#,
+            r#"{"method":"GET","response":{"status":200},"user":"amy smith"}"#,
+            "not json",
+        ];
+        let matching = |expr: &str| {
+            let predicate = JsonPredicate::parse(expr).unwrap();
+            lines
+                .iter()
+                .map(|l| predicate.matches(l))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            matching(".response.status >= 500"),
+            vec![true, true, false, false]
+        );
+        assert_eq!(
+            matching(".method == GET and .response.status<300"),
+            vec![false, false, true, false]
+        );
+        assert_eq!(
+            matching(r#".user == "amy smith" or .method != GET"#),
+            vec![false, true, true, false]
+        );
+        assert_eq!(matching(".user"), vec![true, false, true, false]);
+
+        assert!(JsonPredicate::parse("status > 1").is_err());
+        assert!(JsonPredicate::parse(".status >").is_err());
+        assert!(JsonPredicate::parse(".a .b").is_err());
+        assert!(JsonPredicate::parse(".a =~ x").is_err());
+    }
+
+    #[test]
+    fn test_json_transform() {
+        let transform = JsonTransform::parse(r#".ts + " [" + .level + "] "+.msg+.n"#).unwrap();
+        assert_eq!(
+            transform.apply(r#"{"ts":"10:00","level":"info","msg":"hi","n":3}"#),
+            Some("10:00 [info] hi3".to_owned())
+        );
+        assert_eq!(
+            transform.apply(r#"{"ts":"10:01"}"#),
+            Some("10:01 [] ".to_owned())
+        );
+        assert_eq!(transform.apply("not json"), None);
+
+        assert!(JsonTransform::parse(".a + ").is_err());
+        assert!(JsonTransform::parse(".a .b").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+/// Log levels, least severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    fn from_word(word: &str) -> Option<Self> {
+        match word.to_ascii_uppercase().as_str() {
+            "TRACE" | "TRC" => Some(Level::Trace),
+            "DEBUG" | "DBG" => Some(Level::Debug),
+            "INFO" | "INF" | "NOTICE" => Some(Level::Info),
+            "WARN" | "WARNING" | "WRN" => Some(Level::Warn),
+            "ERROR" | "ERR" => Some(Level::Error),
+            "FATAL" | "CRITICAL" | "CRIT" | "PANIC" | "EMERG" | "ALERT" => Some(Level::Fatal),
+            _ => None,
+        }
+    }
+
+    /// Parse a threshold such as `WARN` or `>= warn`.
+    pub fn parse_threshold(threshold: &str) -> Result<Self> {
+        let word = threshold.trim();
+        let word = word.strip_prefix(">=").unwrap_or(word).trim();
+
+        Level::from_word(word).ok_or_else(|| anyhow!("Unknown log level: {}", threshold))
+    }
+
+    /// The level of a line: the first word that names one, e.g. `INFO`, `[warn]` or
+    /// `"level":"error"`. Only the first few words are looked at, so messages mentioning a level
+    /// later on don't count.
+    pub fn detect(line: &str) -> Option<Self> {
+        line.split(|c: char| !c.is_ascii_alphabetic())
+            .filter(|w| !w.is_empty())
+            .take(DETECT_WORDS)
+            .find_map(Level::from_word)
+    }
+}
+
+// Enough to get past a timestamp, logger name or JSON keys before the level.
+const DETECT_WORDS: usize = 12;
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+            Level::Fatal => "FATAL",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let lines = [
+            "2024-03-01T10:00:00Z INFO app: started",
+            "2024-03-01 10:00:00,123 [main] WARN c.e.App - slow",
+            r#"{"ts":"10:00","level":"error","msg":"failed"}"#,
+            "E0301 10:00:00.000 crit: disk gone",
+            "just a message with no level",
+        ];
+        assert_eq!(
+            lines.iter().map(|l| Level::detect(l)).collect::<Vec<_>>(),
+            vec![
+                Some(Level::Info),
+                Some(Level::Warn),
+                Some(Level::Error),
+                Some(Level::Fatal),
+                None
+            ]
+        );
+
+        assert_eq!(Level::parse_threshold(">= warning").unwrap(), Level::Warn);
+        assert_eq!(Level::parse_threshold("Error").unwrap(), Level::Error);
+        assert!(Level::parse_threshold("loud").is_err());
+    }
+}
@@ -1,11 +1,38 @@
+pub mod analysis;
+pub mod annotations;
 pub mod backing_file;
+pub mod chords;
+pub mod clipboard;
+pub mod cluster;
 pub mod colour_spec;
+pub mod columns;
 pub mod common;
 pub mod config;
 pub mod ffile;
+pub mod file_list;
+pub mod filter_cache;
+pub mod filter_file;
 pub mod filter_spec;
+pub mod history;
 pub mod ifile;
+pub mod input_source;
+pub mod investigation;
+pub mod json_query;
+pub mod level;
+pub mod opener;
 pub mod panic;
+pub mod print;
+pub mod query;
 pub mod reader;
+pub mod retry;
+#[cfg(test)]
+pub mod sim;
+pub mod spool;
+pub mod substitution;
+pub mod theme;
+pub mod timestamp;
 pub mod tui;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod uring;
 pub mod view;
+pub mod websocket;
@@ -1,27 +1,64 @@
-use std::{fs::File, io::stdout};
+use std::io::{stdout, BufWriter, ErrorKind, IsTerminal};
+use std::path::Path;
 
+use anyhow::bail;
 use clap::{command, Parser};
 use flexi_logger::{detailed_format, FileSpec};
 use log::{error, info};
-use otail::config::load_config_from;
-use otail::ifile::IFile;
+use otail::backing_file::LineDelimiter;
+use otail::colour_spec::ColouringScope;
+use otail::columns::ColumnMode;
+use otail::common::{
+    DEFAULT_FILTER_CACHE_LINES, DEFAULT_MAX_LINE_BYTES, DEFAULT_STREAM_BUFFER_LINES,
+};
+use otail::config::{load_config_from, LocatedConfig, TailPanes};
+use otail::ffile::ripgrep_available;
+use otail::file_list::{glob_regex, FileList};
+use otail::filter_cache::FilterCache;
+use otail::filter_file::FilterFile;
+use otail::filter_spec::{set_regex_macros, FilterSpec, FilterType};
+use otail::opener::FileOpener;
 use otail::panic::init_panic_handler;
+use otail::print::Printer;
+use otail::spool::{
+    is_fifo, DockerSource, DockerSpool, ExecSpool, FifoSpool, K8sSource, K8sSpool, KafkaSource,
+    KafkaSpool, ListenSpool, MergeSpool, RotatedSpool, S3Object, S3Spool, Spool, StdinSpool,
+    StreamBuffer, WebSocketSpool,
+};
+use otail::substitution::Substitution;
+use otail::theme::{set_theme, Theme};
+use otail::timestamp::{parse_time_arg, TimeWindow, TimestampParser};
 use otail::tui::Tui;
-use otail::{backing_file::FileBackingFile, ffile::FFile};
+use otail::websocket::WebSocketUrl;
 
 use ratatui::{
     backend::CrosstermBackend,
     crossterm::{
+        style::{Colored, Print},
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
         ExecutableCommand,
     },
     Terminal,
 };
 
+// Save the terminal title to restore on exit, with xterm's title stack. Terminals without one
+// ignore these.
+const SAVE_TITLE: &str = "\x1b[22;0t";
+const RESTORE_TITLE: &str = "\x1b[23;0t";
+
+// What is read: input spooled to a temporary file, or the files given, with the one to open first.
+enum Input {
+    Spooled(Box<dyn Spool>),
+    Files(FileList, String),
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    path: String,
+    #[arg(
+        help = "Files to view: a file, several files, a directory or a quoted glob such as 'logs/*.log'. Use - (or nothing) to read piped input, a ws:// or wss:// URL to read WebSocket messages, or a kafka://<brokers>/<topic>[/<partition>][?offset=<offset>] URL to consume a Kafka topic with kcat, an s3://<bucket>/<key> URL to fetch an object with the AWS CLI, a docker://<container>[?tail=<n>&since=<when>] URL to follow a container's logs, or a k8s://<namespace>/<pod>[/<container>][?tail=<n>&since=<when>] URL to follow a pod's logs with kubectl"
+    )]
+    paths: Vec<String>,
 
     #[arg(
         short = 'c',
@@ -29,6 +66,196 @@ struct Args {
         help = "Specify a custom config file path"
     )]
     config: Option<String>,
+
+    #[arg(
+        long = "columns",
+        value_name = "DELIMITER",
+        num_args = 0..=1,
+        default_missing_value = "auto",
+        help = "Show lines as aligned columns: auto, csv, tsv or a delimiter character"
+    )]
+    columns: Option<String>,
+
+    #[arg(
+        long = "pin",
+        value_name = "LINES",
+        help = "Pin the first lines of the file above the scrolling lines, e.g. column headers or run details"
+    )]
+    pin: Option<usize>,
+
+    #[arg(
+        long = "since",
+        value_name = "TIME",
+        help = "Only show lines from this time: RFC3339, YYYY-MM-DD [HH:MM[:SS]], HH:MM[:SS] or ago (e.g. 2h)"
+    )]
+    since: Option<String>,
+
+    #[arg(
+        long = "until",
+        value_name = "TIME",
+        help = "Only show lines up to this time, in the same forms as --since"
+    )]
+    until: Option<String>,
+
+    #[arg(
+        long = "filter",
+        value_name = "REGEX",
+        conflicts_with = "filter_file",
+        help = "Start with the lines matching a regex filtered"
+    )]
+    filter: Option<String>,
+
+    #[arg(
+        long = "print",
+        help = "Print the lines matching the filter (--filter or --filter-file) to stdout, like grep, instead of showing the file"
+    )]
+    print: bool,
+
+    #[arg(
+        short = 'f',
+        long = "follow",
+        requires = "print",
+        help = "With --print, keep printing matching lines as they are written, like tail -f"
+    )]
+    follow: bool,
+
+    #[arg(
+        long = "filter-file",
+        value_name = "FILE",
+        help = "Apply a filter from a YAML or JSON file: any, all and not of filters, with context lines and a time window"
+    )]
+    filter_file: Option<String>,
+
+    #[arg(
+        long = "profile",
+        value_name = "NAME",
+        conflicts_with = "filter_file",
+        help = "Start with a profile from the config: a filter, colouring and tailing used together"
+    )]
+    profile: Option<String>,
+
+    #[arg(
+        long = "monochrome",
+        help = "Show colouring with bold, underline, reverse and margin symbols instead of colour (also with NO_COLOR set)"
+    )]
+    monochrome: bool,
+
+    #[arg(
+        long = "lines",
+        value_name = "N",
+        conflicts_with = "print",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Start with only the last lines of the file, like tail -n, so a huge file can be used straight away, indexing the rest in the background"
+    )]
+    lines: Option<u64>,
+
+    #[arg(
+        long = "tail",
+        value_name = "PANES",
+        num_args = 0..=1,
+        default_missing_value = "both",
+        help = "Start tailing, like tail -f: both panes, or only the content or filter pane"
+    )]
+    tail: Option<TailPanes>,
+
+    #[arg(
+        long = "tabs",
+        help = "Open each file in a tab of its own, keeping its filter, colouring and place when switching between them"
+    )]
+    tabs: bool,
+
+    #[arg(
+        long = "dir",
+        value_name = "DIR",
+        conflicts_with_all = ["paths", "tabs", "merge", "rotated"],
+        help = "Tail the most recently modified file in the directory, or matching a quoted glob, switching to a newer file when one appears, e.g. date-stamped logs"
+    )]
+    dir: Option<String>,
+
+    #[arg(
+        long = "accessible",
+        help = "Draw for screen readers and braille displays: no box-drawing borders, fewer redraws, and the current line and status as text on the bottom line"
+    )]
+    accessible: bool,
+
+    #[arg(
+        long = "ripgrep",
+        help = "Use ripgrep (rg), if installed, to find filter matches in the existing file quickly"
+    )]
+    ripgrep: bool,
+
+    #[arg(
+        long = "no-ignore",
+        help = "Read every line, including those matching the ignore patterns in the config"
+    )]
+    no_ignore: bool,
+
+    #[arg(
+        long = "follow-links",
+        help = "When the file is a symlink, switch to the file it points to when it is re-pointed, e.g. on rotation"
+    )]
+    follow_links: bool,
+
+    #[arg(
+        long = "follow-name",
+        help = "Follow the file by name, like tail -F: when it is moved away or removed and another created in its place, e.g. on rotation, read the new one"
+    )]
+    follow_name: bool,
+
+    #[arg(
+        long = "retry",
+        help = "If the file doesn't exist yet, wait for it to be created rather than failing"
+    )]
+    retry: bool,
+
+    #[arg(
+        long = "rotated",
+        help = "Read the file's rotated siblings (e.g. app.log.2.gz, app.log.1) before it, as one file, following further rotations"
+    )]
+    rotated: bool,
+
+    #[arg(
+        long = "merge",
+        conflicts_with = "rotated",
+        help = "Tail the files together in one pane, interleaved as lines arrive, each line tagged with its file. Number keys hide and show each file's lines"
+    )]
+    merge: bool,
+
+    #[arg(
+        long = "by-time",
+        requires = "merge",
+        help = "With --merge, interleave the lines in the order of their timestamps, found with the timestamp formats from the config, rather than as they arrive"
+    )]
+    by_time: bool,
+
+    #[arg(
+        long = "delimiter",
+        value_name = "DELIMITER",
+        help = "Split lines on something other than newlines: nul (as from find -print0), or the delimiter with escapes such as \\x1e"
+    )]
+    delimiter: Option<String>,
+
+    #[arg(
+        long = "listen",
+        value_name = "ADDR",
+        help = "Receive syslog messages, or plain lines, sent over UDP and TCP to an address such as 0.0.0.0:5140, or over just one with tcp:// or udp:// before it"
+    )]
+    listen: Option<String>,
+
+    #[arg(
+        long = "exec",
+        value_name = "COMMAND",
+        conflicts_with_all = ["paths", "listen"],
+        help = "Run a command and view its output and errors as they are written, e.g. --exec 'make test', with its exit status in the title. Also given as a path starting with !, e.g. otail '!make test'"
+    )]
+    exec: Option<String>,
+
+    #[arg(
+        long = "theme",
+        value_name = "FILE",
+        help = "Use the colours of a base16 scheme or terminal.sexy palette, to match the terminal's theme"
+    )]
+    theme: Option<String>,
 }
 
 #[tokio::main]
@@ -41,12 +268,22 @@ async fn main() -> anyhow::Result<()> {
         .format(detailed_format)
         .start()?;
 
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     info!("otail starting: {:?}", args);
 
+    // A directory to follow is opened as if it had been given as a path, listing its files, but
+    // with the newest file opened first.
+    if let Some(dir) = &args.dir {
+        if !Path::new(dir).is_dir() && !dir.contains(['*', '?']) {
+            eprintln!("Not a directory: {}", dir);
+            return Ok(());
+        }
+        args.paths = vec![dir.clone()];
+    }
+
     // Load config first, exit if specified config file doesn't exist
-    let config = match load_config_from(args.config) {
+    let mut config = match load_config_from(args.config.clone()) {
         Ok(config) => config,
         Err(e) => {
             error!("{}", e);
@@ -55,45 +292,462 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
-    // Quickly check the file before starting... can produce a better error.
-    if let Err(e) = File::open(&args.path) {
-        let message = format!("Failed to open: {} - {:?}", &args.path, e);
-        error!("{}", message);
-        eprintln!("{}", message);
+    let delimiter = match args.delimiter.as_deref().map(LineDelimiter::parse) {
+        Some(Ok(delimiter)) => Some(delimiter),
+        Some(Err(e)) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+        None => None,
+    };
+
+    // Streams that can't be read again are buffered, if the config says so. Not when printing, as
+    // nothing says what has been printed, so the buffer would drop lines not read yet.
+    let stream_buffer = config
+        .config
+        .stream_buffer
+        .as_ref()
+        .filter(|_| !args.print)
+        .map(|stream_buffer| {
+            StreamBuffer::new(
+                stream_buffer.policy,
+                stream_buffer.lines.unwrap_or(DEFAULT_STREAM_BUFFER_LINES),
+            )
+            .with_delimiter(delimiter.clone().unwrap_or_default())
+        });
+
+    // Piped input, a named pipe, a file and its rotated siblings, merged files, messages received
+    // over the network or from a WebSocket, a Kafka topic, an S3 object, a command's output, or a
+    // container's or pod's logs are spooled to a temporary file, removed when the spool is dropped
+    // on exit. Otherwise the files given, compressed or not, can be switched between.
+    let input = match open_input(&args, &config, stream_buffer) {
+        Ok(input) => input,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+    };
+    let (path, name, spool, file_list) = match input {
+        Input::Spooled(spool) => (spool.path(), spool.name(), Some(spool), None),
+        Input::Files(list, first) => (first.clone(), first, None, Some(list)),
+    };
+
+    set_regex_macros(&config.config.regex_macros);
+
+    if let Some(path) = args.theme.as_ref().or(config.config.theme.as_ref()) {
+        let path = match (path.strip_prefix("~/"), std::env::var("HOME")) {
+            (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+            _ => path.clone(),
+        };
+        match Theme::load(&path) {
+            Ok(theme) => set_theme(Some(theme)),
+            Err(e) => {
+                error!("{}", e);
+                eprintln!("{}", e);
+                return Ok(());
+            }
+        }
+    }
+
+    // Filters loaded from the config need building, now the macros are known.
+    config.config.colouring = match config.config.colouring.compiled() {
+        Ok(colouring) => colouring,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("Bad colouring rule in the config: {}", e);
+            return Ok(());
+        }
+    };
+    let substitutions = match config
+        .config
+        .substitutions
+        .iter()
+        .map(|s| Substitution::parse(s))
+        .collect::<anyhow::Result<Vec<_>>>()
+    {
+        Ok(substitutions) => substitutions,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("Bad substitution in the config: {}", e);
+            return Ok(());
+        }
+    };
+    let profile = match &args.profile {
+        Some(name) => match config.config.profiles.get(name) {
+            Some(profile) => profile.compiled(),
+            None if config.config.profiles.is_empty() => Err(anyhow::anyhow!(
+                "Unknown profile: {}, the config has no profiles",
+                name
+            )),
+            None => Err(anyhow::anyhow!(
+                "Unknown profile: {}, the config has: {}",
+                name,
+                config
+                    .config
+                    .profiles
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+        .map(|profile| Some((name.clone(), profile))),
+        None => Ok(None),
+    };
+    let profile = match profile {
+        Ok(profile) => profile,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+    };
+
+    let timestamps = match TimestampParser::new(&config.config.timestamp_formats) {
+        Ok(timestamps) => timestamps,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+    };
+
+    let column_delimiter = match args.columns.as_deref().map(ColumnMode::parse_delimiter) {
+        Some(Err(e)) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+        Some(Ok(delimiter)) => Some(delimiter),
+        None => None,
+    };
+
+    let filter_file = match args.filter_file.as_deref().map(FilterFile::load) {
+        Some(Err(e)) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+        Some(Ok(filter_file)) => Some(filter_file),
+        None => None,
+    };
+    let filter_spec = match filter_file.as_ref().map(|f| f.filter_spec()) {
+        Some(Err(e)) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+        Some(Ok(filter_spec)) => Some(filter_spec),
+        None => None,
+    };
+    let filter_spec = match args
+        .filter
+        .as_deref()
+        .map(|f| FilterSpec::new(FilterType::Regex, f))
+    {
+        Some(Err(e)) => {
+            error!("{}", e);
+            eprintln!("Bad filter: {}", e);
+            return Ok(());
+        }
+        Some(Ok(filter_spec)) => Some(filter_spec),
+        None => filter_spec,
+    };
+    if args.print && filter_spec.is_none() {
+        eprintln!("--print needs a filter, from --filter or --filter-file");
         return Ok(());
     }
-    let mut ifile = IFile::new(
-        &args.path,
-        FileBackingFile::new_from_path(&args.path.clone())?,
-    );
-    let mut ffile = FFile::new("ff".to_owned(), &args.path, ifile.get_view_sender());
-
-    let tui = Tui::new(
-        args.path.clone(),
-        ifile.get_view_sender(),
-        ffile.get_view_sender(),
-        ffile.get_ff_sender(),
-        config,
-    );
-
-    tokio::spawn(async move {
-        let result = ifile.run().await;
-        info!("IFile finished: {:?}", result);
-    });
-
-    tokio::spawn(async move {
-        let result = ffile.run().await;
-        info!("FFile finished: {:?}", result);
-    });
+
+    // The command line overrides any time window in the filter file.
+    let since = args
+        .since
+        .or_else(|| filter_file.as_ref().and_then(|f| f.since.clone()));
+    let until = args
+        .until
+        .or_else(|| filter_file.as_ref().and_then(|f| f.until.clone()));
+
+    let now = chrono::Local::now();
+    let time_window = match (
+        since.as_deref().map(|s| parse_time_arg(s, now)).transpose(),
+        until.as_deref().map(|u| parse_time_arg(u, now)).transpose(),
+    ) {
+        (Ok(None), Ok(None)) => None,
+        (Ok(since), Ok(until)) => Some(TimeWindow::new(timestamps.clone(), since, until)),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+    };
+
+    let mut opener = FileOpener::new(timestamps.clone());
+    // Printing works like grep, so lines are filtered and printed whole rather than cut for display.
+    let max_line_bytes = match args.print {
+        true => 0,
+        false => config
+            .config
+            .max_line_bytes
+            .unwrap_or(DEFAULT_MAX_LINE_BYTES),
+    };
+    match max_line_bytes {
+        0 => info!("Not capping line length"),
+        max_line_bytes => opener = opener.with_max_line_bytes(max_line_bytes),
+    }
+    if let Some(time_window) = &time_window {
+        opener = opener.with_time_window(time_window.clone());
+    }
+    if args.follow_links || config.config.follow_links {
+        opener = opener.with_follow_links();
+    }
+    if args.follow_name || config.config.follow_name {
+        opener = opener.with_follow_name();
+    }
+    if args.retry {
+        opener = opener.with_wait_for_files();
+    }
+    if let Some(lines) = args.lines {
+        opener = opener.with_tail_lines(lines as usize);
+    }
+    if let Some(reader_retry) = config.config.reader_retry {
+        opener = opener.with_retry_policy(reader_retry);
+    }
+    // Whether a spool with an end has copied all of its input.
+    if let Some(done) = spool.as_ref().and_then(|spool| spool.done()) {
+        opener = opener.with_spool(&path, done);
+    }
+    if let Some(delimiter) = delimiter {
+        opener = opener.with_delimiter(delimiter);
+    }
+    for (glob, delimiter) in &config.config.line_delimiters {
+        match (glob_regex(glob), LineDelimiter::parse(delimiter)) {
+            (Ok(name), Ok(delimiter)) => opener = opener.with_file_delimiter(name, delimiter),
+            (Err(e), _) | (_, Err(e)) => {
+                error!("{}", e);
+                eprintln!("Bad line delimiter in the config for {}: {}", glob, e);
+                return Ok(());
+            }
+        }
+    }
+    if !config.config.ignore_patterns.is_empty() {
+        match config
+            .config
+            .ignore_patterns
+            .iter()
+            .map(|p| regex::Regex::new(p))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(ignore_patterns) => {
+                opener = opener
+                    .with_ignore_patterns(ignore_patterns)
+                    .with_ignoring(!args.no_ignore)
+            }
+            Err(e) => {
+                error!("{}", e);
+                eprintln!("Bad ignore pattern in the config: {}", e);
+                return Ok(());
+            }
+        }
+    }
+    // Spools are new files each time, so there is nothing to cache.
+    match config
+        .config
+        .filter_cache_lines
+        .unwrap_or(DEFAULT_FILTER_CACHE_LINES)
+    {
+        0 => info!("Not caching filter matches"),
+        // Printing relies on every line being filtered in turn, to tell when all have been.
+        _ if file_list.is_none() || args.print => {}
+        min_lines => {
+            if let Some(dir) = FilterCache::default_dir() {
+                opener = opener.with_filter_cache(FilterCache::new(dir, min_lines));
+            }
+        }
+    }
+    if (args.ripgrep || config.config.ripgrep) && !args.print {
+        if ripgrep_available() {
+            opener = opener.with_ripgrep();
+        } else {
+            info!("Ripgrep (rg) not found, filtering without it");
+        }
+    }
+
+    let file = match opener.open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            return Ok(());
+        }
+    };
+
+    if let Some(filter_spec) = filter_spec.as_ref().filter(|_| args.print) {
+        let mut printer = Printer::new(filter_spec.clone());
+        if stdout().is_terminal() && !args.monochrome && !Colored::ansi_color_disabled() {
+            printer =
+                printer.with_colouring(config.config.colouring.for_pane(ColouringScope::Filter));
+        }
+        if args.follow {
+            printer = printer.with_follow();
+        }
+
+        // Stop quietly when the output is closed, e.g. when piped to head.
+        return match printer.run(&file, &mut BufWriter::new(stdout())).await {
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == ErrorKind::BrokenPipe) =>
+            {
+                Ok(())
+            }
+            result => result,
+        };
+    }
+
+    let accessible = args.accessible || config.config.accessible;
+    // Following a directory's newest file tails it unless asked otherwise.
+    let tail = args
+        .tail
+        .or(config.config.tail)
+        .or(args.dir.is_some().then_some(TailPanes::Content));
+    let mut tui = Tui::new(name, opener, file, config).with_timestamps(timestamps);
+    if let Some(file_list) = file_list {
+        if args.tabs {
+            let paths = file_list
+                .files()
+                .iter()
+                .map(|f| f.path.clone())
+                .filter(|p| *p != path)
+                .collect();
+            tui = tui.with_tabs(paths);
+        }
+        tui = tui.with_file_list(file_list);
+        if args.dir.is_some() {
+            tui = tui.with_follow_newest();
+        }
+    }
+    if let Some(spool) = &spool {
+        if let Some(tags) = spool.tags() {
+            tui = tui.with_sources(tags);
+        }
+        if let Some(status) = spool.status() {
+            tui = tui.with_command_status(status);
+        }
+        if let Some(buffer) = spool.buffer() {
+            tui = tui.with_stream_buffer(buffer.clone());
+        }
+    }
+    if let Some(delimiter) = column_delimiter {
+        tui = tui.with_columns(delimiter);
+    }
+    if let Some(lines) = args.pin {
+        tui = tui.with_pinned(lines);
+    }
+    if let Some(time_window) = &time_window {
+        tui = tui.with_time_window(time_window.describe());
+    }
+    if let Some(filter_spec) = filter_spec {
+        tui = tui.with_filter(filter_spec);
+    }
+    if let Some((name, profile)) = profile {
+        tui = tui.with_profile(name, profile);
+    }
+    if !substitutions.is_empty() {
+        tui = tui.with_substitutions(substitutions);
+    }
+    if args.monochrome || Colored::ansi_color_disabled() {
+        // Also stops any colour being sent to the terminal.
+        Colored::set_ansi_color_disabled(true);
+        tui = tui.with_monochrome();
+    }
+    if accessible {
+        tui = tui.with_accessible();
+    }
+    if let Some(panes) = tail {
+        tui = tui.with_tail(panes);
+    }
 
     enable_raw_mode()?;
-    stdout().execute(EnterAlternateScreen)?;
+    stdout()
+        .execute(Print(SAVE_TITLE))?
+        .execute(EnterAlternateScreen)?;
     let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
 
-    tui.run(terminal).await?;
+    // Put the terminal back even if the TUI fails.
+    let result = tui.run(terminal).await;
 
     disable_raw_mode()?;
-    stdout().execute(LeaveAlternateScreen)?;
+    stdout()
+        .execute(LeaveAlternateScreen)?
+        .execute(Print(RESTORE_TITLE))?;
+
+    drop(spool);
+
+    result
+}
+
+// Start spooling the input if it is a stream, or files read as one, rather than files to switch
+// between. Streams go through the buffer, if there is one.
+fn open_input(
+    args: &Args,
+    config: &LocatedConfig,
+    buffer: Option<StreamBuffer>,
+) -> anyhow::Result<Input> {
+    let single = match args.paths.as_slice() {
+        [path] => Some(path.as_str()),
+        _ => None,
+    };
+    let exec = args
+        .exec
+        .as_deref()
+        .or_else(|| single.and_then(|path| path.strip_prefix('!')));
+
+    let spool: Box<dyn Spool> = if let Some(command) = exec {
+        Box::new(ExecSpool::start(command, buffer)?)
+    } else if let Some(addr) = &args.listen {
+        if !args.paths.is_empty() {
+            bail!("--listen doesn't take files");
+        }
+        Box::new(ListenSpool::start(addr, buffer)?)
+    } else if let Some(url) = single.filter(|path| WebSocketUrl::is_websocket(path)) {
+        Box::new(WebSocketSpool::start(url, buffer)?)
+    } else if let Some(url) = single.filter(|path| KafkaSource::is_kafka(path)) {
+        Box::new(KafkaSpool::start(url, buffer)?)
+    } else if let Some(url) = single.filter(|path| DockerSource::is_docker(path)) {
+        Box::new(DockerSpool::start(url, buffer)?)
+    } else if let Some(url) = single.filter(|path| K8sSource::is_k8s(path)) {
+        Box::new(K8sSpool::start(url, buffer)?)
+    } else if let Some(url) = single.filter(|path| S3Object::is_s3(path)) {
+        Box::new(S3Spool::start(url)?)
+    } else if let Some(fifo) = single.filter(|path| is_fifo(path)) {
+        // Spooled rather than opened as a file, as reading from a pipe takes what is written.
+        Box::new(FifoSpool::start(fifo, buffer)?)
+    } else if args.paths.is_empty() || args.paths == ["-"] {
+        if std::io::stdin().is_terminal() {
+            bail!("No file given and nothing piped in, see --help");
+        }
+        Box::new(StdinSpool::start(buffer)?)
+    } else if args.rotated {
+        let [live] = args.paths.as_slice() else {
+            bail!("--rotated takes a single file");
+        };
+        Box::new(RotatedSpool::start(live)?)
+    } else if args.merge {
+        let (list, _) = FileList::from_args(&args.paths)?;
+        let paths = list
+            .files()
+            .iter()
+            .map(|f| f.path.clone())
+            .collect::<Vec<_>>();
+        let by_time = args
+            .by_time
+            .then(|| TimestampParser::new(&config.config.timestamp_formats))
+            .transpose()?;
+        Box::new(MergeSpool::start(&paths, by_time)?)
+    } else {
+        let (list, first) = FileList::from_args(&args.paths)?;
+        return Ok(Input::Files(list, first));
+    };
 
-    Ok(())
+    Ok(Input::Spooled(spool))
 }
@@ -0,0 +1,323 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info};
+use regex::Regex;
+use tokio::task::JoinHandle;
+
+use crate::backing_file::{Compression, LineDelimiter};
+use crate::ffile::{FFReqSender, FFResp, FFile};
+use crate::filter_cache::FilterCache;
+use crate::ifile::{FileReqSender, IFResp, IFile};
+use crate::input_source::{DecompressSource, FileSource, InputSource, SpoolSource};
+use crate::retry::RetryPolicy;
+use crate::spool::{is_fifo, SpoolDone};
+use crate::timestamp::{TimeWindow, TimestampParser};
+
+/// A file being read by its own IFile and FFile. They are stopped when this is dropped.
+#[derive(Debug)]
+pub struct OpenFile {
+    pub path: String,
+    pub ifreq_sender: FileReqSender<IFResp<String>>,
+    pub ffreq_sender: FileReqSender<FFResp>,
+    pub ff_sender: FFReqSender,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Drop for OpenFile {
+    fn drop(&mut self) {
+        debug!("Closing file: {}", self.path);
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Opens files with the options given on the command line, at start up and when switching files.
+#[derive(Debug, Clone)]
+pub struct FileOpener {
+    timestamps: TimestampParser,
+    time_window: Option<TimeWindow>,
+    ripgrep: bool,
+    max_line_bytes: Option<usize>,
+    follow_links: bool,
+    follow_name: bool,
+    // Wait for files that don't exist yet to be created.
+    wait_for_files: bool,
+    // Start with only the last lines of each file, indexing the rest in the background.
+    tail_lines: Option<usize>,
+    // The line delimiter for every file, else the first for the file's name, else newlines.
+    delimiter: Option<LineDelimiter>,
+    file_delimiters: Vec<(Regex, LineDelimiter)>,
+    // Lines starting with these are left out, e.g. the tags of hidden merged files.
+    hidden_prefixes: Vec<String>,
+    // Lines matching these are left out whilst ignoring is on, e.g. known noise.
+    ignore_patterns: Vec<Regex>,
+    ignoring: bool,
+    filter_cache: Option<FilterCache>,
+    retry_policy: RetryPolicy,
+    // The spool file of a stream given on the command line, only spooled once the stream is done.
+    spool: Option<(String, SpoolDone)>,
+}
+
+impl FileOpener {
+    pub fn new(timestamps: TimestampParser) -> Self {
+        FileOpener {
+            timestamps,
+            time_window: None,
+            ripgrep: false,
+            max_line_bytes: None,
+            follow_links: false,
+            follow_name: false,
+            wait_for_files: false,
+            tail_lines: None,
+            delimiter: None,
+            file_delimiters: Vec::new(),
+            hidden_prefixes: Vec::new(),
+            ignore_patterns: Vec::new(),
+            ignoring: true,
+            filter_cache: None,
+            retry_policy: RetryPolicy::default(),
+            spool: None,
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+
+        self
+    }
+
+    pub fn with_time_window(mut self, time_window: TimeWindow) -> Self {
+        self.time_window = Some(time_window);
+
+        self
+    }
+
+    pub fn with_ripgrep(mut self) -> Self {
+        self.ripgrep = true;
+
+        self
+    }
+
+    pub fn with_max_line_bytes(mut self, max_line_bytes: usize) -> Self {
+        self.max_line_bytes = Some(max_line_bytes);
+
+        self
+    }
+
+    pub fn with_follow_links(mut self) -> Self {
+        self.follow_links = true;
+
+        self
+    }
+
+    pub fn follows_links(&self) -> bool {
+        self.follow_links
+    }
+
+    pub fn with_follow_name(mut self) -> Self {
+        self.follow_name = true;
+
+        self
+    }
+
+    pub fn with_wait_for_files(mut self) -> Self {
+        self.wait_for_files = true;
+
+        self
+    }
+
+    pub fn with_tail_lines(mut self, lines: usize) -> Self {
+        self.tail_lines = Some(lines);
+
+        self
+    }
+
+    pub fn with_delimiter(mut self, delimiter: LineDelimiter) -> Self {
+        self.delimiter = Some(delimiter);
+
+        self
+    }
+
+    /// Split files with names matching the regex on the delimiter.
+    pub fn with_file_delimiter(mut self, name: Regex, delimiter: LineDelimiter) -> Self {
+        self.file_delimiters.push((name, delimiter));
+
+        self
+    }
+
+    pub fn with_hidden_prefixes(mut self, hidden_prefixes: Vec<String>) -> Self {
+        self.hidden_prefixes = hidden_prefixes;
+
+        self
+    }
+
+    pub fn with_ignore_patterns(mut self, ignore_patterns: Vec<Regex>) -> Self {
+        self.ignore_patterns = ignore_patterns;
+
+        self
+    }
+
+    /// Leave out the lines matching the ignore patterns, or read them all.
+    pub fn with_ignoring(mut self, ignoring: bool) -> Self {
+        self.ignoring = ignoring;
+
+        self
+    }
+
+    pub fn has_ignore_patterns(&self) -> bool {
+        !self.ignore_patterns.is_empty()
+    }
+
+    /// Whether lines are being left out by the ignore patterns.
+    pub fn ignoring(&self) -> bool {
+        self.ignoring && self.has_ignore_patterns()
+    }
+
+    /// Start filters from the matches cached for the file, caching them in turn.
+    pub fn with_filter_cache(mut self, filter_cache: FilterCache) -> Self {
+        self.filter_cache = Some(filter_cache);
+
+        self
+    }
+
+    /// Read the file as a stream's spool file, spooled once the spool is done.
+    pub fn with_spool(mut self, path: &str, done: SpoolDone) -> Self {
+        self.spool = Some((path.to_owned(), done));
+
+        self
+    }
+
+    fn delimiter_for(&self, path: &str) -> LineDelimiter {
+        if let Some(delimiter) = &self.delimiter {
+            return delimiter.clone();
+        }
+
+        let name = Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.file_delimiters
+            .iter()
+            .find(|(regex, _)| regex.is_match(&name))
+            .map(|(_, delimiter)| delimiter.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn open(&self, path: &str) -> Result<OpenFile> {
+        let delimiter = self.delimiter_for(path);
+        let mut source = if self.wait_for_files && !Path::new(path).exists() {
+            debug!("Waiting for {} to be created", path);
+            FileSource::pending(path)
+        } else if is_fifo(path) {
+            // Opening a pipe waits for a writer, and it can't be read again, so it is only read
+            // when spooled from the command line.
+            bail!("Can't open a named pipe here, open it with otail {}", path);
+        } else {
+            // Quickly check the file before starting... can produce a better error.
+            File::open(path).map_err(|e| anyhow!("Failed to open: {} - {:?}", path, e))?;
+            if let Some(compression) = Compression::detect(path) {
+                let source = DecompressSource::new(path, compression, delimiter.clone())?;
+                return self.open_source(path, source, delimiter, false);
+            }
+            FileSource::new(path)?
+        }
+        .with_delimiter(delimiter.clone());
+        if self.follow_links {
+            source = source.with_follow_link();
+        }
+        if self.follow_name {
+            source = source.with_follow_name();
+        }
+        if self.wait_for_files {
+            source = source.with_wait_for_file();
+        }
+
+        match &self.spool {
+            Some((spool, done)) if spool == path => self.open_source(
+                path,
+                SpoolSource::new(source, done.clone()),
+                delimiter,
+                true,
+            ),
+            _ => self.open_source(path, source, delimiter, true),
+        }
+    }
+
+    // Open a file read from the source. Ripgrep and the filter cache read the file at the path
+    // itself, so are only used if its lines are as they are read, e.g. not if it is decompressed.
+    fn open_source<S: InputSource + Send + 'static>(
+        &self,
+        path: &str,
+        source: S,
+        delimiter: LineDelimiter,
+        as_read: bool,
+    ) -> Result<OpenFile> {
+        let mut ifile = IFile::new(path, source)
+            .with_timestamps(self.timestamps.clone())
+            .with_retry_policy(self.retry_policy);
+        if let Some(time_window) = &self.time_window {
+            ifile = ifile.with_time_window(time_window.clone());
+        }
+        if let Some(max_line_bytes) = self.max_line_bytes {
+            ifile = ifile.with_max_line_bytes(max_line_bytes);
+        }
+        if let Some(lines) = self.tail_lines {
+            ifile = ifile.with_tail_lines(lines);
+        }
+        if !self.hidden_prefixes.is_empty() {
+            ifile = ifile.with_hidden_prefixes(self.hidden_prefixes.clone());
+        }
+        if self.ignoring() {
+            ifile = ifile.with_ignore_patterns(self.ignore_patterns.clone());
+        }
+        let leaving_out = !self.hidden_prefixes.is_empty() || self.ignoring();
+        // Lines are numbered from the last lines until the rest are indexed, unlike ripgrep's and
+        // cached matches.
+        let renumbered = self.tail_lines.is_some();
+        let mut ffile = FFile::new("ff".to_owned(), path, ifile.get_bulk_sender());
+        // Ripgrep only splits on newlines, and counts the lines left out and those outside a time
+        // window.
+        if self.ripgrep
+            && self.time_window.is_none()
+            && as_read
+            && delimiter == LineDelimiter::Newline
+            && !leaving_out
+            && !renumbered
+        {
+            ffile = ffile.with_ripgrep();
+        }
+        // Cached matches are by line number, so can't be used when lines are numbered differently.
+        if let Some(filter_cache) = &self.filter_cache {
+            if self.time_window.is_none()
+                && as_read
+                && !leaving_out
+                && !renumbered
+                && delimiter == LineDelimiter::Newline
+            {
+                ffile = ffile.with_cache(filter_cache.clone());
+            }
+        }
+
+        let open_file = OpenFile {
+            path: path.to_owned(),
+            ifreq_sender: ifile.get_view_sender(),
+            ffreq_sender: ffile.get_view_sender(),
+            ff_sender: ffile.get_ff_sender(),
+            tasks: vec![
+                tokio::spawn(async move {
+                    let result = ifile.run().await;
+                    info!("IFile finished: {:?}", result);
+                }),
+                tokio::spawn(async move {
+                    let result = ffile.run().await;
+                    info!("FFile finished: {:?}", result);
+                }),
+            ],
+        };
+
+        Ok(open_file)
+    }
+}
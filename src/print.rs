@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::Result;
+use log::{debug, trace};
+use ratatui::crossterm::style::{self, Stylize};
+use tokio::select;
+use tokio::sync::mpsc;
+
+use crate::colour_spec::ColouringSpec;
+use crate::common::CHANNEL_BUFFER;
+use crate::ffile::{FFReq, FFResp};
+use crate::filter_spec::FilterSpec;
+use crate::ifile::{FileReq, FileResp, IFResp};
+use crate::opener::OpenFile;
+use crate::tui::colour_to_color;
+
+const PRINT_ID: &str = "print";
+
+/// Prints the lines of a file matching a filter, like grep, for scripts and pipelines. The file is
+/// read and filtered as it is for the TUI, so the same filters and colouring can be used.
+pub struct Printer {
+    filter_spec: FilterSpec,
+    colouring: Option<ColouringSpec>,
+    follow: bool,
+}
+
+impl Printer {
+    pub fn new(filter_spec: FilterSpec) -> Self {
+        Printer {
+            filter_spec,
+            colouring: None,
+            follow: false,
+        }
+    }
+
+    /// Colour the lines printed, e.g. when printing to a terminal.
+    pub fn with_colouring(mut self, colouring: ColouringSpec) -> Self {
+        self.colouring = Some(colouring);
+
+        self
+    }
+
+    /// Keep printing lines as they are written, like `tail -f`, rather than stopping at the end.
+    pub fn with_follow(mut self) -> Self {
+        self.follow = true;
+
+        self
+    }
+
+    pub async fn run(self, file: &OpenFile, out: &mut impl Write) -> Result<()> {
+        let (ifresp_sender, mut ifresp_recv) = mpsc::channel(CHANNEL_BUFFER);
+        file.ifreq_sender
+            .send(FileReq::RegisterClient {
+                id: PRINT_ID.to_owned(),
+                client_sender: ifresp_sender,
+            })
+            .await?;
+        let (ffresp_sender, mut ffresp_recv) = mpsc::channel(CHANNEL_BUFFER);
+        file.ffreq_sender
+            .send(FileReq::RegisterClient {
+                id: PRINT_ID.to_owned(),
+                client_sender: ffresp_sender,
+            })
+            .await?;
+        file.ff_sender
+            .send(FFReq::SetFilter {
+                filter_spec: Some(self.filter_spec.clone()),
+                from: 0,
+                to: None,
+            })
+            .await?;
+        // Every match is sent as it is found, including those found already.
+        file.ffreq_sender
+            .send(FileReq::EnableTailing {
+                id: PRINT_ID.to_owned(),
+                last_seen_line: 0,
+            })
+            .await?;
+
+        let mut matches = Matches::default();
+        // The lines read and filtered so far, and the lines in the file as it was when opened.
+        let mut file_lines = 0;
+        let mut filtered_lines = 0;
+        let mut spooled_lines = None;
+        loop {
+            select! {
+                resp = ifresp_recv.recv() => match resp {
+                    Some(IFResp::ViewUpdate { update: FileResp::Stats { file_lines: lines, .. } }) => {
+                        file_lines = lines;
+                    }
+                    Some(IFResp::Spooled) => spooled_lines = Some(file_lines),
+                    Some(IFResp::FileError { reason }) => anyhow::bail!("{}: {}", file.path, reason),
+                    Some(resp) => trace!("Ignoring file response whilst printing: {:?}", resp),
+                    None => break,
+                },
+                resp = ffresp_recv.recv() => match resp {
+                    Some(FFResp::ViewUpdate { update: FileResp::Stats { view_lines, file_lines, .. } }) => {
+                        matches.found = view_lines;
+                        filtered_lines = file_lines;
+                    }
+                    Some(FFResp::ViewUpdate { update: FileResp::Line { line_no, line_content, partial } }) => {
+                        matches.add(line_no, line_content.line, partial);
+                        for line in matches.complete() {
+                            self.print(out, &line)?;
+                        }
+                        if self.follow {
+                            out.flush()?;
+                        }
+                    }
+                    // The file was truncated or replaced, so is filtered again from the start.
+                    Some(FFResp::Clear) => matches = Matches::default(),
+                    None => break,
+                },
+            }
+
+            let done = spooled_lines.is_some_and(|lines| filtered_lines >= lines);
+            if !self.follow && done && matches.all_received() {
+                debug!("Printed {} matches from {}", matches.found, file.path);
+                break;
+            }
+        }
+
+        // A last line not ended yet is printed as it is.
+        for line in matches.rest() {
+            self.print(out, &line)?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+
+    fn print(&self, out: &mut impl Write, line: &str) -> Result<()> {
+        let colours = self.colouring.as_ref().and_then(|c| c.maybe_colour(line));
+        match colours {
+            Some((fg, bg)) => {
+                let mut styled = style::style(line);
+                if let Some(fg) = fg {
+                    styled = styled.with(colour_to_color(fg).into());
+                }
+                if let Some(bg) = bg {
+                    styled = styled.on(colour_to_color(bg).into());
+                }
+                writeln!(out, "{}", styled)?;
+            }
+            None => writeln!(out, "{}", line)?,
+        }
+
+        Ok(())
+    }
+}
+
+// The matches received, printed in order once whole.
+#[derive(Debug, Default)]
+struct Matches {
+    // How many the filter has found.
+    found: usize,
+    // The next to print.
+    next: usize,
+    // Those received but not printed yet, and whether they are partial.
+    received: BTreeMap<usize, (String, bool)>,
+}
+
+impl Matches {
+    fn add(&mut self, match_no: usize, line: String, partial: bool) {
+        if match_no >= self.next {
+            self.received.insert(match_no, (line, partial));
+        }
+    }
+
+    // The whole lines that can be printed next.
+    fn complete(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let Some((_, false)) = self.received.get(&self.next) {
+            if let Some((line, _)) = self.received.remove(&self.next) {
+                lines.push(line);
+            }
+            self.next += 1;
+        }
+
+        lines
+    }
+
+    fn all_received(&self) -> bool {
+        (self.next..self.found).all(|match_no| self.received.contains_key(&match_no))
+    }
+
+    fn rest(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.received)
+            .into_values()
+            .map(|(line, _)| line)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::DEFAULT_MAX_LINE_BYTES;
+    use crate::filter_spec::FilterType;
+    use crate::opener::FileOpener;
+    use crate::timestamp::TimestampParser;
+
+    #[test]
+    fn test_matches() {
+        let mut matches = Matches {
+            found: 3,
+            ..Default::default()
+        };
+        matches.add(1, "second".to_owned(), false);
+        assert!(matches.complete().is_empty());
+        matches.add(0, "fir".to_owned(), true);
+        assert!(matches.complete().is_empty());
+        matches.add(0, "first".to_owned(), false);
+        assert_eq!(matches.complete(), vec!["first", "second"]);
+        assert!(!matches.all_received());
+
+        matches.add(2, "thi".to_owned(), true);
+        assert!(matches.all_received());
+        assert!(matches.complete().is_empty());
+        assert_eq!(matches.rest(), vec!["thi"]);
+    }
+
+    #[tokio::test]
+    async fn test_print_long_line() {
+        let path = std::env::temp_dir().join(format!("otail-test-print-{}", std::process::id()));
+        let long = format!("{}needle", "x".repeat(DEFAULT_MAX_LINE_BYTES));
+        std::fs::write(&path, format!("short\n{}\nneedle\n", long)).unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        // Opened without a cap, as when printing, a match past the cap is found and printed whole.
+        let file = FileOpener::new(TimestampParser::default())
+            .open(&path)
+            .unwrap();
+        let filter_spec = FilterSpec::new(FilterType::SimpleCaseSensitive, "needle").unwrap();
+        let mut out = Vec::new();
+        Printer::new(filter_spec)
+            .run(&file, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{}\nneedle\n", long)
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,568 @@
+use anyhow::{bail, Result};
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::analysis::{ResultRow, ResultTable};
+use crate::columns::compare_cells;
+use crate::json_query::{value_text, JsonPath, JsonPredicate};
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Clause {
+    Select,
+    Where,
+    OrderBy,
+    Limit,
+}
+
+/// A query over the fields of structured lines, e.g.
+/// `select ts, msg where level = 'error' and duration > 100 order by duration desc limit 20`.
+///
+/// Fields are names or paths into JSON lines, such as `response.status`, or the keys of logfmt
+/// lines. `where` takes the conditions of a JSON filter, with strings quoted with `'` or `"`.
+/// Lines that aren't JSON or logfmt are left out.
+#[derive(Debug, Clone)]
+pub struct Query {
+    text: String,
+    // The selected fields as written and their paths, None for `*`.
+    fields: Option<Vec<(String, JsonPath)>>,
+    predicate: Option<JsonPredicate>,
+    // The field to order by, and if descending.
+    order_by: Option<(JsonPath, bool)>,
+    limit: Option<usize>,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self> {
+        let words = words(query);
+        let mut clauses: Vec<(Clause, usize, usize)> = Vec::new();
+        let mut i = 0;
+        while i < words.len() {
+            let (start, word) = words[i];
+            let clause = match word.to_lowercase().as_str() {
+                "select" => Some((Clause::Select, 1)),
+                "where" => Some((Clause::Where, 1)),
+                "order"
+                    if words
+                        .get(i + 1)
+                        .is_some_and(|(_, w)| w.eq_ignore_ascii_case("by")) =>
+                {
+                    Some((Clause::OrderBy, 2))
+                }
+                "limit" => Some((Clause::Limit, 1)),
+                _ => None,
+            };
+            match clause {
+                Some((clause, len)) => {
+                    if clauses.last().is_some_and(|(last, _, _)| *last >= clause) {
+                        bail!("Unexpected '{}' in: {}", word, query);
+                    }
+                    let body = words.get(i + len).map_or(query.len(), |(start, _)| *start);
+                    clauses.push((clause, start, body));
+                    i += len;
+                }
+                None if i == 0 => bail!("A query starts with 'select': {}", query),
+                None => i += 1,
+            }
+        }
+
+        // Each clause runs up to the next one.
+        let body = |clause| {
+            let at = clauses.iter().position(|(c, _, _)| *c == clause)?;
+            let end = clauses
+                .get(at + 1)
+                .map_or(query.len(), |(_, start, _)| *start);
+            Some(query[clauses[at].2.min(end)..end].trim())
+        };
+
+        let fields = match body(Clause::Select) {
+            Some("*") => None,
+            Some(select) if !select.is_empty() => Some(
+                select
+                    .split(',')
+                    .map(|name| {
+                        let name = name.trim();
+                        Ok((name.to_owned(), field_path(name)?))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            _ => bail!("Expected the fields to select, or *, in: {}", query),
+        };
+
+        let predicate = match body(Clause::Where) {
+            Some(condition) => Some(JsonPredicate::parse(&predicate_expr(condition))?),
+            None => None,
+        };
+
+        let order_by = match body(Clause::OrderBy) {
+            Some(order) => {
+                let mut parts = order.split_whitespace();
+                let field = parts.next().map(field_path).transpose()?;
+                let descending = match parts.next().map(|d| d.to_lowercase()).as_deref() {
+                    None | Some("asc") => false,
+                    Some("desc") => true,
+                    Some(other) => bail!("Expected asc or desc, not '{}' in: {}", other, query),
+                };
+                match (field, parts.next()) {
+                    (Some(field), None) => Some((field, descending)),
+                    _ => bail!("Expected a field to order by in: {}", query),
+                }
+            }
+            None => None,
+        };
+
+        let limit = match body(Clause::Limit) {
+            Some(limit) => match limit.parse() {
+                Ok(limit) => Some(limit),
+                Err(_) => bail!("Expected a number of rows to limit to in: {}", query),
+            },
+            None => None,
+        };
+
+        Ok(Query {
+            text: query.trim().to_owned(),
+            fields,
+            predicate,
+            order_by,
+            limit,
+        })
+    }
+
+    /// The fields of the line, if it has any and they pass the conditions.
+    pub fn matching(&self, line: &str) -> Option<Value> {
+        let record = parse_record(line)?;
+        match &self.predicate {
+            Some(predicate) if !predicate.matches_value(&record) => None,
+            _ => Some(record),
+        }
+    }
+
+    /// Whether enough rows have been found to stop collecting them. All are needed to order them.
+    pub fn is_full(&self, rows: usize) -> bool {
+        self.order_by.is_none() && self.limit.is_some_and(|limit| rows >= limit)
+    }
+
+    /// The table of the selected fields of the matching records, by file line.
+    pub fn table(&self, mut records: Vec<(usize, Value)>) -> ResultTable {
+        if let Some((field, descending)) = &self.order_by {
+            // Stable sort, so equal values stay in file order.
+            records.sort_by(|(_, a), (_, b)| {
+                let ordering = compare_cells(&value_text(field.get(a)), &value_text(field.get(b)));
+                if *descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+        }
+        if let Some(limit) = self.limit {
+            records.truncate(limit);
+        }
+
+        // For `*`, the fields of all the records in the order first seen.
+        let fields = self.fields.clone().unwrap_or_else(|| {
+            let mut names: Vec<String> = Vec::new();
+            for (_, record) in &records {
+                if let Value::Object(map) = record {
+                    for name in map.keys() {
+                        if !names.contains(name) {
+                            names.push(name.clone());
+                        }
+                    }
+                }
+            }
+            names
+                .into_iter()
+                .filter_map(|name| Some((name.clone(), field_path(&name).ok()?)))
+                .collect()
+        });
+
+        ResultTable {
+            title: format!("Query: {}", self.text),
+            header: fields.iter().map(|(name, _)| name.clone()).collect(),
+            rows: records
+                .iter()
+                .map(|(line_no, record)| ResultRow {
+                    line_no: Some(*line_no),
+                    cells: fields
+                        .iter()
+                        .map(|(_, path)| value_text(path.get(record)))
+                        .collect(),
+                    filter: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+// A field name such as `level` or `response.status`, with or without the leading `.` of a JSON
+// path.
+fn field_path(name: &str) -> Result<JsonPath> {
+    if name.starts_with('.') {
+        JsonPath::parse(name)
+    } else {
+        JsonPath::parse(&format!(".{}", name))
+    }
+}
+
+// The words of the query with their offsets, keeping quoted strings whole.
+fn words(query: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    for (i, c) in query.char_indices() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+        } else if c.is_whitespace() {
+            if let Some(s) = start.take() {
+                words.push((s, &query[s..i]));
+            }
+        } else {
+            start.get_or_insert(i);
+            if c == '\'' || c == '"' {
+                quote = Some(c);
+            }
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &query[s..]));
+    }
+
+    words
+}
+
+const OP_CHARS: &[char] = &['=', '!', '<', '>'];
+
+// Rewrite SQL style conditions as a JSON filter: fields become paths, single quoted strings
+// double quoted and `<>` is `!=`. Values after an operator are left as they are.
+fn predicate_expr(condition: &str) -> String {
+    let mut expr = String::new();
+    let mut after_op = false;
+    let mut rest = condition;
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_whitespace() {
+            expr.push(c);
+            c.len_utf8()
+        } else if c == '\'' {
+            // Doubled quotes are a quote, as in SQL.
+            let mut value = String::new();
+            let mut chars = rest.char_indices().skip(1).peekable();
+            let mut end = rest.len();
+            while let Some((i, c)) = chars.next() {
+                if c == '\'' && chars.next_if(|(_, c)| *c == '\'').is_none() {
+                    end = i + 1;
+                    break;
+                }
+                value.push(c);
+            }
+            expr.push_str(&Value::String(value).to_string());
+            after_op = false;
+            end
+        } else if c == '"' {
+            let mut escaped = false;
+            let end = rest[1..]
+                .find(|c| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                })
+                .map_or(rest.len(), |end| end + 2);
+            expr.push_str(&rest[..end]);
+            after_op = false;
+            end
+        } else if OP_CHARS.contains(&c) {
+            let op = &rest[..rest.find(|c| !OP_CHARS.contains(&c)).unwrap_or(rest.len())];
+            expr.push_str(&format!(" {} ", if op == "<>" { "!=" } else { op }));
+            after_op = true;
+            op.len()
+        } else {
+            let word = &rest[..rest
+                .find(|c: char| c.is_whitespace() || c == '\'' || c == '"' || OP_CHARS.contains(&c))
+                .unwrap_or(rest.len())];
+            let lower = word.to_lowercase();
+            if lower == "and" || lower == "or" {
+                expr.push_str(&lower);
+            } else if after_op || word.starts_with('.') {
+                expr.push_str(word);
+            } else {
+                expr.push('.');
+                expr.push_str(word);
+            }
+            after_op = false;
+            word.len()
+        };
+        rest = &rest[len..];
+    }
+
+    expr
+}
+
+/// A value taken from each line to group or count them by: a field of JSON or logfmt lines, such
+/// as `request_id` or `.http.status`, or a regex with a capture group, such as `req=(\w+)`,
+/// taking the group's text.
+#[derive(Debug, Clone)]
+pub enum LineField {
+    // The path and the name of its last field, e.g. `status` for `.http.status`.
+    Field { path: JsonPath, key: String },
+    Capture(Regex),
+}
+
+impl LineField {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            bail!("Give a field, or a regex with a capture group");
+        }
+
+        if spec.contains('(') {
+            Ok(LineField::Capture(Regex::new(spec)?))
+        } else {
+            let key = spec.rsplit('.').next().unwrap_or(spec);
+            Ok(LineField::Field {
+                path: field_path(spec)?,
+                key: key[..key.find('[').unwrap_or(key.len())].to_owned(),
+            })
+        }
+    }
+
+    /// A regex for lines with the value: the field set to it, or the capture group's regex with
+    /// the group replaced by it.
+    pub fn filter_regex(&self, value: &str) -> String {
+        let value = regex::escape(value);
+        match self {
+            LineField::Field { key, .. } => format!(
+                r#"\b{}"?\s*[:=]\s*"?{}(?:[",}}\s]|$)"#,
+                regex::escape(key),
+                value
+            ),
+            LineField::Capture(regex) => replace_capture(regex.as_str(), &value).unwrap_or(value),
+        }
+    }
+
+    /// The line's value, None if it has none.
+    pub fn value(&self, line: &str) -> Option<String> {
+        match self {
+            LineField::Field { path, .. } => {
+                let record = parse_record(line)?;
+                Some(value_text(path.get(&record))).filter(|v| !v.is_empty())
+            }
+            LineField::Capture(regex) => {
+                let captures = regex.captures(line)?;
+                let m = captures.get(1).or_else(|| captures.get(0))?;
+                Some(m.as_str().to_owned())
+            }
+        }
+    }
+}
+
+// The regex with its first capture group replaced, None if it has none.
+fn replace_capture(pattern: &str, replacement: &str) -> Option<String> {
+    let mut escaped = false;
+    let mut in_class = false;
+    let mut depth = 0;
+    let mut start = None;
+    for (i, c) in pattern.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            '(' if !in_class => {
+                // Groups starting `(?` don't capture, unless named.
+                let rest = &pattern[i + 1..];
+                let capturing = !rest.starts_with('?')
+                    || rest.starts_with("?P<")
+                    || (rest.starts_with("?<")
+                        && !rest.starts_with("?<=")
+                        && !rest.starts_with("?<!"));
+                if start.is_none() && capturing {
+                    start = Some((i, depth));
+                }
+                depth += 1;
+            }
+            ')' if !in_class => {
+                depth -= 1;
+                if start.is_some_and(|(_, d)| d == depth) {
+                    let (s, _) = start?;
+                    return Some(format!(
+                        "{}(?:{}){}",
+                        &pattern[..s],
+                        replacement,
+                        &pattern[i + 1..]
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// The fields of a line: a JSON object, or the `key=value` pairs of a logfmt line such as
+/// `level=info msg="request done" duration=12`. Values in logfmt are strings, compared as numbers
+/// where they are numbers. None if the line has neither.
+pub fn parse_record(line: &str) -> Option<Value> {
+    if line.trim_start().starts_with('{') {
+        return serde_json::from_str::<Value>(line)
+            .ok()
+            .filter(|value| value.is_object());
+    }
+
+    let mut map = Map::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let Some(eq) = rest[..word_end].find('=').filter(|eq| *eq > 0) else {
+            // Not a pair, e.g. a bare word before the pairs.
+            rest = rest[word_end..].trim_start();
+            continue;
+        };
+        let key = &rest[..eq];
+        rest = &rest[eq + 1..];
+
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    '\\' => {
+                        if let Some((_, c)) = chars.next() {
+                            value.push(c);
+                        }
+                    }
+                    _ => value.push(c),
+                }
+            }
+            rest = &quoted[end..];
+            value
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let value = rest[..end].to_owned();
+            rest = &rest[end..];
+            value
+        };
+        map.insert(key.to_owned(), Value::String(value));
+        rest = rest.trim_start();
+    }
+
+    (!map.is_empty()).then_some(Value::Object(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_record() {
+        assert_eq!(
+            parse_record(r#"{"level":"error","n":3}"#),
+            Some(serde_json::json!({"level": "error", "n": 3}))
+        );
+        assert_eq!(
+            parse_record(r#"10:00 level=warn msg="disk \"sda\" full" duration=12"#),
+            Some(
+                serde_json::json!({"level": "warn", "msg": "disk \"sda\" full", "duration": "12"})
+            )
+        );
+        assert_eq!(parse_record("just some text"), None);
+        assert_eq!(parse_record("[1, 2]"), None);
+    }
+
+    #[test]
+    fn test_line_field() {
+        let field = LineField::parse("request_id").unwrap();
+        assert_eq!(
+            field.value(r#"{"request_id":"a1","msg":"start"}"#),
+            Some("a1".to_owned())
+        );
+        assert_eq!(
+            field.value("level=info request_id=b2"),
+            Some("b2".to_owned())
+        );
+        assert_eq!(field.value("level=info"), None);
+
+        let capture = LineField::parse(r"req=(\w+)").unwrap();
+        assert_eq!(capture.value("GET / req=c3 200"), Some("c3".to_owned()));
+        assert_eq!(capture.value("GET / 200"), None);
+
+        let filter = |field: &LineField, value| Regex::new(&field.filter_regex(value)).unwrap();
+        let status = filter(&LineField::parse(".http.status").unwrap(), "200");
+        assert!(status.is_match(r#"{"http":{"status":200}}"#));
+        assert!(status.is_match("status=200 path=/"));
+        assert!(!status.is_match("status=2000"));
+        let req = filter(&capture, "c3");
+        assert_eq!(req.as_str(), r"req=(?:c3)");
+        assert_eq!(
+            filter(&LineField::parse(r"(?:id|ref) ([a-z]+)").unwrap(), "x.y").as_str(),
+            r"(?:id|ref) (?:x\.y)"
+        );
+
+        assert!(LineField::parse(" ").is_err());
+        assert!(LineField::parse("req=(").is_err());
+    }
+
+    #[test]
+    fn test_query() {
+        let lines = [
+            r#"{"ts":"10:00","level":"error","msg":"timeout","duration":250}"#,
+            "ts=10:01 level=info msg=\"all good\" duration=20",
+            "ts=10:02 level=error msg=\"it's slow\" duration=120",
+            r#"{"ts":"10:03","level":"error","msg":"quick","duration":50}"#,
+            "plain text",
+        ];
+        let run = |query: &str| {
+            let query = Query::parse(query).unwrap();
+            let records = lines
+                .iter()
+                .enumerate()
+                .filter_map(|(i, l)| query.matching(l).map(|r| (i, r)))
+                .collect::<Vec<_>>();
+            let table = query.table(records);
+            (
+                table.header,
+                table
+                    .rows
+                    .into_iter()
+                    .map(|r| (r.line_no.unwrap(), r.cells))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let (header, rows) = run("select ts,msg where level='error' and duration>100");
+        assert_eq!(header, vec!["ts", "msg"]);
+        assert_eq!(
+            rows,
+            vec![
+                (0, vec!["10:00".to_owned(), "timeout".to_owned()]),
+                (2, vec!["10:02".to_owned(), "it's slow".to_owned()]),
+            ]
+        );
+
+        let (_, rows) = run("SELECT msg WHERE level <> error ORDER BY duration DESC LIMIT 1");
+        assert_eq!(rows, vec![(1, vec!["all good".to_owned()])]);
+
+        let (_, rows) = run("select duration order by duration limit 3");
+        let durations = rows.iter().map(|(_, r)| r[0].as_str()).collect::<Vec<_>>();
+        assert_eq!(durations, vec!["20", "50", "120"]);
+
+        let (header, rows) = run("select * where msg = 'it''s slow'");
+        assert_eq!(header, vec!["duration", "level", "msg", "ts"]);
+        assert_eq!(rows.len(), 1);
+
+        assert!(Query::parse("ts, msg").is_err());
+        assert!(Query::parse("select where level = x").is_err());
+        assert!(Query::parse("select a limit 1 where b").is_err());
+        assert!(Query::parse("select a order by b sideways").is_err());
+        assert!(Query::parse("select a limit lots").is_err());
+    }
+}
@@ -1,13 +1,25 @@
 use crate::backing_file::BackingFile;
+use crate::common::LINK_CHECK_MS;
 use anyhow::Result;
-use log::{error, trace};
+use log::{debug, error, trace, warn};
 use notify::{Config, Event, EventKind, RecommendedWatcher, Watcher};
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::runtime::Runtime;
+use tokio::select;
 use tokio::sync::mpsc::{self, Receiver};
+use tokio::time::interval;
 
-use crate::backing_file::FileBackingFile;
+use crate::backing_file::{FileBackingFile, LineDelimiter};
+
+// How much of the first lines read is kept, to tell when the file has been written again from the
+// start.
+const HEAD_BYTES: usize = 1024;
+
+// How much is read at a time when looking back from the end of a file for its last lines.
+const TAIL_BLOCK_BYTES: u64 = 64 * 1024;
 
 #[derive(Debug)]
 pub enum ReaderUpdate {
@@ -19,9 +31,43 @@ pub enum ReaderUpdate {
         file_bytes: u64,
     },
     Truncated,
+    // The symlink being followed now points at another file, which is read from the start.
+    Relinked {
+        target: PathBuf,
+    },
+    // Another file was moved into place of the one read, or following by name, it was moved away or
+    // removed and another created in its place, as on rotation. The new file is read from the start.
+    Rotated {
+        target: PathBuf,
+    },
     FileError {
         reason: String,
     },
+    // The source failed in a way reading it again won't fix, e.g. a corrupt compressed file.
+    Failed {
+        reason: String,
+    },
+    // The file doesn't exist yet, so is waited for.
+    Waiting,
+    // The file waited for has been created, and is read from the start.
+    Created,
+    // The file has been read to the end, as it was when the reader started.
+    Spooled,
+}
+
+/// How the reader reads a file.
+#[derive(Debug, Clone, Default)]
+pub struct ReaderOptions {
+    /// Read the file a symlink points to now, when it is re-pointed.
+    pub follow_link: bool,
+    /// Read the file with the name now, when the one read is moved away or removed.
+    pub follow_name: bool,
+    /// Wait for the file to be created if it doesn't exist yet, rather than failing.
+    pub wait_for_file: bool,
+    pub delimiter: LineDelimiter,
+    /// Only the start of longer lines is kept and sent, a little over this so they are known to be
+    /// longer, rather than the whole line each time more of it is read.
+    pub max_line_bytes: Option<usize>,
 }
 
 pub type ReaderUpdateSender = mpsc::Sender<ReaderUpdate>;
@@ -30,26 +76,57 @@ pub type ReaderUpdateReceiver = mpsc::Receiver<ReaderUpdate>;
 pub struct Reader {}
 
 impl Reader {
-    pub async fn run(path: PathBuf, sender: ReaderUpdateSender) -> Result<()> {
-        let metadata_file = File::open(&path)?;
+    // Read the file from the start offset, then tail it. When following a symlink, the file it
+    // points to is read again if the link is re-pointed. When following by name, the file with the
+    // name is read once the one being read is moved away or removed, after reading the rest of it.
+    // When waiting for the file, it is read once created if it doesn't exist yet.
+    pub async fn run(
+        path: PathBuf,
+        options: ReaderOptions,
+        start: u64,
+        sender: ReaderUpdateSender,
+    ) -> Result<()> {
+        let ReaderOptions {
+            follow_link,
+            follow_name,
+            wait_for_file,
+            delimiter,
+            max_line_bytes,
+        } = options;
+
+        if wait_for_file && !path.exists() {
+            sender.send(ReaderUpdate::Waiting).await?;
+            wait_for(&path).await?;
+            debug!("File created: {:?}", path);
+            sender.send(ReaderUpdate::Created).await?;
+        }
+
+        let mut metadata_file = File::open(&path)?;
+        let mut target = fs::canonicalize(&path)?;
 
-        let mut bf = FileBackingFile::new(&path)?;
+        let mut bf = FileBackingFile::new(&path)?.with_delimiter(delimiter.clone());
 
         trace!("Opened file: {:?}", path);
 
-        // Start by spooling the file
-        let mut pos = 0;
+        // Start by spooling the file, from where a previous reader got to if restarted.
+        bf.seek(start)?;
+        let mut pos = start;
         let mut line = String::new();
         let mut line_bytes = 0;
+        // Whether only the start of the line is kept.
+        let mut line_cut = false;
         let mut previous_partial = false;
         let mut file_lines: usize = 0;
         let mut line_offset = 0;
+        // The first lines read.
+        let mut head = Head::default();
 
         trace!("Spooling file: {:?}", path);
         loop {
             if !previous_partial {
                 line.clear();
                 line_bytes = 0;
+                line_cut = false;
                 line_offset = pos;
             }
 
@@ -63,15 +140,17 @@ impl Reader {
 
             line_bytes += bytes;
             pos += bytes as u64;
+            line_cut |= max_line_bytes.is_some_and(|max| cut_read(&mut line, max));
 
             if !previous_partial {
                 file_lines += 1;
             }
 
+            head.add(line_offset, &line, partial || line_cut);
             trace!("Sending ReaderUpdate::Line (spooling) - line_bytes: {}, partial: {}, file_bytes: {}", line_bytes, partial, pos);
             sender
                 .send(ReaderUpdate::Line {
-                    // Deliver the whole line each time we send the line.
+                    // Deliver the whole line, or its start, each time we send the line.
                     line_content: line.clone(),
                     offset: line_offset,
                     line_bytes,
@@ -83,16 +162,90 @@ impl Reader {
             previous_partial = partial;
         }
 
+        sender.send(ReaderUpdate::Spooled).await?;
+
         // Now tail the file.
         trace!("Tailing file: {:?} {} lines", path, file_lines);
         let (mut watcher, mut rx) = async_watcher()?;
         watcher.watch(&path, notify::RecursiveMode::Recursive)?;
 
+        let mut link_check = interval(Duration::from_millis(LINK_CHECK_MS));
+        // Anything written whilst the watch was being set up is read straight away, rather than
+        // once the file is next written to.
+        let mut catch_up = metadata_file.metadata()?.len() > pos;
+
         trace!("Waiting to receive file system events for path: {:?}", path);
-        while let Some(m) = rx.recv().await {
+        loop {
+            let m = select! {
+                m = rx.recv() => match m {
+                    Some(m) => m,
+                    None => break,
+                },
+                _ = std::future::ready(()), if catch_up => {
+                    catch_up = false;
+                    Ok(Event::new(EventKind::Any))
+                }
+                _ = link_check.tick(), if follow_link || follow_name => {
+                    let (new_target, rotated) = match relinked(&path, &target) {
+                        Some(new_target) if follow_link => (new_target, false),
+                        _ if follow_name && replaced(&path, &metadata_file) => {
+                            (fs::canonicalize(&path)?, true)
+                        }
+                        _ => continue,
+                    };
+                    // Finish the old file first, it may have been written to since it was moved.
+                    if rotated && metadata_file.metadata()?.len() > pos {
+                        trace!("File replaced, reading the rest of the old one: {:?}", path);
+                        Ok(Event::new(EventKind::Any))
+                    } else {
+                        let update = match rotated {
+                            true => {
+                                debug!("{:?} replaced by {:?}, following it", path, new_target);
+                                ReaderUpdate::Rotated { target: new_target.clone() }
+                            }
+                            false => {
+                                debug!("Link {:?} now points to {:?}", path, new_target);
+                                ReaderUpdate::Relinked { target: new_target.clone() }
+                            }
+                        };
+
+                        // Read the new file from the start, as if it was truncated.
+                        (metadata_file, bf) = reopen(&mut watcher, &path, &new_target, &delimiter)?;
+                        target = new_target;
+
+                        line.clear();
+                        line_bytes = 0;
+                        previous_partial = false;
+                        line_offset = 0;
+                        pos = 0;
+                        head.clear();
+
+                        sender.send(update).await?;
+
+                        Ok(Event::new(EventKind::Any))
+                    }
+                }
+            };
             trace!("Received file system event: {:?}", m);
             match m {
                 Ok(event) => {
+                    // A followed link may be re-pointed before its old file is removed.
+                    if let (EventKind::Remove(_), true) = (&event.kind, follow_link) {
+                        if fs::canonicalize(&path).is_ok() {
+                            trace!("Linked file removed, waiting for the link: {:?}", target);
+                            link_check.reset_immediately();
+                            continue;
+                        }
+                    }
+
+                    // Following by name, wait for another file to be created in place of one
+                    // removed.
+                    if let (EventKind::Remove(_), true) = (&event.kind, follow_name) {
+                        trace!("File removed, waiting for another: {:?}", path);
+                        link_check.reset_immediately();
+                        continue;
+                    }
+
                     if let EventKind::Remove(_) = event.kind {
                         trace!("File or directory removed: {:?}", path);
 
@@ -106,16 +259,47 @@ impl Reader {
                         return Ok(());
                     }
 
-                    let new_size = fs::metadata(&path)?.len();
+                    // Another file moved into place of the one being read, e.g. `mv new.log
+                    // app.log`, is read from the start. Following by name or a link, that is
+                    // checked for regularly instead.
+                    if !follow_name
+                        && relinked(&path, &target).is_none()
+                        && replaced(&path, &metadata_file)
+                    {
+                        debug!("{:?} replaced by another file, reading it", path);
+                        (metadata_file, bf) = reopen(&mut watcher, &path, &target, &delimiter)?;
+
+                        line.clear();
+                        line_bytes = 0;
+                        previous_partial = false;
+                        line_offset = 0;
+                        pos = 0;
+                        head.clear();
+
+                        sender
+                            .send(ReaderUpdate::Rotated {
+                                target: target.clone(),
+                            })
+                            .await?;
+                    }
+
+                    // Following by name, the name may already be another file's.
+                    let new_size = match follow_name {
+                        true => metadata_file.metadata()?.len(),
+                        false => fs::metadata(&target)?.len(),
+                    };
+
+                    // The file may have been truncated and written again to as long or longer
+                    // before the event arrived, so the first lines read are checked too.
+                    let rewritten = head.rewritten(&mut bf);
 
-                    if new_size < pos {
-                        // TODO: Is there a way to detect file truncation where the new content is
-                        // longer than the old content?
+                    if new_size < pos || rewritten {
                         trace!(
-                            "File truncated: {:?}, old size: {}, new size: {}",
+                            "File truncated: {:?}, old size: {}, new size: {}, rewritten: {}",
                             path,
                             pos,
-                            new_size
+                            new_size,
+                            rewritten
                         );
 
                         trace!(
@@ -130,8 +314,9 @@ impl Reader {
                         previous_partial = false;
                         line_offset = 0;
                         pos = 0;
+                        head.clear();
 
-                        bf = FileBackingFile::new(&path)?;
+                        bf = FileBackingFile::new(&target)?.with_delimiter(delimiter.clone());
                     }
 
                     let fmd = metadata_file.metadata()?;
@@ -146,6 +331,7 @@ impl Reader {
                         if !previous_partial {
                             line.clear();
                             line_bytes = 0;
+                            line_cut = false;
                             line_offset = pos;
                         }
 
@@ -157,11 +343,13 @@ impl Reader {
 
                         line_bytes += bytes;
                         pos += bytes as u64;
+                        line_cut |= max_line_bytes.is_some_and(|max| cut_read(&mut line, max));
 
+                        head.add(line_offset, &line, partial || line_cut);
                         trace!("Sending ReaderUpdate::Line (tailing) - line_bytes: {}, partial: {}, file_bytes: {}, content_preview: {:?}", line_bytes, partial, pos, line.chars().take(50).collect::<String>());
                         sender
                             .send(ReaderUpdate::Line {
-                                // Deliver the whole line each time we send the line.
+                                // Deliver the whole line, or its start, each time we send the line.
                                 line_content: line.clone(),
                                 offset: line_offset,
                                 line_bytes,
@@ -192,6 +380,178 @@ impl Reader {
     }
 }
 
+// The file a symlink now points to, if it has been re-pointed away from the target.
+fn relinked(path: &Path, target: &Path) -> Option<PathBuf> {
+    fs::canonicalize(path)
+        .ok()
+        .filter(|new_target| new_target != target)
+}
+
+// Open a file in place of the one being read, watching the path again as the watch stays with the
+// old file.
+fn reopen(
+    watcher: &mut RecommendedWatcher,
+    path: &Path,
+    target: &Path,
+    delimiter: &LineDelimiter,
+) -> Result<(File, FileBackingFile)> {
+    if let Err(e) = watcher.unwatch(path) {
+        warn!("Failed to stop watching {:?}: {:?}", path, e);
+    }
+    watcher.watch(path, notify::RecursiveMode::Recursive)?;
+
+    Ok((
+        File::open(target)?,
+        FileBackingFile::new(&target.to_path_buf())?.with_delimiter(delimiter.clone()),
+    ))
+}
+
+// Keep only the start of a line longer than the cap, a little over it so it is known to be longer.
+// Returns whether it was cut.
+fn cut_read(line: &mut String, max_line_bytes: usize) -> bool {
+    let mut end = max_line_bytes + 1;
+    if line.len() <= end {
+        return false;
+    }
+
+    while !line.is_char_boundary(end) {
+        end += 1;
+    }
+    line.truncate(end);
+
+    true
+}
+
+// The first whole lines read, as they were read, with their offsets. Lines are kept up to
+// HEAD_BYTES, the last only the start of it if longer.
+#[derive(Debug, Default)]
+struct Head {
+    lines: Vec<(u64, String, bool)>,
+    bytes: usize,
+}
+
+impl Head {
+    fn add(&mut self, offset: u64, line: &str, partial: bool) {
+        if partial || self.bytes >= HEAD_BYTES {
+            return;
+        }
+
+        let mut end = line.len().min(HEAD_BYTES - self.bytes);
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.lines
+            .push((offset, line[..end].to_owned(), end == line.len()));
+        self.bytes += end.max(1);
+    }
+
+    fn clear(&mut self) {
+        *self = Head::default();
+    }
+
+    // Whether the lines read first are no longer in the file where they were read, as when it has
+    // been written again from the start.
+    fn rewritten(&self, bf: &mut impl BackingFile) -> bool {
+        self.lines
+            .iter()
+            .any(|(offset, line, whole)| match bf.read_line(Some(*offset)) {
+                Ok(now) if *whole => now != *line,
+                Ok(now) => !now.starts_with(line.as_str()),
+                Err(_) => false,
+            })
+    }
+}
+
+// Whether the path now names another file than the one open, e.g. once rotated. Not whilst nothing
+// has the name.
+fn replaced(path: &Path, open: &File) -> bool {
+    match (fs::metadata(path), open.metadata()) {
+        (Ok(named), Ok(open)) => (named.dev(), named.ino()) != (open.dev(), open.ino()),
+        _ => false,
+    }
+}
+
+/// Where the last lines of a file start, found by reading back from the end as `tail -n` does. The
+/// start of the file if it has no more lines than that.
+pub fn tail_offset(path: &Path, delimiter: &LineDelimiter, lines: usize) -> Result<u64> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let delimiter: &[u8] = match delimiter {
+        LineDelimiter::Newline => b"\n",
+        LineDelimiter::Bytes(delimiter) => delimiter,
+    };
+    if lines == 0 {
+        return Ok(size);
+    }
+
+    let mut found = 0;
+    let mut block_end = size;
+    // The start of the block after, for a delimiter split across blocks.
+    let mut after = Vec::new();
+    while block_end > 0 {
+        let block_start = block_end.saturating_sub(TAIL_BLOCK_BYTES);
+        let mut block = vec![0; (block_end - block_start) as usize];
+        file.read_exact_at(&mut block, block_start)?;
+        let block_len = block.len();
+        block.extend_from_slice(&after);
+
+        for start in (0..block_len).rev() {
+            if !block[start..].starts_with(delimiter) {
+                continue;
+            }
+            // The delimiter ending the last line doesn't start another.
+            let line_start = block_start + (start + delimiter.len()) as u64;
+            if line_start >= size {
+                continue;
+            }
+            found += 1;
+            if found == lines {
+                return Ok(line_start);
+            }
+        }
+
+        after = block[..block_len.min(delimiter.len() - 1)].to_vec();
+        block_end = block_start;
+    }
+
+    Ok(0)
+}
+
+/// The file a path links to, if it is a symlink.
+pub fn link_target(path: &str) -> Option<PathBuf> {
+    let is_link = fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+    is_link.then(|| fs::canonicalize(path).ok()).flatten()
+}
+
+// Wait until the file exists, watching the directory it will be created in. The directory may not
+// exist yet either, so the file is also looked for now and again.
+async fn wait_for(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let (mut watcher, mut rx) = async_watcher()?;
+    if let Err(e) = watcher.watch(dir, notify::RecursiveMode::NonRecursive) {
+        debug!(
+            "Cannot watch {:?} for the file, looking now and again: {:?}",
+            dir, e
+        );
+    }
+    let mut look = interval(Duration::from_millis(LINK_CHECK_MS));
+
+    trace!("Waiting for file: {:?}", path);
+    while !path.exists() {
+        select! {
+            event = rx.recv() => trace!("Directory event whilst waiting: {:?}", event),
+            _ = look.tick() => {}
+        }
+    }
+    // Stop watching before the events stop being received.
+    drop(watcher);
+
+    Ok(())
+}
+
 fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
     let (tx, rx) = mpsc::channel(1);
 
@@ -209,3 +569,159 @@ fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Resul
 
     Ok((watcher, rx))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tokio::time::timeout;
+
+    // Read the file, then make a change once it has been read, returning the updates up to the
+    // lines expected after the change.
+    async fn read_change(
+        name: &str,
+        content: &str,
+        change: impl FnOnce(&Path),
+        expected: &[&str],
+    ) -> Vec<String> {
+        let dir = std::env::temp_dir().join(format!("otail-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, content).unwrap();
+
+        let (sender, mut receiver) = mpsc::channel(100);
+        let reader = tokio::spawn(Reader::run(
+            path.clone(),
+            ReaderOptions::default(),
+            0,
+            sender,
+        ));
+
+        let mut updates = Vec::new();
+        let mut lines = Vec::new();
+        let mut change = Some(change);
+        while lines.len() < expected.len() {
+            let update = timeout(Duration::from_secs(5), receiver.recv())
+                .await
+                .expect("No update from the reader")
+                .unwrap();
+            match update {
+                ReaderUpdate::Spooled if change.is_some() => {
+                    change.take().unwrap()(&path);
+                }
+                ReaderUpdate::Line {
+                    line_content,
+                    partial: false,
+                    ..
+                } if change.is_none() => {
+                    lines.push(line_content.clone());
+                    updates.push(line_content);
+                }
+                ReaderUpdate::Line { .. } | ReaderUpdate::Spooled => {}
+                update => updates.push(format!("{:?}", update)),
+            }
+        }
+        assert_eq!(lines, expected);
+        reader.abort();
+        fs::remove_dir_all(&dir).unwrap();
+
+        updates
+    }
+
+    // Write over the start of a file, without truncating it.
+    fn write_over(path: &Path, content: &str) {
+        let mut file = fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rewritten() {
+        // Written again as long, so it never looks truncated.
+        let updates = read_change(
+            "same-size",
+            "one\ntwo\n",
+            |path| write_over(path, "uno\ndos\n"),
+            &["uno", "dos"],
+        )
+        .await;
+        assert_eq!(updates, vec!["Truncated", "uno", "dos"]);
+
+        // Written again longer.
+        let updates = read_change(
+            "longer",
+            "one\n",
+            |path| write_over(path, "first\nsecond\n"),
+            &["first", "second"],
+        )
+        .await;
+        assert_eq!(updates, vec!["Truncated", "first", "second"]);
+
+        // Only added to, so not rewritten.
+        let updates = read_change(
+            "appended",
+            "one\n",
+            |path| write_over(path, "one\ntwo\n"),
+            &["two"],
+        )
+        .await;
+        assert_eq!(updates, vec!["two"]);
+    }
+
+    #[tokio::test]
+    async fn test_replaced() {
+        let updates = read_change(
+            "replaced",
+            "old\n",
+            |path| {
+                let new = path.with_file_name("new.log");
+                fs::write(&new, "new\n").unwrap();
+                fs::rename(&new, path).unwrap();
+            },
+            &["new"],
+        )
+        .await;
+        assert_eq!(updates.len(), 2);
+        assert!(updates[0].starts_with("Rotated"));
+        assert_eq!(updates[1], "new");
+    }
+
+    #[test]
+    fn test_tail_offset() {
+        let path = std::env::temp_dir().join(format!("otail-test-tail-{}", std::process::id()));
+
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        assert_eq!(tail_offset(&path, &LineDelimiter::Newline, 1).unwrap(), 8);
+        assert_eq!(tail_offset(&path, &LineDelimiter::Newline, 2).unwrap(), 4);
+        assert_eq!(tail_offset(&path, &LineDelimiter::Newline, 5).unwrap(), 0);
+
+        // A last line not ended yet counts.
+        fs::write(&path, "one\ntwo\nthr").unwrap();
+        assert_eq!(tail_offset(&path, &LineDelimiter::Newline, 1).unwrap(), 8);
+
+        // A delimiter split across the blocks read.
+        let record = "x".repeat(TAIL_BLOCK_BYTES as usize - 1);
+        fs::write(&path, format!("{record}--{record}")).unwrap();
+        let delimiter = LineDelimiter::Bytes(b"--".to_vec());
+        assert_eq!(
+            tail_offset(&path, &delimiter, 1).unwrap(),
+            TAIL_BLOCK_BYTES + 1
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cut_read() {
+        let mut line = "0123456789".to_owned();
+        assert!(!cut_read(&mut line, 9));
+        assert_eq!(line, "0123456789");
+
+        assert!(cut_read(&mut line, 5));
+        assert_eq!(line, "012345");
+
+        // Not within a character.
+        let mut line = "0123é567".to_owned();
+        assert!(cut_read(&mut line, 4));
+        assert_eq!(line, "0123é");
+    }
+}
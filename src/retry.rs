@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::{DEFAULT_RETRY_ATTEMPTS, DEFAULT_RETRY_DELAY_MS, DEFAULT_RETRY_MAX_DELAY_MS};
+
+/// How a file is reopened when its reader fails, e.g. on a permissions flip, an NFS hiccup or a
+/// watcher error: after a delay that doubles with each failure in a row, up to a limit, giving up
+/// after so many attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub delay_ms: u64,
+    pub max_delay_ms: u64,
+    // Failures in a row before giving up. 0 never gives up.
+    pub attempts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            delay_ms: DEFAULT_RETRY_DELAY_MS,
+            max_delay_ms: DEFAULT_RETRY_MAX_DELAY_MS,
+            attempts: DEFAULT_RETRY_ATTEMPTS,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before an attempt, counting from 1, or None once given up.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.attempts > 0 && attempt > self.attempts {
+            return None;
+        }
+
+        let delay = 2_u64
+            .saturating_pow(attempt.saturating_sub(1))
+            .saturating_mul(self.delay_ms);
+
+        Some(Duration::from_millis(delay.min(self.max_delay_ms)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            delay_ms: 100,
+            max_delay_ms: 1000,
+            attempts: 6,
+        };
+        let delays = (1..=7)
+            .map(|attempt| policy.delay(attempt).map(|d| d.as_millis()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![
+                Some(100),
+                Some(200),
+                Some(400),
+                Some(800),
+                Some(1000),
+                Some(1000),
+                None
+            ]
+        );
+
+        let forever = RetryPolicy {
+            attempts: 0,
+            ..policy
+        };
+        assert_eq!(forever.delay(100), Some(Duration::from_millis(1000)));
+    }
+}
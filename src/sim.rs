@@ -0,0 +1,471 @@
+//! A deterministic simulation of a file being viewed, for reproducing races between the IFile,
+//! FFile and views. Rather than running as tasks, each is stepped one message at a time in an
+//! order chosen by a seeded random number generator, so a seed that shows a bug shows it every
+//! time. A mock clock moves on a millisecond each step, and scripted changes to the file and keys
+//! pressed land when it reaches them, part way through whatever is in progress.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::backing_file::BackingFile;
+use crate::common::CHANNEL_BUFFER;
+use crate::ffile::{FFReq, FFReqSender, FFResp, FFRespReceiver, FFSource, FFile, FilterLine};
+use crate::filter_spec::{FilterSpec, FilterType};
+use crate::ifile::{FileRespReceiver, IFResp, IFile};
+use crate::input_source::{read_through, InputSource, SourceLines};
+use crate::reader::{ReaderUpdate, ReaderUpdateSender};
+use crate::view::View;
+
+// How far the clock moves each step.
+const STEP: Duration = Duration::from_millis(1);
+
+// A simulation still busy after this many steps is taken never to settle.
+const MAX_STEPS: usize = 100_000;
+
+/// Something done to the file, or pressed in the TUI.
+#[derive(Debug, Clone)]
+pub enum SimEvent {
+    // Lines written to the end of the file.
+    Append(Vec<String>),
+    // The file truncated, then these lines written.
+    Truncate(Vec<String>),
+    // A filter set on the lines containing the text, or removed.
+    Filter(Option<String>),
+    TailContent(bool),
+    TailFilter(bool),
+    // The content pane's current line moved.
+    Goto(usize),
+}
+
+/// The file in memory, written by the script and read by the IFile.
+#[derive(Debug, Clone, Default)]
+pub struct SimFile {
+    content: Arc<Mutex<Vec<u8>>>,
+    pos: usize,
+}
+
+impl BackingFile for SimFile {
+    fn read_line(&mut self, offset: Option<u64>) -> Result<String> {
+        if let Some(offset) = offset {
+            self.seek(offset)?;
+        }
+
+        let mut line = String::new();
+        self.incremental_read(&mut line)?;
+        if line.ends_with('\n') {
+            line.pop();
+        }
+
+        Ok(line)
+    }
+
+    // The file is in memory, so the whole line is read and cut.
+    fn read_line_start(&mut self, offset: u64, max_bytes: usize) -> Result<(String, bool)> {
+        let mut line = BackingFile::read_line(self, Some(offset))?;
+        if line.len() <= max_bytes {
+            return Ok((line, false));
+        }
+
+        let mut end = max_bytes;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        line.truncate(end);
+
+        Ok((line, true))
+    }
+
+    fn seek(&mut self, offset: u64) -> Result<()> {
+        self.pos = offset as usize;
+
+        Ok(())
+    }
+
+    fn incremental_read(&mut self, line: &mut String) -> Result<(usize, bool)> {
+        let content = self.content.lock().unwrap();
+        let rest = content.get(self.pos..).unwrap_or_default();
+        let (bytes, partial) = match rest.iter().position(|b| *b == b'\n') {
+            Some(end) => (end + 1, false),
+            None => (rest.len(), true),
+        };
+        line.push_str(&String::from_utf8_lossy(&rest[..bytes]));
+        self.pos += bytes;
+
+        Ok((bytes, partial))
+    }
+
+    fn reopen(&mut self, _path: &Path) -> Result<()> {
+        self.pos = 0;
+
+        Ok(())
+    }
+}
+
+impl InputSource for SimFile {
+    fn read_line(&mut self, offset: Option<u64>) -> Result<String> {
+        BackingFile::read_line(self, offset)
+    }
+
+    fn read_line_start(&mut self, offset: u64, max_bytes: usize) -> Result<(String, bool)> {
+        BackingFile::read_line_start(self, offset, max_bytes)
+    }
+
+    fn reopen(&mut self, path: &Path) -> Result<()> {
+        BackingFile::reopen(self, path)
+    }
+
+    // The simulation sends what the reader would as it steps, so nothing is followed.
+    fn follow(
+        &self,
+        _start: u64,
+        _max_line_bytes: Option<usize>,
+        _sender: ReaderUpdateSender,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async {})
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(self.content.lock().unwrap().len() as u64)
+    }
+
+    fn identity(&self) -> Option<(u64, u64)> {
+        None
+    }
+
+    fn tail_offset(&self, _lines: usize) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn lines(&self) -> Result<SourceLines> {
+        Ok(read_through(SimFile {
+            content: self.content.clone(),
+            pos: 0,
+        }))
+    }
+}
+
+// Where the next message is taken from.
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    Reader,
+    IFileViews,
+    IFileBulk,
+    FFile(FFSource),
+    ContentView,
+    FilterView,
+}
+
+const SOURCES: [Source; 8] = [
+    Source::Reader,
+    Source::IFileViews,
+    Source::IFileBulk,
+    Source::FFile(FFSource::Views),
+    Source::FFile(FFSource::Filter),
+    Source::FFile(FFSource::IFile),
+    Source::ContentView,
+    Source::FilterView,
+];
+
+/// A file with content and filter views, as the TUI has, driven by a script.
+pub struct Sim {
+    rng: u64,
+    now: Duration,
+    script: VecDeque<(Duration, SimEvent)>,
+    // The steps taken, to see what order led to a failure.
+    pub log: Vec<String>,
+
+    file: SimFile,
+    // Lines written but not yet read, as the reader would send them.
+    reader: VecDeque<ReaderUpdate>,
+
+    ifile: IFile<SimFile>,
+    ffile: FFile,
+    ff_sender: FFReqSender,
+    content: View<IFResp<String>, String>,
+    content_receiver: FileRespReceiver<IFResp<String>>,
+    filter: View<FFResp, FilterLine>,
+    filter_receiver: FFRespReceiver,
+}
+
+impl Sim {
+    /// A simulation ordering its steps by the seed, with views of the given height.
+    pub async fn new(seed: u64, height: usize) -> Result<Self> {
+        let file = SimFile::default();
+        let ifile = IFile::new("sim", file.clone());
+        let ffile = FFile::new("ff".to_owned(), "sim", ifile.get_bulk_sender());
+        let (content_sender, content_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        let (filter_sender, filter_receiver) = mpsc::channel(CHANNEL_BUFFER);
+        let mut content = View::new(
+            "content".to_owned(),
+            ifile.get_view_sender(),
+            content_sender,
+        );
+        let mut filter = View::new("filter".to_owned(), ffile.get_view_sender(), filter_sender);
+
+        ffile.register().await?;
+        content.init().await?;
+        content.set_height(height).await?;
+        filter.init().await?;
+        filter.set_height(height).await?;
+
+        Ok(Sim {
+            // Seeds near 0 give poor sequences, so are spread out first.
+            rng: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1,
+            now: Duration::ZERO,
+            script: VecDeque::new(),
+            log: Vec::new(),
+
+            file,
+            reader: VecDeque::new(),
+
+            ff_sender: ffile.get_ff_sender(),
+            ifile,
+            ffile,
+            content,
+            content_receiver,
+            filter,
+            filter_receiver,
+        })
+    }
+
+    /// Do something once the clock reaches the time, after anything already scripted.
+    pub fn at(mut self, ms: u64, event: SimEvent) -> Self {
+        self.script.push_back((Duration::from_millis(ms), event));
+
+        self
+    }
+
+    /// Run until the script is done and nothing is left to do.
+    pub async fn run(&mut self) -> Result<()> {
+        for _ in 0..MAX_STEPS {
+            if let Some((at, _)) = self.script.front() {
+                if *at <= self.now {
+                    let (_, event) = self.script.pop_front().unwrap();
+                    self.apply(event).await?;
+                    continue;
+                }
+            }
+
+            if self.step().await? {
+                self.now += STEP;
+                continue;
+            }
+            // With nothing to do, the clock jumps to what is next in the script.
+            match self.script.front() {
+                Some((at, _)) => self.now = *at,
+                None => return Ok(()),
+            }
+        }
+
+        bail!("The simulation didn't settle after {} steps", MAX_STEPS)
+    }
+
+    /// The lines of the file, as the content pane shows them.
+    pub fn content_lines(&self) -> Vec<String> {
+        self.content
+            .range()
+            .map_while(|line_no| self.content.get_line(line_no))
+            .collect()
+    }
+
+    /// The lines of the file matched, as the filter pane shows them, with their line numbers.
+    pub fn filter_lines(&self) -> Vec<(usize, String)> {
+        self.filter
+            .range()
+            .map_while(|match_no| self.filter.get_line(match_no))
+            .map(|f| (f.line_no, f.line))
+            .collect()
+    }
+
+    pub fn file_lines(&self) -> usize {
+        self.content.get_stats().file_lines
+    }
+
+    async fn apply(&mut self, event: SimEvent) -> Result<()> {
+        self.log.push(format!("{:?} {:?}", self.now, event));
+        match event {
+            SimEvent::Append(lines) => self.write(lines),
+            SimEvent::Truncate(lines) => {
+                self.file.content.lock().unwrap().clear();
+                self.reader.push_back(ReaderUpdate::Truncated);
+                self.write(lines);
+            }
+            SimEvent::Filter(text) => {
+                let filter_spec = text
+                    .map(|text| FilterSpec::new(FilterType::SimpleCaseInsensitive, &text))
+                    .transpose()?;
+                self.ff_sender
+                    .send(FFReq::SetFilter {
+                        filter_spec,
+                        from: 0,
+                        to: None,
+                    })
+                    .await?;
+            }
+            SimEvent::TailContent(tail) => self.content.set_tail(tail).await?,
+            SimEvent::TailFilter(tail) => self.filter.set_tail(tail).await?,
+            SimEvent::Goto(line_no) => self.content.set_current(line_no).await?,
+        }
+
+        Ok(())
+    }
+
+    // Write whole lines to the file, to be read in a later step.
+    fn write(&mut self, lines: Vec<String>) {
+        let mut content = self.file.content.lock().unwrap();
+        for line in lines {
+            let offset = content.len() as u64;
+            content.extend_from_slice(line.as_bytes());
+            content.push(b'\n');
+            self.reader.push_back(ReaderUpdate::Line {
+                line_bytes: line.len() + 1,
+                line_content: line,
+                offset,
+                partial: false,
+                file_bytes: content.len() as u64,
+            });
+        }
+    }
+
+    // Take one message from a source chosen at random from those with one waiting, returning
+    // whether there was one.
+    async fn step(&mut self) -> Result<bool> {
+        let mut sources = SOURCES;
+        for i in (1..sources.len()).rev() {
+            sources.swap(i, (self.random() % (i as u64 + 1)) as usize);
+        }
+
+        for source in sources {
+            if self.step_source(source).await? {
+                self.log.push(format!("{:?} {:?}", self.now, source));
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn step_source(&mut self, source: Source) -> Result<bool> {
+        match source {
+            Source::Reader => {
+                let Some(update) = self.reader.pop_front() else {
+                    return Ok(false);
+                };
+                self.ifile.handle_reader_update(update).await?;
+            }
+            Source::IFileViews => return self.ifile.step(false).await,
+            Source::IFileBulk => return self.ifile.step(true).await,
+            Source::FFile(source) => return self.ffile.step(source).await,
+            // As the TUI handles them.
+            Source::ContentView => match self.content_receiver.try_recv() {
+                Ok(IFResp::ViewUpdate { update }) => self.content.handle_update(update).await,
+                Ok(IFResp::Truncated) => {
+                    self.content.reset().await?;
+                    self.filter.reset().await?;
+                }
+                Ok(_) => {}
+                Err(_) => return Ok(false),
+            },
+            Source::FilterView => match self.filter_receiver.try_recv() {
+                Ok(FFResp::ViewUpdate { update }) => self.filter.handle_update(update).await,
+                Ok(FFResp::Clear) => self.filter.reset().await?,
+                Err(_) => return Ok(false),
+            },
+        }
+
+        Ok(true)
+    }
+
+    // xorshift64.
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        self.rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: u64 = 50;
+
+    fn lines(prefix: &str, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| match i % 3 {
+                0 => format!("{} {} ERROR", prefix, i),
+                _ => format!("{} {} ok", prefix, i),
+            })
+            .collect()
+    }
+
+    fn errors(lines: &[String]) -> Vec<(usize, String)> {
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.contains("ERROR"))
+            .map(|(i, l)| (i, l.clone()))
+            .collect()
+    }
+
+    async fn check(seed: u64, sim: &mut Sim, expected: &[String]) {
+        sim.run().await.unwrap();
+        let context = format!("seed {}: {}", seed, sim.log.join("\n"));
+        assert_eq!(sim.file_lines(), expected.len(), "{}", context);
+        assert_eq!(sim.content_lines(), expected, "{}", context);
+        assert_eq!(sim.filter_lines(), errors(expected), "{}", context);
+    }
+
+    #[tokio::test]
+    async fn test_sim_filter_whilst_reading() {
+        for seed in 0..SEEDS {
+            let expected = lines("line", 60);
+            let mut sim = Sim::new(seed, 100)
+                .await
+                .unwrap()
+                .at(0, SimEvent::Append(expected[..30].to_vec()))
+                .at(5, SimEvent::Filter(Some("error".to_owned())))
+                .at(10, SimEvent::Append(expected[30..].to_vec()));
+            check(seed, &mut sim, &expected).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sim_truncate_whilst_filtering() {
+        for seed in 0..SEEDS {
+            let expected = lines("new", 20);
+            let mut sim = Sim::new(seed, 100)
+                .await
+                .unwrap()
+                .at(0, SimEvent::Append(lines("old", 80)))
+                .at(40, SimEvent::Filter(Some("error".to_owned())))
+                .at(45, SimEvent::Truncate(expected.clone()));
+            check(seed, &mut sim, &expected).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sim_tailing() {
+        for seed in 0..SEEDS {
+            let expected = lines("line", 40);
+            let mut sim = Sim::new(seed, 100)
+                .await
+                .unwrap()
+                .at(0, SimEvent::TailContent(true))
+                .at(0, SimEvent::TailFilter(true))
+                .at(0, SimEvent::Filter(Some("error".to_owned())));
+            for (i, line) in expected.iter().enumerate() {
+                sim = sim.at(i as u64 * 2, SimEvent::Append(vec![line.clone()]));
+            }
+            check(seed, &mut sim, &expected).await;
+        }
+    }
+}
@@ -0,0 +1,2776 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::backing_file::{Compression, LineDelimiter};
+use crate::timestamp::TimestampParser;
+use crate::websocket::{WebSocket, WebSocketUrl};
+
+const SPOOL_BUFFER: usize = 64 * 1024;
+
+// How often the live file of a rotated set is checked for new lines and rotation.
+const ROTATED_POLL_MS: u64 = 250;
+
+// How often merged files are checked for new lines and rotation.
+const MERGE_POLL_MS: u64 = 250;
+
+// How long a line merged in time order waits for lines from quiet files that could be earlier.
+const MERGE_HOLD_MS: u64 = 1000;
+
+// How long to wait before reconnecting a WebSocket, doubling after each failure up to the maximum.
+const RECONNECT_MS: u64 = 1000;
+const MAX_RECONNECT_MS: u64 = 30_000;
+
+// How much of an S3 object to fetch with each range request.
+const S3_CHUNK_BYTES: u64 = 8 * 1024 * 1024;
+
+// A buffered stream's lines are written to its spool file whilst no more than this much of it is
+// waiting to be read.
+const SPOOL_AHEAD_BYTES: u64 = 4 * 1024 * 1024;
+
+// How often a buffered stream checks whether more of its spool file has been read.
+const STREAM_POLL_MS: u64 = 50;
+
+// How often a stopped container is checked for having started again.
+const DOCKER_POLL_MS: u64 = 1000;
+
+// How often a pod whose logs have ended is checked for its container running again.
+const K8S_POLL_MS: u64 = 1000;
+
+// How often a command being run is checked for having finished.
+const EXEC_POLL_MS: u64 = 100;
+
+// The largest message that can arrive in a UDP packet, and the largest taken after its length over
+// TCP.
+const LISTEN_PACKET: usize = 64 * 1024;
+
+// Syslog severities by number, named as otail's colouring rules would expect.
+const SEVERITIES: [&str; 8] = [
+    "EMERG", "ALERT", "CRIT", "ERROR", "WARN", "NOTICE", "INFO", "DEBUG",
+];
+
+// Syslog facilities by number, named as syslog daemons name them, avoiding the names of levels.
+const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv",
+    "ftp", "ntp", "audit", "console", "clock", "local0", "local1", "local2", "local3", "local4",
+    "local5", "local6", "local7",
+];
+
+/// Whether a spool has copied all there is to copy into its file, e.g. once its stream has ended,
+/// and why if it stopped early, shared with what reads the file to tell when it has all been read.
+#[derive(Debug, Clone, Default)]
+pub struct SpoolDone {
+    done: Arc<AtomicBool>,
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+impl SpoolDone {
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    pub fn finish(&self) {
+        self.done.store(true, Ordering::Relaxed);
+    }
+
+    /// Done, having failed to copy everything.
+    pub fn fail(&self, reason: String) {
+        if let Ok(mut failure) = self.failure.lock() {
+            *failure = Some(reason);
+        }
+        self.finish();
+    }
+
+    pub fn failure(&self) -> Option<String> {
+        self.failure.lock().ok().and_then(|failure| failure.clone())
+    }
+}
+
+// How many names are tried for a spool file before giving up, when others are already taken.
+const SPOOL_FILE_ATTEMPTS: usize = 100;
+
+/// Creates a new temporary file, e.g. `otail-stdin-1234.log`, readable only by this user. It is
+/// never a file or link already there, which anyone could have made given the name is predictable,
+/// so another name is tried, e.g. `otail-stdin-1234-1.log`, if it is taken.
+pub fn create_spool_file(name: &str, extension: &str) -> Result<(PathBuf, File)> {
+    let pid = std::process::id();
+    for attempt in 0..SPOOL_FILE_ATTEMPTS {
+        let file_name = match attempt {
+            0 => format!("otail-{}-{}.{}", name, pid, extension),
+            attempt => format!("otail-{}-{}-{}.{}", name, pid, attempt, extension),
+        };
+        let path = std::env::temp_dir().join(file_name);
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                debug!("Spool file {:?} already exists, trying another", path);
+            }
+            Err(e) => return Err(anyhow!("Failed to create spool file {:?}: {}", path, e)),
+        }
+    }
+
+    bail!(
+        "Failed to create a spool file for {}, {} names were already taken",
+        name,
+        SPOOL_FILE_ATTEMPTS
+    )
+}
+
+/// The temporary file a spool copies into, removed when it is dropped, and what is spooled to it.
+#[derive(Debug)]
+pub struct SpoolFile {
+    path: PathBuf,
+    name: String,
+}
+
+impl SpoolFile {
+    // Create the file for a kind of input, e.g. `stdin`, named as it is shown in the title.
+    fn create(kind: &str, name: String) -> Result<(Self, File)> {
+        let (path, file) = create_spool_file(kind, "log")?;
+        debug!("Spooling {} to {:?}", name, path);
+
+        Ok((SpoolFile { path, name }, file))
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove spool file {:?}: {:?}", self.path, e);
+        }
+    }
+}
+
+/// Input copied into a temporary file as it arrives, so it can be indexed, filtered and tailed
+/// like any other file: a stream, or files read as one. Dropping it stops the copying and removes
+/// the file.
+pub trait Spool {
+    fn file(&self) -> &SpoolFile;
+
+    fn path(&self) -> String {
+        self.file().path.to_string_lossy().into_owned()
+    }
+
+    /// What is spooled, as shown in the title.
+    fn name(&self) -> String {
+        self.file().name.clone()
+    }
+
+    /// For input with an end, done once it has all been copied. Input without one, e.g. messages
+    /// received over the network, is read as far as it has got.
+    fn done(&self) -> Option<SpoolDone> {
+        None
+    }
+
+    /// The buffer the lines go through, for a stream that is buffered.
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        None
+    }
+
+    /// The tags of the files a line may have come from, for merged files.
+    fn tags(&self) -> Option<&[String]> {
+        None
+    }
+
+    /// How the command is getting on, for a command's output.
+    fn status(&self) -> Option<CommandStatus> {
+        None
+    }
+}
+
+/// Copies piped input into a temporary file as it arrives, so it can be indexed, filtered and
+/// tailed like any other file. With a buffer, lines go through it.
+#[derive(Debug)]
+pub struct StdinSpool {
+    file: SpoolFile,
+    buffer: Option<StreamBuffer>,
+    done: SpoolDone,
+}
+
+impl StdinSpool {
+    pub fn start(buffer: Option<StreamBuffer>) -> Result<Self> {
+        let (spool_file, mut file) = SpoolFile::create("stdin", "(stdin)".to_owned())?;
+
+        let done = SpoolDone::default();
+        let spool = StdinSpool {
+            file: spool_file,
+            buffer: buffer.clone(),
+            done: done.clone(),
+        };
+
+        if let Some(buffer) = buffer {
+            buffer.spool_to(file, done);
+            thread::spawn(move || {
+                queue_lines(io::stdin().lock(), &buffer);
+                buffer.end();
+            });
+            return Ok(spool);
+        }
+
+        thread::spawn(move || {
+            if let Err(e) = copy_stream(io::stdin().lock(), &mut file) {
+                error!("Failed to spool stdin: {:?}", e);
+            }
+            done.finish();
+        });
+
+        Ok(spool)
+    }
+}
+
+impl Spool for StdinSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    /// Done once the input has ended.
+    fn done(&self) -> Option<SpoolDone> {
+        Some(self.done.clone())
+    }
+
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        self.buffer.as_ref()
+    }
+}
+
+/// Copies what is written to a named pipe (FIFO) into a temporary file as it arrives, as piped
+/// input is, since a pipe can't be seeked or checked for truncation like a file. When a writer
+/// closes the pipe it is opened again, waiting for the next.
+#[derive(Debug)]
+pub struct FifoSpool {
+    file: SpoolFile,
+    buffer: Option<StreamBuffer>,
+    done: SpoolDone,
+}
+
+impl FifoSpool {
+    pub fn start(fifo: &str, buffer: Option<StreamBuffer>) -> Result<Self> {
+        let (spool_file, mut file) = SpoolFile::create("fifo", format!("{} (pipe)", fifo))?;
+
+        let done = SpoolDone::default();
+        let spool = FifoSpool {
+            file: spool_file,
+            buffer: buffer.clone(),
+            done: done.clone(),
+        };
+
+        // Opening a pipe waits for a writer, so is done whilst spooling. Buffered, the spool is
+        // never done, as the buffer is kept for the next writer.
+        let fifo = PathBuf::from(fifo);
+        if let Some(buffer) = buffer {
+            buffer.spool_to(file, SpoolDone::default());
+            thread::spawn(move || {
+                while let Some(pipe) = open_fifo(&fifo) {
+                    queue_lines(BufReader::new(pipe), &buffer);
+                }
+            });
+            return Ok(spool);
+        }
+
+        thread::spawn(move || {
+            while let Some(pipe) = open_fifo(&fifo) {
+                let copied = copy_stream(pipe, &mut file);
+                done.finish();
+                if let Err(e) = copied {
+                    error!("Failed to spool {:?}: {:?}", fifo, e);
+                    break;
+                }
+            }
+            done.finish();
+        });
+
+        Ok(spool)
+    }
+}
+
+impl Spool for FifoSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    /// Done once the first writer has closed the pipe.
+    fn done(&self) -> Option<SpoolDone> {
+        Some(self.done.clone())
+    }
+
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        self.buffer.as_ref()
+    }
+}
+
+// Open a pipe for reading, waiting for a writer.
+fn open_fifo(fifo: &Path) -> Option<File> {
+    match File::open(fifo) {
+        Ok(pipe) => {
+            debug!("{:?} opened by a writer", fifo);
+            Some(pipe)
+        }
+        Err(e) => {
+            error!("Failed to open {:?}: {:?}", fifo, e);
+            None
+        }
+    }
+}
+
+/// Whether the path is a named pipe (FIFO).
+pub fn is_fifo(path: &str) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+// Copy a stream to its spool file until it ends, writing straight through so the reader sees each
+// chunk as it arrives.
+fn copy_stream(mut input: impl Read, file: &mut File) -> Result<()> {
+    let mut buffer = vec![0; SPOOL_BUFFER];
+    loop {
+        match input.read(&mut buffer) {
+            Ok(0) => {
+                debug!("End of stream");
+                return Ok(());
+            }
+            Ok(bytes) => {
+                trace!("Spooling {} bytes", bytes);
+                file.write_all(&buffer[..bytes])?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// What a buffered stream does with lines arriving when its buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropPolicy {
+    // Stop reading the stream until there is room, holding up the sender.
+    Block,
+    // Make room by dropping the line waiting longest.
+    DropOldest,
+    // Drop the line arriving.
+    DropNewest,
+}
+
+#[derive(Debug)]
+struct StreamQueue {
+    lines: Mutex<VecDeque<Vec<u8>>>,
+    // Signalled when lines are queued, and when they are taken.
+    queued: Condvar,
+    taken: Condvar,
+    // How far the spool file has been read, and the lines dropped.
+    read: AtomicU64,
+    dropped: AtomicU64,
+    // The spool file is to be emptied before the lines queued since are written. Only set whilst
+    // holding the lines' lock, so they are known to be from after it.
+    truncate: AtomicBool,
+    // The stream has ended, so no more lines will be queued.
+    ended: AtomicBool,
+}
+
+/// Lines from a stream waiting to be written to its spool file, which is only written a little
+/// ahead of where it has been read. When otail falls behind a stream, the lines wait here, up to
+/// the buffer's size, and then the policy decides what happens to more.
+#[derive(Debug, Clone)]
+pub struct StreamBuffer {
+    policy: DropPolicy,
+    size: usize,
+    // What ends the stream's lines, so only whole lines are dropped.
+    delimiter: LineDelimiter,
+    queue: Arc<StreamQueue>,
+}
+
+impl StreamBuffer {
+    pub fn new(policy: DropPolicy, size: usize) -> Self {
+        StreamBuffer {
+            policy,
+            size: size.max(1),
+            delimiter: LineDelimiter::Newline,
+            queue: Arc::new(StreamQueue {
+                lines: Mutex::new(VecDeque::new()),
+                queued: Condvar::new(),
+                taken: Condvar::new(),
+                read: AtomicU64::new(0),
+                dropped: AtomicU64::new(0),
+                truncate: AtomicBool::new(false),
+                ended: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn with_delimiter(mut self, delimiter: LineDelimiter) -> Self {
+        self.delimiter = delimiter;
+
+        self
+    }
+
+    pub fn policy(&self) -> DropPolicy {
+        self.policy
+    }
+
+    /// Queue a line, with its delimiter, to be spooled.
+    pub fn push(&self, line: Vec<u8>) {
+        let Ok(mut lines) = self.queue.lines.lock() else {
+            return;
+        };
+        if lines.len() >= self.size {
+            match self.policy {
+                DropPolicy::Block => {
+                    let Ok(waited) = self
+                        .queue
+                        .taken
+                        .wait_while(lines, |lines| lines.len() >= self.size)
+                    else {
+                        return;
+                    };
+                    lines = waited;
+                }
+                DropPolicy::DropOldest => {
+                    lines.pop_front();
+                    self.queue.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                DropPolicy::DropNewest => {
+                    self.queue.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+        lines.push_back(line);
+        self.queue.queued.notify_one();
+    }
+
+    /// How far the spool file has been read, letting more be written.
+    pub fn set_read(&self, bytes: u64) {
+        self.queue.read.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Empty the spool file, dropping the lines waiting, so it is read again from the start as if
+    /// truncated.
+    pub fn truncate(&self) {
+        let Ok(mut lines) = self.queue.lines.lock() else {
+            return;
+        };
+        lines.clear();
+        self.queue.truncate.store(true, Ordering::Relaxed);
+        self.queue.queued.notify_one();
+        self.queue.taken.notify_all();
+    }
+
+    /// The stream has ended, so the spool is done once the lines waiting have been written.
+    pub fn end(&self) {
+        let Ok(_lines) = self.queue.lines.lock() else {
+            return;
+        };
+        self.queue.ended.store(true, Ordering::Relaxed);
+        self.queue.queued.notify_one();
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue
+            .lines
+            .lock()
+            .map(|lines| lines.len())
+            .unwrap_or(0)
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+
+    // Write the queued lines to the spool file as it is read, until the stream has ended.
+    fn spool_to(&self, mut file: File, done: SpoolDone) {
+        let buffer = self.clone();
+        thread::spawn(move || {
+            let mut written: u64 = 0;
+            loop {
+                // Once truncated, the file is read from the start again.
+                if written.saturating_sub(buffer.queue.read.load(Ordering::Relaxed))
+                    >= SPOOL_AHEAD_BYTES
+                    && !buffer.queue.truncate.load(Ordering::Relaxed)
+                {
+                    thread::sleep(Duration::from_millis(STREAM_POLL_MS));
+                    continue;
+                }
+
+                let (lines, truncate, ended) = match buffer.queue.lines.lock() {
+                    Ok(lines) => match buffer.queue.queued.wait_while(lines, |lines| {
+                        lines.is_empty()
+                            && !buffer.queue.truncate.load(Ordering::Relaxed)
+                            && !buffer.queue.ended.load(Ordering::Relaxed)
+                    }) {
+                        Ok(mut lines) => (
+                            lines.drain(..).collect::<Vec<_>>(),
+                            buffer.queue.truncate.swap(false, Ordering::Relaxed),
+                            buffer.queue.ended.load(Ordering::Relaxed),
+                        ),
+                        Err(_) => break,
+                    },
+                    Err(_) => break,
+                };
+                buffer.queue.taken.notify_all();
+
+                if truncate {
+                    if let Err(e) = file.set_len(0).and_then(|_| file.rewind()) {
+                        error!("Failed to truncate a stream's spool file: {:?}", e);
+                        return;
+                    }
+                    written = 0;
+                }
+
+                for line in lines {
+                    if let Err(e) = file.write_all(&line) {
+                        error!("Failed to spool a stream: {:?}", e);
+                        return;
+                    }
+                    written += line.len() as u64;
+                }
+
+                if ended {
+                    done.finish();
+                    return;
+                }
+            }
+        });
+    }
+}
+
+// Queue the lines read, whole so that only whole lines are dropped.
+fn queue_lines(mut reader: impl BufRead, buffer: &StreamBuffer) {
+    loop {
+        let mut line = Vec::new();
+        match read_record(&mut reader, &buffer.delimiter, &mut line) {
+            Ok(0) => {
+                debug!("End of stream");
+                break;
+            }
+            Ok(_) => buffer.push(line),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                error!("Failed to read stream: {:?}", e);
+                break;
+            }
+        }
+    }
+}
+
+// Read up to and including the delimiter, or to the end of the stream. Gives the bytes read.
+fn read_record(
+    reader: &mut impl BufRead,
+    delimiter: &LineDelimiter,
+    record: &mut Vec<u8>,
+) -> io::Result<usize> {
+    let delimiter: &[u8] = match delimiter {
+        LineDelimiter::Newline => b"\n",
+        LineDelimiter::Bytes(delimiter) => delimiter,
+    };
+    let last = delimiter[delimiter.len() - 1];
+    loop {
+        if reader.read_until(last, record)? == 0 || record.ends_with(delimiter) {
+            return Ok(record.len());
+        }
+    }
+}
+
+// Where a stream's lines go: straight to its spool file, or through a buffer.
+#[derive(Debug)]
+enum SpoolSink {
+    File(Mutex<File>),
+    Buffer(StreamBuffer),
+}
+
+impl SpoolSink {
+    // Done once ended and, if buffered, the lines waiting have been written.
+    fn new(file: File, buffer: Option<StreamBuffer>, done: &SpoolDone) -> Self {
+        match buffer {
+            Some(buffer) => {
+                buffer.spool_to(file, done.clone());
+                SpoolSink::Buffer(buffer)
+            }
+            None => SpoolSink::File(Mutex::new(file)),
+        }
+    }
+
+    // The stream has ended.
+    fn end(&self, done: &SpoolDone) {
+        match self {
+            SpoolSink::File(_) => done.finish(),
+            SpoolSink::Buffer(buffer) => buffer.end(),
+        }
+    }
+
+    // Empty the file, so it is read again from the start as if truncated, e.g. for a restarted
+    // container's logs.
+    fn truncate(&self) -> io::Result<()> {
+        match self {
+            SpoolSink::File(file) => {
+                let mut file = file.lock().map_err(|e| io::Error::other(e.to_string()))?;
+                file.set_len(0)?;
+                file.rewind()
+            }
+            SpoolSink::Buffer(buffer) => {
+                buffer.truncate();
+                Ok(())
+            }
+        }
+    }
+
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        match self {
+            SpoolSink::File(file) => {
+                let mut file = file.lock().map_err(|e| io::Error::other(e.to_string()))?;
+                writeln!(file, "{}", line)
+            }
+            SpoolSink::Buffer(buffer) => {
+                buffer.push(format!("{}\n", line).into_bytes());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Joins a log file and its rotated siblings, e.g. `app.log.2.gz`, `app.log.1` and `app.log`, into
+/// a temporary file read as one, oldest first. The live file is followed across further
+/// rotations. Compressed siblings are decompressed with their tool, e.g. `gzip`.
+#[derive(Debug)]
+pub struct RotatedSpool {
+    file: SpoolFile,
+    stop: Arc<AtomicBool>,
+    done: SpoolDone,
+}
+
+impl RotatedSpool {
+    pub fn start(live: &str) -> Result<Self> {
+        let name = live.to_owned();
+        let live = PathBuf::from(live);
+        File::open(&live).map_err(|e| anyhow!("Failed to open: {:?} - {:?}", live, e))?;
+        let siblings = rotated_siblings(&live)?;
+        for sibling in &siblings {
+            if let Some(compression) = Compression::from_name(&sibling.to_string_lossy()) {
+                compression.check_installed()?;
+            }
+        }
+        info!("Joining {:?} with rotated {:?}", live, siblings);
+
+        let name = format!("{} (+{} rotated)", name, siblings.len());
+        let (spool_file, mut file) = SpoolFile::create("rotated", name)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let done = SpoolDone::default();
+        let spool = RotatedSpool {
+            file: spool_file,
+            stop: stop.clone(),
+            done: done.clone(),
+        };
+
+        thread::spawn(move || {
+            for sibling in &siblings {
+                if let Err(e) = copy_sibling(sibling, &mut file) {
+                    warn!("Failed to read rotated file {:?}: {:?}", sibling, e);
+                }
+            }
+            if let Err(e) = follow_live(&live, &mut file, &stop, &done) {
+                error!("Failed to follow {:?}: {:?}", live, e);
+                done.fail(format!("Failed to follow {:?}: {}", live, e));
+            }
+        });
+
+        Ok(spool)
+    }
+}
+
+impl Spool for RotatedSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    /// Done once the rotated files, and the live file as it was, have been joined.
+    fn done(&self) -> Option<SpoolDone> {
+        Some(self.done.clone())
+    }
+}
+
+impl Drop for RotatedSpool {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The rotation number of a sibling of the live file, e.g. 2 for `app.log.2`, `app.log.2.gz` or
+/// `app.log.2.zst`.
+pub fn rotation_number(live_name: &str, name: &str) -> Option<usize> {
+    let suffix = name.strip_prefix(live_name)?.strip_prefix('.')?;
+    let number = match Compression::from_name(suffix) {
+        Some(compression) => compression.strip(suffix),
+        None => suffix,
+    };
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    number.parse().ok()
+}
+
+// The rotated siblings of the live file, oldest (highest numbered) first.
+fn rotated_siblings(live: &Path) -> Result<Vec<PathBuf>> {
+    let Some(live_name) = live.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+        bail!("Not a file: {:?}", live);
+    };
+    let dir = match live.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut siblings = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            rotation_number(&live_name, &name).map(|n| (n, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    siblings.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    Ok(siblings.into_iter().map(|(_, path)| path).collect())
+}
+
+// Copy a rotated file into the spool, ending it with a newline so the next file starts a line.
+fn copy_sibling(sibling: &Path, file: &mut File) -> Result<()> {
+    let mut content = Vec::new();
+    if let Some(compression) = Compression::from_name(&sibling.to_string_lossy()) {
+        content = compression.decompress_all(sibling)?;
+    } else {
+        File::open(sibling)?.read_to_end(&mut content)?;
+    }
+    if content.last().is_some_and(|c| *c != b'\n') {
+        content.push(b'\n');
+    }
+    trace!("Spooling {} bytes from {:?}", content.len(), sibling);
+
+    Ok(file.write_all(&content)?)
+}
+
+// Copy the live file into the spool as it grows, done once what was in it has been copied. When it
+// is rotated, finish the old file and carry on with the new one.
+fn follow_live(live: &Path, file: &mut File, stop: &AtomicBool, done: &SpoolDone) -> Result<()> {
+    let mut current = File::open(live)?;
+    let mut buffer = vec![0; SPOOL_BUFFER];
+    let mut ends_line = true;
+    while !stop.load(Ordering::Relaxed) {
+        loop {
+            let bytes = current.read(&mut buffer)?;
+            if bytes == 0 {
+                break;
+            }
+            file.write_all(&buffer[..bytes])?;
+            ends_line = buffer[bytes - 1] == b'\n';
+        }
+        done.finish();
+
+        // A different file at the path, or a shorter one, means it was rotated.
+        let position = current.stream_position()?;
+        let rotated = match fs::metadata(live) {
+            Ok(metadata) => {
+                metadata.ino() != current.metadata()?.ino() || metadata.len() < position
+            }
+            Err(_) => false,
+        };
+        if rotated {
+            debug!("{:?} rotated", live);
+            let mut rest = Vec::new();
+            current.read_to_end(&mut rest)?;
+            file.write_all(&rest)?;
+            if !rest.is_empty() {
+                ends_line = rest.ends_with(b"\n");
+            }
+            if !ends_line {
+                file.write_all(b"\n")?;
+                ends_line = true;
+            }
+            current = File::open(live)?;
+            continue;
+        }
+
+        thread::sleep(Duration::from_millis(ROTATED_POLL_MS));
+    }
+
+    Ok(())
+}
+
+/// Tails several files into a temporary file, their lines interleaved as they arrive, like
+/// `tail -f a b`. Each line starts with a tag for the file it came from, e.g. `[app] `. What is
+/// already in the files is copied first, a file at a time.
+///
+/// Given a timestamp parser, the lines are merged in the order of their timestamps instead, lines
+/// without one going with the line before. The earliest line waiting is written once every file
+/// has a line waiting, or once it has waited a while for the quiet files.
+#[derive(Debug)]
+pub struct MergeSpool {
+    file: SpoolFile,
+    tags: Vec<String>,
+    stop: Arc<AtomicBool>,
+    done: SpoolDone,
+}
+
+impl MergeSpool {
+    pub fn start(paths: &[String], by_time: Option<TimestampParser>) -> Result<Self> {
+        let tags = source_tags(paths);
+        let mut sources = paths
+            .iter()
+            .zip(&tags)
+            .map(|(path, tag)| MergeSource::open(Path::new(path), tag))
+            .collect::<Result<Vec<_>>>()?;
+        info!("Merging {:?} as {:?}", paths, tags);
+
+        let name = format!("{} (merged)", tags.join(" + "));
+        let (spool_file, mut file) = SpoolFile::create("merge", name)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let done = SpoolDone::default();
+        let spool = MergeSpool {
+            file: spool_file,
+            tags,
+            stop: stop.clone(),
+            done: done.clone(),
+        };
+
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match &by_time {
+                    Some(parser) => {
+                        if let Err(e) = merge_by_time(&mut sources, parser, &mut file) {
+                            error!("Failed to spool merged files: {:?}", e);
+                            done.fail(format!("Failed to merge the files: {}", e));
+                            break;
+                        }
+                    }
+                    None => {
+                        for source in &mut sources {
+                            if let Err(e) = source.follow(&mut file) {
+                                warn!("Failed to follow {:?}: {:?}", source.path, e);
+                            }
+                        }
+                    }
+                }
+                // What was in the files has all been merged the first time round.
+                done.finish();
+                thread::sleep(Duration::from_millis(MERGE_POLL_MS));
+            }
+        });
+
+        Ok(spool)
+    }
+}
+
+impl Spool for MergeSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    /// Done once what was in the files has been merged.
+    fn done(&self) -> Option<SpoolDone> {
+        Some(self.done.clone())
+    }
+
+    /// The files' tags, in the order they were given.
+    fn tags(&self) -> Option<&[String]> {
+        Some(&self.tags)
+    }
+}
+
+impl Drop for MergeSpool {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// What a merged file's lines start with.
+pub fn source_prefix(tag: &str) -> String {
+    format!("[{}] ", tag)
+}
+
+/// Short tags for merged files: their names without the extension, numbered where that leaves two
+/// the same, e.g. `app`, `db` and `app-2`.
+pub fn source_tags(paths: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        let stem = path
+            .file_stem()
+            .or(path.file_name())
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string_lossy().into_owned());
+        let mut tag = stem.clone();
+        let mut n = 1;
+        while tags.contains(&tag) {
+            n += 1;
+            tag = format!("{}-{}", stem, n);
+        }
+        tags.push(tag);
+    }
+
+    tags
+}
+
+// Write the lines waiting in time order, for as long as the earliest can't be beaten by a line not
+// read yet.
+fn merge_by_time(
+    sources: &mut [MergeSource],
+    parser: &TimestampParser,
+    file: &mut File,
+) -> Result<()> {
+    loop {
+        for source in sources.iter_mut() {
+            if let Err(e) = source.peek(parser) {
+                warn!("Failed to follow {:?}: {:?}", source.path, e);
+            }
+        }
+
+        // The first file's line goes first when they are at the same time.
+        let Some(earliest) = sources
+            .iter()
+            .enumerate()
+            .filter_map(|(i, source)| source.waiting.as_ref().map(|line| (i, line.time)))
+            .min_by_key(|(_, time)| *time)
+            .map(|(i, _)| i)
+        else {
+            return Ok(());
+        };
+        let all_waiting = sources.iter().all(|source| source.waiting.is_some());
+        let held = sources[earliest].waiting.as_ref().is_some_and(|line| {
+            line.since
+                .is_none_or(|since| since.elapsed() >= Duration::from_millis(MERGE_HOLD_MS))
+        });
+        if !all_waiting && !held {
+            return Ok(());
+        }
+
+        if let Some(line) = sources[earliest].waiting.take() {
+            file.write_all(&line.line)?;
+        }
+    }
+}
+
+// A file being merged, with the start of a line not yet finished.
+struct MergeSource {
+    path: PathBuf,
+    prefix: String,
+    reader: BufReader<File>,
+    partial: Vec<u8>,
+    // When merging in time order, the next line, the time of the last line with a timestamp, and
+    // whether what was in the file when opened has been read.
+    waiting: Option<MergeLine>,
+    last_time: Option<DateTime<FixedOffset>>,
+    caught_up: bool,
+}
+
+// A finished line, tagged, waiting to be merged in time order.
+struct MergeLine {
+    line: Vec<u8>,
+    time: Option<DateTime<FixedOffset>>,
+    // When it was read, or None if it was in the file when opened, so isn't waited on.
+    since: Option<Instant>,
+}
+
+impl MergeSource {
+    fn open(path: &Path, tag: &str) -> Result<Self> {
+        let file = File::open(path).map_err(|e| anyhow!("Failed to open: {:?} - {:?}", path, e))?;
+
+        Ok(MergeSource {
+            path: path.to_path_buf(),
+            prefix: source_prefix(tag),
+            reader: BufReader::new(file),
+            partial: Vec::new(),
+            waiting: None,
+            last_time: None,
+            caught_up: false,
+        })
+    }
+
+    // Copy the lines finished since last time, tagged.
+    fn follow(&mut self, file: &mut File) -> Result<()> {
+        while let Some(line) = self.next_line()? {
+            file.write_all(&line)?;
+        }
+
+        Ok(())
+    }
+
+    // Read the next line to merge in time order, if there isn't one waiting already.
+    fn peek(&mut self, parser: &TimestampParser) -> Result<()> {
+        if self.waiting.is_some() {
+            return Ok(());
+        }
+
+        match self.next_line()? {
+            Some(line) => {
+                let text = String::from_utf8_lossy(&line[self.prefix.len()..]);
+                if let Some(timestamp) = parser.find(&text) {
+                    self.last_time = Some(timestamp.time);
+                }
+                self.waiting = Some(MergeLine {
+                    line,
+                    time: self.last_time,
+                    since: self.caught_up.then(Instant::now),
+                });
+            }
+            None => self.caught_up = true,
+        }
+
+        Ok(())
+    }
+
+    // The next finished line, tagged. Lines are only given once they are finished, so lines from
+    // different files aren't mixed. When the file is rotated, finish the old file and carry on with
+    // the new one.
+    fn next_line(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            self.reader.read_until(b'\n', &mut self.partial)?;
+            if self.partial.ends_with(b"\n") {
+                return Ok(Some(self.tagged()));
+            }
+
+            // A different file at the path, or a shorter one, means it was rotated.
+            let position = self.reader.stream_position()?;
+            let rotated = match fs::metadata(&self.path) {
+                Ok(metadata) => {
+                    metadata.ino() != self.reader.get_ref().metadata()?.ino()
+                        || metadata.len() < position
+                }
+                Err(_) => false,
+            };
+            if !rotated {
+                return Ok(None);
+            }
+
+            debug!("{:?} rotated", self.path);
+            if !self.partial.is_empty() {
+                self.partial.push(b'\n');
+                return Ok(Some(self.tagged()));
+            }
+            self.reader = BufReader::new(File::open(&self.path)?);
+        }
+    }
+
+    fn tagged(&mut self) -> Vec<u8> {
+        let mut line = Vec::with_capacity(self.prefix.len() + self.partial.len());
+        line.extend_from_slice(self.prefix.as_bytes());
+        line.append(&mut self.partial);
+        trace!("Spooling {} bytes from {:?}", line.len(), self.path);
+
+        line
+    }
+}
+
+/// Receives syslog messages (RFC 3164 or 5424), or plain lines, sent over UDP or TCP to an address
+/// and writes them to a temporary file, so otail can be the sink for devices and services that
+/// ship their logs over the network. With a buffer, lines go through it.
+#[derive(Debug)]
+pub struct ListenSpool {
+    file: SpoolFile,
+    buffer: Option<StreamBuffer>,
+}
+
+impl ListenSpool {
+    pub fn start(addr: &str, buffer: Option<StreamBuffer>) -> Result<Self> {
+        let name = format!("(listening on {})", addr);
+        let listen = ListenAddr::parse(addr)?;
+        let addr = listen.addr.as_str();
+        let udp = listen
+            .udp
+            .then(|| {
+                UdpSocket::bind(addr)
+                    .map_err(|e| anyhow!("Failed to listen on UDP {}: {:?}", addr, e))
+            })
+            .transpose()?;
+        let tcp = listen
+            .tcp
+            .then(|| {
+                TcpListener::bind(addr)
+                    .map_err(|e| anyhow!("Failed to listen on TCP {}: {:?}", addr, e))
+            })
+            .transpose()?;
+        info!("Listening on {:?}", listen);
+
+        let (spool_file, file) = SpoolFile::create("listen", name)?;
+        let spool = ListenSpool {
+            file: spool_file,
+            buffer: buffer.clone(),
+        };
+        let file = Arc::new(SpoolSink::new(file, buffer, &SpoolDone::default()));
+
+        if let Some(udp) = udp {
+            let file = file.clone();
+            thread::spawn(move || {
+                let mut buffer = vec![0; LISTEN_PACKET];
+                loop {
+                    match udp.recv_from(&mut buffer) {
+                        Ok((bytes, from)) => {
+                            trace!("Received {} bytes from {}", bytes, from);
+                            // Some senders put several messages in a packet, a line each.
+                            for message in String::from_utf8_lossy(&buffer[..bytes]).lines() {
+                                spool_message(&file, message);
+                            }
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => {
+                            error!("Failed to receive on UDP: {:?}", e);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
+        if let Some(tcp) = tcp {
+            thread::spawn(move || {
+                for stream in tcp.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let file = file.clone();
+                            thread::spawn(move || {
+                                if let Err(e) = receive_stream(stream, &file) {
+                                    warn!("Failed to receive on TCP: {:?}", e);
+                                }
+                            });
+                        }
+                        Err(e) => warn!("Failed to accept a connection: {:?}", e),
+                    }
+                }
+            });
+        }
+
+        Ok(spool)
+    }
+}
+
+impl Spool for ListenSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        self.buffer.as_ref()
+    }
+}
+
+/// Where to listen: `tcp://` or `udp://` before the address listens for just that, otherwise both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenAddr {
+    pub addr: String,
+    pub udp: bool,
+    pub tcp: bool,
+}
+
+impl ListenAddr {
+    pub fn parse(arg: &str) -> Result<Self> {
+        let (addr, udp, tcp) = match arg.split_once("://") {
+            Some(("tcp", addr)) => (addr, false, true),
+            Some(("udp", addr)) => (addr, true, false),
+            Some((scheme, _)) => bail!("Can't listen for {}, only tcp:// or udp://", scheme),
+            None => (arg, true, true),
+        };
+
+        Ok(ListenAddr {
+            addr: addr.to_owned(),
+            udp,
+            tcp,
+        })
+    }
+}
+
+/// Writes the messages received from a WebSocket to a temporary file, a line each. When the
+/// connection drops it is reconnected, with a line marking where messages may have been missed.
+#[derive(Debug)]
+pub struct WebSocketSpool {
+    file: SpoolFile,
+    buffer: Option<StreamBuffer>,
+    stop: Arc<AtomicBool>,
+}
+
+impl WebSocketSpool {
+    pub fn start(url: &str, buffer: Option<StreamBuffer>) -> Result<Self> {
+        let parsed = WebSocketUrl::parse(url)?;
+        // Fail straight away if it can't be connected to at all.
+        let socket = WebSocket::connect(&parsed)
+            .map_err(|e| anyhow!("Failed to connect to {}: {:?}", url, e))?;
+        info!("Connected to {}", url);
+
+        let (spool_file, file) = SpoolFile::create("ws", url.to_owned())?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let spool = WebSocketSpool {
+            file: spool_file,
+            buffer: buffer.clone(),
+            stop: stop.clone(),
+        };
+        let file = SpoolSink::new(file, buffer, &SpoolDone::default());
+
+        let url = url.to_owned();
+        thread::spawn(move || {
+            let mut socket = Some(socket);
+            let mut wait = RECONNECT_MS;
+            while !stop.load(Ordering::Relaxed) {
+                let Some(connected) = &mut socket else {
+                    thread::sleep(Duration::from_millis(wait));
+                    match WebSocket::connect(&parsed) {
+                        Ok(connected) => {
+                            info!("Reconnected to {}", url);
+                            let marker = format!(
+                                "--- otail: reconnected to {}, messages sent whilst disconnected are missing ---",
+                                url
+                            );
+                            if let Err(e) = file.write_line(&marker) {
+                                error!("Failed to spool WebSocket messages: {:?}", e);
+                                break;
+                            }
+                            socket = Some(connected);
+                            wait = RECONNECT_MS;
+                        }
+                        Err(e) => {
+                            debug!("Failed to reconnect to {}: {:?}", url, e);
+                            wait = (wait * 2).min(MAX_RECONNECT_MS);
+                        }
+                    }
+                    continue;
+                };
+
+                match connected.read_message() {
+                    Ok(Some(message)) => {
+                        if let Err(e) = String::from_utf8_lossy(&message)
+                            .lines()
+                            .try_for_each(|line| file.write_line(line))
+                        {
+                            error!("Failed to spool WebSocket messages: {:?}", e);
+                            break;
+                        }
+                    }
+                    Ok(None) => {
+                        warn!("Disconnected from {}", url);
+                        socket = None;
+                    }
+                    Err(e) => {
+                        warn!("Disconnected from {}: {:?}", url, e);
+                        socket = None;
+                    }
+                }
+            }
+        });
+
+        Ok(spool)
+    }
+}
+
+impl Spool for WebSocketSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        self.buffer.as_ref()
+    }
+}
+
+impl Drop for WebSocketSpool {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A Kafka topic to consume, from a URL such as `kafka://broker1:9092,broker2:9092/events/3?offset=-100`,
+/// with an optional partition. The offset is as `kcat` takes it: `beginning`, `end` (the default),
+/// `stored`, an offset, or a negative one counting back from the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaSource {
+    pub brokers: String,
+    pub topic: String,
+    pub partition: Option<u32>,
+    pub offset: String,
+}
+
+impl KafkaSource {
+    pub fn parse(url: &str) -> Result<Self> {
+        let Some(rest) = url.strip_prefix("kafka://") else {
+            bail!("Not a Kafka URL: {}", url);
+        };
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut parts = rest.split('/');
+        let brokers = parts.next().unwrap_or_default();
+        let topic = parts.next().unwrap_or_default();
+        if brokers.is_empty() || topic.is_empty() {
+            bail!("A Kafka URL needs brokers and a topic, e.g. kafka://localhost:9092/events");
+        }
+        let partition = match parts.next() {
+            Some(partition) if !partition.is_empty() => Some(
+                partition
+                    .parse()
+                    .map_err(|_| anyhow!("Bad Kafka partition: {}", partition))?,
+            ),
+            _ => None,
+        };
+        if parts.next().is_some() {
+            bail!("Too many parts in the Kafka URL: {}", url);
+        }
+
+        let mut offset = "end".to_owned();
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            match param.split_once('=') {
+                Some(("offset", value)) => {
+                    let valid = matches!(value, "beginning" | "end" | "stored")
+                        || value.parse::<i64>().is_ok();
+                    if !valid {
+                        bail!("Bad Kafka offset: {}", value);
+                    }
+                    offset = value.to_owned();
+                }
+                _ => bail!("Unknown Kafka URL parameter: {}", param),
+            }
+        }
+
+        Ok(KafkaSource {
+            brokers: brokers.to_owned(),
+            topic: topic.to_owned(),
+            partition,
+            offset,
+        })
+    }
+
+    pub fn is_kafka(url: &str) -> bool {
+        url.starts_with("kafka://")
+    }
+}
+
+/// Consumes a Kafka topic with `kcat` (or `kafkacat`), writing each record's value to a temporary
+/// file as a line and carrying on as records arrive. Running the client rather than linking one
+/// keeps librdkafka out of the build.
+#[derive(Debug)]
+pub struct KafkaSpool {
+    file: SpoolFile,
+    buffer: Option<StreamBuffer>,
+    consumer: Child,
+}
+
+impl KafkaSpool {
+    pub fn start(url: &str, buffer: Option<StreamBuffer>) -> Result<Self> {
+        let source = KafkaSource::parse(url)?;
+        let (spool_file, file) = SpoolFile::create("kafka", url.to_owned())?;
+
+        let mut args = vec![
+            "-C".to_owned(),
+            "-b".to_owned(),
+            source.brokers.clone(),
+            "-t".to_owned(),
+            source.topic.clone(),
+            "-o".to_owned(),
+            source.offset.clone(),
+            "-u".to_owned(),
+            "-q".to_owned(),
+            "-f".to_owned(),
+            "%s\n".to_owned(),
+        ];
+        if let Some(partition) = source.partition {
+            args.extend(["-p".to_owned(), partition.to_string()]);
+        }
+
+        // Records go straight to the file, or through the buffer.
+        let spawn = |client: &str| {
+            let stdout = match &buffer {
+                Some(_) => Stdio::piped(),
+                None => file.try_clone()?.into(),
+            };
+            Command::new(client)
+                .args(&args)
+                .stdin(Stdio::null())
+                .stdout(stdout)
+                .stderr(Stdio::piped())
+                .spawn()
+        };
+        let mut consumer = match spawn("kcat") {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => spawn("kafkacat"),
+            result => result,
+        }
+        .map_err(|e| anyhow!("Failed to run kcat (or kafkacat), is it installed? {:?}", e))?;
+        info!("Consuming {:?} with {:?}", source, args);
+
+        if let (Some(buffer), Some(stdout)) = (buffer.clone(), consumer.stdout.take()) {
+            buffer.spool_to(file, SpoolDone::default());
+            thread::spawn(move || queue_lines(BufReader::new(stdout), &buffer));
+        }
+
+        // The client reports broker problems on stderr, and retries itself.
+        if let Some(stderr) = consumer.stderr.take() {
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    warn!("kcat: {}", line);
+                }
+            });
+        }
+
+        Ok(KafkaSpool {
+            file: spool_file,
+            buffer,
+            consumer,
+        })
+    }
+}
+
+impl Spool for KafkaSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        self.buffer.as_ref()
+    }
+}
+
+impl Drop for KafkaSpool {
+    fn drop(&mut self) {
+        self.consumer.kill().ok();
+        self.consumer.wait().ok();
+    }
+}
+
+/// A container's logs, from a URL such as `docker://web?tail=100`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DockerSource {
+    pub container: String,
+    // How many of the lines already logged to start with, or `all`.
+    pub tail: String,
+    // Only lines logged since, as `docker logs --since` takes, e.g. `10m` or a timestamp.
+    pub since: Option<String>,
+}
+
+impl DockerSource {
+    pub fn parse(url: &str) -> Result<Self> {
+        let Some(rest) = url.strip_prefix("docker://") else {
+            bail!("Not a Docker URL: {}", url);
+        };
+        let (container, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if container.is_empty() || container.contains('/') {
+            bail!("A Docker URL needs a container name or ID, e.g. docker://web");
+        }
+
+        let mut tail = "all".to_owned();
+        let mut since = None;
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            match param.split_once('=') {
+                Some(("tail", value)) => {
+                    if value != "all" && value.parse::<u64>().is_err() {
+                        bail!("Bad Docker tail: {}", value);
+                    }
+                    tail = value.to_owned();
+                }
+                Some(("since", value)) if !value.is_empty() => since = Some(value.to_owned()),
+                _ => bail!("Unknown Docker URL parameter: {}", param),
+            }
+        }
+
+        Ok(DockerSource {
+            container: container.to_owned(),
+            tail,
+            since,
+        })
+    }
+
+    pub fn is_docker(url: &str) -> bool {
+        url.starts_with("docker://")
+    }
+
+    // Whether the container is running, failing if there is no such container.
+    fn running(&self) -> Result<bool> {
+        let output = Command::new("docker")
+            .args(["inspect", "--format", "{{.State.Running}}", &self.container])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| anyhow!("Failed to run docker, is it installed? {:?}", e))?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim() == "true")
+    }
+
+    // Follow the logs from the start asked for, or, when following them again, since a time.
+    fn follow(&self, again_since: Option<&str>) -> io::Result<Child> {
+        let mut command = Command::new("docker");
+        command.args(["logs", "--follow"]);
+        if let Some(since) = again_since {
+            command.args(["--since", since]);
+        } else {
+            command.args(["--tail", &self.tail]);
+            if let Some(since) = &self.since {
+                command.args(["--since", since]);
+            }
+        }
+
+        command
+            .arg(&self.container)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+}
+
+/// Follows a container's logs with `docker logs`, writing its output and errors to a temporary
+/// file a line at a time. The daemon rotates the logs, which `docker logs` follows. When the
+/// container stops, it is waited for and its logs followed again from when they stopped, with a
+/// line marking where. Running the CLI rather than calling the API uses its context and
+/// credentials, and keeps a client out of the build.
+#[derive(Debug)]
+pub struct DockerSpool {
+    file: SpoolFile,
+    buffer: Option<StreamBuffer>,
+    stop: Arc<AtomicBool>,
+    logs: Arc<Mutex<Option<Child>>>,
+}
+
+impl DockerSpool {
+    pub fn start(url: &str, buffer: Option<StreamBuffer>) -> Result<Self> {
+        let source = DockerSource::parse(url)?;
+        // Fail straight away if there is no such container.
+        source.running()?;
+
+        let (spool_file, file) = SpoolFile::create("docker", url.to_owned())?;
+        let file = Arc::new(SpoolSink::new(file, buffer.clone(), &SpoolDone::default()));
+
+        let mut child = source
+            .follow(None)
+            .map_err(|e| anyhow!("Failed to run docker logs: {:?}", e))?;
+        info!("Following the logs of {:?}", source);
+        let mut readers = spool_output(&mut child, &file);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let logs = Arc::new(Mutex::new(Some(child)));
+        let spool = DockerSpool {
+            file: spool_file,
+            buffer,
+            stop: stop.clone(),
+            logs: logs.clone(),
+        };
+
+        let source = source.clone();
+        thread::spawn(move || loop {
+            // The logs end when the container stops.
+            for reader in readers.drain(..) {
+                reader.join().ok();
+            }
+            if let Some(mut child) = logs.lock().ok().and_then(|mut logs| logs.take()) {
+                child.wait().ok();
+            }
+            let stopped = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true);
+            debug!("Logs of {} ended", source.container);
+
+            // Wait for it to start again, e.g. by its restart policy or compose.
+            while !stop.load(Ordering::Relaxed) && !source.running().unwrap_or(false) {
+                thread::sleep(Duration::from_millis(DOCKER_POLL_MS));
+            }
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            info!("Following the logs of {} again", source.container);
+            let marker = format!(
+                "--- otail: following the logs of {} again ---",
+                source.container
+            );
+            if let Err(e) = file.write_line(&marker) {
+                error!("Failed to spool Docker logs: {:?}", e);
+                break;
+            }
+            let mut child = match source.follow(Some(&stopped)) {
+                Ok(child) => child,
+                Err(e) => {
+                    error!("Failed to run docker logs: {:?}", e);
+                    break;
+                }
+            };
+            readers = spool_output(&mut child, &file);
+            if let Ok(mut logs) = logs.lock() {
+                // Dropped whilst starting it again.
+                if stop.load(Ordering::Relaxed) {
+                    child.kill().ok();
+                }
+                *logs = Some(child);
+            }
+        });
+
+        Ok(spool)
+    }
+}
+
+impl Spool for DockerSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        self.buffer.as_ref()
+    }
+}
+
+impl Drop for DockerSpool {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(mut child) = self.logs.lock().ok().and_then(|mut logs| logs.take()) {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+}
+
+/// A pod's logs, from a URL such as `k8s://prod/web-7d4b9/app?tail=100`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct K8sSource {
+    pub namespace: String,
+    pub pod: String,
+    // The pod's container, or its default one.
+    pub container: Option<String>,
+    // How many of the lines already logged to start with, or `all`.
+    pub tail: String,
+    // Only lines logged since, as `kubectl logs --since` takes, e.g. `10m`.
+    pub since: Option<String>,
+}
+
+impl K8sSource {
+    pub fn parse(url: &str) -> Result<Self> {
+        let Some(rest) = url.strip_prefix("k8s://") else {
+            bail!("Not a Kubernetes URL: {}", url);
+        };
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let parts = rest.split('/').collect::<Vec<_>>();
+        let (namespace, pod, container) = match parts[..] {
+            [namespace, pod] => (namespace, pod, None),
+            [namespace, pod, container] if !container.is_empty() => {
+                (namespace, pod, Some(container.to_owned()))
+            }
+            _ => bail!("A Kubernetes URL needs a namespace and pod, e.g. k8s://default/web-0"),
+        };
+        if namespace.is_empty() || pod.is_empty() {
+            bail!("A Kubernetes URL needs a namespace and pod, e.g. k8s://default/web-0");
+        }
+
+        let mut tail = "all".to_owned();
+        let mut since = None;
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            match param.split_once('=') {
+                Some(("tail", value)) => {
+                    if value != "all" && value.parse::<u64>().is_err() {
+                        bail!("Bad Kubernetes tail: {}", value);
+                    }
+                    tail = value.to_owned();
+                }
+                Some(("since", value)) if !value.is_empty() => since = Some(value.to_owned()),
+                _ => bail!("Unknown Kubernetes URL parameter: {}", param),
+            }
+        }
+
+        Ok(K8sSource {
+            namespace: namespace.to_owned(),
+            pod: pod.to_owned(),
+            container,
+            tail,
+            since,
+        })
+    }
+
+    pub fn is_k8s(url: &str) -> bool {
+        url.starts_with("k8s://")
+    }
+
+    // How many times the container has restarted, and whether it is running now, failing if there
+    // is no such pod.
+    fn state(&self) -> Result<(u64, bool)> {
+        let status = match &self.container {
+            Some(container) => format!(".status.containerStatuses[?(@.name==\"{}\")]", container),
+            None => ".status.containerStatuses[0]".to_owned(),
+        };
+        let jsonpath = format!(
+            "jsonpath={{{status}.restartCount}} {{{status}.state.running.startedAt}}",
+            status = status
+        );
+        let output = Command::new("kubectl")
+            .args(["get", "pod", &self.pod, "--namespace", &self.namespace])
+            .args(["--output", &jsonpath])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| anyhow!("Failed to run kubectl, is it installed? {:?}", e))?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+
+        let state = String::from_utf8_lossy(&output.stdout);
+        let mut fields = state.split_whitespace();
+        let restarts = fields.next().and_then(|r| r.parse().ok()).unwrap_or(0);
+
+        Ok((restarts, fields.next().is_some()))
+    }
+
+    // Follow the logs from the start asked for, all of a restarted container's, or, when following
+    // them again, since a time.
+    fn follow(&self, from: LogsFrom) -> io::Result<Child> {
+        let mut command = Command::new("kubectl");
+        command
+            .args([
+                "logs",
+                "--follow",
+                &self.pod,
+                "--namespace",
+                &self.namespace,
+            ])
+            .args(self.container.iter().flat_map(|c| ["--container", c]));
+        match from {
+            LogsFrom::Start => {
+                let tail = match self.tail.as_str() {
+                    "all" => "-1",
+                    tail => tail,
+                };
+                command.args(["--tail", tail]);
+                if let Some(since) = &self.since {
+                    command.args(["--since", since]);
+                }
+            }
+            LogsFrom::Restarted => {
+                command.args(["--tail", "-1"]);
+            }
+            LogsFrom::Since(since) => {
+                command.args(["--since-time", &since]);
+            }
+        }
+
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+}
+
+// Where to follow a pod's logs from.
+enum LogsFrom {
+    Start,
+    Restarted,
+    Since(String),
+}
+
+/// Follows a pod's logs with `kubectl logs`, writing its container's output and errors to a
+/// temporary file a line at a time. When the container restarts, the file is emptied and the new
+/// container's logs written to it, so the file is read afresh as if truncated, starting with a line
+/// saying so. If the logs stop without a restart, e.g. on a dropped connection, they are followed
+/// again from when they stopped. Running `kubectl` rather than calling the API uses its config and
+/// credentials. With a buffer, lines go through it.
+#[derive(Debug)]
+pub struct K8sSpool {
+    file: SpoolFile,
+    buffer: Option<StreamBuffer>,
+    stop: Arc<AtomicBool>,
+    logs: Arc<Mutex<Option<Child>>>,
+}
+
+impl K8sSpool {
+    pub fn start(url: &str, buffer: Option<StreamBuffer>) -> Result<Self> {
+        let source = K8sSource::parse(url)?;
+        // Fail straight away if there is no such pod.
+        let (mut restarts, _) = source.state()?;
+
+        let (spool_file, file) = SpoolFile::create("k8s", url.to_owned())?;
+        let file = Arc::new(SpoolSink::new(file, buffer.clone(), &SpoolDone::default()));
+
+        let mut child = source
+            .follow(LogsFrom::Start)
+            .map_err(|e| anyhow!("Failed to run kubectl logs: {:?}", e))?;
+        info!("Following the logs of {:?}", source);
+        let mut readers = spool_output(&mut child, &file);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let logs = Arc::new(Mutex::new(Some(child)));
+        let spool = K8sSpool {
+            file: spool_file,
+            buffer,
+            stop: stop.clone(),
+            logs: logs.clone(),
+        };
+
+        let source = source.clone();
+        thread::spawn(move || loop {
+            // The logs end when the container stops, or the connection drops.
+            for reader in readers.drain(..) {
+                reader.join().ok();
+            }
+            if let Some(mut child) = logs.lock().ok().and_then(|mut logs| logs.take()) {
+                child.wait().ok();
+            }
+            let stopped = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+            debug!("Logs of {} ended", source.pod);
+
+            // Wait for the container to be running, perhaps again.
+            let mut state = None;
+            while !stop.load(Ordering::Relaxed) {
+                match source.state() {
+                    Ok((now, true)) => {
+                        state = Some(now);
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) => debug!("Failed to get the state of {}: {:?}", source.pod, e),
+                }
+                thread::sleep(Duration::from_millis(K8S_POLL_MS));
+            }
+            let Some(now) = state else {
+                break;
+            };
+
+            let from = if now > restarts {
+                info!("{} restarted, following the new logs", source.pod);
+                restarts = now;
+                let marker = format!(
+                    "--- otail: {} restarted, its logs from before are shown by kubectl logs --previous ---",
+                    source.pod
+                );
+                if let Err(e) = file.truncate().and_then(|_| file.write_line(&marker)) {
+                    error!("Failed to spool Kubernetes logs: {:?}", e);
+                    break;
+                }
+                LogsFrom::Restarted
+            } else {
+                info!("Following the logs of {} again", source.pod);
+                LogsFrom::Since(stopped)
+            };
+            let mut child = match source.follow(from) {
+                Ok(child) => child,
+                Err(e) => {
+                    error!("Failed to run kubectl logs: {:?}", e);
+                    break;
+                }
+            };
+            readers = spool_output(&mut child, &file);
+            if let Ok(mut logs) = logs.lock() {
+                // Dropped whilst starting it again.
+                if stop.load(Ordering::Relaxed) {
+                    child.kill().ok();
+                }
+                *logs = Some(child);
+            }
+        });
+
+        Ok(spool)
+    }
+}
+
+impl Spool for K8sSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        self.buffer.as_ref()
+    }
+}
+
+impl Drop for K8sSpool {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(mut child) = self.logs.lock().ok().and_then(|mut logs| logs.take()) {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+}
+
+/// Runs a command with `sh -c`, writing its output and errors to a temporary file a line at a time
+/// as they are written, so a build or test run can be watched, filtered and searched. The command
+/// runs in a process group of its own, which is killed if still running, along with anything it
+/// started, when the spool is dropped.
+#[derive(Debug)]
+pub struct ExecSpool {
+    file: SpoolFile,
+    buffer: Option<StreamBuffer>,
+    child: Arc<Mutex<Child>>,
+    status: CommandStatus,
+    done: SpoolDone,
+}
+
+impl ExecSpool {
+    pub fn start(command: &str, buffer: Option<StreamBuffer>) -> Result<Self> {
+        let (spool_file, file) = SpoolFile::create("exec", format!("$ {}", command))?;
+        let done = SpoolDone::default();
+        let file = Arc::new(SpoolSink::new(file, buffer.clone(), &done));
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run {}: {:?}", command, e))?;
+        info!("Running {:?}", command);
+
+        let readers = spool_output(&mut child, &file);
+
+        let child = Arc::new(Mutex::new(child));
+        let status = CommandStatus::default();
+        let waiting = (child.clone(), status.clone(), done.clone());
+        thread::spawn(move || {
+            let (child, status, done) = waiting;
+            loop {
+                let exited = match child.lock() {
+                    Ok(mut child) => child.try_wait(),
+                    Err(_) => break,
+                };
+                match exited {
+                    Ok(Some(exit)) => {
+                        info!("Command finished: {}", exit);
+                        status.set(exit);
+                        break;
+                    }
+                    Ok(None) => thread::sleep(Duration::from_millis(EXEC_POLL_MS)),
+                    Err(e) => {
+                        error!("Failed to wait for the command: {:?}", e);
+                        break;
+                    }
+                }
+            }
+
+            // The output is all spooled once the command, and anything it started, has closed it.
+            for reader in readers {
+                reader.join().ok();
+            }
+            file.end(&done);
+        });
+
+        Ok(ExecSpool {
+            file: spool_file,
+            buffer,
+            child,
+            status,
+            done,
+        })
+    }
+}
+
+impl Spool for ExecSpool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    /// Done once the command has finished and all of its output has been spooled.
+    fn done(&self) -> Option<SpoolDone> {
+        Some(self.done.clone())
+    }
+
+    fn buffer(&self) -> Option<&StreamBuffer> {
+        self.buffer.as_ref()
+    }
+
+    fn status(&self) -> Option<CommandStatus> {
+        Some(self.status.clone())
+    }
+}
+
+impl Drop for ExecSpool {
+    fn drop(&mut self) {
+        if let Ok(mut child) = self.child.lock() {
+            if let Ok(None) = child.try_wait() {
+                let group = format!("-{}", child.id());
+                let killed = Command::new("kill")
+                    .args(["--", &group])
+                    .status()
+                    .is_ok_and(|status| status.success());
+                if !killed {
+                    child.kill().ok();
+                }
+                child.wait().ok();
+            }
+        }
+    }
+}
+
+// Write a child's output and errors to its spool file, interleaved a line at a time as they are
+// written, returning the threads doing so, which finish when the child closes them.
+fn spool_output(child: &mut Child, file: &Arc<SpoolSink>) -> Vec<thread::JoinHandle<()>> {
+    let stdout = child
+        .stdout
+        .take()
+        .map(|out| Box::new(out) as Box<dyn Read + Send>);
+    let stderr = child
+        .stderr
+        .take()
+        .map(|err| Box::new(err) as Box<dyn Read + Send>);
+
+    [stdout, stderr]
+        .into_iter()
+        .flatten()
+        .map(|output| {
+            let file = file.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(output);
+                let mut line = Vec::new();
+                while let Ok(bytes) = reader.read_until(b'\n', &mut line) {
+                    if bytes == 0 {
+                        break;
+                    }
+                    let text = String::from_utf8_lossy(&line);
+                    if let Err(e) = file.write_line(text.trim_end_matches(['\r', '\n'])) {
+                        error!("Failed to spool output: {:?}", e);
+                        break;
+                    }
+                    line.clear();
+                }
+            })
+        })
+        .collect()
+}
+
+/// How a command run for its output is getting on, shared with the TUI to show.
+#[derive(Debug, Clone, Default)]
+pub struct CommandStatus {
+    exit: Arc<Mutex<Option<ExitStatus>>>,
+}
+
+impl CommandStatus {
+    fn set(&self, exit: ExitStatus) {
+        if let Ok(mut status) = self.exit.lock() {
+            *status = Some(exit);
+        }
+    }
+
+    /// E.g. `Running`, `Exited 0` or `Killed by signal 9`.
+    pub fn describe(&self) -> String {
+        let exit = self.exit.lock().ok().and_then(|exit| *exit);
+        match exit {
+            None => "Running".to_owned(),
+            Some(exit) => match (exit.code(), exit.signal()) {
+                (Some(code), _) => format!("Exited {}", code),
+                (None, Some(signal)) => format!("Killed by signal {}", signal),
+                (None, None) => "Finished".to_owned(),
+            },
+        }
+    }
+}
+
+/// An object in S3, from a URL such as `s3://bucket/exports/app.log.gz`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3Object {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl S3Object {
+    pub fn parse(url: &str) -> Result<Self> {
+        let Some((bucket, key)) = url
+            .strip_prefix("s3://")
+            .and_then(|rest| rest.split_once('/'))
+        else {
+            bail!("An S3 URL needs a bucket and key, e.g. s3://bucket/app.log");
+        };
+        if bucket.is_empty() || key.is_empty() {
+            bail!("An S3 URL needs a bucket and key, e.g. s3://bucket/app.log");
+        }
+
+        Ok(S3Object {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+        })
+    }
+
+    pub fn is_s3(url: &str) -> bool {
+        url.starts_with("s3://")
+    }
+
+    fn size(&self) -> Result<u64> {
+        let output = self.s3api(
+            "head-object",
+            &["--query", "ContentLength", "--output", "text"],
+        )?;
+        let size = String::from_utf8_lossy(&output);
+
+        size.trim()
+            .parse()
+            .map_err(|_| anyhow!("Unexpected size of {:?}: {}", self, size.trim()))
+    }
+
+    // Fetch the bytes from start up to and including end.
+    fn range(&self, start: u64, end: u64, chunk: &Path) -> Result<Vec<u8>> {
+        let range = format!("bytes={}-{}", start, end);
+        let chunk_arg = chunk.to_string_lossy();
+        self.s3api("get-object", &["--range", &range, &chunk_arg])?;
+        let bytes = fs::read(chunk)?;
+
+        Ok(bytes)
+    }
+
+    fn s3api(&self, command: &str, args: &[&str]) -> Result<Vec<u8>> {
+        let output = Command::new("aws")
+            .args([
+                "s3api",
+                command,
+                "--bucket",
+                &self.bucket,
+                "--key",
+                &self.key,
+            ])
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| anyhow!("Failed to run the AWS CLI (aws), is it installed? {:?}", e))?;
+        if !output.status.success() {
+            bail!(
+                "aws s3api {} failed for s3://{}/{}: {}",
+                command,
+                self.bucket,
+                self.key,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+/// Fetches an S3 object into a temporary file a range at a time, with the AWS CLI, so a large
+/// archived export can be browsed as soon as its start arrives. Objects ending `.gz` are
+/// decompressed as they arrive.
+#[derive(Debug)]
+pub struct S3Spool {
+    file: SpoolFile,
+    stop: Arc<AtomicBool>,
+    done: SpoolDone,
+}
+
+impl S3Spool {
+    pub fn start(url: &str) -> Result<Self> {
+        let object = S3Object::parse(url)?;
+        // Fail straight away if it can't be read at all.
+        let size = object.size()?;
+        if let Some(compression) = Compression::from_name(&object.key) {
+            compression.check_installed()?;
+        }
+        info!("Fetching {:?}, {} bytes", object, size);
+
+        let (spool_file, file) = SpoolFile::create("s3", url.to_owned())?;
+        // The AWS CLI writes each range over this file, which is kept until the object has been
+        // fetched so the path is never free for anyone else to take.
+        let (chunk, _) = create_spool_file("s3", "chunk")?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let done = SpoolDone::default();
+        let spool = S3Spool {
+            file: spool_file,
+            stop: stop.clone(),
+            done: done.clone(),
+        };
+
+        thread::spawn(move || {
+            match fetch_object(&object, size, &chunk, file, &stop) {
+                Ok(()) => done.finish(),
+                Err(e) => {
+                    error!("Failed to fetch {:?}: {:?}", object, e);
+                    done.fail(format!("Failed to fetch the object: {}", e));
+                }
+            }
+            fs::remove_file(&chunk).ok();
+        });
+
+        Ok(spool)
+    }
+}
+
+impl Spool for S3Spool {
+    fn file(&self) -> &SpoolFile {
+        &self.file
+    }
+
+    /// Done once the whole object has been fetched.
+    fn done(&self) -> Option<SpoolDone> {
+        Some(self.done.clone())
+    }
+}
+
+impl Drop for S3Spool {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn fetch_object(
+    object: &S3Object,
+    size: u64,
+    chunk: &Path,
+    file: File,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let mut decompressor = None;
+    let mut out: Box<dyn Write> = if let Some(compression) = Compression::from_name(&object.key) {
+        let mut started = compression.decompress(None, file)?;
+        let input = started
+            .input()
+            .ok_or(anyhow!("No {} input", compression.command()))?;
+        decompressor = Some(started);
+        Box::new(input)
+    } else {
+        Box::new(file)
+    };
+
+    let mut start = 0;
+    while start < size && !stop.load(Ordering::Relaxed) {
+        let end = (start + S3_CHUNK_BYTES).min(size) - 1;
+        let bytes = object.range(start, end, chunk)?;
+        trace!("Fetched {} bytes from {}", bytes.len(), start);
+        if bytes.is_empty() {
+            bail!("No bytes at {}", start);
+        }
+        out.write_all(&bytes)?;
+        start += bytes.len() as u64;
+    }
+    debug!("Fetched {:?}", object);
+
+    // Close the decompressor's input so it finishes.
+    drop(out);
+    if let Some(mut decompressor) = decompressor {
+        decompressor.finish()?;
+    }
+
+    Ok(())
+}
+
+// Read the messages sent over a TCP connection: a line each, or each after its length as RFC 6587
+// allows, e.g. `12 <14>Hello...`.
+fn receive_stream(stream: TcpStream, file: &SpoolSink) -> Result<()> {
+    debug!("Connection from {}", stream.peer_addr()?);
+    let mut reader = BufReader::new(stream);
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+
+        let digits = buffer.iter().take_while(|b| b.is_ascii_digit()).count();
+        let counted = digits > 0 && buffer[digits..].starts_with(b" <");
+        let mut message = Vec::new();
+        if counted {
+            let length = String::from_utf8_lossy(&buffer[..digits]).parse::<usize>()?;
+            // The length is the sender's, so a message any longer closes the connection rather
+            // than being read into memory.
+            if length > LISTEN_PACKET {
+                bail!("Message of {} bytes, longer than {}", length, LISTEN_PACKET);
+            }
+            reader.consume(digits + 1);
+            message.resize(length, 0);
+            reader.read_exact(&mut message)?;
+        } else {
+            reader.read_until(b'\n', &mut message)?;
+        }
+        spool_message(file, &String::from_utf8_lossy(&message));
+    }
+
+    Ok(())
+}
+
+fn spool_message(file: &SpoolSink, message: &str) {
+    let message = message.trim_end_matches(['\r', '\n']);
+    if message.is_empty() {
+        return;
+    }
+
+    if let Err(e) = file.write_line(&format_syslog(message)) {
+        error!("Failed to spool a message: {:?}", e);
+    }
+}
+
+/// A syslog message as a line starting with its timestamp, severity and facility, followed by the
+/// host, app and message, e.g. `2025-01-23T10:15:00Z ERROR daemon web1 nginx[42]: upstream timed
+/// out`, so both can be coloured or filtered on. Anything that isn't syslog is left as it is.
+pub fn format_syslog(message: &str) -> String {
+    let Some((pri, rest)) = message.strip_prefix('<').and_then(|m| m.split_once('>')) else {
+        return message.to_owned();
+    };
+    let (severity, facility) = match pri.parse::<u8>() {
+        Ok(pri) if pri < 192 => (
+            SEVERITIES[(pri % 8) as usize],
+            FACILITIES[(pri / 8) as usize],
+        ),
+        _ => return message.to_owned(),
+    };
+    let priority = format!("{} {}", severity, facility);
+
+    if let Some(rest) = rest.strip_prefix("1 ") {
+        if let Some(line) = format_rfc5424(&priority, rest) {
+            return line;
+        }
+    }
+
+    // RFC 3164 starts with a timestamp such as `Jan 23 10:15:00`, then the host and tag.
+    match rest.get(..15) {
+        Some(timestamp)
+            if timestamp.as_bytes()[9] == b':'
+                && timestamp.as_bytes()[12] == b':'
+                && rest[15..].starts_with(' ') =>
+        {
+            format!("{} {} {}", timestamp, priority, &rest[16..])
+        }
+        _ => format!("{} {}", priority, rest),
+    }
+}
+
+// VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG, after the version, where `-`
+// is a missing field.
+fn format_rfc5424(priority: &str, rest: &str) -> Option<String> {
+    let mut fields = rest.splitn(6, ' ');
+    let mut field = || fields.next().map(|f| (f != "-").then_some(f));
+    let (timestamp, host, app, procid, msgid) = (field()?, field()?, field()?, field()?, field()?);
+    let rest = fields.next().unwrap_or_default();
+
+    // Structured data is `-` or bracketed elements, where `\]` doesn't end one.
+    let (data, msg) = if let Some(msg) = rest.strip_prefix('-') {
+        (None, msg)
+    } else if rest.starts_with('[') {
+        let mut escaped = false;
+        let mut depth = 0;
+        let mut end = rest.len();
+        for (i, c) in rest.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                ' ' if depth == 0 => {
+                    end = i;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        (Some(&rest[..end]), &rest[end..])
+    } else {
+        return None;
+    };
+    let msg = msg.trim_start_matches(' ').trim_start_matches('\u{feff}');
+
+    let tag = match (app, procid) {
+        (Some(app), Some(procid)) => Some(format!("{}[{}]:", app, procid)),
+        (Some(app), None) => Some(format!("{}:", app)),
+        _ => None,
+    };
+    let parts = [
+        timestamp,
+        Some(priority),
+        host,
+        tag.as_deref(),
+        msgid,
+        data,
+        Some(msg),
+    ];
+
+    Some(
+        parts
+            .into_iter()
+            .flatten()
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_buffer() {
+        let waiting = |buffer: &StreamBuffer| {
+            buffer
+                .queue
+                .lines
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|line| String::from_utf8_lossy(line).into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let oldest = StreamBuffer::new(DropPolicy::DropOldest, 2);
+        let newest = StreamBuffer::new(DropPolicy::DropNewest, 2);
+        for line in ["one\n", "two\n", "three\n"] {
+            oldest.push(line.as_bytes().to_vec());
+            newest.push(line.as_bytes().to_vec());
+        }
+        assert_eq!(waiting(&oldest), vec!["two\n", "three\n"]);
+        assert_eq!(oldest.dropped(), 1);
+        assert_eq!(waiting(&newest), vec!["one\n", "two\n"]);
+        assert_eq!(newest.dropped(), 1);
+        assert_eq!(newest.queued(), 2);
+
+        // Blocking waits for the lines to be spooled, dropping none.
+        let path = std::env::temp_dir().join(format!("otail-test-stream-{}", std::process::id()));
+        let block = StreamBuffer::new(DropPolicy::Block, 2);
+        block.spool_to(File::create(&path).unwrap(), SpoolDone::default());
+        for i in 0..100 {
+            block.push(format!("line {}\n", i).into_bytes());
+        }
+        let spooled = || fs::read_to_string(&path).unwrap().lines().count();
+        for _ in 0..200 {
+            if spooled() == 100 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(spooled(), 100);
+        assert_eq!(block.dropped(), 0);
+        fs::remove_file(&path).unwrap();
+
+        // Records are queued whole, whatever ends them.
+        let dashes = StreamBuffer::new(DropPolicy::DropOldest, 2)
+            .with_delimiter(LineDelimiter::Bytes(b"--".to_vec()));
+        queue_lines(&b"one\ntwo--three-x--four"[..], &dashes);
+        assert_eq!(waiting(&dashes), vec!["three-x--", "four"]);
+        assert_eq!(dashes.dropped(), 1);
+
+        // Truncating empties the file, even when written well ahead of what has been read.
+        let truncated = StreamBuffer::new(DropPolicy::Block, 10);
+        truncated.spool_to(File::create(&path).unwrap(), SpoolDone::default());
+        truncated.push(vec![b'x'; SPOOL_AHEAD_BYTES as usize]);
+        truncated.push(b"not spooled yet\n".to_vec());
+        let spooled = || fs::read(&path).unwrap();
+        for _ in 0..200 {
+            if spooled().len() as u64 == SPOOL_AHEAD_BYTES {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        truncated.truncate();
+        truncated.push(b"afresh\n".to_vec());
+        for _ in 0..200 {
+            if spooled() == b"afresh\n" {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(spooled(), b"afresh\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_create_spool_file() {
+        let pid = std::process::id();
+        let dir = std::env::temp_dir();
+        let victim = dir.join(format!("otail-test-victim-{}", pid));
+        fs::write(&victim, "precious\n").unwrap();
+        // A link left where the spool file would go.
+        let taken = dir.join(format!("otail-test-spool-{}.log", pid));
+        std::os::unix::fs::symlink(&victim, &taken).unwrap();
+
+        let (path, mut file) = create_spool_file("test-spool", "log").unwrap();
+        assert_ne!(path, taken);
+        file.write_all(b"spooled\n").unwrap();
+        assert_eq!(fs::read_to_string(&victim).unwrap(), "precious\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "spooled\n");
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+
+        for path in [victim, taken, path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rotation_number() {
+        assert_eq!(rotation_number("app.log", "app.log.1"), Some(1));
+        assert_eq!(rotation_number("app.log", "app.log.12.gz"), Some(12));
+        assert_eq!(rotation_number("app.log", "app.log.3.zst"), Some(3));
+        assert_eq!(rotation_number("app.log", "app.log.4.bz2"), Some(4));
+        assert_eq!(rotation_number("app.log", "app.log"), None);
+        assert_eq!(rotation_number("app.log", "app.log.gz"), None);
+        assert_eq!(rotation_number("app.log", "app.log.old"), None);
+        assert_eq!(rotation_number("app.log", "app.log.1.bak"), None);
+        assert_eq!(rotation_number("app.log", "other.log.1"), None);
+    }
+
+    #[test]
+    fn test_source_tags() {
+        let paths = [
+            "/var/log/app.log",
+            "db.log",
+            "logs/app.log",
+            "app.txt",
+            "README",
+        ]
+        .map(String::from);
+        assert_eq!(
+            source_tags(&paths),
+            vec!["app", "db", "app-2", "app-3", "README"]
+        );
+        assert_eq!(source_prefix("db"), "[db] ");
+    }
+
+    #[test]
+    fn test_exec() {
+        let spool = ExecSpool::start("echo out; echo err >&2; exit 3", None).unwrap();
+        assert_eq!(spool.name(), "$ echo out; echo err >&2; exit 3");
+        let status = spool.status().unwrap();
+        let mut lines = Vec::new();
+        for _ in 0..200 {
+            lines = fs::read_to_string(spool.path())
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            if status.describe() != "Running" && lines.len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(status.describe(), "Exited 3");
+
+        // The lines are written whole, in whichever order they arrived.
+        lines.sort();
+        assert_eq!(lines, vec!["err", "out"]);
+        drop(spool);
+
+        // What the command started is killed with it.
+        let killed = ExecSpool::start("sleep 10 & echo $!; wait", None).unwrap();
+        let path = killed.path();
+        let mut pid = String::new();
+        for _ in 0..200 {
+            pid = fs::read_to_string(&path).unwrap().trim().to_owned();
+            if !pid.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(killed);
+        assert!(!Path::new(&path).exists());
+        thread::sleep(Duration::from_millis(100));
+        // Gone, or dead but not yet reaped by init.
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+    }
+
+    #[test]
+    fn test_listen_addr() {
+        let listen = |udp, tcp| ListenAddr {
+            addr: "0.0.0.0:5000".to_owned(),
+            udp,
+            tcp,
+        };
+        assert_eq!(
+            ListenAddr::parse("0.0.0.0:5000").unwrap(),
+            listen(true, true)
+        );
+        assert_eq!(
+            ListenAddr::parse("tcp://0.0.0.0:5000").unwrap(),
+            listen(false, true)
+        );
+        assert_eq!(
+            ListenAddr::parse("udp://0.0.0.0:5000").unwrap(),
+            listen(true, false)
+        );
+        assert!(ListenAddr::parse("http://0.0.0.0:5000").is_err());
+    }
+
+    #[test]
+    fn test_receive_stream_too_long() {
+        let path = std::env::temp_dir().join(format!("otail-test-receive-{}", std::process::id()));
+        let sink = SpoolSink::File(Mutex::new(File::create(&path).unwrap()));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        // A message within the limit is spooled, one claiming to be longer closes the connection.
+        sender.write_all(b"5 <14>a99999999999 <14>b").unwrap();
+        let result = receive_stream(stream, &sink);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("99999999999 bytes"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "INFO user a\n");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_fifo() {
+        let dir = std::env::temp_dir().join(format!("otail-test-fifo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let fifo = dir.join("pipe").to_string_lossy().into_owned();
+        let status = Command::new("mkfifo").arg(&fifo).status().unwrap();
+        assert!(status.success());
+        assert!(is_fifo(&fifo));
+        assert!(!is_fifo(&dir.to_string_lossy()));
+
+        // Each writer's lines are spooled in turn.
+        let spool = FifoSpool::start(&fifo, None).unwrap();
+        for line in ["first\n", "second\n"] {
+            fs::write(&fifo, line).unwrap();
+        }
+        let mut spooled = String::new();
+        for _ in 0..100 {
+            spooled = fs::read_to_string(spool.path()).unwrap();
+            if spooled.len() == 13 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(spooled, "first\nsecond\n");
+
+        drop(spool);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_by_time() {
+        let dir = std::env::temp_dir().join(format!("otail-test-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let app = dir.join("app.log");
+        let db = dir.join("db.log");
+        fs::write(
+            &app,
+            "2024-03-01 10:00:01 start\n  more\n2024-03-01 10:00:04 stop\n",
+        )
+        .unwrap();
+        fs::write(&db, "2024-03-01 10:00:02 open\n2024-03-01 10:00:04 close\n").unwrap();
+        let merged = dir.join("merged.log");
+        let mut file = File::create(&merged).unwrap();
+
+        let mut sources = vec![
+            MergeSource::open(&app, "app").unwrap(),
+            MergeSource::open(&db, "db").unwrap(),
+        ];
+        let parser = TimestampParser::default();
+        merge_by_time(&mut sources, &parser, &mut file).unwrap();
+        assert_eq!(
+            fs::read_to_string(&merged).unwrap(),
+            "[app] 2024-03-01 10:00:01 start\n[app]   more\n[db] 2024-03-01 10:00:02 open\n\
+             [app] 2024-03-01 10:00:04 stop\n[db] 2024-03-01 10:00:04 close\n"
+        );
+
+        // A line written later waits for the quiet file, which could have an earlier line.
+        let mut db_file = fs::OpenOptions::new().append(true).open(&db).unwrap();
+        db_file.write_all(b"2024-03-01 10:00:06 query\n").unwrap();
+        merge_by_time(&mut sources, &parser, &mut file).unwrap();
+        let mut app_file = fs::OpenOptions::new().append(true).open(&app).unwrap();
+        app_file.write_all(b"2024-03-01 10:00:05 ping\n").unwrap();
+        merge_by_time(&mut sources, &parser, &mut file).unwrap();
+        assert!(fs::read_to_string(&merged)
+            .unwrap()
+            .ends_with("[app] 2024-03-01 10:00:05 ping\n"));
+        assert!(sources[1].waiting.is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_kafka() {
+        assert_eq!(
+            KafkaSource::parse("kafka://b1:9092,b2:9092/events/3?offset=-100").unwrap(),
+            KafkaSource {
+                brokers: "b1:9092,b2:9092".to_owned(),
+                topic: "events".to_owned(),
+                partition: Some(3),
+                offset: "-100".to_owned(),
+            }
+        );
+        let source = KafkaSource::parse("kafka://localhost:9092/events").unwrap();
+        assert_eq!((source.partition, source.offset.as_str()), (None, "end"));
+
+        assert!(KafkaSource::parse("kafka://localhost:9092").is_err());
+        assert!(KafkaSource::parse("kafka://localhost:9092/events/x").is_err());
+        assert!(KafkaSource::parse("kafka://localhost:9092/events?offset=soon").is_err());
+        assert!(KafkaSource::parse("kafka://localhost:9092/events?group=g").is_err());
+    }
+
+    #[test]
+    fn test_parse_docker() {
+        assert_eq!(
+            DockerSource::parse("docker://web?tail=100&since=10m").unwrap(),
+            DockerSource {
+                container: "web".to_owned(),
+                tail: "100".to_owned(),
+                since: Some("10m".to_owned()),
+            }
+        );
+        let source = DockerSource::parse("docker://4f2a9c").unwrap();
+        assert_eq!((source.tail.as_str(), source.since), ("all", None));
+
+        assert!(DockerSource::parse("docker://").is_err());
+        assert!(DockerSource::parse("docker://web/logs").is_err());
+        assert!(DockerSource::parse("docker://web?tail=lots").is_err());
+        assert!(DockerSource::parse("docker://web?follow=false").is_err());
+    }
+
+    #[test]
+    fn test_parse_k8s() {
+        assert_eq!(
+            K8sSource::parse("k8s://prod/web-7d4b9/app?tail=100&since=1h").unwrap(),
+            K8sSource {
+                namespace: "prod".to_owned(),
+                pod: "web-7d4b9".to_owned(),
+                container: Some("app".to_owned()),
+                tail: "100".to_owned(),
+                since: Some("1h".to_owned()),
+            }
+        );
+        let source = K8sSource::parse("k8s://default/web-0").unwrap();
+        assert_eq!((source.container, source.tail.as_str()), (None, "all"));
+
+        assert!(K8sSource::parse("k8s://default").is_err());
+        assert!(K8sSource::parse("k8s:///web-0").is_err());
+        assert!(K8sSource::parse("k8s://default/web-0/app/x").is_err());
+        assert!(K8sSource::parse("k8s://default/web-0?tail=lots").is_err());
+        assert!(K8sSource::parse("k8s://default/web-0?previous=true").is_err());
+    }
+
+    #[test]
+    fn test_parse_s3() {
+        assert_eq!(
+            S3Object::parse("s3://logs/exports/2025/app.log.gz").unwrap(),
+            S3Object {
+                bucket: "logs".to_owned(),
+                key: "exports/2025/app.log.gz".to_owned(),
+            }
+        );
+        assert!(S3Object::parse("s3://logs").is_err());
+        assert!(S3Object::parse("s3://logs/").is_err());
+        assert!(S3Object::parse("s3:///app.log").is_err());
+    }
+
+    #[test]
+    fn test_format_syslog() {
+        assert_eq!(
+            format_syslog("<34>Oct 11 22:14:15 mymachine su: 'su root' failed on /dev/pts/8"),
+            "Oct 11 22:14:15 CRIT auth mymachine su: 'su root' failed on /dev/pts/8"
+        );
+        assert_eq!(format_syslog("<13>no header"), "NOTICE user no header");
+        assert_eq!(
+            format_syslog("<165>1 2003-10-11T22:14:15.003Z host.example.com evntslog - ID47 [exampleSDID@32473 iut=\"3\" eventID=\"10\\]11\"][more x=\"y\"] \u{feff}An application event"),
+            "2003-10-11T22:14:15.003Z NOTICE local4 host.example.com evntslog: ID47 [exampleSDID@32473 iut=\"3\" eventID=\"10\\]11\"][more x=\"y\"] An application event"
+        );
+        assert_eq!(
+            format_syslog("<11>1 2025-01-23T10:15:00Z web1 nginx 42 - - upstream timed out"),
+            "2025-01-23T10:15:00Z ERROR user web1 nginx[42]: upstream timed out"
+        );
+        assert_eq!(format_syslog("<11>1 - - - - - -"), "ERROR user");
+        assert_eq!(
+            format_syslog("<191>local7 debug"),
+            "DEBUG local7 local7 debug"
+        );
+        assert_eq!(format_syslog("plain line"), "plain line");
+        assert_eq!(format_syslog("<999>x"), "<999>x");
+        assert_eq!(format_syslog("<a>x"), "<a>x");
+    }
+}
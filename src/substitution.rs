@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+
+use crate::filter_spec::macro_regex;
+
+/// A display rewrite written as in sed: `s/regex/replacement/` replaces the first match, and with
+/// a trailing `g` every match. Any delimiter can be used, e.g. `s|/opt/app/||`. In the
+/// replacement `\1` is a capture group and `&` the whole match.
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    expr: String,
+    regex: Regex,
+    replacement: String,
+    global: bool,
+}
+
+impl Substitution {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let expr = expr.trim();
+        let usage = || {
+            anyhow!(
+                "Expected s/regex/replacement/ or s/regex/replacement/g: {}",
+                expr
+            )
+        };
+
+        let mut chars = expr.chars();
+        if chars.next() != Some('s') {
+            return Err(usage());
+        }
+        let delimiter = chars
+            .next()
+            .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && *c != '\\')
+            .ok_or_else(usage)?;
+
+        // Split on the delimiter, unless escaped. Other escapes are kept for the regex.
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        for c in chars {
+            let part = parts.last_mut().expect("Always a part");
+            if escaped {
+                if c != delimiter {
+                    part.push('\\');
+                }
+                part.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == delimiter {
+                parts.push(String::new());
+            } else {
+                part.push(c);
+            }
+        }
+
+        // The closing delimiter can be left off, as in vim.
+        let (pattern, replacement, flags) = match parts.as_slice() {
+            [pattern, replacement] => (pattern, replacement, ""),
+            [pattern, replacement, flags] => (pattern, replacement, flags.as_str()),
+            _ => return Err(usage()),
+        };
+        let global = match flags {
+            "" => false,
+            "g" => true,
+            _ => bail!("Unknown substitution flags '{}' in: {}", flags, expr),
+        };
+
+        Ok(Substitution {
+            expr: expr.to_owned(),
+            regex: macro_regex(pattern)?,
+            replacement: sed_replacement(replacement),
+            global,
+        })
+    }
+
+    pub fn expr(&self) -> &str {
+        &self.expr
+    }
+
+    pub fn apply<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        if self.global {
+            self.regex.replace_all(line, self.replacement.as_str())
+        } else {
+            self.regex.replace(line, self.replacement.as_str())
+        }
+    }
+}
+
+/// Apply the substitutions in turn.
+pub fn substitute(substitutions: &[Substitution], line: String) -> String {
+    substitutions
+        .iter()
+        .fold(line, |line, substitution| match substitution.apply(&line) {
+            Cow::Borrowed(_) => line,
+            Cow::Owned(replaced) => replaced,
+        })
+}
+
+// Convert a sed replacement to the regex crate's: `\1` to `${1}`, `&` to `${0}` and `\&` to `&`,
+// with `$` kept literal.
+fn sed_replacement(replacement: &str) -> String {
+    let mut converted = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => converted.push_str(&format!("${{{}}}", d)),
+                Some('$') => converted.push_str("$$"),
+                Some(c) => converted.push(c),
+                None => converted.push('\\'),
+            },
+            '&' => converted.push_str("${0}"),
+            '$' => converted.push_str("$$"),
+            c => converted.push(c),
+        }
+    }
+
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitution() {
+        let apply =
+            |expr: &str, line: &str| Substitution::parse(expr).unwrap().apply(line).into_owned();
+
+        assert_eq!(apply("s/o/0/", "foo boo"), "f0o boo");
+        assert_eq!(apply("s/o/0/g", "foo boo"), "f00 b00");
+        assert_eq!(
+            apply("s|/opt/app/[^/]+/||", "at /opt/app/1.2/lib/x.rs"),
+            "at lib/x.rs"
+        );
+        assert_eq!(apply(r"s/(\w+)@(\w+)/\2:\1/", "bob@host"), "host:bob");
+        assert_eq!(apply("s/id=[0-9]+/[&]/", "id=42 ok"), "[id=42] ok");
+        assert_eq!(apply(r"s/a\/b/$1 \&/", "x a/b"), "x $1 &");
+        assert_eq!(apply("s/x/y", "axb"), "ayb");
+
+        let substitutions = vec![
+            Substitution::parse("s/[0-9a-f]{8}-[0-9a-f-]{27}/<uuid>/g").unwrap(),
+            Substitution::parse("s/<uuid>/U/").unwrap(),
+        ];
+        assert_eq!(
+            substitute(
+                &substitutions,
+                "req 123e4567-e89b-12d3-a456-426614174000 done".to_owned()
+            ),
+            "req U done"
+        );
+
+        assert!(Substitution::parse("x/a/b/").is_err());
+        assert!(Substitution::parse("sa/b/").is_err());
+        assert!(Substitution::parse("s/a/b/c/d").is_err());
+        assert!(Substitution::parse("s/a/b/i").is_err());
+        assert!(Substitution::parse("s/(/b/").is_err());
+    }
+}
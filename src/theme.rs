@@ -0,0 +1,189 @@
+use std::fs::read_to_string;
+use std::sync::RwLock;
+
+use anyhow::{anyhow, bail, Result};
+use lazy_static::lazy_static;
+
+use crate::colour_spec::Colour;
+
+lazy_static! {
+    static ref THEME: RwLock<Option<Theme>> = RwLock::new(None);
+}
+
+pub type Rgb = (u8, u8, u8);
+
+// Where otail's colours are in a terminal palette, as ANSI numbers. Gray is ANSI white and White
+// bright white.
+const TERMINAL_SLOTS: [(Colour, usize); 9] = [
+    (Colour::Black, 0),
+    (Colour::Red, 1),
+    (Colour::Green, 2),
+    (Colour::Yellow, 3),
+    (Colour::Blue, 4),
+    (Colour::Magenta, 5),
+    (Colour::Cyan, 6),
+    (Colour::Gray, 7),
+    (Colour::White, 15),
+];
+
+// Where otail's colours are in a base16 scheme, as base16-shell puts them in the terminal palette.
+const BASE16_SLOTS: [(Colour, &str); 9] = [
+    (Colour::Black, "base00"),
+    (Colour::Red, "base08"),
+    (Colour::Green, "base0B"),
+    (Colour::Yellow, "base0A"),
+    (Colour::Blue, "base0D"),
+    (Colour::Magenta, "base0E"),
+    (Colour::Cyan, "base0C"),
+    (Colour::Gray, "base05"),
+    (Colour::White, "base07"),
+];
+
+/// Colours from a terminal theme used for otail's colours, in the UI and colouring rules, so they
+/// match the rest of the terminal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    colours: Vec<(Colour, Rgb)>,
+}
+
+impl Theme {
+    pub fn load(path: &str) -> Result<Self> {
+        let text =
+            read_to_string(path).map_err(|e| anyhow!("Failed to read theme {}: {}", path, e))?;
+
+        Theme::parse(&text).map_err(|e| anyhow!("Bad theme {}: {}", path, e))
+    }
+
+    /// A base16 scheme (`base00` to `base0F`, optionally under `palette`) or a terminal.sexy
+    /// export (a `color` list of the 16 ANSI colours), in YAML or JSON.
+    pub fn parse(text: &str) -> Result<Self> {
+        let value: serde_yaml::Value = serde_yaml::from_str(text)?;
+        let hex = |value: Option<&serde_yaml::Value>, name: &str| {
+            value
+                .and_then(|v| v.as_str())
+                .ok_or(anyhow!("No colour {}", name))
+                .and_then(parse_hex)
+        };
+
+        let colours = if let Some(list) = value.get("color").and_then(|c| c.as_sequence()) {
+            TERMINAL_SLOTS
+                .iter()
+                .map(|(colour, i)| {
+                    Ok((colour.clone(), hex(list.get(*i), &format!("color {}", i))?))
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            let scheme = value.get("palette").unwrap_or(&value);
+            if scheme.get("base00").is_none() {
+                bail!("Not a base16 scheme or terminal.sexy palette");
+            }
+            BASE16_SLOTS
+                .iter()
+                .map(|(colour, name)| Ok((colour.clone(), hex(scheme.get(*name), name)?)))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        Ok(Theme { colours })
+    }
+
+    pub fn rgb(&self, colour: &Colour) -> Option<Rgb> {
+        self.colours
+            .iter()
+            .find(|(c, _)| c == colour)
+            .map(|(_, rgb)| *rgb)
+    }
+}
+
+pub fn set_theme(theme: Option<Theme>) {
+    if let Ok(mut t) = THEME.write() {
+        *t = theme;
+    }
+}
+
+/// The theme's colour for one of otail's colours, if a theme is set.
+pub fn theme_rgb(colour: &Colour) -> Option<Rgb> {
+    THEME.read().ok()?.as_ref()?.rgb(colour)
+}
+
+// A colour such as `#1d2021` or `1d2021`.
+fn parse_hex(hex: &str) -> Result<Rgb> {
+    let digits = hex.trim_start_matches('#');
+    let channel = |i: usize| {
+        digits
+            .get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+            .ok_or(anyhow!("Bad colour: {}", hex))
+    };
+    if digits.len() != 6 {
+        bail!("Bad colour: {}", hex);
+    }
+
+    Ok((channel(0)?, channel(2)?, channel(4)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base16() {
+        let scheme = r#"
+scheme: "Gruvbox dark"
+author: "Dawid Kurek"
+base00: "282828"
+base01: "3c3836"
+base02: "504945"
+base03: "665c54"
+base04: "bdae93"
+base05: "d5c4a1"
+base06: "ebdbb2"
+base07: "fbf1c7"
+base08: "fb4934"
+base09: "fe8019"
+base0A: "fabd2f"
+base0B: "b8bb26"
+base0C: "8ec07c"
+base0D: "83a598"
+base0E: "d3869b"
+base0F: "d65d0e"
+"#;
+        let theme = Theme::parse(scheme).unwrap();
+        assert_eq!(theme.rgb(&Colour::Black), Some((0x28, 0x28, 0x28)));
+        assert_eq!(theme.rgb(&Colour::Red), Some((0xfb, 0x49, 0x34)));
+        assert_eq!(theme.rgb(&Colour::White), Some((0xfb, 0xf1, 0xc7)));
+
+        // The newer layout, with the colours under palette.
+        let nested = "system: base16\npalette:\n".to_owned()
+            + &scheme
+                .lines()
+                .filter(|l| l.starts_with("base"))
+                .map(|l| format!("  {}\n", l))
+                .collect::<String>();
+        assert_eq!(Theme::parse(&nested).unwrap(), theme);
+    }
+
+    #[test]
+    fn test_terminal_sexy() {
+        let colours = (0..16)
+            .map(|i| format!("\"#0000{:02x}\"", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let palette = format!(
+            r##"{{"name":"x","color":[{}],"foreground":"#ffffff","background":"#000000"}}"##,
+            colours
+        );
+        let theme = Theme::parse(&palette).unwrap();
+        assert_eq!(theme.rgb(&Colour::Blue), Some((0, 0, 4)));
+        assert_eq!(theme.rgb(&Colour::Gray), Some((0, 0, 7)));
+        assert_eq!(theme.rgb(&Colour::White), Some((0, 0, 15)));
+    }
+
+    #[test]
+    fn test_bad_themes() {
+        assert!(Theme::parse("name: nothing").is_err());
+        assert!(Theme::parse(r##"{"color": ["#000000"]}"##).is_err());
+        assert!(parse_hex("#12345").is_err());
+        assert!(parse_hex("#12345g").is_err());
+        assert_eq!(parse_hex("#A0b1C2").unwrap(), (0xa0, 0xb1, 0xc2));
+    }
+}
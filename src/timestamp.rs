@@ -0,0 +1,545 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta,
+    TimeZone, Utc,
+};
+use regex::Regex;
+
+pub const DEFAULT_TIME_GAP_SECS: f64 = 5.0;
+
+// The built in formats, tried in order after any user defined formats.
+const BUILTIN_FORMATS: [FormatKind; 4] = [
+    FormatKind::Rfc3339,
+    FormatKind::Java,
+    FormatKind::Syslog,
+    FormatKind::EpochMillis,
+];
+
+/// How timestamps are shown. Only the display changes, filtering still uses the raw text.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TimeDisplay {
+    #[default]
+    Original,
+    Local,
+    Utc,
+}
+
+impl TimeDisplay {
+    pub fn next(&self) -> Self {
+        match self {
+            TimeDisplay::Original => TimeDisplay::Local,
+            TimeDisplay::Local => TimeDisplay::Utc,
+            TimeDisplay::Utc => TimeDisplay::Original,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TimeDisplay::Original => "Original",
+            TimeDisplay::Local => "Local",
+            TimeDisplay::Utc => "UTC",
+        }
+    }
+}
+
+/// A timestamp found in a line. Timestamps without a zone are taken to be local time and are not
+/// `zoned`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedTimestamp {
+    pub range: Range<usize>,
+    pub time: DateTime<FixedOffset>,
+    pub zoned: bool,
+}
+
+#[derive(Debug, Clone)]
+enum FormatKind {
+    // 2024-03-01T10:15:30.250+02:00, also with a space separator or +0200 offset.
+    Rfc3339,
+    // 2024-03-01 10:15:30,250 as logged by log4j/logback, or any ISO8601 without a zone.
+    Java,
+    // Mar  1 10:15:30, the year is assumed to be the current one.
+    Syslog,
+    // 1709288130250
+    EpochMillis,
+    // A strftime pattern from the config.
+    Custom(String),
+}
+
+#[derive(Debug, Clone)]
+struct TimestampFormat {
+    kind: FormatKind,
+    regex: Regex,
+}
+
+impl TimestampFormat {
+    fn new(kind: FormatKind) -> Result<Self> {
+        let pattern = match &kind {
+            FormatKind::Rfc3339 => {
+                r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:?\d{2})".to_owned()
+            }
+            FormatKind::Java => r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}([.,]\d+)?".to_owned(),
+            FormatKind::Syslog => {
+                r"\b(Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) [ \d]\d \d{2}:\d{2}:\d{2}\b"
+                    .to_owned()
+            }
+            FormatKind::EpochMillis => r"\b1\d{12}\b".to_owned(),
+            FormatKind::Custom(format) => strftime_to_regex(format)?,
+        };
+
+        Ok(TimestampFormat {
+            kind,
+            regex: Regex::new(&pattern)?,
+        })
+    }
+
+    fn parse(&self, text: &str) -> Option<(DateTime<FixedOffset>, bool)> {
+        match &self.kind {
+            FormatKind::Rfc3339 => {
+                // Normalise to the strict RFC3339 form chrono expects.
+                let mut text = text.replacen(' ', "T", 1);
+                if !text.ends_with('Z') && text.as_bytes()[text.len() - 3] != b':' {
+                    text.insert(text.len() - 2, ':');
+                }
+
+                DateTime::parse_from_rfc3339(&text)
+                    .ok()
+                    .map(|ts| (ts, true))
+            }
+            FormatKind::Java => {
+                let text = text.replacen('T', " ", 1).replacen(',', ".", 1);
+                NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S%.f")
+                    .ok()
+                    .and_then(assume_local)
+            }
+            FormatKind::Syslog => {
+                let text = format!("{} {}", Local::now().year(), text.replace("  ", " "));
+                NaiveDateTime::parse_from_str(&text, "%Y %b %d %H:%M:%S")
+                    .ok()
+                    .and_then(assume_local)
+            }
+            FormatKind::EpochMillis => DateTime::from_timestamp_millis(text.parse().ok()?)
+                .map(|ts| (ts.fixed_offset(), true)),
+            FormatKind::Custom(format) => match DateTime::parse_from_str(text, format) {
+                Ok(ts) => Some((ts, true)),
+                Err(_) => NaiveDateTime::parse_from_str(text, format)
+                    .ok()
+                    .and_then(assume_local),
+            },
+        }
+    }
+}
+
+fn assume_local(naive: NaiveDateTime) -> Option<(DateTime<FixedOffset>, bool)> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|ts| (ts.fixed_offset(), false))
+}
+
+/// Build a regex to find text matching a strftime pattern.
+fn strftime_to_regex(format: &str) -> Result<String> {
+    let mut regex = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            regex.push_str(&regex::escape(&c.to_string()));
+            continue;
+        }
+
+        let spec = match chars.next() {
+            // %:z
+            Some(':') if chars.next() == Some('z') => r"[+-]\d{2}:\d{2}",
+            // %.f, %.3f etc.
+            Some('.') => match chars.next() {
+                Some('f') => r"(\.\d+)?",
+                Some('3' | '6' | '9') if chars.next() == Some('f') => r"\.\d+",
+                _ => {
+                    return Err(anyhow!(
+                        "Unsupported fraction in timestamp format: {}",
+                        format
+                    ))
+                }
+            },
+            // %3f etc.
+            Some('3' | '6' | '9') if chars.next() == Some('f') => r"\d+",
+            Some('Y') => r"\d{4}",
+            Some('y' | 'm' | 'd' | 'H' | 'I' | 'M' | 'S') => r"\d{2}",
+            Some('e') => r"[ \d]\d",
+            Some('j') => r"\d{3}",
+            Some('f') => r"\d+",
+            Some('b' | 'h' | 'a') => r"[A-Z][a-z]{2}",
+            Some('B' | 'A') => r"[A-Z][a-z]+",
+            Some('p') => r"[AaPp][Mm]",
+            Some('z') => r"[+-]\d{4}",
+            Some('Z') => r"[A-Za-z]+",
+            Some('s') => r"\d+",
+            Some('T') => r"\d{2}:\d{2}:\d{2}",
+            Some('D') => r"\d{2}/\d{2}/\d{2}",
+            Some('F') => r"\d{4}-\d{2}-\d{2}",
+            Some('%') => "%",
+            Some(other) => return Err(anyhow!("Unsupported timestamp format: %{}", other)),
+            None => return Err(anyhow!("Incomplete timestamp format: {}", format)),
+        };
+        regex.push_str(spec);
+    }
+
+    Ok(regex)
+}
+
+/// Finds timestamps in lines using the user's formats from the config, then the built in ones.
+#[derive(Debug, Clone)]
+pub struct TimestampParser {
+    formats: Vec<TimestampFormat>,
+}
+
+impl Default for TimestampParser {
+    fn default() -> Self {
+        TimestampParser::new(&[]).expect("Invalid built in timestamp format")
+    }
+}
+
+impl TimestampParser {
+    pub fn new(custom_formats: &[String]) -> Result<Self> {
+        let mut formats = Vec::new();
+        for format in custom_formats {
+            formats.push(
+                TimestampFormat::new(FormatKind::Custom(format.clone()))
+                    .map_err(|e| anyhow!("Bad timestamp format \"{}\": {}", format, e))?,
+            );
+        }
+        for kind in BUILTIN_FORMATS {
+            formats.push(TimestampFormat::new(kind)?);
+        }
+
+        Ok(TimestampParser { formats })
+    }
+
+    /// Find the first timestamp in the line, using the first format that matches.
+    pub fn find(&self, line: &str) -> Option<ParsedTimestamp> {
+        self.formats.iter().find_map(|format| {
+            format.regex.find_iter(line).find_map(|m| {
+                format
+                    .parse(m.as_str())
+                    .map(|(time, zoned)| ParsedTimestamp {
+                        range: m.range(),
+                        time,
+                        zoned,
+                    })
+            })
+        })
+    }
+
+    /// Re-render the first timestamp in the line in the requested time zone. Timestamps without a
+    /// zone are left alone as they can't be converted reliably.
+    pub fn display(&self, line: &str, display: TimeDisplay) -> String {
+        if display == TimeDisplay::Original {
+            return line.to_owned();
+        }
+
+        let Some(ts) = self.find(line).filter(|ts| ts.zoned) else {
+            return line.to_owned();
+        };
+
+        let rendered = match display {
+            TimeDisplay::Original => return line.to_owned(),
+            TimeDisplay::Local => ts
+                .time
+                .with_timezone(&Local)
+                .format("%Y-%m-%dT%H:%M:%S%.f%:z")
+                .to_string(),
+            TimeDisplay::Utc => ts
+                .time
+                .with_timezone(&Utc)
+                .format("%Y-%m-%dT%H:%M:%S%.fZ")
+                .to_string(),
+        };
+
+        format!(
+            "{}{}{}",
+            &line[..ts.range.start],
+            rendered,
+            &line[ts.range.end..]
+        )
+    }
+}
+
+/// Parse a time given on the command line. Accepts RFC3339, a local `YYYY-MM-DD[ HH:MM[:SS]]`,
+/// a local `HH:MM[:SS]` today, or a duration ago such as `90s`, `15m`, `2h` or `1d`.
+pub fn parse_time_arg(arg: &str, now: DateTime<Local>) -> Result<DateTime<FixedOffset>> {
+    let arg = arg.trim();
+
+    if let Ok(ts) = DateTime::parse_from_rfc3339(arg) {
+        return Ok(ts);
+    }
+
+    let local = |naive: NaiveDateTime| {
+        assume_local(naive)
+            .map(|(ts, _)| ts)
+            .ok_or_else(|| anyhow!("Time does not exist locally: {}", arg))
+    };
+
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(arg, format) {
+            return local(naive);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(arg, "%Y-%m-%d") {
+        return local(date.and_time(NaiveTime::MIN));
+    }
+    for format in ["%H:%M:%S", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(arg, format) {
+            return local(now.date_naive().and_time(time));
+        }
+    }
+
+    let ago = arg.strip_prefix('-').unwrap_or(arg);
+    if let Some(unit) = ago.chars().last() {
+        if let Ok(n) = ago[..ago.len() - unit.len_utf8()].parse::<i64>() {
+            let delta = match unit {
+                's' => Some(TimeDelta::seconds(n)),
+                'm' => Some(TimeDelta::minutes(n)),
+                'h' => Some(TimeDelta::hours(n)),
+                'd' => Some(TimeDelta::days(n)),
+                _ => None,
+            };
+            if let Some(delta) = delta {
+                return Ok((now - delta).fixed_offset());
+            }
+        }
+    }
+
+    Err(anyhow!("Cannot understand time: {}", arg))
+}
+
+/// Decides which lines fall within a time window. Lines without a timestamp, such as stack
+/// traces, go with the line before them.
+#[derive(Debug, Clone)]
+pub struct TimeWindow {
+    parser: TimestampParser,
+    since: Option<DateTime<FixedOffset>>,
+    until: Option<DateTime<FixedOffset>>,
+    last_admitted: bool,
+}
+
+impl TimeWindow {
+    pub fn new(
+        parser: TimestampParser,
+        since: Option<DateTime<FixedOffset>>,
+        until: Option<DateTime<FixedOffset>>,
+    ) -> Self {
+        TimeWindow {
+            parser,
+            since,
+            until,
+            // Lines before the first timestamp can only be placed if there is no start.
+            last_admitted: since.is_none(),
+        }
+    }
+
+    pub fn admits(&mut self, line: &str) -> bool {
+        if let Some(ts) = self.parser.find(line) {
+            self.last_admitted = self.since.is_none_or(|since| ts.time >= since)
+                && self.until.is_none_or(|until| ts.time <= until);
+        }
+
+        self.last_admitted
+    }
+
+    /// Start again, e.g. after the file is truncated.
+    pub fn reset(&mut self) {
+        self.last_admitted = self.since.is_none();
+    }
+
+    pub fn describe(&self) -> String {
+        let format = |ts: &Option<DateTime<FixedOffset>>| match ts {
+            Some(ts) => ts.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => "…".to_owned(),
+        };
+
+        format!("{} to {}", format(&self.since), format(&self.until))
+    }
+}
+
+/// Spots where time jumps forward by more than the threshold between timestamped lines. Lines
+/// without a timestamp (e.g. stack traces) are skipped.
+#[derive(Debug, Clone)]
+pub struct GapDetector {
+    threshold: TimeDelta,
+    last: Option<DateTime<FixedOffset>>,
+}
+
+impl GapDetector {
+    pub fn new(threshold: TimeDelta) -> Self {
+        GapDetector {
+            threshold,
+            last: None,
+        }
+    }
+
+    pub fn check(&mut self, parser: &TimestampParser, line: &str) -> Option<TimeDelta> {
+        let time = parser.find(line)?.time;
+        let gap = self.last.map(|last| time - last);
+        self.last = Some(time);
+
+        gap.filter(|gap| *gap > self.threshold)
+    }
+}
+
+/// Format a gap compactly, e.g. `+5.2s`, `+3m10s`, `+2h05m`.
+pub fn format_gap(gap: TimeDelta) -> String {
+    let secs = gap.num_seconds();
+    if secs < 60 {
+        format!("+{:.1}s", gap.num_milliseconds() as f64 / 1000.0)
+    } else if secs < 3600 {
+        format!("+{}m{:02}s", secs / 60, secs % 60)
+    } else if secs < 86400 {
+        format!("+{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    } else {
+        format!("+{}d{:02}h", secs / 86400, (secs % 86400) / 3600)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_utc() {
+        let parser = TimestampParser::default();
+
+        assert_eq!(
+            parser.display(
+                "2024-03-01T10:15:30.250+02:00 INFO started",
+                TimeDisplay::Utc
+            ),
+            "2024-03-01T08:15:30.250Z INFO started"
+        );
+        assert_eq!(
+            parser.display("[2024-03-01 23:00:00-0130] late", TimeDisplay::Utc),
+            "[2024-03-02T00:30:00Z] late"
+        );
+        assert_eq!(
+            parser.display("at 1709288130250 done", TimeDisplay::Utc),
+            "at 2024-03-01T10:15:30.250Z done"
+        );
+
+        // No zone, so cannot be converted.
+        assert_eq!(
+            parser.display("2024-03-01 10:15:30 INFO", TimeDisplay::Utc),
+            "2024-03-01 10:15:30 INFO"
+        );
+        assert_eq!(
+            parser.display("2024-03-01T10:15:30Z x", TimeDisplay::Original),
+            "2024-03-01T10:15:30Z x"
+        );
+    }
+
+    #[test]
+    fn test_find_formats() {
+        let parser = TimestampParser::new(&["%d/%b/%Y:%H:%M:%S %z".to_owned()]).unwrap();
+
+        let ts = parser
+            .find(r#"127.0.0.1 - - [01/Mar/2024:10:15:30 +0000] "GET / HTTP/1.1""#)
+            .unwrap();
+        assert_eq!(ts.range, 15..41);
+        assert_eq!(ts.time.to_rfc3339(), "2024-03-01T10:15:30+00:00");
+        assert!(ts.zoned);
+
+        let java = parser.find("2024-03-01 10:15:30,123 [main] INFO").unwrap();
+        assert_eq!(java.range, 0..23);
+        assert!(!java.zoned);
+        assert_eq!(
+            java.time.naive_local().to_string(),
+            "2024-03-01 10:15:30.123"
+        );
+
+        let syslog = parser.find("Mar  1 10:15:30 host sshd[1]: hello").unwrap();
+        assert_eq!(syslog.range, 0..15);
+        assert_eq!(
+            syslog.time.format("%m-%d %H:%M:%S").to_string(),
+            "03-01 10:15:30"
+        );
+
+        assert_eq!(parser.find("no time here 12345"), None);
+        assert!(TimestampParser::new(&["%Q".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_time_arg() {
+        let now = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let local = |s: &str| {
+            parse_time_arg(s, now)
+                .unwrap()
+                .naive_local()
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+
+        assert_eq!(
+            parse_time_arg("2024-03-01T10:00:00+02:00", now)
+                .unwrap()
+                .to_rfc3339(),
+            "2024-03-01T10:00:00+02:00"
+        );
+        assert_eq!(local("2024-02-29 08:30"), "2024-02-29 08:30:00");
+        assert_eq!(local("2024-02-29"), "2024-02-29 00:00:00");
+        assert_eq!(local("09:15"), "2024-03-01 09:15:00");
+        assert_eq!(local("90m"), "2024-03-01 10:30:00");
+        assert_eq!(local("-2h"), "2024-03-01 10:00:00");
+        assert!(parse_time_arg("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn test_time_window() {
+        let since = DateTime::parse_from_rfc3339("2024-03-01T10:00:00Z").ok();
+        let until = DateTime::parse_from_rfc3339("2024-03-01T11:00:00Z").ok();
+        let mut window = TimeWindow::new(TimestampParser::default(), since, until);
+
+        let admitted = [
+            "no timestamp yet",
+            "2024-03-01T09:59:59Z early",
+            "  at early.stack",
+            "2024-03-01T10:30:00Z in",
+            "  at in.stack",
+            "2024-03-01T11:00:01Z late",
+        ]
+        .iter()
+        .map(|l| window.admits(l))
+        .collect::<Vec<_>>();
+
+        assert_eq!(admitted, vec![false, false, false, true, true, false]);
+    }
+
+    #[test]
+    fn test_gap_detector() {
+        let parser = TimestampParser::default();
+        let mut detector = GapDetector::new(TimeDelta::seconds(5));
+
+        let gaps = [
+            "2024-03-01T10:00:00Z start",
+            "2024-03-01T10:00:03Z ok",
+            "  at some.stack.Frame",
+            "2024-03-01T10:00:10.500Z stalled",
+            "2024-03-01T09:00:00Z clock went back",
+            "2024-03-01T11:02:05Z restarted",
+        ]
+        .iter()
+        .map(|l| detector.check(&parser, l).map(format_gap))
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            gaps,
+            vec![
+                None,
+                None,
+                None,
+                Some("+7.5s".to_owned()),
+                None,
+                Some("+2h02m".to_owned())
+            ]
+        );
+    }
+}
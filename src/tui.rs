@@ -1,10 +1,42 @@
 #![allow(unused_imports, unused_variables)]
 use crate::{
-    colour_spec::{Colour, ColouringRule, ColouringSpec, Colours},
-    config::{self, load_config, maybe_save_config, LocatedConfig},
-    filter_spec::{FilterSpec, FilterType},
+    analysis::{
+        spawn_aggregate, spawn_cluster_scan, spawn_clusters, spawn_fetch_full_line,
+        spawn_fetch_lines, spawn_find_time, spawn_gap_scan, spawn_groups, spawn_line_offset,
+        spawn_lines_at, spawn_query, spawn_sort, spawn_time_fraction, spawn_top_values,
+        AnalysisReceiver, AnalysisResult, AnalysisSender, AnalysisSource, ClusterScan, GapScan,
+        LineGroups, LinesPurpose, ResultRow, ResultTable,
+    },
+    annotations::Annotations,
+    chords::{ChordMatch, Chords, DEFAULT_LEADER},
+    clipboard,
+    cluster::{self, Clusterer, RareLines, DEFAULT_RARE_THRESHOLD},
+    colour_spec::{Colour, ColouringRule, ColouringScope, ColouringSpec, Colours},
+    columns::{
+        detect_delimiter, parse_sort_spec, pretty_json, ColumnMode, ColumnState,
+        DEFAULT_JSON_FIELDS, MAX_COLUMN_WIDTH,
+    },
+    config::{
+        self, load_config, maybe_save_config, CurrentLineStyle, LocatedConfig, PaneLayout, Profile,
+        TailPanes,
+    },
+    file_list::{common_prefix, complete_path, expand_home, FileList},
+    filter_spec::{Context, FilterSpec, FilterType},
+    history::{History, HistoryEntry, HistoryKind, DEFAULT_HISTORY_SIZE},
+    json_query::JsonTransform,
+    opener::{FileOpener, OpenFile},
+    query::{LineField, Query},
+    reader,
+    spool::{self, CommandStatus, StreamBuffer},
+    substitution::{substitute, Substitution},
+    theme::theme_rgb,
+    timestamp::{
+        format_gap, parse_time_arg, GapDetector, TimeDisplay, TimestampParser,
+        DEFAULT_TIME_GAP_SECS,
+    },
 };
 use anyhow::{bail, Result};
+use chrono::TimeDelta;
 use clap::builder::Styles;
 use crossterm::event::{EventStream, KeyModifiers};
 use fmtsize::{Conventional, FmtSize};
@@ -14,12 +46,15 @@ use log::{debug, error, info, trace, warn};
 use num_format::{Locale, ToFormattedString};
 use regex::Regex;
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fmt::Display,
     io::{self, stdout},
     isize,
     marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
     thread::{self, Thread},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{select, sync::mpsc, time::interval};
 use tui_input::{backend::crossterm::EventHandler, Input};
@@ -28,8 +63,14 @@ use ratatui::{
     backend::CrosstermBackend,
     buffer::Buffer,
     crossterm::{
-        event::{self, Event, KeyCode},
-        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+        event::{
+            self, Event, KeyCode, KeyEvent, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+            PushKeyboardEnhancementFlags,
+        },
+        terminal::{
+            disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+            LeaveAlternateScreen, SetTitle,
+        },
         ExecutableCommand,
     },
     layout::{Alignment, Constraint, Flex, Layout, Margin, Position, Rect},
@@ -39,15 +80,20 @@ use ratatui::{
     widgets::{
         block::BlockExt, Block, BorderType, Borders, Cell, Clear, List, ListItem, ListState,
         Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Table,
-        TableState, Widget,
+        TableState, Widget, Wrap,
     },
     DefaultTerminal, Frame, Terminal,
 };
 
 use crate::{
-    common::{self, clamped_add, LineContent, CHANNEL_BUFFER, MS_PER_FRAME},
+    common::{
+        self, clamped_add, fill_template, is_capped, GrowthRate, LineContent, CHANNEL_BUFFER,
+        DEFAULT_SAMPLE_EVERY, FILE_LIST_REFRESH_MS, FILTER_SPOOLING_BATCH_SIZE, FPS,
+        GAP_SCAN_LINES, GROWTH_WINDOW_MS, IDLE_AFTER_MS, IDLE_FPS, LONG_LINE_BYTES,
+    },
     ffile::{FFReq, FFReqSender, FFResp, FFRespReceiver, FilterLine},
-    ifile::{FileReqSender, FileRespReceiver, IFResp},
+    ifile::{FileReqSender, FileResp, FileRespReceiver, IFResp},
+    investigation::{Investigation, SavedBookmark},
     view::View,
 };
 
@@ -55,6 +101,89 @@ const MARGIN_EXTRAS: usize = 1; // Allow space between line number ond content
 const SCROLLBAR_EXTRAS: usize = 1; // Allow space for scrollbar
 const TOTAL_EXTRAS: usize = MARGIN_EXTRAS + SCROLLBAR_EXTRAS;
 
+const SIDEBAR_WIDTH: u16 = 44;
+
+// The share of the screen for the content pane unless the config says otherwise, the least either
+// pane can have and how much a resize moves the split.
+const DEFAULT_CONTENT_PERCENT: usize = 70;
+const MIN_PANE_PERCENT: usize = 10;
+const RESIZE_STEP_PERCENT: isize = 5;
+
+// How long a notice is shown for.
+const NOTICE_SECS: u64 = 3;
+
+// How often the screen is redrawn in accessible mode, as screen readers read out what changes.
+const ACCESSIBLE_FPS: u64 = 1;
+
+// Shown after the file's name until a file waited for is created.
+const WAITING_FOR_FILE: &str = "waiting for the file";
+
+// Borders of spaces, so screen readers don't read out box-drawing characters.
+const BLANK_BORDER: symbols::border::Set = symbols::border::Set {
+    top_left: " ",
+    top_right: " ",
+    bottom_left: " ",
+    bottom_right: " ",
+    vertical_left: " ",
+    vertical_right: " ",
+    horizontal_top: " ",
+    horizontal_bottom: " ",
+};
+
+// Whether accessible mode is on. Dialogs are drawn without the TUI to hand, so it is kept here.
+static ACCESSIBLE: AtomicBool = AtomicBool::new(false);
+
+fn accessible() -> bool {
+    ACCESSIBLE.load(Ordering::Relaxed)
+}
+
+// The border, blank in accessible mode.
+fn border(set: symbols::border::Set) -> symbols::border::Set {
+    if accessible() {
+        BLANK_BORDER
+    } else {
+        set
+    }
+}
+
+// In accessible mode, which pane has focus is said rather than shown by its border.
+fn focus_marker(focused: bool) -> Line<'static> {
+    match accessible() && focused {
+        true => Line::from(" (focused) "),
+        false => Line::default(),
+    }
+}
+
+fn bordered<'a>() -> Block<'a> {
+    Block::bordered().border_set(border(symbols::border::PLAIN))
+}
+
+// Background colours given to highlights in turn, each the first not already in use.
+const HIGHLIGHT_PALETTE: [Color; 6] = [
+    Color::Yellow,
+    Color::Cyan,
+    Color::Green,
+    Color::Magenta,
+    Color::LightBlue,
+    Color::LightRed,
+];
+
+// Colours given to the tags of merged files in turn.
+const SOURCE_PALETTE: [Color; 6] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Magenta,
+    Color::Yellow,
+    Color::LightBlue,
+    Color::LightRed,
+];
+
+// Content lines jumped from that are remembered.
+const MAX_JUMPS: usize = 100;
+
+type ContentView = View<IFResp<String>, String>;
+type FilterView = View<FFResp, FilterLine>;
+
 const RADIO_SELECTED: &str = "●";
 const RADIO_UNSELECTED: &str = "○";
 
@@ -72,13 +201,124 @@ struct LazyState<T, L> {
 
     pub colouring: ColouringSpec,
 
+    // Render lines as aligned columns.
+    pub columns: Option<ColumnState>,
+
+    pub time_display: TimeDisplay,
+    pub timestamps: TimestampParser,
+
+    // Convey colouring with modifiers and margin symbols instead of colour.
+    pub monochrome: bool,
+
+    pub current_line: CurrentLineStyle,
+
+    // Show tabs and trailing spaces.
+    pub show_whitespace: bool,
+
+    // Show only this capture group of each line, where it matches.
+    pub projection: Option<(Regex, usize)>,
+
+    // Reshape JSON lines for display.
+    pub transform: Option<JsonTransform>,
+
+    // Rewrite lines for display.
+    pub substitutions: Vec<Substitution>,
+
+    // Text highlighted wherever it is shown.
+    pub highlights: Vec<Highlight>,
+
+    // The tags starting lines of merged files, with their colours.
+    pub sources: Vec<(String, Color)>,
+
+    // Lines following a time gap, by file line number.
+    pub gaps: BTreeMap<usize, TimeDelta>,
+
+    // Dividers shown above file lines, by file line number.
+    pub marks: BTreeMap<usize, String>,
+
+    // Notes on bookmarked lines, by file line number.
+    pub bookmarks: BTreeMap<usize, String>,
+
+    // Lines held above the others whilst scrolling, by file line number.
+    pub pinned: BTreeMap<usize, String>,
+
+    // Lines selected for bulk actions, by file line number.
+    pub selected: BTreeSet<usize>,
+
+    // Lines with notes saved beside the file, by file line number.
+    pub annotated: BTreeSet<usize>,
+
+    // Every so many lines to the end, shown in place of the others whilst sampling.
+    pub sampled: Option<Vec<(usize, String)>>,
+
     cell_renders: u32,
 }
 
+// Text picked out in its own colour within lines, for a quick look without a colouring rule.
+#[derive(Debug, Clone)]
+struct Highlight {
+    text: String,
+    regex: Regex,
+    colour: Color,
+}
+
+impl Highlight {
+    fn style(&self, monochrome: bool) -> Style {
+        if monochrome {
+            Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD)
+        } else {
+            Style::default()
+                .fg(themed(Color::Black))
+                .bg(themed(self.colour))
+        }
+    }
+}
+
+// Split the shown text into spans, with the highlighted parts in their colours. Where highlights
+// overlap the earlier one wins.
+fn highlight_spans(
+    text: String,
+    style: Style,
+    highlights: &[Highlight],
+    monochrome: bool,
+) -> Vec<Span<'static>> {
+    let mut found = highlights
+        .iter()
+        .flat_map(|h| h.regex.find_iter(&text).map(move |m| (m.range(), h)))
+        .filter(|(range, _)| !range.is_empty())
+        .collect::<Vec<_>>();
+    if found.is_empty() {
+        return vec![Span::styled(text, style)];
+    }
+    found.sort_by_key(|(range, _)| range.start);
+
+    let mut spans = Vec::new();
+    let mut at = 0;
+    for (range, highlight) in found {
+        if range.start < at {
+            continue;
+        }
+        if range.start > at {
+            spans.push(Span::styled(text[at..range.start].to_owned(), style));
+        }
+        spans.push(Span::styled(
+            text[range.clone()].to_owned(),
+            style.patch(highlight.style(monochrome)),
+        ));
+        at = range.end;
+    }
+    if at < text.len() {
+        spans.push(Span::styled(text[at..].to_owned(), style));
+    }
+
+    spans
+}
+
 #[derive(Debug)]
 struct LazyList<'a, T, L> {
     block: Option<Block<'a>>,
     start_point: usize,
+    rare_lines: Option<&'a RareLines>,
     _phantom_resp: PhantomData<T>,
     _phantom_line: PhantomData<L>,
 }
@@ -88,6 +328,7 @@ impl<'a, T, L> LazyList<'a, T, L> {
         Self {
             block: None,
             start_point,
+            rare_lines: None,
 
             _phantom_resp: PhantomData,
             _phantom_line: PhantomData,
@@ -98,6 +339,11 @@ impl<'a, T, L> LazyList<'a, T, L> {
         self.block = Some(block);
         self
     }
+
+    pub fn rare_lines(mut self, rare_lines: Option<&'a RareLines>) -> Self {
+        self.rare_lines = rare_lines;
+        self
+    }
 }
 
 impl<'a, T: std::marker::Send + 'static, L: Clone + Default + LineContent> StatefulWidget
@@ -106,7 +352,29 @@ impl<'a, T: std::marker::Send + 'static, L: Clone + Default + LineContent> State
     type State = LazyState<T, L>;
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
         self.block.render(area, buf);
-        let inner = self.block.inner_if_some(area);
+        let mut inner = self.block.inner_if_some(area);
+
+        // Column mode pins the header above the lines.
+        let header_area = if state.columns.is_some() {
+            let [header_area, lines_area] =
+                Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(inner);
+            inner = lines_area;
+            Some(header_area)
+        } else {
+            None
+        };
+
+        // Pinned lines stay above the others, taking up to a third of the pane.
+        let pinned_rows = state.pinned.len().min(inner.height as usize / 3) as u16;
+        let pinned_area = if pinned_rows > 0 {
+            let [pinned_area, lines_area] =
+                Layout::vertical([Constraint::Length(pinned_rows), Constraint::Fill(1)])
+                    .areas(inner);
+            inner = lines_area;
+            Some(pinned_area)
+        } else {
+            None
+        };
 
         let height = inner.height;
         let width = inner.width;
@@ -122,63 +390,439 @@ impl<'a, T: std::marker::Send + 'static, L: Clone + Default + LineContent> State
         let all_subtractions = margin_width + SCROLLBAR_EXTRAS;
         let content_width = common::clamped_sub(width as usize, all_subtractions);
 
+        if let (Some(header_area), Some(columns)) = (header_area, &state.columns) {
+            let header = match &columns.header {
+                Some(header) => columns.render(header),
+                None => String::new(),
+            };
+            let header_line = format!(
+                "{:margin_width$} {header:.content_width$}",
+                "",
+                margin_width = margin_width,
+                header = header,
+                content_width = content_width
+            );
+            Line::from(header_line)
+                .style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED))
+                .render(header_area, buf);
+        }
+
+        // Lines shown apart from the view, pinned or sampled, with only their foreground colour.
+        let plain_line = |line_no: usize, line: &str, marker: char| {
+            let style = match state.colouring.maybe_colour(line) {
+                Some((Some(fg), _)) if !state.monochrome => {
+                    Style::default().fg(colour_to_color(fg))
+                }
+                _ => Style::default(),
+            };
+            let line = state.timestamps.display(line, state.time_display);
+            let line = substitute(&state.substitutions, line);
+            let content = common::display_window(
+                &common::replace_for_view(&line),
+                self.start_point,
+                content_width,
+            );
+            Line::from(vec![
+                Span::styled(
+                    format!("{:>margin_width$}{}", line_no, marker),
+                    Style::default().add_modifier(Modifier::DIM),
+                ),
+                Span::raw(content),
+            ])
+            .style(style)
+        };
+
+        if let Some(pinned_area) = pinned_area {
+            let pinned = state
+                .pinned
+                .iter()
+                .take(pinned_rows as usize)
+                .enumerate()
+                .map(|(row, (line_no, line))| {
+                    let line = plain_line(*line_no, line, '^');
+                    // The last pinned line marks where the scrolling lines start.
+                    if row + 1 == pinned_rows as usize {
+                        line.patch_style(Modifier::UNDERLINED)
+                    } else {
+                        line
+                    }
+                })
+                .collect::<Vec<_>>();
+            Text::from(pinned).render(pinned_area, buf);
+        }
+
+        if let Some(sampled) = &state.sampled {
+            let skip = sampled.len().saturating_sub(height as usize);
+            let sampled = sampled
+                .iter()
+                .skip(skip)
+                .map(|(line_no, line)| plain_line(*line_no, line, '~'))
+                .collect::<Vec<_>>();
+            Text::from(sampled).render(inner, buf);
+            return;
+        }
+
         let mut lines = Vec::with_capacity(state.height_hint);
+        let mut dividers = 0;
+        let mut prev_file_line_no = None;
         for i in state.view.range() {
-            if i >= num_lines {
+            if i >= num_lines || lines.len() >= height as usize {
                 break;
             }
-            let maybe_l = state.view.get_line(i);
-
-            let l = match maybe_l {
-                Some(l) => l.render(),
-                None => "...".to_owned(),
+            let maybe_l = state.view.get_line_ref(i);
+
+            let loaded = maybe_l.is_some();
+            let file_line_no = maybe_l.and_then(|l| l.file_line_no()).unwrap_or(i);
+
+            // Show any dividers marked since the previous line. The filtered pane may skip lines.
+            let after = prev_file_line_no.unwrap_or(file_line_no.saturating_sub(1));
+            if loaded && file_line_no > after {
+                for mark in state.marks.range(after + 1..=file_line_no).map(|(_, m)| m) {
+                    lines.push(Line::styled(
+                        format!(
+                            "{:margin_width$} {:=<content_width$}",
+                            "",
+                            format!("==== {} ", mark),
+                            margin_width = margin_width,
+                            content_width = content_width
+                        ),
+                        Style::default()
+                            .fg(themed(Color::Magenta))
+                            .add_modifier(Modifier::BOLD),
+                    ));
+                    dividers += 1;
+                }
+            }
+            if loaded {
+                prev_file_line_no = Some(file_line_no);
+            }
+            // Very long lines are cut down to the part on screen, rather than copied and processed
+            // whole every frame. Colouring and transforms then only see that part.
+            let (l, cut) = match maybe_l {
+                Some(l) if l.content().len() > LONG_LINE_BYTES && state.columns.is_none() => {
+                    let (slice, offset, to_end) =
+                        common::display_slice(l.content(), self.start_point, content_width);
+                    (slice.to_owned(), Some((offset, to_end)))
+                }
+                Some(l) => (l.content().to_owned(), None),
+                None => ("...".to_owned(), None),
             };
 
+            // Colour the raw line, as filters match it.
+            let mut content_style = Style::default();
+            let mut symbol = ' ';
+            match state.colouring.maybe_colour(&l) {
+                Some(colours) if state.monochrome => {
+                    content_style = content_style.add_modifier(colours_to_modifier(&colours));
+                    symbol = colours_to_symbol(&colours);
+                }
+                Some((fg, bg)) => {
+                    if let Some(fg) = fg {
+                        content_style = content_style.fg(colour_to_color(fg));
+                    }
+                    if let Some(bg) = bg {
+                        content_style = content_style.bg(colour_to_color(bg));
+                    }
+                }
+                None => {}
+            }
+
+            // The current line style goes over any colouring.
             let base_style = if i == current {
-                Style::default().add_modifier(Modifier::BOLD)
+                content_style = apply_current_line_style(&state.current_line, content_style);
+                apply_current_line_style(&state.current_line, Style::default())
             } else {
                 Style::default()
             };
 
-            // TODO: We are looking at the rendered line content... does this matter for colouring?
-            let mut content_style = base_style.clone();
-            if let Some((fg, bg)) = state.colouring.maybe_colour(&l) {
-                if let Some(fg) = fg {
-                    content_style = content_style.fg(colour_to_color(fg));
-                }
-                if let Some(bg) = bg {
-                    content_style = content_style.bg(colour_to_color(bg));
-                }
-            }
+            // Colouring matched the raw text, now adjust it for display.
+            let projected = match (&state.projection, &state.transform) {
+                (Some((regex, group)), _) if loaded => regex
+                    .captures(&l)
+                    .and_then(|c| c.get(*group))
+                    .map(|m| m.as_str().to_owned()),
+                // Column mode shows the JSON fields itself.
+                (_, Some(transform)) if loaded && state.columns.is_none() => transform.apply(&l),
+                _ => None,
+            };
+            let l = match projected {
+                Some(projected) => projected,
+                None if loaded => state.timestamps.display(&l, state.time_display),
+                None => l,
+            };
+            let l = if loaded && !state.substitutions.is_empty() {
+                substitute(&state.substitutions, l)
+            } else {
+                l
+            };
 
             // Break the line into margin and content. Only colour the content.
 
             let margin = format!(
                 "{i:>margin_width$}{c}",
                 i = i,
-                c = if i == current { ">" } else { " " }
+                c = if i == current {
+                    state.current_line.arrow
+                } else {
+                    symbol
+                }
             );
 
-            let content = format!(
-                "{l:.content_width$}",
-                content_width = content_width,
-                l = l.get(self.start_point..).unwrap_or(""),
+            // Selected lines and rare messages stand out in the margin.
+            let margin_style = match self.rare_lines {
+                _ if loaded && state.selected.contains(&file_line_no) => {
+                    base_style.fg(themed(Color::Green)).reversed()
+                }
+                Some(rare_lines) if loaded && rare_lines.is_rare(file_line_no) => {
+                    base_style.fg(themed(Color::Magenta)).reversed()
+                }
+                _ => base_style,
+            };
+
+            let badge = match state.gaps.get(&file_line_no) {
+                Some(gap) if loaded => format!(" {} ", format_gap(*gap)),
+                _ => String::new(),
+            };
+            let bookmark = match state.bookmarks.get(&file_line_no) {
+                Some(note) if note.is_empty() => " ★ ".to_owned(),
+                Some(note) => format!(" ★ {} ", note),
+                None => String::new(),
+            };
+            let note = if loaded && state.annotated.contains(&file_line_no) {
+                " ✎ "
+            } else {
+                ""
+            };
+            let content_width = common::clamped_sub(
+                content_width,
+                common::display_width(&badge)
+                    + common::display_width(&bookmark)
+                    + common::display_width(note),
             );
 
+            // Column mode pans by columns, otherwise pan to the start point.
+            let (visible, start_point) = match &mut state.columns {
+                Some(columns) if loaded => match columns.mode.split(&l) {
+                    Some(fields) => {
+                        columns.fit(&fields);
+                        (columns.render(&fields), 0)
+                    }
+                    None => (l, 0),
+                },
+                _ => (l, cut.map_or(self.start_point, |(offset, _)| offset)),
+            };
+            let visible = if state.show_whitespace && loaded {
+                show_whitespace(&visible, cut.is_none_or(|(_, to_end)| to_end))
+            } else {
+                common::replace_for_view(&visible)
+            };
+
+            let content = common::display_window(&visible, start_point, content_width);
+
             // TODO: Render the line_no, not the match_no for FilterLine. Will need to encapsulate
             // String and have a render columns method or similar.
-            lines.push(Line::from(vec![
-                Span::styled(margin, base_style),
-                Span::styled(content, content_style),
-            ]));
+            // The line style fills the whole row, so the current line stands out past the end of
+            // short lines.
+            let mut spans = vec![
+                Span::styled(margin, margin_style),
+                Span::styled(badge, base_style.fg(themed(Color::Yellow)).reversed()),
+                Span::styled(bookmark, base_style.fg(themed(Color::Cyan)).reversed()),
+                Span::styled(note, base_style.fg(themed(Color::Blue)).reversed()),
+            ];
+            // The tag of a merged file in its colour.
+            let mut content = content;
+            if let Some((prefix, colour)) = state
+                .sources
+                .iter()
+                .find(|(prefix, _)| content.starts_with(prefix.as_str()))
+            {
+                let tag_style = if state.monochrome {
+                    content_style.bold()
+                } else {
+                    content_style.fg(themed(*colour)).bold()
+                };
+                spans.push(Span::styled(prefix.clone(), tag_style));
+                content = content[prefix.len()..].to_owned();
+            }
+            spans.extend(highlight_spans(
+                content,
+                content_style,
+                &state.highlights,
+                state.monochrome,
+            ));
+            lines.push(Line::from(spans).style(base_style));
 
             state.cell_renders += 1;
         }
         Text::from(lines).render(inner, buf);
+
+        // Dividers take up rows, so ask for fewer lines.
+        state.height_hint = common::clamped_sub(height as usize, dividers).max(1);
+    }
+}
+
+// Cycle through the capture groups of the regex being edited, then back to the whole line.
+// Ctrl-s switches between case insensitive and smart case.
+fn toggle_smart_case(filter_type: &FilterType) -> FilterType {
+    match filter_type {
+        FilterType::SimpleCaseInsensitive => FilterType::SmartCase,
+        _ => FilterType::SimpleCaseInsensitive,
+    }
+}
+
+// The growth rate of a file, from the size it is loaded to.
+fn new_growth_rate(path: &str) -> GrowthRate {
+    let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+    GrowthRate::new(Duration::from_millis(GROWTH_WINDOW_MS)).loading_to(size)
+}
+
+// Telling Ctrl-i from Tab also tells Ctrl-m and Ctrl-[ from Enter and Escape, but they are still
+// used as them.
+fn legacy_keys(event: Event) -> Event {
+    match event {
+        Event::Key(key) if key.modifiers == KeyModifiers::CONTROL => match key.code {
+            KeyCode::Char('m') => Event::Key(KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+                ..key
+            }),
+            KeyCode::Char('[') => Event::Key(KeyEvent {
+                code: KeyCode::Esc,
+                modifiers: KeyModifiers::NONE,
+                ..key
+            }),
+            _ => event,
+        },
+        _ => event,
+    }
+}
+
+// Keys sent so that Ctrl-i can be told from Tab, where the terminal can, until dropped. Dropping it
+// however the Tui ends, even failing or panicking, stops the shell being sent keys it can't read.
+struct KeyboardEnhancement;
+
+impl KeyboardEnhancement {
+    fn push() -> Option<Self> {
+        if !supports_keyboard_enhancement().unwrap_or(false) {
+            return None;
+        }
+
+        match stdout().execute(PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES,
+        )) {
+            Ok(_) => Some(KeyboardEnhancement),
+            Err(e) => {
+                warn!("Failed to enhance the keyboard: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for KeyboardEnhancement {
+    fn drop(&mut self) {
+        if let Err(e) = stdout().execute(PopKeyboardEnhancementFlags) {
+            error!("Failed to restore the keyboard: {:?}", e);
+        }
+    }
+}
+
+// How the reader is getting on reopening a file.
+fn describe_retry(reason: &str, attempt: u32, attempts: u32, delay: Duration) -> String {
+    let attempt = match attempts {
+        0 => attempt.to_string(),
+        attempts => format!("{}/{}", attempt, attempts),
+    };
+
+    format!(
+        "reopening in {:.1}s, attempt {}: {}",
+        delay.as_secs_f64(),
+        attempt,
+        reason
+    )
+}
+
+// Where a symlink points, as just the file name when it is beside the link.
+fn describe_link(path: &str) -> Option<String> {
+    let target = reader::link_target(path)?;
+    // A bare file name has an empty parent, meaning the current directory.
+    let link_dir = match Path::new(path).parent() {
+        Some(dir) if dir.as_os_str().is_empty() => std::fs::canonicalize(".").ok(),
+        Some(dir) => std::fs::canonicalize(dir).ok(),
+        None => None,
+    };
+    match (target.parent(), target.file_name()) {
+        (Some(dir), Some(name)) if Some(dir) == link_dir.as_deref() => {
+            Some(name.to_string_lossy().into_owned())
+        }
+        _ => Some(target.display().to_string()),
+    }
+}
+
+// A copy of part of a rendered buffer, to draw again later.
+fn copy_area(buffer: &Buffer, area: Rect) -> Buffer {
+    let mut copy = Buffer::empty(area);
+    for position in area.positions() {
+        copy[position] = buffer[position].clone();
+    }
+
+    copy
+}
+
+// A leading line range on a command, e.g. "1000,2000 filter err", giving the first and last lines.
+fn split_line_range(command: &str) -> Option<(usize, usize, &str)> {
+    let (range, rest) = command.split_once(' ').unwrap_or((command, ""));
+    let (first, last) = range.split_once(',')?;
+    let (first, last): (usize, usize) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+
+    Some((first.min(last), first.max(last), rest.trim()))
+}
+
+fn next_projection(filter_edit: &FilterEditState) -> Option<usize> {
+    if filter_edit.filter_type != FilterType::Regex {
+        return None;
+    }
+    let groups = FilterSpec::new(FilterType::Regex, filter_edit.input.value())
+        .ok()
+        .and_then(|spec| spec.regex().map(|r| r.captures_len() - 1))
+        .unwrap_or(0);
+
+    match filter_edit.projection {
+        None if groups > 0 => Some(1),
+        Some(group) if group < groups => Some(group + 1),
+        _ => None,
+    }
+}
+
+// Make tabs and trailing spaces visible. Spaces are only trailing if this is the end of the line.
+fn show_whitespace(line: &str, line_end: bool) -> String {
+    let trimmed = if line_end {
+        line.trim_end_matches(' ')
+    } else {
+        line
+    };
+    let trailing = line.len() - trimmed.len();
+
+    let mut shown = trimmed.replace('\t', "→");
+    shown.extend(std::iter::repeat_n('·', trailing));
+
+    shown
+}
+
+fn format_value(v: f64) -> String {
+    if v.fract() == 0.0 {
+        format!("{:.0}", v)
+    } else {
+        format!("{:.3}", v)
     }
 }
 
-fn colour_to_color(colour: Colour) -> Color {
+/// The colour to draw a colouring colour in, from the theme if there is one.
+pub fn colour_to_color(colour: Colour) -> Color {
+    if let Some((r, g, b)) = theme_rgb(&colour) {
+        return Color::Rgb(r, g, b);
+    }
+
     match colour {
         Colour::Black => Color::Black,
         Colour::Red => Color::Red,
@@ -192,11 +836,91 @@ fn colour_to_color(colour: Colour) -> Color {
     }
 }
 
+// A basic colour for the UI, from the theme if there is one.
+fn themed(color: Color) -> Color {
+    let colour = match color {
+        Color::Black => Colour::Black,
+        Color::Red => Colour::Red,
+        Color::Green => Colour::Green,
+        Color::Blue => Colour::Blue,
+        Color::Yellow => Colour::Yellow,
+        Color::Magenta => Colour::Magenta,
+        Color::Cyan => Colour::Cyan,
+        Color::Gray => Colour::Gray,
+        Color::White => Colour::White,
+        _ => return color,
+    };
+
+    colour_to_color(colour)
+}
+
+fn apply_current_line_style(current_line: &CurrentLineStyle, mut style: Style) -> Style {
+    if current_line.bold {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+    if current_line.reverse {
+        style = style.add_modifier(Modifier::REVERSED);
+    }
+    if let Some(bg) = &current_line.bg_colour {
+        style = style.bg(colour_to_color(bg.clone()));
+    }
+
+    style
+}
+
+// Monochrome stand ins for colours: error-like colours are bold, warning-like underlined and any
+// background reversed.
+fn colours_to_modifier(colours: &Colours) -> Modifier {
+    let mut modifier = match colours.0 {
+        Some(Colour::Red | Colour::Magenta) => Modifier::BOLD,
+        Some(Colour::Yellow) => Modifier::UNDERLINED,
+        Some(_) => Modifier::ITALIC,
+        None => Modifier::empty(),
+    };
+    if colours.1.is_some() {
+        modifier |= Modifier::REVERSED;
+    }
+
+    modifier
+}
+
+// Monochrome margin symbols for coloured lines.
+fn colours_to_symbol(colours: &Colours) -> char {
+    match colours.0 {
+        Some(Colour::Red | Colour::Magenta) => '!',
+        Some(Colour::Yellow) => '?',
+        _ => '*',
+    }
+}
+
 #[derive(Debug, Clone)]
 struct FilterEditState {
     enabled: bool,
     input: Input,
     filter_type: FilterType,
+    // Capture group to show from each match, regex filters only.
+    projection: Option<usize>,
+    // Where the filter starts in the file, filters only.
+    from: FilterFrom,
+}
+
+// Where a filter starts. Starting later gives matches from there on straight away, skipping the
+// lines before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FilterFrom {
+    Start,
+    CurrentLine,
+    End,
+}
+
+impl FilterFrom {
+    fn next(self) -> Self {
+        match self {
+            FilterFrom::Start => FilterFrom::CurrentLine,
+            FilterFrom::CurrentLine => FilterFrom::End,
+            FilterFrom::End => FilterFrom::Start,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -219,20 +943,202 @@ enum ColouringFocusArea {
     ColourPicker,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+enum PromptKind {
+    Aggregate,
+    JsonFields,
+    Sort,
+    Transform,
+    GotoTime,
+    Command,
+    Profile,
+    Bookmark,
+    Annotation,
+    Query,
+}
+
+impl PromptKind {
+    fn title(&self) -> &'static str {
+        match self {
+            PromptKind::Aggregate => {
+                "Aggregate: regex with a numeric capture group (Enter to run, Esc to close)"
+            }
+            PromptKind::JsonFields => {
+                "JSON fields: comma separated, empty for delimited columns (Enter to apply)"
+            }
+            PromptKind::Sort => "Sort matches by: column [asc|desc] (Enter to sort, Esc to close)",
+            PromptKind::Transform => {
+                "Show JSON lines as: e.g. .ts + \" \" + .msg, empty for raw lines (Enter to apply)"
+            }
+            PromptKind::GotoTime => {
+                "Go to time: e.g. 10:15, 2024-03-01 10:15 or 15m for 15 minutes ago (Enter to go)"
+            }
+            PromptKind::Command => {
+                "Command: e <file> opens a file (Tab completes), s/regex/replacement/ rewrites lines"
+            }
+            PromptKind::Profile => "Profile: name, Tab completes, empty to list (Enter to use)",
+            PromptKind::Bookmark => "Bookmark note (Enter to save, empty to remove a bookmark)",
+            PromptKind::Annotation => "Note saved beside the file (Enter to save, empty to remove)",
+            PromptKind::Query => {
+                "Query: select ts, msg where level = 'error' and duration > 100 (Enter to run)"
+            }
+        }
+    }
+}
+
+// A single line input dialog.
+#[derive(Debug, Clone)]
+struct PromptState {
+    kind: PromptKind,
+    input: Input,
+}
+
+// The file list sidebar. Keys go to it whilst it has focus.
+#[derive(Debug, Clone)]
+struct SidebarState {
+    selected: usize,
+    focused: bool,
+    refreshed: Instant,
+}
+
+// Following the newest file listed: the newest when last looked, and when that was.
+#[derive(Debug)]
+struct NewestFile {
+    path: String,
+    checked: Instant,
+}
+
+// A read-only information dialog.
+#[derive(Debug, Clone)]
+struct PopupState {
+    title: String,
+    lines: Vec<String>,
+}
+
+// The fuzzy picker over the history, with the entries matching what has been typed and how they
+// are shown.
+#[derive(Debug, Clone)]
+struct HistoryPickerState {
+    input: Input,
+    matches: Vec<(HistoryEntry, String)>,
+    selected: usize,
+}
+
+// A line marked by the user, with a note and the line's text for listing.
+#[derive(Debug, Clone)]
+struct Bookmark {
+    note: String,
+    line: String,
+}
+
+// A table of results shown over the filtered pane. Moving the selection syncs the content pane to
+// the row's line.
+#[derive(Debug)]
+struct ResultTableView {
+    table: ResultTable,
+    selected: usize,
+    offset: usize,
+    // The groups the table shows, if it is of groups, and the group of each row.
+    groups: Option<LineGroups>,
+    row_groups: Vec<usize>,
+}
+
+impl ResultTableView {
+    fn new(table: ResultTable) -> Self {
+        ResultTableView {
+            table,
+            selected: 0,
+            offset: 0,
+            groups: None,
+            row_groups: Vec::new(),
+        }
+    }
+
+    fn grouped(groups: LineGroups) -> Self {
+        let (table, row_groups) = groups.table();
+        ResultTableView {
+            groups: Some(groups),
+            row_groups,
+            ..ResultTableView::new(table)
+        }
+    }
+
+    // Expand or collapse the selected row's group, keeping the group selected.
+    fn toggle_group(&mut self) {
+        let Some(groups) = &mut self.groups else {
+            return;
+        };
+        let Some(&i) = self.row_groups.get(self.selected) else {
+            return;
+        };
+
+        groups.groups[i].expanded = !groups.groups[i].expanded;
+        (self.table, self.row_groups) = groups.table();
+        self.selected = self.row_groups.iter().position(|g| *g == i).unwrap_or(0);
+    }
+}
+
 pub struct Tui {
     path: String,
+    // The file read, when the path is a symlink.
+    link_target: Option<String>,
+    // Content lines jumped from, oldest first, and the position in them when moving back and forth.
+    jumps: Vec<usize>,
+    jump_index: usize,
+    // Bookmarked lines, by file line number, and the line a note is being asked for.
+    bookmarks: BTreeMap<usize, Bookmark>,
+    bookmark_line: Option<(usize, String)>,
+    // Notes saved beside the file, if it is one, and the line a note is being asked for. New
+    // notes wait for their line's offset, by line number. Notes not yet placed are looked for
+    // again as the file grows.
+    annotations: Option<Annotations>,
+    annotation_line: Option<(usize, String)>,
+    pending_notes: BTreeMap<usize, (String, String)>,
+    notes_requested: bool,
+    notes_looked_for: Option<usize>,
+    // How fast the file is growing, and how that was last shown.
+    growth: GrowthRate,
+    growth_shown: String,
+    // Filters and searches used before, and the position in them whilst stepping back with Up.
+    history: History,
+    history_pos: Option<usize>,
+    history_picker: Option<HistoryPickerState>,
+    // Chords, the key pressed before them and the keys of one pressed so far.
+    chords: Chords,
+    leader: char,
+    chord: Option<String>,
 
     config: LocatedConfig,
 
     content_ifresp_recv: FileRespReceiver<IFResp<String>>,
     filter_ffresp_recv: FFRespReceiver,
 
-    ff_sender: FFReqSender,
+    // The file being viewed, and how to open others.
+    file: OpenFile,
+    opener: FileOpener,
+
+    // Results from background analyses
+    analysis_sender: AnalysisSender,
+    analysis_recv: AnalysisReceiver,
 
     content_state: LazyState<IFResp<String>, String>,
     content_scroll_state: ScrollbarState,
     content_tail: bool,
 
+    // Place the content scrollbar by the current line's time, with the line and file length last
+    // asked about and the line's fraction of the file's time span when it arrives.
+    time_scrollbar: bool,
+    time_fraction_requested: Option<(usize, usize)>,
+    time_fraction: Option<(usize, f64)>,
+
+    // Show one line in so many whilst tailing lines arriving faster than the threshold, with the
+    // last sampled line asked for.
+    sample_lines_per_sec: Option<u64>,
+    sample_every: usize,
+    sampling: bool,
+    sample_requested: bool,
+    sampled_to: Option<usize>,
+
     filter_state: LazyState<FFResp, FilterLine>,
     filter_scroll_state: ScrollbarState,
     filter_tail: bool,
@@ -240,15 +1146,33 @@ pub struct Tui {
     // The current filter
     filter_spec: FilterSpec,
     filter_enabled: bool,
+    // Capture group of the filter regex to show in the filtered pane.
+    filter_projection: Option<usize>,
+    // Where the filter was asked to start, and the file line that was.
+    filter_from: FilterFrom,
+    filter_start: usize,
+    // The line the filter stops before, when restricted to a range.
+    filter_end: Option<usize>,
+    // One end of the lines being selected to filter.
+    select_anchor: Option<usize>,
+    // Lines selected for bulk actions, by file line number.
+    selection: BTreeMap<usize, String>,
 
     // true for content, false for filter
     current_window: bool,
-    // Fill ratio for content pane... 1..9
-    content_fill: usize,
+    // Percentage of the height (or width) for the content pane.
+    content_percent: usize,
+    // Stacked or side by side. Show only the focused pane, and show the filter pane first.
+    layout: PaneLayout,
+    maximized: bool,
+    swapped: bool,
     // Margin for line numbers and carret
     line_no_width: usize,
     // Force a full redraw
     redraw: bool,
+    // The panes as last rendered, reused until something they show changes.
+    content_render: Option<Buffer>,
+    filter_render: Option<Buffer>,
 
     // Are we showing the filter edit modal?
     filter_edit: Option<FilterEditState>,
@@ -261,33 +1185,227 @@ pub struct Tui {
 
     // Are are we showing the colouring edit modal?
     colouring_edit: Option<ColouringEditState>,
+
+    // Are we showing a single line prompt?
+    prompt: Option<PromptState>,
+
+    // Are we showing an information popup?
+    popup: Option<PopupState>,
+
+    // Are we showing a result table?
+    result_table: Option<ResultTableView>,
+
+    // Description of the time window lines are restricted to, if any.
+    time_window: Option<String>,
+
+    // The profile from the config last used, if any.
+    profile: Option<String>,
+    // The profile started with, compiled when it was checked.
+    initial_profile: Option<Profile>,
+
+    // The panes tailed as soon as a file is opened, if any.
+    start_tail: Option<TailPanes>,
+
+    // A short message shown in place of the filter for a moment, and when it was shown.
+    notice: Option<(String, Instant)>,
+
+    // The terminal title last set.
+    title: String,
+
+    // Time gap detection, the threshold is None if disabled. The detector is taken whilst a scan
+    // is running. The generation discards scans started before a truncation.
+    gap_threshold: Option<TimeDelta>,
+    gap_detector: Option<GapDetector>,
+    gaps_scanned_to: usize,
+    gap_generation: usize,
+
+    // Rare message highlighting, None if off. Lines are clustered in the background, with the
+    // clusterer taken whilst a scan is running.
+    rare_threshold: usize,
+    rare_lines: Option<RareLines>,
+    rare_clusterer: Option<Clusterer>,
+    rare_generation: usize,
+
+    // Column mode: the declared delimiter (else detected from the header), the projected JSON
+    // fields, whether column mode is wanted and if the header line has been requested.
+    column_delimiter: Option<char>,
+    json_fields: Option<Vec<String>>,
+    columns_wanted: bool,
+    column_header_requested: bool,
+
+    // Pin this many lines from the start of the file, how many have arrived and if more have been
+    // requested. Those unpinned since are not requested again.
+    pin_first: usize,
+    pins_fetched: usize,
+    pins_requested: bool,
+
+    // The display transform for JSON lines, as entered.
+    transform: Option<String>,
+
+    // Display rewrites from the config and added since, and whether they are in use.
+    substitutions: Vec<Substitution>,
+    substitutions_enabled: bool,
+
+    // Text highlighted in both panes, each in its own colour.
+    highlights: Vec<Highlight>,
+
+    // The files to switch between, None for piped input, and the sidebar listing them if shown.
+    file_list: Option<FileList>,
+    sidebar: Option<SidebarState>,
+    newest: Option<NewestFile>,
+
+    // The files merged into the one being viewed, if it is.
+    sources: Vec<Source>,
+
+    // The buffer of the stream being viewed, if it has one, with the lines waiting and dropped.
+    stream_buffer: Option<StreamBuffer>,
+    stream_backlog: (usize, u64),
+
+    // How the command whose output is being viewed is getting on, if it is, and as last shown.
+    command_status: Option<CommandStatus>,
+    command_shown: String,
+
+    // Why the file can't be read, whilst it is being reopened or once given up on.
+    reader_trouble: Option<String>,
+
+    // The files open in tabs, each parked whilst another is shown, None for the one shown. Files
+    // given to open in tabs of their own once running.
+    tabs: Vec<Option<Tab>>,
+    tab: usize,
+    tab_paths: Vec<String>,
 }
 
-impl Tui {
-    pub fn new(
-        path: String,
-        ifreq_sender: FileReqSender<IFResp<String>>,
-        ffreq_sender: FileReqSender<FFResp>,
-        ff_sender: FFReqSender,
-        config: LocatedConfig,
-    ) -> Self {
-        let (content_ifresp_sender, content_ifresp_recv) = mpsc::channel(CHANNEL_BUFFER);
-        let (filter_ifresp_sender, filter_ifresp_recv) = mpsc::channel(CHANNEL_BUFFER);
+// A file open in a tab whilst another is shown, with its own file, views, filter and colouring.
+// Its views are kept up to date, so it is as it was left when shown again.
+struct Tab {
+    path: String,
+    link_target: Option<String>,
+    file: OpenFile,
+    growth: GrowthRate,
+    content_ifresp_recv: FileRespReceiver<IFResp<String>>,
+    filter_ffresp_recv: FFRespReceiver,
+    content_view: ContentView,
+    filter_view: FilterView,
+    content_tail: bool,
+    filter_tail: bool,
+    filter_spec: FilterSpec,
+    filter_enabled: bool,
+    filter_projection: Option<usize>,
+    filter_from: FilterFrom,
+    filter_start: usize,
+    filter_end: Option<usize>,
+    colouring: ColouringSpec,
+    profile: Option<String>,
+    jumps: Vec<usize>,
+    jump_index: usize,
+    bookmarks: BTreeMap<usize, Bookmark>,
+    annotations: Option<Annotations>,
+    reader_trouble: Option<String>,
+}
 
-        let content_view = View::new(
-            "content".to_owned(),
-            ifreq_sender.clone(),
-            content_ifresp_sender,
-        );
-        let filter_view = View::new(
-            "filter".to_owned(),
-            ffreq_sender.clone(),
-            filter_ifresp_sender,
-        );
+impl Tab {
+    // Keep the views up to date with what has arrived for them.
+    async fn handle_updates(&mut self) -> Result<()> {
+        while let Ok(resp) = self.content_ifresp_recv.try_recv() {
+            match resp {
+                IFResp::ViewUpdate { update } => self.content_view.handle_update(update).await,
+                IFResp::Truncated => {
+                    self.content_view.reset().await?;
+                    self.filter_view.reset().await?;
+                }
+                IFResp::FileError { reason } => {
+                    self.reader_trouble = Some(format!("gave up: {}", reason))
+                }
+                IFResp::Rotated | IFResp::ReaderRestarted { .. } => self.reader_trouble = None,
+                IFResp::ReaderRetrying {
+                    reason,
+                    attempt,
+                    attempts,
+                    delay,
+                } => self.reader_trouble = Some(describe_retry(&reason, attempt, attempts, delay)),
+                IFResp::Waiting => self.reader_trouble = Some(WAITING_FOR_FILE.to_owned()),
+                IFResp::Created => self.reader_trouble = None,
+                IFResp::Spooled => {}
+                IFResp::Backfilled { lines } => self.content_view.prepended(lines).await?,
+            }
+        }
+        while let Ok(resp) = self.filter_ffresp_recv.try_recv() {
+            match resp {
+                FFResp::ViewUpdate { update } => self.filter_view.handle_update(update).await,
+                FFResp::Clear => self.filter_view.reset().await?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// A merged file, with the colour of its tag and whether its lines are shown.
+#[derive(Debug, Clone)]
+struct Source {
+    tag: String,
+    colour: Color,
+    shown: bool,
+}
+
+impl Tui {
+    pub fn new(path: String, opener: FileOpener, file: OpenFile, config: LocatedConfig) -> Self {
+        let (analysis_sender, analysis_recv) = mpsc::channel(CHANNEL_BUFFER);
+
+        let scrolloff = config.config.scrolloff.unwrap_or(0);
+        let time_scrollbar = config.config.time_scrollbar;
+        let sample_lines_per_sec = config.config.sample_lines_per_sec;
+        let sample_every = config
+            .config
+            .sample_every
+            .unwrap_or(DEFAULT_SAMPLE_EVERY)
+            .max(1);
+        let default_filter_type = if config.config.smart_case {
+            FilterType::SmartCase
+        } else {
+            FilterType::SimpleCaseInsensitive
+        };
+        let (content_view, content_ifresp_recv, filter_view, filter_ifresp_recv) =
+            Tui::new_views(&file, scrolloff);
 
         let colouring = config.config.colouring.clone();
+        let current_line = config.config.current_line.clone().unwrap_or_default();
+        let layout = config.config.layout.unwrap_or_default();
+        let content_percent = config
+            .config
+            .content_percent
+            .unwrap_or(DEFAULT_CONTENT_PERCENT)
+            .clamp(MIN_PANE_PERCENT, 100 - MIN_PANE_PERCENT);
+        let time_gap_secs = config.config.time_gap_secs.unwrap_or(DEFAULT_TIME_GAP_SECS);
+        let gap_threshold =
+            (time_gap_secs > 0.0).then(|| TimeDelta::milliseconds((time_gap_secs * 1000.0) as i64));
+        let rare_threshold = config
+            .config
+            .rare_message_threshold
+            .unwrap_or(DEFAULT_RARE_THRESHOLD);
 
         let s = Self {
+            link_target: describe_link(&file.path),
+            jumps: Vec::new(),
+            jump_index: 0,
+            bookmarks: BTreeMap::new(),
+            bookmark_line: None,
+            annotations: None,
+            annotation_line: None,
+            pending_notes: BTreeMap::new(),
+            notes_requested: false,
+            notes_looked_for: None,
+            growth: new_growth_rate(&file.path),
+            growth_shown: String::new(),
+            history: History::load(
+                History::default_path(),
+                config.config.history_size.unwrap_or(DEFAULT_HISTORY_SIZE),
+            ),
+            history_pos: None,
+            history_picker: None,
+            chords: Chords::new(&config.config.chords),
+            leader: config.config.leader.unwrap_or(DEFAULT_LEADER),
+            chord: None,
             path,
 
             config,
@@ -295,59 +1413,370 @@ impl Tui {
             content_ifresp_recv,
             filter_ffresp_recv: filter_ifresp_recv,
 
-            ff_sender,
+            file,
+            opener,
+
+            analysis_sender,
+            analysis_recv,
 
             content_state: LazyState {
                 view: content_view,
                 height_hint: 0,
                 width_hint: 0,
                 content_num_lines: 0,
-                colouring: colouring.clone(),
+                colouring: colouring.for_pane(ColouringScope::Content),
+                columns: None,
+                time_display: TimeDisplay::Original,
+                monochrome: false,
+                current_line: current_line.clone(),
+                show_whitespace: false,
+                projection: None,
+                transform: None,
+                substitutions: Vec::new(),
+                highlights: Vec::new(),
+                sources: Vec::new(),
+                timestamps: TimestampParser::default(),
+                gaps: BTreeMap::new(),
+                marks: BTreeMap::new(),
+                bookmarks: BTreeMap::new(),
+                pinned: BTreeMap::new(),
+                selected: BTreeSet::new(),
+                annotated: BTreeSet::new(),
+                sampled: None,
                 cell_renders: 0,
             },
             content_scroll_state: ScrollbarState::new(0),
             content_tail: false,
 
+            time_scrollbar,
+            time_fraction_requested: None,
+            time_fraction: None,
+
+            sample_lines_per_sec,
+            sample_every,
+            sampling: false,
+            sample_requested: false,
+            sampled_to: None,
+
             filter_scroll_state: ScrollbarState::new(0),
             filter_state: LazyState {
                 view: filter_view,
                 height_hint: 0,
                 width_hint: 0,
                 content_num_lines: 0,
-                colouring: colouring.clone(),
+                colouring: colouring.for_pane(ColouringScope::Filter),
+                columns: None,
+                time_display: TimeDisplay::Original,
+                monochrome: false,
+                current_line: current_line.clone(),
+                show_whitespace: false,
+                projection: None,
+                transform: None,
+                substitutions: Vec::new(),
+                highlights: Vec::new(),
+                sources: Vec::new(),
+                timestamps: TimestampParser::default(),
+                gaps: BTreeMap::new(),
+                marks: BTreeMap::new(),
+                bookmarks: BTreeMap::new(),
+                pinned: BTreeMap::new(),
+                selected: BTreeSet::new(),
+                annotated: BTreeSet::new(),
+                sampled: None,
                 cell_renders: 0,
             },
             filter_tail: false,
-            filter_spec: FilterSpec::new(FilterType::SimpleCaseInsensitive, "")
+            filter_spec: FilterSpec::new(default_filter_type, "")
                 .expect("Unexpected error building empty filter"),
             filter_enabled: false,
+            filter_projection: None,
+            filter_from: FilterFrom::Start,
+            filter_start: 0,
+            filter_end: None,
+            select_anchor: None,
+            selection: BTreeMap::new(),
 
             current_window: true,
-            content_fill: 7,
+            content_percent,
+            layout,
+            maximized: false,
+            swapped: false,
             line_no_width: 0,
             redraw: false,
+            content_render: None,
+            filter_render: None,
 
             filter_edit: None,
             sync_filter_to_content: false,
 
             colouring,
             colouring_edit: None,
+
+            prompt: None,
+            popup: None,
+            result_table: None,
+
+            time_window: None,
+            profile: None,
+            initial_profile: None,
+            start_tail: None,
+            notice: None,
+            title: String::new(),
+
+            gap_threshold,
+            gap_detector: gap_threshold.map(GapDetector::new),
+            gaps_scanned_to: 0,
+            gap_generation: 0,
+
+            rare_threshold,
+            rare_lines: None,
+            rare_clusterer: None,
+            rare_generation: 0,
+
+            column_delimiter: None,
+            json_fields: None,
+            columns_wanted: false,
+            column_header_requested: false,
+
+            pin_first: 0,
+            pins_fetched: 0,
+            pins_requested: false,
+
+            transform: None,
+            substitutions: Vec::new(),
+            substitutions_enabled: true,
+
+            highlights: Vec::new(),
+
+            file_list: None,
+            sidebar: None,
+            newest: None,
+
+            sources: Vec::new(),
+
+            stream_buffer: None,
+            stream_backlog: (0, 0),
+            command_status: None,
+            command_shown: String::new(),
+
+            reader_trouble: None,
+
+            tabs: vec![None],
+            tab: 0,
+            tab_paths: Vec::new(),
         };
 
         s
     }
 
+    // The content and filter views for a file, with the channels their updates arrive on.
+    fn new_views(
+        file: &OpenFile,
+        scrolloff: usize,
+    ) -> (
+        ContentView,
+        FileRespReceiver<IFResp<String>>,
+        FilterView,
+        FFRespReceiver,
+    ) {
+        let (content_ifresp_sender, content_ifresp_recv) = mpsc::channel(CHANNEL_BUFFER);
+        let (filter_ifresp_sender, filter_ifresp_recv) = mpsc::channel(CHANNEL_BUFFER);
+
+        let content_view = View::new(
+            "content".to_owned(),
+            file.ifreq_sender.clone(),
+            content_ifresp_sender,
+        )
+        .with_scrolloff(scrolloff);
+        let filter_view = View::new(
+            "filter".to_owned(),
+            file.ffreq_sender.clone(),
+            filter_ifresp_sender,
+        )
+        .with_scrolloff(scrolloff);
+
+        (
+            content_view,
+            content_ifresp_recv,
+            filter_view,
+            filter_ifresp_recv,
+        )
+    }
+
+    pub fn with_file_list(mut self, file_list: FileList) -> Self {
+        self.file_list = Some(file_list);
+
+        self
+    }
+
+    /// Switch to a newer file in the file list when one appears, e.g. the next of a set of
+    /// date-stamped logs.
+    pub fn with_follow_newest(mut self) -> Self {
+        self.newest = Some(NewestFile {
+            path: self.file.path.clone(),
+            checked: Instant::now(),
+        });
+
+        self
+    }
+
+    /// Open these files in tabs of their own after the first.
+    pub fn with_tabs(mut self, paths: Vec<String>) -> Self {
+        self.tab_paths = paths;
+
+        self
+    }
+
+    /// View a stream spooled through a buffer, which is told how far its file has been read.
+    pub fn with_stream_buffer(mut self, stream_buffer: StreamBuffer) -> Self {
+        self.stream_buffer = Some(stream_buffer);
+
+        self
+    }
+
+    /// View a command's output, showing whether it is running or how it exited.
+    pub fn with_command_status(mut self, command_status: CommandStatus) -> Self {
+        self.command_shown = command_status.describe();
+        self.command_status = Some(command_status);
+
+        self
+    }
+
+    /// View files merged with their tags, which can be hidden and shown.
+    pub fn with_sources(mut self, tags: &[String]) -> Self {
+        self.sources = tags
+            .iter()
+            .zip(SOURCE_PALETTE.iter().cycle())
+            .map(|(tag, colour)| Source {
+                tag: tag.clone(),
+                colour: *colour,
+                shown: true,
+            })
+            .collect();
+        let sources = self
+            .sources
+            .iter()
+            .map(|s| (spool::source_prefix(&s.tag), s.colour))
+            .collect::<Vec<_>>();
+        self.content_state.sources = sources.clone();
+        self.filter_state.sources = sources;
+
+        self
+    }
+
+    pub fn with_timestamps(mut self, timestamps: TimestampParser) -> Self {
+        self.content_state.timestamps = timestamps.clone();
+        self.filter_state.timestamps = timestamps;
+
+        self
+    }
+
+    /// Draw for screen readers and braille displays: no box-drawing characters, fewer redraws
+    /// whilst tailing, and the current line and status as plain text on the bottom line.
+    pub fn with_accessible(self) -> Self {
+        ACCESSIBLE.store(true, Ordering::Relaxed);
+
+        self
+    }
+
+    pub fn with_monochrome(mut self) -> Self {
+        self.content_state.monochrome = true;
+        self.filter_state.monochrome = true;
+
+        self
+    }
+
+    /// Start with a filter applied, e.g. from a filter file.
+    pub fn with_filter(mut self, filter_spec: FilterSpec) -> Self {
+        self.filter_spec = filter_spec;
+        self.filter_enabled = true;
+
+        self
+    }
+
+    /// Start with a profile from the config, already compiled.
+    pub fn with_profile(mut self, name: String, profile: Profile) -> Self {
+        self.profile = Some(name);
+        self.initial_profile = Some(profile);
+
+        self
+    }
+
+    /// Start tailing the panes, for the first file and for each tab opened.
+    pub fn with_tail(mut self, panes: TailPanes) -> Self {
+        self.start_tail = Some(panes);
+
+        self
+    }
+
+    pub fn with_substitutions(mut self, substitutions: Vec<Substitution>) -> Self {
+        self.substitutions = substitutions;
+        self.update_substitutions();
+
+        self
+    }
+
+    pub fn with_time_window(mut self, time_window: String) -> Self {
+        self.time_window = Some(time_window);
+
+        self
+    }
+
+    /// Start in column mode, with a declared delimiter or detecting it from the first line.
+    pub fn with_columns(mut self, delimiter: Option<char>) -> Self {
+        self.column_delimiter = delimiter;
+        self.columns_wanted = true;
+
+        self
+    }
+
+    /// Pin the first lines of the file above the content pane.
+    pub fn with_pinned(mut self, lines: usize) -> Self {
+        self.pin_first = lines;
+
+        self
+    }
+
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
         let mut should_quit = false;
 
+        // Where the terminal can tell Ctrl-i from Tab, it goes forward through the jumps as in Vim.
+        let keyboard = KeyboardEnhancement::push();
+
         self.content_state.view.init().await?;
         self.filter_state.view.init().await?;
+        self.load_annotations(&self.file.path.clone());
 
-        // Initialise the filter spec.
-        self.set_filter_spec(self.filter_spec.clone()).await?;
+        // Initialise the filter spec, from the profile if there is one.
+        match self.initial_profile.take() {
+            Some(profile) => self.apply_profile(profile).await?,
+            None => self.set_filter_spec(self.filter_spec.clone()).await?,
+        }
+        self.start_tailing().await?;
+
+        // Open the other tabs, showing the first.
+        for path in std::mem::take(&mut self.tab_paths) {
+            self.open_tab(&path).await?;
+        }
+        self.switch_tab(0).await?;
 
         let mut reader = EventStream::new();
-        let mut interval = tokio::time::interval(Duration::from_millis(MS_PER_FRAME));
+        // Render quickly whilst things change, dropping to the idle rate when nothing has for a
+        // while.
+        let fps = match accessible() {
+            true => ACCESSIBLE_FPS,
+            false => self.config.config.fps.unwrap_or(FPS).max(1),
+        };
+        let idle_fps = self
+            .config
+            .config
+            .idle_fps
+            .unwrap_or(IDLE_FPS)
+            .clamp(1, fps);
+        let frame = |fps: u64| interval(Duration::from_millis(1_000 / fps));
+        let mut ticker = frame(fps);
+        let mut idle = false;
+        let mut last_change = Instant::now();
 
         // Indicate if enough time has passed to render, or if something timely should render.
         let mut can_render = true;
@@ -367,6 +1796,9 @@ impl Tui {
                     .content_scroll_state
                     .content_length(content_stats.file_lines)
                     .viewport_content_length(self.content_state.view.get_viewport_height());
+                if self.time_scrollbar {
+                    self.place_by_time(content_stats.file_lines);
+                }
                 self.filter_scroll_state = self
                     .filter_scroll_state
                     .content_length(self.filter_state.view.get_stats().view_lines)
@@ -374,10 +1806,12 @@ impl Tui {
 
                 if self.redraw {
                     terminal.clear()?;
+                    self.damage_panes();
                     self.redraw = false;
                 }
                 trace!("Draw!");
                 terminal.draw(|frame| self.draw(frame))?;
+                self.update_title()?;
                 can_render = false;
                 dirty = false;
 
@@ -393,19 +1827,37 @@ impl Tui {
                     .await?;
             }
 
-            let timeout = interval.tick();
+            let timeout = ticker.tick();
             let crossterm_event = reader.next().fuse();
             select! {
                 _ = timeout => {
                     can_render = true;
+                    if self.follow_newest().await? {
+                        dirty = true;
+                    }
+                    if self.refresh_sidebar()
+                        | self.expire_notice()
+                        | self.sample_growth()
+                        | self.update_sampling()
+                        | self.update_stream_buffer()
+                        | self.update_command_status()
+                    {
+                        dirty = true;
+                    }
+                    for tab in self.tabs.iter_mut().flatten() {
+                        tab.handle_updates().await?;
+                    }
                 },
                 maybe_event = crossterm_event => {
                     trace!("Event: {:?}", maybe_event);
                     dirty = true;
                     can_render = true;
+                    self.damage_panes();
                     match maybe_event {
                         Some(Ok(e)) => {
-                            should_quit = self.handle_event(&e).await?;
+                            should_quit = self.handle_event(&legacy_keys(e)).await?;
+                            // Scrolling away from the end stops sampling at once.
+                            self.update_sampling();
                         },
                         Some(Err(err)) => {
                             error!("Terminal error: {:?}", err);
@@ -426,6 +1878,10 @@ impl Tui {
                             match cr {
                                 IFResp::ViewUpdate { update } => {
                                     trace!("TUI: Processing content view update: {:?}", update);
+                                    // Stats only change the pane when tailing moves it.
+                                    if self.content_tail || !matches!(update, FileResp::Stats { .. }) {
+                                        self.content_render = None;
+                                    }
                                     self.content_state.view.handle_update(update).await;
                                 }
                                 IFResp::Truncated => {
@@ -434,18 +1890,72 @@ impl Tui {
 
                                     self.content_state.view.reset().await?;
                                     self.filter_state.view.reset().await?;
+                                    self.damage_panes();
+
+                                    // A followed link may have been re-pointed.
+                                    if self.opener.follows_links() {
+                                        self.link_target = describe_link(&self.file.path);
+                                    }
+
+                                    // The header may have changed with the new content.
+                                    self.content_state.columns = None;
+                                    self.filter_state.columns = None;
+                                    self.reset_pinned();
+
+                                    self.reset_gaps();
+                                    self.reset_marks();
+                                    self.reset_rare();
                                 }
                                 IFResp::FileError { reason } => {
                                     trace!("TUI: Content file error received: {}", reason);
                                     error!("{}: File error: {reason}", self.path);
 
-                                    // TODO: Put this in a dlg...
+                                    self.reader_trouble = Some(format!("gave up: {}", reason));
+                                }
+                                IFResp::Rotated => {
+                                    debug!("{}: File replaced", self.path);
+                                    self.reader_trouble = None;
+                                    self.show_notice("File replaced, reading the new one".to_owned());
+                                }
+                                IFResp::ReaderRestarted { reason } => {
+                                    self.reader_trouble = None;
+                                    self.show_notice(format!("Reader restarted: {}", reason));
+                                }
+                                IFResp::ReaderRetrying { reason, attempt, attempts, delay } => {
+                                    self.reader_trouble =
+                                        Some(describe_retry(&reason, attempt, attempts, delay));
+                                }
+                                IFResp::Waiting => {
+                                    self.reader_trouble = Some(WAITING_FOR_FILE.to_owned());
+                                }
+                                IFResp::Created => {
+                                    debug!("{}: File created", self.path);
+                                    self.reader_trouble = None;
+                                    self.show_notice("File created".to_owned());
                                 }
+                                IFResp::Spooled => {}
+                                IFResp::Backfilled { lines } => self.backfilled(lines).await?,
                             }
                         }
                     }
 
-                    self.line_no_width = common::count_digits(self.content_state.view.get_stats().file_lines) + MARGIN_EXTRAS;
+                    let line_no_width = common::count_digits(self.content_state.view.get_stats().file_lines) + MARGIN_EXTRAS;
+                    if line_no_width != self.line_no_width {
+                        // Both panes' margins widen.
+                        self.damage_panes();
+                        self.line_no_width = line_no_width;
+                    }
+
+                    if self.columns_wanted && self.content_state.columns.is_none() {
+                        self.apply_columns();
+                    }
+
+                    self.update_stream_buffer();
+                    self.request_pinned();
+                    self.request_sampled();
+                    self.request_annotations();
+                    self.maybe_scan_gaps();
+                    self.maybe_scan_rare();
                 },
                 filter_resp = self.filter_ffresp_recv.recv() => {
                     trace!("TUI: Received filter response from FFile channel: {:?}", filter_resp);
@@ -459,22 +1969,53 @@ impl Tui {
                             match fr {
                                 FFResp::ViewUpdate { update } => {
                                     trace!("TUI: Processing filter view update: {:?}", update);
+                                    if self.filter_tail || !matches!(update, FileResp::Stats { .. }) {
+                                        self.filter_render = None;
+                                    }
                                     self.filter_state.view.handle_update(update).await;
                                 }
                                 FFResp::Clear => {
                                     trace!("TUI: Filter cleared, resetting filter view");
                                     self.filter_state.view.reset().await?;
+                                    self.filter_render = None;
                                 }
                             }
 
                             trace!("TUI: Auto-syncing after filter response if needed");
+                            let content_line = self.content_state.view.current();
                             self.auto_sync_if_needed().await?;
+                            if self.content_state.view.current() != content_line {
+                                self.content_render = None;
+                            }
                         }
                     }
                 }
+                analysis_result = self.analysis_recv.recv() => {
+                    trace!("TUI: Received analysis result: {:?}", analysis_result);
+                    dirty = true;
+                    self.damage_panes();
+                    if let Some(result) = analysis_result {
+                        self.handle_analysis_result(result).await?;
+                    }
+                }
+            }
+
+            if dirty {
+                last_change = Instant::now();
+                if idle {
+                    trace!("TUI: Rendering at {} fps", fps);
+                    idle = false;
+                    ticker = frame(fps);
+                }
+            } else if !idle && last_change.elapsed() >= Duration::from_millis(IDLE_AFTER_MS) {
+                trace!("TUI: Idle, rendering at {} fps", idle_fps);
+                idle = true;
+                ticker = frame(idle_fps);
             }
         }
 
+        // Restored on the alternate screen it was enhanced on.
+        drop(keyboard);
         disable_raw_mode()?;
         stdout().execute(LeaveAlternateScreen)?;
 
@@ -485,9 +2026,45 @@ impl Tui {
         let mut filter_spec_to_apply = None;
         if let Event::Key(key) = event {
             if key.kind == event::KeyEventKind::Press {
+                // Stepping through the history only carries on whilst Up and Down are pressed.
+                if !matches!(key.code, KeyCode::Up | KeyCode::Down) {
+                    self.history_pos = None;
+                }
+
+                if self.popup.is_some() {
+                    self.handle_popup_key(key);
+                    return Ok(false);
+                }
+
+                if self.prompt.is_some() {
+                    self.handle_prompt_key(key).await?;
+                    return Ok(false);
+                }
+
+                if self.history_picker.is_some() {
+                    self.handle_history_picker_key(key);
+                    return Ok(false);
+                }
+
+                if self.chord.is_some() {
+                    self.handle_chord_key(key).await?;
+                    return Ok(false);
+                }
+
+                if self.result_table.is_some() {
+                    self.handle_result_table_key(key).await?;
+                    return Ok(false);
+                }
+
+                if self.sidebar.as_ref().is_some_and(|sidebar| sidebar.focused) {
+                    self.handle_sidebar_key(key).await?;
+                    return Ok(false);
+                }
+
                 match (&mut self.filter_edit, &mut self.colouring_edit) {
                     // Showing the main window.
                     (None, None) => match (key.code, key.modifiers) {
+                        (KeyCode::Char(c), _) if c == self.leader => self.start_chord(),
                         (KeyCode::Char('q'), _) => return Ok(true),
 
                         (KeyCode::Char('j') | KeyCode::Down, _) => self.scroll(1).await?,
@@ -509,16 +2086,62 @@ impl Tui {
 
                         (KeyCode::Char('=') | KeyCode::Char('+'), _) => self.resize(1).await,
                         (KeyCode::Char('-') | KeyCode::Char('_'), _) => self.resize(-1).await,
+                        (KeyCode::Esc, _) if self.filter_progress().is_some() => {
+                            self.cancel_filter().await?
+                        }
+                        (KeyCode::Char('M'), _) => self.maximized = !self.maximized,
+                        (KeyCode::Char('X'), _) => self.swapped = !self.swapped,
+                        (KeyCode::Char('V'), _) => self.layout = self.layout.toggle(),
 
                         (KeyCode::Char('t'), _) => self.toggle_tail().await?,
 
                         (KeyCode::Tab, _) => self.current_window = !self.current_window,
 
-                        (KeyCode::Char('s'), _) => self.sync_filter_to_content().await?,
+                        (KeyCode::Char('s'), _) => {
+                            self.record_jump();
+                            self.sync_filter_to_content().await?;
+                        }
+                        (KeyCode::Char('o'), KeyModifiers::CONTROL) => self.jump_back().await?,
+                        // Ctrl-i is Tab unless the terminal tells them apart, so Ctrl-n also goes
+                        // forward.
+                        (KeyCode::Char('i') | KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                            self.jump_forward().await?
+                        }
+                        (KeyCode::Char('p'), KeyModifiers::CONTROL) => self.show_history_picker(),
                         (KeyCode::Char('S'), _) => self.toggle_sync_lock().await?,
 
                         (KeyCode::Char('/'), _) => self.start_edit_filter(),
                         (KeyCode::Char('C'), _) => self.start_edit_colouring(),
+                        (KeyCode::Char('a'), _) => self.start_aggregate(),
+                        (KeyCode::Char('Q'), _) => self.start_query(),
+                        (KeyCode::Char('c'), _) => self.run_clusters(),
+                        (KeyCode::Char('R'), _) => self.toggle_rare(),
+                        (KeyCode::Char('w'), _) => self.toggle_show_whitespace(),
+                        (KeyCode::Char('|'), _) => self.toggle_columns(),
+                        (KeyCode::Char('J'), _) => self.start_edit_json_fields(),
+                        (KeyCode::Char('o'), _) => self.start_sort(),
+                        (KeyCode::Char('x'), _) => self.start_edit_transform(),
+                        (KeyCode::Char('@'), _) => self.start_goto_time(),
+                        (KeyCode::Char('F'), _) => self.focus_sidebar(),
+                        (KeyCode::Char(':'), _) => self.start_command(),
+                        (KeyCode::Char('P'), _) => self.start_profile(),
+                        (KeyCode::Char('T'), _) => self.toggle_time_display(),
+                        (KeyCode::Char('m'), _) => self.mark_now(),
+                        (KeyCode::Char('b'), _) => self.start_bookmark(),
+                        (KeyCode::Char('B'), _) => self.show_bookmarks(),
+                        (KeyCode::Char('N'), _) => self.start_annotation(),
+                        (KeyCode::Char(']'), _) => self.jump_to_gap(true).await?,
+                        (KeyCode::Char('['), _) => self.jump_to_gap(false).await?,
+                        (KeyCode::Char('}'), _) => self.step_tab(true).await?,
+                        (KeyCode::Char('{'), _) => self.step_tab(false).await?,
+                        (KeyCode::Enter, _) => self.show_line_detail(),
+                        (KeyCode::Char('y'), _) => self.yank_line(),
+                        (KeyCode::Char('p'), _) => self.toggle_pin(),
+                        (KeyCode::Char('v'), _) => self.select_lines().await?,
+                        (KeyCode::Char('*'), _) => self.toggle_selected(),
+                        (KeyCode::Char(c @ '1'..='9'), _) if !self.sources.is_empty() => {
+                            self.toggle_source(c as usize - '1' as usize).await?
+                        }
 
                         (KeyCode::Char('r'), KeyModifiers::CONTROL) => self.redraw = true,
 
@@ -534,9 +2157,31 @@ impl Tui {
                                 filter_edit.input.value()
                             );
                             self.filter_enabled = filter_edit.enabled;
-                            let input = filter_edit.input.value();
-                            filter_spec_to_apply =
-                                Some(FilterSpec::new(filter_edit.filter_type.clone(), input)?);
+                            self.filter_projection = filter_edit.projection;
+                            self.filter_from = filter_edit.from;
+                            self.filter_start = match filter_edit.from {
+                                FilterFrom::Start => 0,
+                                FilterFrom::CurrentLine => self.content_state.view.current(),
+                                FilterFrom::End => self.content_state.view.get_stats().file_lines,
+                            };
+                            self.filter_end = None;
+                            if filter_edit.filter_type != FilterType::Compound {
+                                self.history.add(HistoryEntry {
+                                    kind: HistoryKind::Filter,
+                                    pattern: filter_edit.input.value().to_owned(),
+                                    filter_type: Some(filter_edit.filter_type.clone()),
+                                });
+                            }
+                            // Combined filters can only be changed in their file. Context lines
+                            // from the file are kept for other filters.
+                            filter_spec_to_apply = Some(match filter_edit.filter_type {
+                                FilterType::Compound => self.filter_spec.clone(),
+                                _ => FilterSpec::new(
+                                    filter_edit.filter_type.clone(),
+                                    filter_edit.input.value(),
+                                )?
+                                .with_context(self.filter_spec.context()),
+                            });
                         }
                         (KeyCode::Char('t'), KeyModifiers::CONTROL) => {
                             filter_edit.enabled = !filter_edit.enabled;
@@ -544,7 +2189,7 @@ impl Tui {
                         (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
                             // Note: C-i is sent as a TAB keycode, so we cannot use it for this
                             // option.
-                            filter_edit.filter_type = FilterType::SimpleCaseInsensitive;
+                            filter_edit.filter_type = toggle_smart_case(&filter_edit.filter_type);
                         }
                         (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
                             filter_edit.filter_type = FilterType::SimpleCaseSensitive;
@@ -552,6 +2197,30 @@ impl Tui {
                         (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
                             filter_edit.filter_type = FilterType::Regex;
                         }
+                        (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
+                            filter_edit.filter_type = FilterType::Level;
+                        }
+                        (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
+                            filter_edit.filter_type = FilterType::Json;
+                        }
+                        (KeyCode::Char('g'), KeyModifiers::CONTROL) => {
+                            filter_edit.projection = next_projection(filter_edit);
+                        }
+                        (KeyCode::Char('f'), KeyModifiers::CONTROL) => {
+                            filter_edit.from = filter_edit.from.next();
+                        }
+                        (KeyCode::Up | KeyCode::Down, _) => {
+                            let older = key.code == KeyCode::Up;
+                            if let Some(entry) =
+                                self.history
+                                    .step(HistoryKind::Filter, &mut self.history_pos, older)
+                            {
+                                filter_edit.input = entry.pattern.into();
+                                if let Some(filter_type) = entry.filter_type {
+                                    filter_edit.filter_type = filter_type;
+                                }
+                            }
+                        }
                         _ => {
                             filter_edit.input.handle_event(&Event::Key(*key));
                         }
@@ -622,6 +2291,23 @@ impl Tui {
                                         }
                                     }
                                 }
+                                (KeyCode::Char('p'), KeyModifiers::NONE) => {
+                                    // Cycle the panes the current rule applies to
+                                    if let Some(colouring_edit) = &mut self.colouring_edit {
+                                        if let Some(rule) = colouring_edit
+                                            .spec
+                                            .rules()
+                                            .get(colouring_edit.selected_rule_index)
+                                        {
+                                            let mut updated_rule = rule.clone();
+                                            updated_rule.scope = updated_rule.scope.next();
+                                            colouring_edit.spec.update_rule(
+                                                colouring_edit.selected_rule_index,
+                                                updated_rule,
+                                            );
+                                        }
+                                    }
+                                }
                                 _ => {}
                             }
                         }
@@ -637,7 +2323,9 @@ impl Tui {
                                 (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
                                     let colouring_edit = self.colouring_edit.as_mut().unwrap();
                                     colouring_edit.filter_edit_state.filter_type =
-                                        FilterType::SimpleCaseInsensitive;
+                                        toggle_smart_case(
+                                            &colouring_edit.filter_edit_state.filter_type,
+                                        );
                                     self.update_selected_rule_from_editor();
                                 }
                                 (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
@@ -652,6 +2340,17 @@ impl Tui {
                                         FilterType::Regex;
                                     self.update_selected_rule_from_editor();
                                 }
+                                (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
+                                    let colouring_edit = self.colouring_edit.as_mut().unwrap();
+                                    colouring_edit.filter_edit_state.filter_type =
+                                        FilterType::Level;
+                                    self.update_selected_rule_from_editor();
+                                }
+                                (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
+                                    let colouring_edit = self.colouring_edit.as_mut().unwrap();
+                                    colouring_edit.filter_edit_state.filter_type = FilterType::Json;
+                                    self.update_selected_rule_from_editor();
+                                }
                                 _ => {
                                     let colouring_edit = self.colouring_edit.as_mut().unwrap();
                                     colouring_edit
@@ -702,48 +2401,2526 @@ impl Tui {
         Ok(false)
     }
 
-    async fn toggle_sync_lock(&mut self) -> Result<()> {
-        trace!(
-            "Toggling sync lock: current: {}",
-            self.sync_filter_to_content
-        );
+    fn handle_popup_key(&mut self, key: &event::KeyEvent) {
+        if let KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') = key.code {
+            self.popup = None;
+        }
+    }
 
-        self.sync_filter_to_content = !self.sync_filter_to_content;
-        self.auto_sync_if_needed().await?;
+    async fn handle_prompt_key(&mut self, key: &event::KeyEvent) -> Result<()> {
+        let Some(prompt) = &mut self.prompt else {
+            return Ok(());
+        };
+
+        match key.code {
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Enter => {
+                if let Some(prompt) = self.prompt.take() {
+                    self.submit_prompt(prompt).await?;
+                }
+            }
+            KeyCode::Tab if prompt.kind == PromptKind::Command => {
+                // Complete the path argument.
+                if let Some((command, path)) = prompt.input.value().split_once(' ') {
+                    if let Some(path) = complete_path(path.trim_start()) {
+                        prompt.input = format!("{} {}", command, path).into();
+                    }
+                }
+            }
+            KeyCode::Up | KeyCode::Down if prompt.kind == PromptKind::GotoTime => {
+                let older = key.code == KeyCode::Up;
+                if let Some(entry) =
+                    self.history
+                        .step(HistoryKind::Time, &mut self.history_pos, older)
+                {
+                    prompt.input = entry.pattern.into();
+                }
+            }
+            KeyCode::Tab if prompt.kind == PromptKind::Profile => {
+                let partial = prompt.input.value().trim();
+                let names = self
+                    .config
+                    .config
+                    .profiles
+                    .keys()
+                    .filter(|name| name.starts_with(partial));
+                if let Some(name) = common_prefix(names) {
+                    prompt.input = name.into();
+                }
+            }
+            _ => {
+                prompt.input.handle_event(&Event::Key(*key));
+            }
+        }
 
         Ok(())
     }
 
-    async fn auto_sync_if_needed(&mut self) -> Result<()> {
-        if self.sync_filter_to_content {
-            trace!("TUI: Auto-sync enabled, syncing filter to content");
-            self.sync_filter_to_content().await?;
-        } else {
-            trace!("TUI: Auto-sync disabled, skipping sync");
+    async fn submit_prompt(&mut self, prompt: PromptState) -> Result<()> {
+        trace!(
+            "TUI: Prompt submitted: {:?} - {}",
+            prompt.kind,
+            prompt.input.value()
+        );
+
+        match prompt.kind {
+            PromptKind::Aggregate => self.run_aggregate(prompt.input.value()),
+            PromptKind::JsonFields => self.set_json_fields(prompt.input.value()),
+            PromptKind::Sort => self.run_sort(prompt.input.value()),
+            PromptKind::Transform => self.set_transform(prompt.input.value()),
+            PromptKind::GotoTime => self.find_time(prompt.input.value()),
+            PromptKind::Command => self.run_command(prompt.input.value()).await?,
+            PromptKind::Profile => self.use_profile(prompt.input.value().trim()).await?,
+            PromptKind::Bookmark => self.save_bookmark(prompt.input.value().trim()),
+            PromptKind::Annotation => self.save_annotation(prompt.input.value().trim()),
+            PromptKind::Query => self.run_query(prompt.input.value()),
         }
 
         Ok(())
     }
 
-    async fn sync_filter_to_content(&mut self) -> Result<()> {
-        trace!("Sync filter to content");
+    fn show_popup(&mut self, title: &str, lines: Vec<String>) {
+        self.popup = Some(PopupState {
+            title: title.to_owned(),
+            lines,
+        });
+    }
 
-        if !self.filter_enabled {
-            trace!("No current filter, done.");
-            return Ok(());
+    fn analysis_source(&self) -> AnalysisSource {
+        AnalysisSource {
+            ifreq_sender: self.file.ifreq_sender.clone(),
+            ff_sender: self.file.ff_sender.clone(),
+            file_lines: self.content_state.view.get_stats().file_lines,
+        }
+    }
+
+    fn start_aggregate(&mut self) {
+        // Offer the current regex filter as a starting point, it likely has the capture group.
+        let initial = if self.filter_enabled && self.filter_spec.filter_type == FilterType::Regex {
+            self.filter_spec.filter_pattern.clone()
+        } else {
+            String::new()
         };
 
-        let match_no = self.filter_state.view.current();
-        let filter_line = self.filter_state.view.get_line(match_no);
+        self.prompt = Some(PromptState {
+            kind: PromptKind::Aggregate,
+            input: initial.into(),
+        });
+    }
 
-        let Some(filter_line) = filter_line else {
-            trace!("Match line not yet populated, cannot sync yet.");
-            return Ok(());
+    fn run_aggregate(&mut self, pattern: &str) {
+        let regex = match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                self.show_popup("Aggregate", vec![format!("Invalid regex: {}", e)]);
+                return;
+            }
         };
 
-        let line_no = filter_line.line_no;
-
-        self.content_state.view.set_current(line_no).await?;
+        let scope = if self.filter_enabled {
+            "current filter matches"
+        } else {
+            "all lines"
+        };
+        self.show_popup(
+            "Aggregate",
+            vec![format!("Computing over {}: {}", scope, pattern)],
+        );
+
+        spawn_aggregate(self.analysis_source(), regex, self.analysis_sender.clone());
+    }
+
+    fn run_clusters(&mut self) {
+        let scope = if self.filter_enabled {
+            "current filter matches"
+        } else {
+            "all lines"
+        };
+        self.show_popup(
+            "Message templates",
+            vec![format!("Clustering {}...", scope)],
+        );
+
+        spawn_clusters(self.analysis_source(), self.analysis_sender.clone());
+    }
+
+    fn run_groups(&mut self, spec: &str) {
+        let field = match LineField::parse(spec) {
+            Ok(field) => field,
+            Err(e) => {
+                self.show_popup(
+                    "Group",
+                    vec![
+                        format!("Invalid field: {}", e),
+                        "e.g. group request_id, or group req=(\\w+)".to_owned(),
+                    ],
+                );
+                return;
+            }
+        };
+
+        let scope = if self.filter_enabled {
+            "current filter matches"
+        } else {
+            "all lines"
+        };
+        self.show_popup("Group", vec![format!("Grouping {} by {}...", scope, spec)]);
+
+        spawn_groups(
+            self.analysis_source(),
+            field,
+            spec.to_owned(),
+            self.analysis_sender.clone(),
+        );
+    }
+
+    fn run_top_values(&mut self, spec: &str) {
+        let field = match LineField::parse(spec) {
+            Ok(field) => field,
+            Err(e) => {
+                self.show_popup(
+                    "Top values",
+                    vec![
+                        format!("Invalid field: {}", e),
+                        "e.g. top status, or top took (\\d+)ms".to_owned(),
+                    ],
+                );
+                return;
+            }
+        };
+
+        let scope = if self.filter_enabled {
+            "current filter matches"
+        } else {
+            "all lines"
+        };
+        self.show_popup(
+            "Top values",
+            vec![format!("Counting {} in {}...", spec, scope)],
+        );
+
+        spawn_top_values(
+            self.analysis_source(),
+            field,
+            spec.to_owned(),
+            self.analysis_sender.clone(),
+        );
+    }
+
+    fn toggle_columns(&mut self) {
+        if self.columns_wanted {
+            trace!("TUI: Leaving column mode");
+            self.columns_wanted = false;
+            self.content_state.columns = None;
+            self.filter_state.columns = None;
+        } else {
+            trace!("TUI: Entering column mode");
+            self.columns_wanted = true;
+            self.apply_columns();
+        }
+    }
+
+    fn apply_columns(&mut self) {
+        let Some(fields) = &self.json_fields else {
+            self.request_column_header();
+            return;
+        };
+
+        let mut columns = ColumnState::new(ColumnMode::Json {
+            fields: fields.clone(),
+        });
+        columns.set_header_fields(fields.clone());
+
+        self.content_state.columns = Some(columns.clone());
+        self.filter_state.columns = Some(columns);
+    }
+
+    fn start_edit_json_fields(&mut self) {
+        let initial = match &self.json_fields {
+            Some(fields) => fields.join(","),
+            None => DEFAULT_JSON_FIELDS.to_owned(),
+        };
+
+        self.prompt = Some(PromptState {
+            kind: PromptKind::JsonFields,
+            input: initial.into(),
+        });
+    }
+
+    fn set_json_fields(&mut self, spec: &str) {
+        let fields = ColumnMode::parse_json_fields(spec);
+        trace!("TUI: JSON fields: {:?}", fields);
+
+        self.content_state.columns = None;
+        self.filter_state.columns = None;
+        self.json_fields = if fields.is_empty() {
+            None
+        } else {
+            Some(fields)
+        };
+
+        self.columns_wanted = true;
+        self.apply_columns();
+    }
+
+    fn start_edit_transform(&mut self) {
+        self.prompt = Some(PromptState {
+            kind: PromptKind::Transform,
+            input: self.transform.clone().unwrap_or_default().into(),
+        });
+    }
+
+    fn set_transform(&mut self, expr: &str) {
+        let expr = expr.trim();
+        let transform = if expr.is_empty() {
+            None
+        } else {
+            match JsonTransform::parse(expr) {
+                Ok(transform) => Some(transform),
+                Err(e) => {
+                    self.show_popup("Transform", vec![format!("Invalid transform: {}", e)]);
+                    return;
+                }
+            }
+        };
+        trace!("TUI: Transform: {:?}", transform);
+
+        self.transform = transform.as_ref().map(|_| expr.to_owned());
+        self.content_state.transform = transform.clone();
+        self.filter_state.transform = transform;
+    }
+
+    fn start_chord(&mut self) {
+        self.chord = Some(String::new());
+        self.show_chord_hint("");
+    }
+
+    // Carry on with the chord, running its command once it is complete. Any other key stops it.
+    async fn handle_chord_key(&mut self, key: &event::KeyEvent) -> Result<()> {
+        let Some(mut keys) = self.chord.take() else {
+            return Ok(());
+        };
+        self.notice = None;
+        let KeyCode::Char(c) = key.code else {
+            return Ok(());
+        };
+        if !keys.is_empty() {
+            keys.push(' ');
+        }
+        keys.push(c);
+
+        match self.chords.lookup(&keys) {
+            // Commands needing more, such as a file name, are finished in the prompt.
+            ChordMatch::Command(command) if command.ends_with(' ') => {
+                self.prompt = Some(PromptState {
+                    kind: PromptKind::Command,
+                    input: Input::new(command),
+                });
+            }
+            ChordMatch::Command(command) => self.run_command(&command).await?,
+            ChordMatch::Prefix(_) => {
+                self.show_chord_hint(&keys);
+                self.chord = Some(keys);
+            }
+            ChordMatch::None => {
+                self.show_notice(format!("No chord {}", self.chord_name(&keys)));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Show the keys that can come next in the chord and what they do.
+    fn show_chord_hint(&mut self, keys: &str) {
+        let ChordMatch::Prefix(next) = self.chords.lookup(keys) else {
+            return;
+        };
+        let next = next
+            .iter()
+            .map(|(key, command)| match command {
+                Some(command) if command.ends_with(' ') => format!("{} {}…", key, command.trim()),
+                Some(command) => format!("{} {}", key, command),
+                None => format!("{} …", key),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.show_notice(format!("{}: {}", self.chord_name(keys), next));
+    }
+
+    fn chord_name(&self, keys: &str) -> String {
+        let leader = match self.leader {
+            ' ' => "<space>".to_owned(),
+            leader => leader.to_string(),
+        };
+
+        format!("{} {}", leader, keys).trim_end().to_owned()
+    }
+
+    fn start_command(&mut self) {
+        self.prompt = Some(PromptState {
+            kind: PromptKind::Command,
+            input: Input::default(),
+        });
+    }
+
+    async fn run_command(&mut self, command: &str) -> Result<()> {
+        let command = command.trim();
+        // Substitutions have their own delimiter, and may have spaces.
+        if command.len() > 1
+            && command.starts_with('s')
+            && !command[1..].starts_with(|c: char| c.is_alphanumeric() || c.is_whitespace())
+        {
+            self.add_substitution(command);
+            return Ok(());
+        }
+
+        let (range, command) = match split_line_range(command) {
+            Some((first, last, rest)) => (Some((first, last)), rest),
+            None => (None, command),
+        };
+
+        let (name, arg) = match command.split_once(' ') {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (command, ""),
+        };
+        trace!("TUI: Command: {} {}", name, arg);
+
+        match name {
+            "filter" => self.filter_lines(range, arg).await?,
+            _ if range.is_some() => self.show_popup(
+                "Command",
+                vec![format!("{} doesn't take a line range", name)],
+            ),
+            "e" | "edit" if !arg.is_empty() => self.switch_file(&expand_home(arg)).await?,
+            "e" | "edit" => self.show_popup(
+                "Command",
+                vec!["Give a file to open, e.g. e /var/log/syslog".to_owned()],
+            ),
+            "tabe" | "tabedit" if !arg.is_empty() => self.open_tab(&expand_home(arg)).await?,
+            "tabe" | "tabedit" => self.show_popup(
+                "Command",
+                vec!["Give a file to open in a new tab, e.g. tabe /var/log/syslog".to_owned()],
+            ),
+            "tabc" | "tabclose" => self.close_tab().await?,
+            "tabn" | "tabnext" => self.step_tab(true).await?,
+            "tabp" | "tabprevious" => self.step_tab(false).await?,
+            "s" => {
+                self.substitutions_enabled = !self.substitutions_enabled;
+                self.update_substitutions();
+            }
+            "pin" => match arg.parse::<usize>() {
+                Ok(lines) => self.pin_first_lines(lines),
+                Err(_) => self.show_popup(
+                    "Command",
+                    vec!["Give the number of lines to pin, e.g. pin 3".to_owned()],
+                ),
+            },
+            "unpin" => self.pin_first_lines(0),
+            "hl" | "highlight" if !arg.is_empty() => self.toggle_highlight(arg),
+            "hl" | "highlight" | "nohl" => self.set_highlights(Vec::new()),
+            "bookmarks" if !arg.is_empty() => self.export_bookmarks(&expand_home(arg)),
+            "bookmarks" => self.show_bookmarks(),
+            "notes" => self.show_annotations(),
+            "export" | "import" if arg.is_empty() => self.show_popup(
+                "Command",
+                vec![format!(
+                    "Give a file to {}, e.g. {} incident.yaml",
+                    name, name
+                )],
+            ),
+            "export" => self.export_investigation(&expand_home(arg)),
+            "import" => self.import_investigation(&expand_home(arg)).await?,
+            "history" => self.show_history_picker(),
+            "sel" | "selection" => self.run_selection(arg).await?,
+            "group" => self.run_groups(arg),
+            "top" => self.run_top_values(arg),
+            "timebar" => self.toggle_time_scrollbar(),
+            "ignore" => self.toggle_ignoring().await?,
+            "" => {}
+            _ => self.show_popup("Command", vec![format!("Unknown command: {}", name)]),
+        }
+
+        Ok(())
+    }
+
+    // Filter the lines in a range, or the whole file, optionally with a new pattern of the current
+    // filter type.
+    async fn filter_lines(&mut self, range: Option<(usize, usize)>, pattern: &str) -> Result<()> {
+        let filter_spec = if pattern.is_empty() {
+            self.filter_spec.clone()
+        } else {
+            let filter_type = match self.filter_spec.filter_type {
+                FilterType::Compound => FilterType::SimpleCaseInsensitive,
+                ref filter_type => filter_type.clone(),
+            };
+            match FilterSpec::new(filter_type, pattern) {
+                Ok(filter_spec) => filter_spec.with_context(self.filter_spec.context()),
+                Err(e) => {
+                    self.show_popup("Filter", vec![format!("Invalid filter: {}", e)]);
+                    return Ok(());
+                }
+            }
+        };
+        trace!("TUI: Filter lines {:?}: {:?}", range, filter_spec);
+
+        self.filter_from = FilterFrom::Start;
+        (self.filter_start, self.filter_end) = match range {
+            Some((first, last)) => (first, Some(last + 1)),
+            None => (0, None),
+        };
+        self.filter_enabled = true;
+        self.set_filter_spec(filter_spec).await
+    }
+
+    // Start selecting lines from the current line, or filter the lines selected.
+    async fn select_lines(&mut self) -> Result<()> {
+        let line_no = self.content_state.view.current();
+        match self.select_anchor.take() {
+            None => {
+                self.select_anchor = Some(line_no);
+                self.show_notice(format!(
+                    "Selecting from line {}, v again to filter to here",
+                    line_no
+                ));
+                Ok(())
+            }
+            Some(anchor) => {
+                self.filter_lines(Some((anchor.min(line_no), anchor.max(line_no))), "")
+                    .await
+            }
+        }
+    }
+
+    fn toggle_selected(&mut self) {
+        let Some((line_no, line)) = self.current_raw_line() else {
+            return;
+        };
+
+        if self.selection.remove(&line_no).is_none() {
+            self.selection.insert(line_no, line);
+        }
+        self.update_selection();
+        self.show_notice(format!(
+            "{} lines selected, :sel for actions",
+            self.selection.len()
+        ));
+    }
+
+    fn update_selection(&mut self) {
+        let selected = self.selection.keys().copied().collect::<BTreeSet<_>>();
+        self.content_state.selected = selected.clone();
+        self.filter_state.selected = selected;
+    }
+
+    // Act on the selected lines: copy, export, pin or filter for them, or clear the selection.
+    async fn run_selection(&mut self, action: &str) -> Result<()> {
+        let (action, arg) = match action.split_once(' ') {
+            Some((action, arg)) => (action, arg.trim()),
+            None => (action, ""),
+        };
+        if self.selection.is_empty() && action != "clear" {
+            self.show_popup(
+                "Selection",
+                vec!["No lines selected, * selects the current line".to_owned()],
+            );
+            return Ok(());
+        }
+
+        let count = self.selection.len();
+        match action {
+            "copy" => {
+                let text = self
+                    .selection
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let mode = self.config.config.clipboard.unwrap_or_default();
+                match clipboard::copy(&text, mode) {
+                    Ok(method) => self.show_notice(format!("Copied {} lines ({})", count, method)),
+                    Err(e) => self.show_popup("Copy", vec![format!("Failed to copy: {}", e)]),
+                }
+            }
+            "export" if !arg.is_empty() => {
+                let path = expand_home(arg);
+                let text = self
+                    .selection
+                    .values()
+                    .map(|line| format!("{}\n", line))
+                    .collect::<String>();
+                match std::fs::write(&path, text) {
+                    Ok(()) => self.show_notice(format!("Exported {} lines to {}", count, path)),
+                    Err(e) => {
+                        self.show_popup("Selection", vec![format!("Failed to export: {}", e)])
+                    }
+                }
+            }
+            "pin" => {
+                self.content_state.pinned.extend(self.selection.clone());
+                self.show_notice(format!("Pinned {} lines", count));
+            }
+            "filter" => {
+                // One alternative per template, so lines alike give one.
+                let mut patterns: Vec<String> = Vec::new();
+                for line in self.selection.values() {
+                    let pattern = cluster::line_filter_regex(line);
+                    if !patterns.contains(&pattern) {
+                        patterns.push(pattern);
+                    }
+                }
+                let pattern = patterns
+                    .iter()
+                    .map(|p| format!("(?:{})", p))
+                    .collect::<Vec<_>>()
+                    .join("|");
+                self.filter_enabled = true;
+                self.filter_projection = None;
+                self.set_filter_spec(FilterSpec::new(FilterType::Regex, &pattern)?)
+                    .await?;
+            }
+            "clear" => {
+                self.selection.clear();
+                self.update_selection();
+            }
+            _ => self.show_popup(
+                "Selection",
+                vec![
+                    format!("{} lines selected. Actions:", count),
+                    "  sel copy           copy them".to_owned(),
+                    "  sel export <file>  write them to a file".to_owned(),
+                    "  sel pin            pin them".to_owned(),
+                    "  sel filter         filter for lines like them".to_owned(),
+                    "  sel clear          clear the selection".to_owned(),
+                ],
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn start_profile(&mut self) {
+        if self.config.config.profiles.is_empty() {
+            self.show_popup(
+                "Profile",
+                vec!["There are no profiles in the config.".to_owned()],
+            );
+            return;
+        }
+
+        self.prompt = Some(PromptState {
+            kind: PromptKind::Profile,
+            input: Input::default(),
+        });
+    }
+
+    // Use a profile from the config, built from it.
+    async fn use_profile(&mut self, name: &str) -> Result<()> {
+        let profile = match self.config.config.profiles.get(name) {
+            Some(profile) => profile.compiled(),
+            None => {
+                let mut lines = vec![];
+                if !name.is_empty() {
+                    lines.push(format!("Unknown profile: {}", name));
+                    lines.push(String::new());
+                }
+                lines.push("Profiles:".to_owned());
+                lines.extend(
+                    self.config
+                        .config
+                        .profiles
+                        .keys()
+                        .map(|n| format!("  {}", n)),
+                );
+                self.show_popup("Profile", lines);
+                return Ok(());
+            }
+        };
+        let profile = match profile {
+            Ok(profile) => profile,
+            Err(e) => {
+                self.show_popup("Profile", vec![format!("Bad profile {}: {}", name, e)]);
+                return Ok(());
+            }
+        };
+        debug!("TUI: Using profile: {}", name);
+        self.profile = Some(name.to_owned());
+
+        self.apply_profile(profile).await
+    }
+
+    // Apply a profile's filter, colouring and tailing.
+    async fn apply_profile(&mut self, profile: Profile) -> Result<()> {
+        if let Some(colouring) = profile.colouring {
+            self.content_state.colouring = colouring.for_pane(ColouringScope::Content);
+            self.filter_state.colouring = colouring.for_pane(ColouringScope::Filter);
+            self.colouring = colouring;
+        }
+
+        match profile.filter_spec {
+            Some(filter_spec) => {
+                self.filter_enabled = true;
+                self.set_filter_spec(filter_spec).await?;
+            }
+            None => self.set_filter_spec(self.filter_spec.clone()).await?,
+        }
+
+        if let Some(tail) = profile.tail {
+            self.content_tail = tail;
+            self.content_state.view.set_tail(tail).await?;
+            self.filter_tail = tail;
+            self.filter_state.view.set_tail(tail).await?;
+        }
+
+        Ok(())
+    }
+
+    fn add_substitution(&mut self, expr: &str) {
+        match Substitution::parse(expr) {
+            Ok(substitution) => {
+                trace!("TUI: Adding substitution: {}", substitution.expr());
+                self.substitutions.push(substitution);
+                self.substitutions_enabled = true;
+                self.update_substitutions();
+            }
+            Err(e) => self.show_popup("Substitution", vec![e.to_string()]),
+        }
+    }
+
+    fn update_substitutions(&mut self) {
+        let substitutions = if self.substitutions_enabled {
+            self.substitutions.clone()
+        } else {
+            Vec::new()
+        };
+        self.content_state.substitutions = substitutions.clone();
+        self.filter_state.substitutions = substitutions;
+    }
+
+    // Highlight the text in the next free colour, or stop highlighting it if it already is.
+    fn toggle_highlight(&mut self, text: &str) {
+        let mut highlights = self.highlights.clone();
+        if let Some(i) = highlights.iter().position(|h| h.text == text) {
+            highlights.remove(i);
+        } else {
+            let Some(colour) = HIGHLIGHT_PALETTE
+                .iter()
+                .find(|c| !highlights.iter().any(|h| h.colour == **c))
+            else {
+                self.show_popup(
+                    "Highlight",
+                    vec![format!(
+                        "There are only {} highlight colours, remove one with hl <text> or all with nohl.",
+                        HIGHLIGHT_PALETTE.len()
+                    )],
+                );
+                return;
+            };
+            let regex = match Regex::new(&format!("(?i){}", regex::escape(text))) {
+                Ok(regex) => regex,
+                Err(e) => {
+                    self.show_popup("Highlight", vec![e.to_string()]);
+                    return;
+                }
+            };
+            highlights.push(Highlight {
+                text: text.to_owned(),
+                regex,
+                colour: *colour,
+            });
+        }
+
+        self.set_highlights(highlights);
+    }
+
+    fn set_highlights(&mut self, highlights: Vec<Highlight>) {
+        self.content_state.highlights = highlights.clone();
+        self.filter_state.highlights = highlights.clone();
+        self.highlights = highlights;
+    }
+
+    // The highlights in their colours, shown under the content pane.
+    fn highlight_legend(&self) -> Line<'static> {
+        let mut spans = Vec::new();
+        for highlight in &self.highlights {
+            spans.push(Span::styled(
+                format!(" {} ", highlight.text),
+                highlight.style(self.content_state.monochrome),
+            ));
+            spans.push(Span::raw(" "));
+        }
+
+        Line::from(spans).right_aligned()
+    }
+
+    fn start_goto_time(&mut self) {
+        self.prompt = Some(PromptState {
+            kind: PromptKind::GotoTime,
+            input: Input::default(),
+        });
+    }
+
+    fn find_time(&mut self, arg: &str) {
+        let time = match parse_time_arg(arg, chrono::Local::now()) {
+            Ok(time) => time,
+            Err(e) => {
+                self.show_popup("Go to time", vec![format!("Invalid time: {}", e)]);
+                return;
+            }
+        };
+        trace!("TUI: Finding time: {}", time);
+        self.history.add(HistoryEntry {
+            kind: HistoryKind::Time,
+            pattern: arg.trim().to_owned(),
+            filter_type: None,
+        });
+
+        spawn_find_time(
+            self.file.ifreq_sender.clone(),
+            time,
+            self.analysis_sender.clone(),
+        );
+    }
+
+    fn current_raw_line(&self) -> Option<(usize, String)> {
+        if self.current_window {
+            let current = self.content_state.view.current();
+            self.content_state
+                .view
+                .get_line(current)
+                .map(|l| (current, l))
+        } else {
+            let current = self.filter_state.view.current();
+            self.filter_state
+                .view
+                .get_line(current)
+                .map(|l| (l.line_no, l.line))
+        }
+    }
+
+    // The current line, or None if it was cut short and is being loaded in full for the purpose.
+    fn current_full_line(&self, purpose: LinesPurpose) -> Option<(usize, String)> {
+        let (line_no, line) = self.current_raw_line()?;
+        if !is_capped(&line) {
+            return Some((line_no, line));
+        }
+
+        trace!("TUI: Loading line {} in full", line_no);
+        spawn_fetch_full_line(
+            self.file.ifreq_sender.clone(),
+            line_no,
+            purpose,
+            self.analysis_sender.clone(),
+        );
+
+        None
+    }
+
+    fn show_line_detail(&mut self) {
+        if let Some((line_no, line)) = self.current_full_line(LinesPurpose::Detail) {
+            self.show_detail(line_no, line);
+        }
+    }
+
+    fn show_detail(&mut self, line_no: usize, line: String) {
+        let mut lines = vec![line.clone()];
+        if let Some(note) = self.annotations.as_ref().and_then(|a| a.note(line_no)) {
+            lines.push(String::new());
+            lines.push(format!("Note: {}", note));
+        }
+        if let Some(pretty) = pretty_json(&line) {
+            lines.push(String::new());
+            lines.extend(pretty);
+        }
+
+        self.show_popup(&format!("Line {}", line_no), lines);
+    }
+
+    fn yank_line(&mut self) {
+        if let Some((line_no, line)) = self.current_full_line(LinesPurpose::Yank) {
+            self.copy_line(line_no, &line);
+        }
+    }
+
+    fn copy_line(&mut self, line_no: usize, line: &str) {
+        let mode = self.config.config.clipboard.unwrap_or_default();
+        match clipboard::copy(line, mode) {
+            Ok(method) => self.show_notice(format!("Copied line {} ({})", line_no, method)),
+            Err(e) => self.show_popup("Copy", vec![format!("Failed to copy: {}", e)]),
+        }
+    }
+
+    fn show_notice(&mut self, notice: String) {
+        self.notice = Some((notice, Instant::now()));
+    }
+
+    // Clear the notice once it has been shown for long enough, returning whether it was cleared. A
+    // chord not carried on whilst its hint was shown is dropped.
+    fn expire_notice(&mut self) -> bool {
+        if self
+            .notice
+            .as_ref()
+            .is_some_and(|(_, shown)| shown.elapsed() >= Duration::from_secs(NOTICE_SECS))
+        {
+            self.notice = None;
+            self.chord = None;
+            return true;
+        }
+
+        false
+    }
+
+    fn request_column_header(&mut self) {
+        // Wait until there is content to take the header from.
+        if self.column_header_requested || self.content_state.view.get_stats().file_lines == 0 {
+            return;
+        }
+
+        self.column_header_requested = true;
+        spawn_fetch_lines(
+            self.file.ifreq_sender.clone(),
+            vec![0],
+            LinesPurpose::ColumnHeader,
+            self.analysis_sender.clone(),
+        );
+    }
+
+    fn set_column_header(&mut self, header: &str) {
+        self.column_header_requested = false;
+        if !self.columns_wanted {
+            return;
+        }
+
+        let Some(delimiter) = self.column_delimiter.or_else(|| detect_delimiter(header)) else {
+            self.columns_wanted = false;
+            self.show_popup(
+                "Columns",
+                vec!["No column delimiter found in the first line.".to_owned()],
+            );
+            return;
+        };
+
+        let mut columns = ColumnState::new(ColumnMode::Delimited { delimiter });
+        columns.set_header(header);
+        trace!("TUI: Column mode: {}", columns.mode.describe());
+
+        self.content_state.columns = Some(columns.clone());
+        self.filter_state.columns = Some(columns);
+    }
+
+    // Pin the current line, or unpin it if it is pinned.
+    fn toggle_pin(&mut self) {
+        let Some((line_no, line)) = self.current_raw_line() else {
+            return;
+        };
+
+        let pinned = &mut self.content_state.pinned;
+        if pinned.remove(&line_no).is_none() {
+            trace!("TUI: Pinning line {}", line_no);
+            pinned.insert(line_no, line);
+        }
+    }
+
+    // Pin the first lines of the file in place of any pinned, none to unpin them all.
+    fn pin_first_lines(&mut self, lines: usize) {
+        self.pin_first = lines;
+        self.reset_pinned();
+        self.request_pinned();
+    }
+
+    fn reset_pinned(&mut self) {
+        self.content_state.pinned.clear();
+        self.pins_fetched = 0;
+        self.pins_requested = false;
+    }
+
+    fn request_pinned(&mut self) {
+        // Lines beyond the end are requested as more content arrives.
+        let file_lines = self.content_state.view.get_stats().file_lines;
+        let line_nos = (self.pins_fetched..self.pin_first.min(file_lines)).collect::<Vec<_>>();
+        if self.pins_requested || line_nos.is_empty() {
+            return;
+        }
+
+        self.pins_requested = true;
+        spawn_fetch_lines(
+            self.file.ifreq_sender.clone(),
+            line_nos,
+            LinesPurpose::Pinned,
+            self.analysis_sender.clone(),
+        );
+    }
+
+    fn set_pinned(&mut self, lines: Vec<(usize, String)>) {
+        self.pins_requested = false;
+        for (line_no, line) in lines {
+            if line_no < self.pin_first {
+                self.pins_fetched = self.pins_fetched.max(line_no + 1);
+                self.content_state.pinned.insert(line_no, line);
+            }
+        }
+    }
+
+    fn toggle_time_display(&mut self) {
+        let time_display = self.content_state.time_display.next();
+        trace!("TUI: Showing timestamps as: {:?}", time_display);
+
+        self.content_state.time_display = time_display;
+        self.filter_state.time_display = time_display;
+    }
+
+    fn toggle_show_whitespace(&mut self) {
+        let show_whitespace = !self.content_state.show_whitespace;
+        trace!("TUI: Show whitespace: {}", show_whitespace);
+
+        self.content_state.show_whitespace = show_whitespace;
+        self.filter_state.show_whitespace = show_whitespace;
+    }
+
+    // The lines before those read were indexed, so everything known by line number moves down past
+    // them.
+    async fn backfilled(&mut self, lines: usize) -> Result<()> {
+        debug!("{}: Indexed {} earlier lines", self.path, lines);
+        self.content_state.view.prepended(lines).await?;
+
+        for marks in [&mut self.content_state.marks, &mut self.filter_state.marks] {
+            *marks = std::mem::take(marks)
+                .into_iter()
+                .map(|(line_no, mark)| (line_no + lines, mark))
+                .collect();
+        }
+        self.bookmarks = std::mem::take(&mut self.bookmarks)
+            .into_iter()
+            .map(|(line_no, bookmark)| (line_no + lines, bookmark))
+            .collect();
+        self.update_bookmarks();
+        for jump in &mut self.jumps {
+            *jump += lines;
+        }
+
+        // Pinned lines, gaps, rare lines and notes are found again from the start.
+        self.reset_pinned();
+        self.reset_gaps();
+        self.reset_rare();
+        self.load_annotations(&self.file.path.clone());
+
+        self.damage_panes();
+        self.show_notice(format!("Indexed {} earlier lines", lines));
+
+        Ok(())
+    }
+
+    fn reset_marks(&mut self) {
+        self.content_state.marks.clear();
+        self.filter_state.marks.clear();
+        self.bookmarks.clear();
+        self.update_bookmarks();
+        self.jumps.clear();
+        self.jump_index = 0;
+    }
+
+    // Ask for a note to bookmark the current line with, editing it if already bookmarked.
+    fn start_bookmark(&mut self) {
+        let Some((line_no, line)) = self.current_raw_line() else {
+            return;
+        };
+
+        let note = self
+            .bookmarks
+            .get(&line_no)
+            .map(|bookmark| bookmark.note.clone())
+            .unwrap_or_default();
+        self.bookmark_line = Some((line_no, line));
+        self.prompt = Some(PromptState {
+            kind: PromptKind::Bookmark,
+            input: note.into(),
+        });
+    }
+
+    fn save_bookmark(&mut self, note: &str) {
+        let Some((line_no, line)) = self.bookmark_line.take() else {
+            return;
+        };
+
+        if note.is_empty() && self.bookmarks.contains_key(&line_no) {
+            trace!("TUI: Removing bookmark at line {}", line_no);
+            self.bookmarks.remove(&line_no);
+        } else {
+            trace!("TUI: Bookmarking line {}: {}", line_no, note);
+            let note = note.to_owned();
+            self.bookmarks.insert(line_no, Bookmark { note, line });
+        }
+        self.update_bookmarks();
+    }
+
+    fn update_bookmarks(&mut self) {
+        let notes = self
+            .bookmarks
+            .iter()
+            .map(|(line_no, bookmark)| (*line_no, bookmark.note.clone()))
+            .collect::<BTreeMap<_, _>>();
+        self.content_state.bookmarks = notes.clone();
+        self.filter_state.bookmarks = notes;
+    }
+
+    // List the bookmarks over the filtered pane, moving to each as it is selected.
+    fn show_history_picker(&mut self) {
+        self.history_picker = Some(HistoryPickerState {
+            input: Input::default(),
+            matches: Vec::new(),
+            selected: 0,
+        });
+        self.update_history_picker();
+    }
+
+    // Match the history to what has been typed in the picker.
+    fn update_history_picker(&mut self) {
+        let Some(picker) = &mut self.history_picker else {
+            return;
+        };
+
+        picker.matches = self
+            .history
+            .pick(picker.input.value())
+            .into_iter()
+            .map(|entry| {
+                let label = match (entry.kind, &entry.filter_type) {
+                    (HistoryKind::Filter, Some(filter_type)) => {
+                        let rendered = FilterSpec::new(filter_type.clone(), &entry.pattern)
+                            .map(|spec| spec.render())
+                            .unwrap_or_else(|_| entry.pattern.clone());
+                        format!("Filter {}", rendered)
+                    }
+                    (HistoryKind::Filter, None) => format!("Filter \"{}\"", entry.pattern),
+                    (HistoryKind::Time, _) => format!("Time {}", entry.pattern),
+                };
+                (entry.clone(), label)
+            })
+            .collect();
+        picker.selected = 0;
+    }
+
+    fn handle_history_picker_key(&mut self, key: &event::KeyEvent) {
+        let Some(picker) = &mut self.history_picker else {
+            return;
+        };
+
+        let last = picker.matches.len().saturating_sub(1);
+        match (key.code, key.modifiers) {
+            (KeyCode::Esc, _) => self.history_picker = None,
+            (KeyCode::Up, _) | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                picker.selected = picker.selected.saturating_sub(1)
+            }
+            (KeyCode::Down, _) | (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                picker.selected = (picker.selected + 1).min(last)
+            }
+            (KeyCode::Enter, _) => {
+                let entry = picker.matches.get(picker.selected).map(|(e, _)| e.clone());
+                self.history_picker = None;
+                if let Some(entry) = entry {
+                    self.use_history_entry(entry);
+                }
+            }
+            _ => {
+                picker.input.handle_event(&Event::Key(*key));
+                self.update_history_picker();
+            }
+        }
+    }
+
+    // Put a filter or time from the history in its dialog, to use as it is or change first.
+    fn use_history_entry(&mut self, entry: HistoryEntry) {
+        match entry.kind {
+            HistoryKind::Filter => {
+                self.start_edit_filter();
+                if let Some(filter_edit) = &mut self.filter_edit {
+                    filter_edit.input = entry.pattern.into();
+                    if let Some(filter_type) = entry.filter_type {
+                        filter_edit.filter_type = filter_type;
+                    }
+                }
+            }
+            HistoryKind::Time => {
+                self.prompt = Some(PromptState {
+                    kind: PromptKind::GotoTime,
+                    input: entry.pattern.into(),
+                });
+            }
+        }
+    }
+
+    fn show_bookmarks(&mut self) {
+        if self.bookmarks.is_empty() {
+            self.show_popup(
+                "Bookmarks",
+                vec!["No bookmarks, b bookmarks the current line.".to_owned()],
+            );
+            return;
+        }
+
+        let rows = self
+            .bookmarks
+            .iter()
+            .map(|(line_no, bookmark)| ResultRow {
+                line_no: Some(*line_no),
+                cells: vec![bookmark.note.clone(), bookmark.line.clone()],
+                filter: None,
+            })
+            .collect();
+        // Moving through the table moves the content pane, so this is where it jumped from.
+        self.record_jump();
+        self.result_table = Some(ResultTableView::new(ResultTable {
+            title: "Bookmarks (:bookmarks <file> exports them)".to_owned(),
+            header: vec!["Note".to_owned(), "Text".to_owned()],
+            rows,
+        }));
+    }
+
+    // Notes are kept beside files, so there are none for piped input and other sources.
+    fn load_annotations(&mut self, path: &str) {
+        self.annotations = match &self.file_list {
+            Some(_) => match Annotations::load(path) {
+                Ok(annotations) => Some(annotations),
+                Err(e) => {
+                    self.show_popup("Notes", vec![e.to_string()]);
+                    None
+                }
+            },
+            None => None,
+        };
+        self.annotation_line = None;
+        self.pending_notes.clear();
+        self.notes_requested = false;
+        self.notes_looked_for = None;
+        self.update_annotations();
+    }
+
+    fn request_annotations(&mut self) {
+        let Some(annotations) = &self.annotations else {
+            return;
+        };
+        // Look again once more of the file has been read.
+        let file_lines = self.content_state.view.get_stats().file_lines;
+        if self.notes_requested || self.notes_looked_for == Some(file_lines) {
+            return;
+        }
+        let offsets = annotations.unresolved();
+        if offsets.is_empty() {
+            return;
+        }
+
+        self.notes_requested = true;
+        self.notes_looked_for = Some(file_lines);
+        spawn_lines_at(
+            self.file.ifreq_sender.clone(),
+            offsets,
+            self.analysis_sender.clone(),
+        );
+    }
+
+    fn update_annotations(&mut self) {
+        let annotated = self
+            .annotations
+            .as_ref()
+            .map(|a| a.line_nos())
+            .unwrap_or_default();
+        self.content_state.annotated = annotated.clone();
+        self.filter_state.annotated = annotated;
+    }
+
+    fn start_annotation(&mut self) {
+        let Some(annotations) = &self.annotations else {
+            self.show_popup(
+                "Notes",
+                vec!["Notes are kept beside files, so can't be added here.".to_owned()],
+            );
+            return;
+        };
+        let Some((line_no, line)) = self.current_raw_line() else {
+            return;
+        };
+
+        let note = annotations.note(line_no).unwrap_or_default().to_owned();
+        self.annotation_line = Some((line_no, line));
+        self.prompt = Some(PromptState {
+            kind: PromptKind::Annotation,
+            input: note.into(),
+        });
+    }
+
+    fn save_annotation(&mut self, note: &str) {
+        let Some((line_no, line)) = self.annotation_line.take() else {
+            return;
+        };
+        let Some(annotations) = &mut self.annotations else {
+            return;
+        };
+
+        // A line already noted has its offset, others have to ask the file for it.
+        match annotations.offset(line_no) {
+            Some(offset) => {
+                annotations.set(offset, line_no, &line, note);
+                self.save_annotations();
+            }
+            None if note.is_empty() => {}
+            None => {
+                trace!("TUI: Finding offset to note line {}", line_no);
+                self.pending_notes.insert(line_no, (line, note.to_owned()));
+                spawn_line_offset(
+                    self.file.ifreq_sender.clone(),
+                    line_no,
+                    self.analysis_sender.clone(),
+                );
+            }
+        }
+    }
+
+    fn save_annotations(&mut self) {
+        if let Some(annotations) = &self.annotations {
+            if let Err(e) = annotations.save() {
+                self.show_popup(
+                    "Notes",
+                    vec![format!(
+                        "Failed to save {}: {}",
+                        annotations.path().display(),
+                        e
+                    )],
+                );
+            }
+        }
+        self.update_annotations();
+    }
+
+    // List the notes over the filtered pane, moving to each as it is selected.
+    fn show_annotations(&mut self) {
+        let Some(annotations) = &self.annotations else {
+            self.show_popup(
+                "Notes",
+                vec!["Notes are kept beside files, so there are none here.".to_owned()],
+            );
+            return;
+        };
+
+        let rows = annotations
+            .placed()
+            .map(|annotation| ResultRow {
+                line_no: annotation.line_no,
+                cells: vec![annotation.note.clone(), annotation.line.clone()],
+                filter: None,
+            })
+            .collect();
+        let title = format!("Notes in {}", annotations.path().display());
+        self.record_jump();
+        self.result_table = Some(ResultTableView::new(ResultTable {
+            title,
+            header: vec!["Note".to_owned(), "Text".to_owned()],
+            rows,
+        }));
+    }
+
+    // Write the bookmarks as a timeline, in file order with each note above its line.
+    fn export_bookmarks(&mut self, path: &str) {
+        let mut timeline = format!("Bookmarks in {}\n", self.path);
+        for (line_no, bookmark) in &self.bookmarks {
+            timeline.push_str(&format!(
+                "\n{}: {}\n    {}\n",
+                line_no, bookmark.note, bookmark.line
+            ));
+        }
+
+        match std::fs::write(path, timeline) {
+            Ok(()) => self.show_notice(format!(
+                "Exported {} bookmarks to {}",
+                self.bookmarks.len(),
+                path
+            )),
+            Err(e) => self.show_popup("Bookmarks", vec![format!("Failed to export: {}", e)]),
+        }
+    }
+
+    // Write where the investigation has got to, to hand over to someone looking at the same log.
+    fn export_investigation(&mut self, path: &str) {
+        let investigation = Investigation {
+            log: self.path.clone(),
+            filter: self.filter_enabled.then(|| self.filter_spec.clone()),
+            context: Some(self.filter_spec.context()).filter(|c| *c != Context::default()),
+            highlights: self.highlights.iter().map(|h| h.text.clone()).collect(),
+            bookmarks: self
+                .bookmarks
+                .iter()
+                .map(|(line_no, bookmark)| SavedBookmark {
+                    line_no: *line_no,
+                    note: bookmark.note.clone(),
+                    line: bookmark.line.clone(),
+                })
+                .collect(),
+            notes: self
+                .annotations
+                .as_ref()
+                .map(|a| a.notes().to_vec())
+                .unwrap_or_default(),
+            line: self.content_state.view.current(),
+        };
+
+        match investigation.save(path) {
+            Ok(()) => self.show_notice(format!("Exported investigation to {}", path)),
+            Err(e) => self.show_popup("Export", vec![e.to_string()]),
+        }
+    }
+
+    // Pick up an exported investigation, replacing the filter, highlights and bookmarks and adding
+    // its notes to this file's.
+    async fn import_investigation(&mut self, path: &str) -> Result<()> {
+        let investigation = match Investigation::load(path) {
+            Ok(investigation) => investigation,
+            Err(e) => {
+                self.show_popup("Import", vec![e.to_string()]);
+                return Ok(());
+            }
+        };
+        debug!(
+            "{}: Importing investigation of {} from {}",
+            self.path, investigation.log, path
+        );
+
+        let filter_spec = match investigation.filter_spec() {
+            Some(Ok(filter_spec)) => Some(filter_spec),
+            Some(Err(e)) => {
+                self.show_popup("Import", vec![format!("Invalid filter: {}", e)]);
+                return Ok(());
+            }
+            None => None,
+        };
+        self.filter_enabled = filter_spec.is_some();
+        self.filter_projection = None;
+        self.filter_from = FilterFrom::Start;
+        self.filter_start = 0;
+        self.filter_end = None;
+        self.set_filter_spec(filter_spec.unwrap_or_else(|| self.filter_spec.clone()))
+            .await?;
+
+        self.set_highlights(Vec::new());
+        for text in &investigation.highlights {
+            self.toggle_highlight(text);
+        }
+
+        self.bookmarks = investigation
+            .bookmarks
+            .iter()
+            .map(|b| {
+                (
+                    b.line_no,
+                    Bookmark {
+                        note: b.note.clone(),
+                        line: b.line.clone(),
+                    },
+                )
+            })
+            .collect();
+        self.update_bookmarks();
+
+        let mut notes = 0;
+        if let Some(annotations) = &mut self.annotations {
+            notes = annotations.merge(&investigation.notes);
+            if notes > 0 {
+                self.save_annotations();
+                self.notes_looked_for = None;
+                self.request_annotations();
+            }
+        }
+
+        self.record_jump();
+        self.goto_content_line(investigation.line).await?;
+
+        let mut notice = format!(
+            "Imported investigation of {}: {} bookmarks, {} new notes",
+            investigation.log,
+            self.bookmarks.len(),
+            notes
+        );
+        if self.annotations.is_none() && !investigation.notes.is_empty() {
+            notice.push_str(", notes can't be kept here");
+        }
+        self.show_notice(notice);
+
+        Ok(())
+    }
+
+    fn reset_gaps(&mut self) {
+        self.gap_generation += 1;
+        self.gap_detector = self.gap_threshold.map(GapDetector::new);
+        self.gaps_scanned_to = 0;
+        self.content_state.gaps.clear();
+        self.filter_state.gaps.clear();
+    }
+
+    // Scan new lines for time gaps, unless a scan is already running.
+    fn maybe_scan_gaps(&mut self) {
+        let file_lines = self.content_state.view.get_stats().file_lines;
+        if file_lines <= self.gaps_scanned_to {
+            return;
+        }
+
+        let Some(detector) = self.gap_detector.take() else {
+            return;
+        };
+
+        let end = file_lines.min(self.gaps_scanned_to + GAP_SCAN_LINES);
+        trace!(
+            "TUI: Scanning for time gaps: {}..{}",
+            self.gaps_scanned_to,
+            end
+        );
+        spawn_gap_scan(
+            self.file.ifreq_sender.clone(),
+            self.gaps_scanned_to..end,
+            self.gap_generation,
+            self.content_state.timestamps.clone(),
+            detector,
+            self.analysis_sender.clone(),
+        );
+    }
+
+    // Add a divider before the next line to arrive.
+    fn mark_now(&mut self) {
+        let line_no = self.content_state.view.get_stats().file_lines;
+        let mark = format!("MARK {}", chrono::Local::now().format("%H:%M:%S"));
+        trace!("TUI: {} at line {}", mark, line_no);
+
+        self.content_state.marks.insert(line_no, mark.clone());
+        self.filter_state.marks.insert(line_no, mark);
+    }
+
+    // Move the content pane to the next/previous time gap, from the current line of the current
+    // pane.
+    async fn jump_to_gap(&mut self, forward: bool) -> Result<()> {
+        let from = if self.current_window {
+            self.content_state.view.current()
+        } else {
+            let current = self.filter_state.view.current();
+            match self.filter_state.view.get_line(current) {
+                Some(filter_line) => filter_line.line_no,
+                None => return Ok(()),
+            }
+        };
+
+        let gaps = &self.content_state.gaps;
+        let target = if forward {
+            gaps.range(from + 1..).next()
+        } else {
+            gaps.range(..from).next_back()
+        };
+
+        let Some((&line_no, _)) = target else {
+            trace!(
+                "TUI: No time gap {} line {}",
+                if forward { "after" } else { "before" },
+                from
+            );
+            return Ok(());
+        };
+
+        self.record_jump();
+        self.current_window = true;
+        self.goto_content_line(line_no).await
+    }
+
+    // Remember the current content line before jumping away from it, dropping any jumps that were
+    // moved back over.
+    fn record_jump(&mut self) {
+        let line_no = self.content_state.view.current();
+        self.jumps.truncate(self.jump_index);
+        if self.jumps.last() != Some(&line_no) {
+            self.jumps.push(line_no);
+        }
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.jump_index = self.jumps.len();
+    }
+
+    // Go back to where the content pane last jumped from.
+    async fn jump_back(&mut self) -> Result<()> {
+        // Keep where this started, to come forward to again.
+        if self.jump_index == self.jumps.len() {
+            self.record_jump();
+            self.jump_index = self.jumps.len().saturating_sub(1);
+        }
+        if self.jump_index == 0 {
+            return Ok(());
+        }
+
+        self.jump_index -= 1;
+        self.goto_jump().await
+    }
+
+    async fn jump_forward(&mut self) -> Result<()> {
+        if self.jump_index + 1 >= self.jumps.len() {
+            return Ok(());
+        }
+
+        self.jump_index += 1;
+        self.goto_jump().await
+    }
+
+    async fn goto_jump(&mut self) -> Result<()> {
+        let line_no = self.jumps[self.jump_index];
+        trace!("TUI: Jump {} to line {}", self.jump_index, line_no);
+        self.current_window = true;
+        self.goto_content_line(line_no).await
+    }
+
+    fn handle_gap_scan(&mut self, scan: GapScan) {
+        if scan.generation != self.gap_generation {
+            trace!("TUI: Discarding stale time gap scan");
+            return;
+        }
+
+        for (line_no, gap) in scan.gaps {
+            self.content_state.gaps.insert(line_no, gap);
+            self.filter_state.gaps.insert(line_no, gap);
+        }
+        self.gaps_scanned_to = scan.end;
+        self.gap_detector = Some(scan.detector);
+
+        self.maybe_scan_gaps();
+    }
+
+    fn toggle_rare(&mut self) {
+        if self.rare_lines.is_some() {
+            trace!("TUI: Rare message highlighting off");
+            self.rare_lines = None;
+            self.rare_clusterer = None;
+            self.rare_generation += 1;
+        } else {
+            trace!("TUI: Rare message highlighting on");
+            self.reset_rare_lines();
+            self.maybe_scan_rare();
+        }
+    }
+
+    fn reset_rare(&mut self) {
+        if self.rare_lines.is_some() {
+            self.reset_rare_lines();
+        }
+    }
+
+    fn reset_rare_lines(&mut self) {
+        self.rare_generation += 1;
+        self.rare_lines = Some(RareLines::new(self.rare_threshold));
+        self.rare_clusterer = Some(Clusterer::new());
+    }
+
+    // Cluster new lines for rare message highlighting, unless off or a scan is already running.
+    fn maybe_scan_rare(&mut self) {
+        let Some(rare_lines) = &self.rare_lines else {
+            return;
+        };
+
+        let file_lines = self.content_state.view.get_stats().file_lines;
+        let start = rare_lines.scanned_to();
+        if file_lines <= start {
+            return;
+        }
+
+        let Some(clusterer) = self.rare_clusterer.take() else {
+            return;
+        };
+
+        let end = file_lines.min(start + GAP_SCAN_LINES);
+        trace!("TUI: Clustering for rare messages: {}..{}", start, end);
+        spawn_cluster_scan(
+            self.file.ifreq_sender.clone(),
+            start..end,
+            self.rare_generation,
+            clusterer,
+            self.analysis_sender.clone(),
+        );
+    }
+
+    fn handle_cluster_scan(&mut self, scan: ClusterScan) {
+        let Some(rare_lines) = &mut self.rare_lines else {
+            return;
+        };
+        if scan.generation != self.rare_generation {
+            trace!("TUI: Discarding stale rare message scan");
+            return;
+        }
+
+        rare_lines.update(scan.end, scan.clusters, scan.clusterer.counts());
+        self.rare_clusterer = Some(scan.clusterer);
+
+        self.maybe_scan_rare();
+    }
+
+    fn start_query(&mut self) {
+        self.prompt = Some(PromptState {
+            kind: PromptKind::Query,
+            input: "select ".into(),
+        });
+    }
+
+    fn run_query(&mut self, text: &str) {
+        let query = match Query::parse(text) {
+            Ok(query) => query,
+            Err(e) => {
+                self.show_popup("Query", vec![e.to_string()]);
+                return;
+            }
+        };
+
+        self.show_popup("Query", vec![format!("Running {}...", text.trim())]);
+
+        spawn_query(self.analysis_source(), query, self.analysis_sender.clone());
+    }
+
+    fn start_sort(&mut self) {
+        if self.content_state.columns.is_none() {
+            self.show_popup(
+                "Sort",
+                vec!["Sorting needs column mode, enter it with | or J.".to_owned()],
+            );
+            return;
+        }
+
+        self.prompt = Some(PromptState {
+            kind: PromptKind::Sort,
+            input: Input::default(),
+        });
+    }
+
+    fn run_sort(&mut self, spec: &str) {
+        let Some(columns) = &self.content_state.columns else {
+            return;
+        };
+        let mode = columns.mode.clone();
+        let header = columns.header.clone().unwrap_or_default();
+
+        let (column, descending) = match parse_sort_spec(spec, &header) {
+            Ok(sort) => sort,
+            Err(e) => {
+                self.show_popup("Sort", vec![e.to_string()]);
+                return;
+            }
+        };
+
+        self.show_popup("Sort", vec![format!("Sorting by {}...", spec)]);
+
+        spawn_sort(
+            self.analysis_source(),
+            mode,
+            header,
+            column,
+            descending,
+            self.analysis_sender.clone(),
+        );
+    }
+
+    // Show the file list sidebar if needed, and give it the keys.
+    fn focus_sidebar(&mut self) {
+        let Some(file_list) = &mut self.file_list else {
+            self.show_popup(
+                "Files",
+                vec!["There are no other files when reading piped input.".to_owned()],
+            );
+            return;
+        };
+
+        match &mut self.sidebar {
+            Some(sidebar) => sidebar.focused = true,
+            None => {
+                file_list.refresh();
+                let selected = file_list
+                    .files()
+                    .iter()
+                    .position(|f| f.path == self.file.path)
+                    .unwrap_or(0);
+                self.sidebar = Some(SidebarState {
+                    selected,
+                    focused: true,
+                    refreshed: Instant::now(),
+                });
+            }
+        }
+    }
+
+    // Check the listed files for changes now and again, returning true if the list was refreshed.
+    fn refresh_sidebar(&mut self) -> bool {
+        let (Some(sidebar), Some(file_list)) = (&mut self.sidebar, &mut self.file_list) else {
+            return false;
+        };
+        if sidebar.refreshed.elapsed() < Duration::from_millis(FILE_LIST_REFRESH_MS) {
+            return false;
+        }
+
+        file_list.refresh();
+        sidebar.selected = sidebar
+            .selected
+            .min(file_list.files().len().saturating_sub(1));
+        sidebar.refreshed = Instant::now();
+
+        true
+    }
+
+    // Switch to a file newer than the last newest now and again, carrying on tailing, returning
+    // whether it switched. A file picked from the list stays shown until a newer one appears.
+    async fn follow_newest(&mut self) -> Result<bool> {
+        let (Some(newest), Some(file_list)) = (&mut self.newest, &mut self.file_list) else {
+            return Ok(false);
+        };
+        if newest.checked.elapsed() < Duration::from_millis(FILE_LIST_REFRESH_MS) {
+            return Ok(false);
+        }
+        newest.checked = Instant::now();
+
+        file_list.refresh();
+        let Some(file) = file_list.newest() else {
+            return Ok(false);
+        };
+        if file.path == newest.path {
+            return Ok(false);
+        }
+        let (path, name) = (file.path.clone(), file.name.clone());
+        newest.path = path.clone();
+        debug!("{}: Following newer file {}", self.path, path);
+
+        let (content_tail, filter_tail) = (self.content_tail, self.filter_tail);
+        self.switch_file(&path).await?;
+        if content_tail {
+            self.content_tail = true;
+            self.content_state.view.set_tail(true).await?;
+        }
+        if filter_tail {
+            self.filter_tail = true;
+            self.filter_state.view.set_tail(true).await?;
+        }
+        self.show_notice(format!("Following newer file {}", name));
+
+        Ok(true)
+    }
+
+    // Sample the file size for its growth rate, returning whether the rate shown has changed.
+    fn sample_growth(&mut self) -> bool {
+        let file_bytes = self.content_state.view.get_stats().file_bytes;
+        self.growth.sample(Instant::now(), file_bytes);
+
+        let shown = self.growth.describe();
+        if shown == self.growth_shown {
+            return false;
+        }
+        self.growth_shown = shown;
+
+        true
+    }
+
+    // Let a buffered stream spool more as its file is read, returning whether the lines waiting or
+    // dropped have changed.
+    fn update_stream_buffer(&mut self) -> bool {
+        let Some(stream_buffer) = &self.stream_buffer else {
+            return false;
+        };
+        stream_buffer.set_read(self.content_state.view.get_stats().file_bytes);
+
+        let backlog = (stream_buffer.queued(), stream_buffer.dropped());
+        if backlog == self.stream_backlog {
+            return false;
+        }
+        self.stream_backlog = backlog;
+
+        true
+    }
+
+    // Check whether the command has finished, returning whether what is shown changed.
+    fn update_command_status(&mut self) -> bool {
+        let Some(command_status) = &self.command_status else {
+            return false;
+        };
+
+        let shown = command_status.describe();
+        if shown == self.command_shown {
+            return false;
+        }
+        self.command_shown = shown;
+
+        true
+    }
+
+    // Sample the lines shown whilst tailing lines arriving faster than the threshold, returning
+    // whether that changed. The line rate is estimated from the growth rate and the average line.
+    fn update_sampling(&mut self) -> bool {
+        let Some(threshold) = self.sample_lines_per_sec else {
+            return false;
+        };
+        let stats = self.content_state.view.get_stats();
+        let lines_per_sec = match stats.file_bytes {
+            0 => 0,
+            file_bytes => {
+                (self.growth.bytes_per_sec() as u128 * stats.file_lines as u128
+                    / file_bytes as u128) as u64
+            }
+        };
+        // Carry on until well below the threshold, so a rate around it doesn't flick between.
+        let sampling = self.content_tail
+            && if self.sampling {
+                lines_per_sec > threshold / 2
+            } else {
+                lines_per_sec > threshold
+            };
+        if sampling == self.sampling {
+            return false;
+        }
+
+        debug!(
+            "{}: {} sampling at {} lines/s",
+            self.path,
+            if sampling { "Starting" } else { "Stopping" },
+            lines_per_sec
+        );
+        self.sampling = sampling;
+        self.sampled_to = None;
+        if !sampling {
+            self.content_state.sampled = None;
+        }
+        self.content_render = None;
+
+        true
+    }
+
+    // Fetch every so many lines up to the end, enough to fill the pane. Lines are sampled at
+    // multiples of the interval, so they scroll up as more arrive rather than changing.
+    fn request_sampled(&mut self) {
+        let file_lines = self.content_state.view.get_stats().file_lines;
+        if !self.sampling || self.sample_requested || file_lines == 0 {
+            return;
+        }
+        let last = (file_lines - 1) / self.sample_every * self.sample_every;
+        if self.sampled_to == Some(last) {
+            return;
+        }
+
+        let rows = self.content_state.height_hint.max(1);
+        let line_nos = (0..rows)
+            .filter_map(|row| last.checked_sub(row * self.sample_every))
+            .rev()
+            .collect::<Vec<_>>();
+        self.sample_requested = true;
+        self.sampled_to = Some(last);
+        spawn_fetch_lines(
+            self.file.ifreq_sender.clone(),
+            line_nos,
+            LinesPurpose::Sampled,
+            self.analysis_sender.clone(),
+        );
+    }
+
+    fn sampling_badge(&self) -> Line<'static> {
+        if !self.sampling {
+            return Line::default();
+        }
+
+        Line::from(Span::styled(
+            format!(" sampling 1/{} ", self.sample_every),
+            Style::default()
+                .fg(themed(Color::Yellow))
+                .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+        ))
+    }
+
+    async fn handle_sidebar_key(&mut self, key: &event::KeyEvent) -> Result<()> {
+        let (Some(sidebar), Some(file_list)) = (&mut self.sidebar, &self.file_list) else {
+            return Ok(());
+        };
+
+        let last = file_list.files().len().saturating_sub(1);
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => sidebar.focused = false,
+            KeyCode::Char('F') => self.sidebar = None,
+            KeyCode::Char('j') | KeyCode::Down => {
+                sidebar.selected = (sidebar.selected + 1).min(last)
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                sidebar.selected = sidebar.selected.saturating_sub(1)
+            }
+            KeyCode::Char('g') => sidebar.selected = 0,
+            KeyCode::Char('G') => sidebar.selected = last,
+            KeyCode::Enter => {
+                sidebar.focused = false;
+                if let Some(file) = file_list.files().get(sidebar.selected) {
+                    let path = file.path.clone();
+                    self.switch_file(&path).await?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Show or hide the lines of a merged file. The file is read again, leaving out the lines of
+    // those hidden, staying at the end if tailing.
+    async fn toggle_source(&mut self, i: usize) -> Result<()> {
+        let Some(source) = self.sources.get(i) else {
+            return Ok(());
+        };
+        if source.shown && self.sources.iter().filter(|s| s.shown).count() == 1 {
+            self.show_notice(format!("{} is the only file shown", source.tag));
+            return Ok(());
+        }
+        self.sources[i].shown = !self.sources[i].shown;
+        let source = &self.sources[i];
+        let notice = format!(
+            "{} {}",
+            if source.shown { "Showing" } else { "Hiding" },
+            source.tag
+        );
+
+        let hidden_prefixes = self
+            .sources
+            .iter()
+            .filter(|s| !s.shown)
+            .map(|s| spool::source_prefix(&s.tag))
+            .collect();
+        self.opener = self.opener.clone().with_hidden_prefixes(hidden_prefixes);
+
+        let (content_tail, filter_tail) = (self.content_tail, self.filter_tail);
+        let path = self.file.path.clone();
+        if self.open_file(&path).await? {
+            self.content_tail = content_tail;
+            self.content_state.view.set_tail(content_tail).await?;
+            self.filter_tail = filter_tail;
+            self.filter_state.view.set_tail(filter_tail).await?;
+        }
+        self.show_notice(notice);
+
+        Ok(())
+    }
+
+    // Leave out the lines matching the ignore patterns, or show them. The file is read again,
+    // staying at the end if tailing.
+    async fn toggle_ignoring(&mut self) -> Result<()> {
+        if !self.opener.has_ignore_patterns() {
+            self.show_popup(
+                "Ignore",
+                vec!["There are no ignore patterns in the config.".to_owned()],
+            );
+            return Ok(());
+        }
+        let ignoring = !self.opener.ignoring();
+        self.opener = self.opener.clone().with_ignoring(ignoring);
+
+        let (content_tail, filter_tail) = (self.content_tail, self.filter_tail);
+        let path = self.file.path.clone();
+        if self.open_file(&path).await? {
+            self.content_tail = content_tail;
+            self.content_state.view.set_tail(content_tail).await?;
+            self.filter_tail = filter_tail;
+            self.filter_state.view.set_tail(filter_tail).await?;
+        }
+        self.show_notice(
+            if ignoring {
+                "Ignoring noise"
+            } else {
+                "Showing all lines"
+            }
+            .to_owned(),
+        );
+
+        Ok(())
+    }
+
+    // The merged files by number, their tags in their colours or crossed out if hidden, shown
+    // under the content pane.
+    fn source_legend(&self) -> Line<'static> {
+        let mut spans = Vec::new();
+        for (i, source) in self.sources.iter().enumerate() {
+            let style = match (source.shown, self.content_state.monochrome) {
+                (false, _) => Style::default()
+                    .fg(themed(Color::DarkGray))
+                    .add_modifier(Modifier::CROSSED_OUT),
+                (true, true) => Style::default().bold(),
+                (true, false) => Style::default().fg(themed(source.colour)).bold(),
+            };
+            spans.push(Span::styled(format!(" {} {} ", i + 1, source.tag), style));
+        }
+
+        Line::from(spans)
+    }
+
+    // View another file, keeping the filter, colouring and display settings.
+    async fn switch_file(&mut self, path: &str) -> Result<()> {
+        if path == self.file.path {
+            return Ok(());
+        }
+
+        if self.open_file(path).await? {
+            self.path = path.to_owned();
+            self.link_target = describe_link(path);
+        }
+
+        Ok(())
+    }
+
+    // Open a file in place of the one being viewed, returning whether it could be opened.
+    async fn open_file(&mut self, path: &str) -> Result<bool> {
+        let file = match self.opener.open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                self.show_popup("Open file", vec![e.to_string()]);
+                return Ok(false);
+            }
+        };
+        debug!("{}: Opening {}", self.path, path);
+
+        let scrolloff = self.config.config.scrolloff.unwrap_or(0);
+        let (content_view, content_ifresp_recv, filter_view, filter_ffresp_recv) =
+            Tui::new_views(&file, scrolloff);
+
+        // Replacing the file closes the old one.
+        self.file = file;
+        self.growth = new_growth_rate(path);
+        self.content_state.view = content_view;
+        self.content_ifresp_recv = content_ifresp_recv;
+        self.filter_state.view = filter_view;
+        self.filter_ffresp_recv = filter_ffresp_recv;
+
+        self.content_tail = false;
+        self.reader_trouble = None;
+        self.filter_tail = false;
+
+        // Line numbers in the old file mean nothing in the new one.
+        self.filter_from = FilterFrom::Start;
+        self.filter_start = 0;
+        self.filter_end = None;
+        self.load_annotations(path);
+        self.reset_file_state();
+
+        self.content_state.view.init().await?;
+        self.filter_state.view.init().await?;
+        self.set_filter_spec(self.filter_spec.clone()).await?;
+
+        Ok(true)
+    }
+
+    // Forget what was worked out about the file shown, when another is shown in its place.
+    fn reset_file_state(&mut self) {
+        self.content_scroll_state = ScrollbarState::new(0);
+        self.time_fraction_requested = None;
+        self.time_fraction = None;
+        self.sampling = false;
+        self.sample_requested = false;
+        self.sampled_to = None;
+        self.content_state.sampled = None;
+        self.filter_scroll_state = ScrollbarState::new(0);
+        self.result_table = None;
+
+        // The header comes from the new file.
+        self.content_state.columns = None;
+        self.filter_state.columns = None;
+        self.column_header_requested = false;
+        self.reset_pinned();
+
+        self.select_anchor = None;
+        self.selection.clear();
+        self.update_selection();
+
+        self.reset_gaps();
+        self.reset_marks();
+        self.reset_rare();
+        self.damage_panes();
+    }
+
+    // Open a file in a new tab after the others, and show it.
+    async fn open_tab(&mut self, path: &str) -> Result<()> {
+        let file = match self.opener.open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                self.show_popup("Open file", vec![e.to_string()]);
+                return Ok(());
+            }
+        };
+        debug!("{}: Opening {} in a new tab", self.path, path);
+
+        let scrolloff = self.config.config.scrolloff.unwrap_or(0);
+        let (content_view, content_ifresp_recv, filter_view, filter_ffresp_recv) =
+            Tui::new_views(&file, scrolloff);
+
+        // The new tab starts with the filter and colouring of the one shown.
+        let mut tab = Tab {
+            path: path.to_owned(),
+            link_target: describe_link(path),
+            file,
+            growth: new_growth_rate(path),
+            content_ifresp_recv,
+            filter_ffresp_recv,
+            content_view,
+            filter_view,
+            content_tail: false,
+            filter_tail: false,
+            filter_spec: self.filter_spec.clone(),
+            filter_enabled: self.filter_enabled,
+            filter_projection: self.filter_projection,
+            filter_from: FilterFrom::Start,
+            filter_start: 0,
+            filter_end: None,
+            colouring: self.colouring.clone(),
+            profile: self.profile.clone(),
+            jumps: Vec::new(),
+            jump_index: 0,
+            bookmarks: BTreeMap::new(),
+            annotations: None,
+            reader_trouble: None,
+        };
+        self.swap_tab(&mut tab);
+        self.tabs[self.tab] = Some(tab);
+        self.tabs.push(None);
+        self.tab = self.tabs.len() - 1;
+
+        self.load_annotations(path);
+        self.reset_file_state();
+        self.content_state.view.init().await?;
+        self.filter_state.view.init().await?;
+        self.set_filter_spec(self.filter_spec.clone()).await?;
+        self.start_tailing().await?;
+
+        Ok(())
+    }
+
+    // Tail the panes asked to be tailed from the start, leaving the others as they are.
+    async fn start_tailing(&mut self) -> Result<()> {
+        let Some(panes) = self.start_tail else {
+            return Ok(());
+        };
+        if panes.content() {
+            self.content_tail = true;
+            self.content_state.view.set_tail(true).await?;
+        }
+        if panes.filter() {
+            self.filter_tail = true;
+            self.filter_state.view.set_tail(true).await?;
+        }
+
+        Ok(())
+    }
+
+    // Show another tab, parking the one shown.
+    async fn switch_tab(&mut self, index: usize) -> Result<()> {
+        if index == self.tab {
+            return Ok(());
+        }
+        let Some(mut tab) = self.tabs.get_mut(index).and_then(|tab| tab.take()) else {
+            return Ok(());
+        };
+        debug!("{}: Switching to tab {} {}", self.path, index + 1, tab.path);
+
+        // Catch up with what arrived whilst parked.
+        tab.handle_updates().await?;
+        self.swap_tab(&mut tab);
+        self.tabs[self.tab] = Some(tab);
+        self.tab = index;
+        self.shown_tab_changed();
+
+        Ok(())
+    }
+
+    // Close the tab shown, showing the one after it, or before it if it was the last.
+    async fn close_tab(&mut self) -> Result<()> {
+        if self.tabs.len() < 2 {
+            self.show_popup("Tabs", vec!["The last tab can't be closed.".to_owned()]);
+            return Ok(());
+        }
+
+        let index = match self.tab + 1 < self.tabs.len() {
+            true => self.tab + 1,
+            false => self.tab - 1,
+        };
+        let Some(mut tab) = self.tabs[index].take() else {
+            return Ok(());
+        };
+        debug!("{}: Closing tab {}", self.path, self.tab + 1);
+        tab.handle_updates().await?;
+        self.swap_tab(&mut tab);
+        // Dropping the tab closes its file.
+        drop(tab);
+        // The tabs after the one closed move down into its place.
+        self.tabs.remove(self.tab);
+        if index < self.tab {
+            self.tab = index;
+        }
+        self.shown_tab_changed();
+
+        Ok(())
+    }
+
+    async fn step_tab(&mut self, forward: bool) -> Result<()> {
+        let tabs = self.tabs.len();
+        let index = match forward {
+            true => (self.tab + 1) % tabs,
+            false => (self.tab + tabs - 1) % tabs,
+        };
+
+        self.switch_tab(index).await
+    }
+
+    // Exchange the file shown, its views and filter and colouring, with a parked tab's.
+    fn swap_tab(&mut self, tab: &mut Tab) {
+        std::mem::swap(&mut self.path, &mut tab.path);
+        std::mem::swap(&mut self.link_target, &mut tab.link_target);
+        std::mem::swap(&mut self.file, &mut tab.file);
+        std::mem::swap(&mut self.growth, &mut tab.growth);
+        std::mem::swap(&mut self.content_ifresp_recv, &mut tab.content_ifresp_recv);
+        std::mem::swap(&mut self.filter_ffresp_recv, &mut tab.filter_ffresp_recv);
+        std::mem::swap(&mut self.content_state.view, &mut tab.content_view);
+        std::mem::swap(&mut self.filter_state.view, &mut tab.filter_view);
+        std::mem::swap(&mut self.content_tail, &mut tab.content_tail);
+        std::mem::swap(&mut self.filter_tail, &mut tab.filter_tail);
+        std::mem::swap(&mut self.filter_spec, &mut tab.filter_spec);
+        std::mem::swap(&mut self.filter_enabled, &mut tab.filter_enabled);
+        std::mem::swap(&mut self.filter_projection, &mut tab.filter_projection);
+        std::mem::swap(&mut self.filter_from, &mut tab.filter_from);
+        std::mem::swap(&mut self.filter_start, &mut tab.filter_start);
+        std::mem::swap(&mut self.filter_end, &mut tab.filter_end);
+        std::mem::swap(&mut self.colouring, &mut tab.colouring);
+        std::mem::swap(&mut self.profile, &mut tab.profile);
+        std::mem::swap(&mut self.jumps, &mut tab.jumps);
+        std::mem::swap(&mut self.jump_index, &mut tab.jump_index);
+        std::mem::swap(&mut self.bookmarks, &mut tab.bookmarks);
+        std::mem::swap(&mut self.annotations, &mut tab.annotations);
+        std::mem::swap(&mut self.reader_trouble, &mut tab.reader_trouble);
+    }
+
+    // Show what the tab now shown has, working out again what isn't kept with it.
+    fn shown_tab_changed(&mut self) {
+        self.content_state.colouring = self.colouring.for_pane(ColouringScope::Content);
+        self.filter_state.colouring = self.colouring.for_pane(ColouringScope::Filter);
+        self.update_projection();
+        self.update_bookmarks();
+        self.annotation_line = None;
+        self.pending_notes.clear();
+        self.notes_requested = false;
+        self.notes_looked_for = None;
+        self.update_annotations();
+        self.reset_file_state();
+    }
+
+    async fn handle_result_table_key(&mut self, key: &event::KeyEvent) -> Result<()> {
+        let Some(view) = &mut self.result_table else {
+            return Ok(());
+        };
+
+        let delta = match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.result_table = None;
+                return Ok(());
+            }
+            KeyCode::Enter => {
+                let row = view.table.rows.get(view.selected).cloned();
+                self.result_table = None;
+                match row {
+                    Some(ResultRow {
+                        filter: Some(pattern),
+                        ..
+                    }) => {
+                        self.filter_enabled = true;
+                        self.filter_projection = None;
+                        self.set_filter_spec(FilterSpec::new(FilterType::Regex, &pattern)?)
+                            .await?;
+                    }
+                    Some(ResultRow {
+                        line_no: Some(line_no),
+                        ..
+                    }) => self.goto_content_line(line_no).await?,
+                    _ => {}
+                }
+                return Ok(());
+            }
+            KeyCode::Char(' ') => {
+                view.toggle_group();
+                return Ok(());
+            }
+            KeyCode::Char('j') | KeyCode::Down => 1,
+            KeyCode::Char('k') | KeyCode::Up => -1,
+            KeyCode::Char('d') | KeyCode::PageDown => 20,
+            KeyCode::Char('u') | KeyCode::PageUp => -20,
+            KeyCode::Char('g') => isize::MIN / 2,
+            KeyCode::Char('G') => isize::MAX / 2,
+            _ => return Ok(()),
+        };
+
+        let last = view.table.rows.len().saturating_sub(1);
+        view.selected = common::clamped_add(view.selected, delta, 0, last);
+
+        // Follow the selection in the content pane.
+        if let Some(line_no) = view.table.rows.get(view.selected).and_then(|r| r.line_no) {
+            self.goto_content_line(line_no).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_analysis_result(&mut self, result: AnalysisResult) -> Result<()> {
+        match result {
+            AnalysisResult::Lines { purpose, lines } => match purpose {
+                LinesPurpose::ColumnHeader => match lines.first() {
+                    Some((_, header)) => self.set_column_header(&header.clone()),
+                    // Not there yet, try again when more content arrives.
+                    None => self.column_header_requested = false,
+                },
+                LinesPurpose::Pinned => self.set_pinned(lines),
+                LinesPurpose::Sampled => {
+                    self.sample_requested = false;
+                    if self.sampling {
+                        self.content_state.sampled = Some(lines);
+                    }
+                }
+                LinesPurpose::Detail => {
+                    if let Some((line_no, line)) = lines.into_iter().next() {
+                        self.show_detail(line_no, line);
+                    }
+                }
+                LinesPurpose::Yank => {
+                    if let Some((line_no, line)) = lines.into_iter().next() {
+                        self.copy_line(line_no, &line);
+                    }
+                }
+            },
+            AnalysisResult::Aggregate {
+                pattern,
+                lines_scanned,
+                stats,
+            } => {
+                let mut lines = vec![
+                    format!("Pattern: {}", pattern),
+                    format!(
+                        "Lines scanned: {}",
+                        lines_scanned.to_formatted_string(&Locale::en)
+                    ),
+                    String::new(),
+                ];
+
+                match stats {
+                    Some(stats) => {
+                        lines.push(format!(
+                            "Count: {}",
+                            stats.count.to_formatted_string(&Locale::en)
+                        ));
+                        lines.push(format!("Min:   {}", format_value(stats.min)));
+                        lines.push(format!("Max:   {}", format_value(stats.max)));
+                        lines.push(format!("Avg:   {}", format_value(stats.avg)));
+                        lines.push(format!("p95:   {}", format_value(stats.p95)));
+                    }
+                    None => lines.push("No numeric values captured.".to_owned()),
+                }
+
+                self.show_popup("Aggregate", lines);
+            }
+            AnalysisResult::Gaps(scan) => self.handle_gap_scan(scan),
+            AnalysisResult::Clusters(scan) => self.handle_cluster_scan(scan),
+            AnalysisResult::Table(table) => {
+                self.popup = None;
+                // Moving through the table moves the content pane, so this is where it jumped from.
+                self.record_jump();
+                self.result_table = Some(ResultTableView::new(table));
+            }
+            AnalysisResult::Groups(groups) => {
+                self.popup = None;
+                self.record_jump();
+                self.result_table = Some(ResultTableView::grouped(groups));
+            }
+            AnalysisResult::TimeFound { target, line_no } => match line_no {
+                Some(line_no) => {
+                    self.record_jump();
+                    self.current_window = true;
+                    self.goto_content_line(line_no).await?;
+                }
+                None => self.show_popup(
+                    "Go to time",
+                    vec![format!("No timestamped lines at or after {}", target)],
+                ),
+            },
+            AnalysisResult::TimeFraction { line_no, fraction } => {
+                self.time_fraction = fraction.map(|fraction| (line_no, fraction));
+            }
+            AnalysisResult::LinesAt(lines) => {
+                self.notes_requested = false;
+                if let Some(annotations) = &mut self.annotations {
+                    if annotations.resolve(&lines) {
+                        self.update_annotations();
+                    }
+                }
+            }
+            AnalysisResult::LineOffset { line_no, offset } => {
+                let (Some((line, note)), Some(annotations)) =
+                    (self.pending_notes.remove(&line_no), &mut self.annotations)
+                else {
+                    return Ok(());
+                };
+                match offset {
+                    Some(offset) => {
+                        annotations.set(offset, line_no, &line, &note);
+                        self.save_annotations();
+                    }
+                    None => self.show_popup(
+                        "Notes",
+                        vec![format!(
+                            "Line {} isn't whole yet, so can't be noted",
+                            line_no
+                        )],
+                    ),
+                }
+            }
+            AnalysisResult::Failed { reason } => {
+                error!("Analysis failed: {}", reason);
+                self.show_popup("Analysis failed", vec![reason]);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn toggle_sync_lock(&mut self) -> Result<()> {
+        trace!(
+            "Toggling sync lock: current: {}",
+            self.sync_filter_to_content
+        );
+
+        self.sync_filter_to_content = !self.sync_filter_to_content;
+        self.auto_sync_if_needed().await?;
+
+        Ok(())
+    }
+
+    async fn auto_sync_if_needed(&mut self) -> Result<()> {
+        if self.sync_filter_to_content {
+            trace!("TUI: Auto-sync enabled, syncing filter to content");
+            self.sync_filter_to_content().await?;
+        } else {
+            trace!("TUI: Auto-sync disabled, skipping sync");
+        }
+
+        Ok(())
+    }
+
+    async fn sync_filter_to_content(&mut self) -> Result<()> {
+        trace!("Sync filter to content");
+
+        if !self.filter_enabled {
+            trace!("No current filter, done.");
+            return Ok(());
+        };
+
+        let match_no = self.filter_state.view.current();
+        let filter_line = self.filter_state.view.get_line(match_no);
+
+        let Some(filter_line) = filter_line else {
+            trace!("Match line not yet populated, cannot sync yet.");
+            return Ok(());
+        };
+
+        self.goto_content_line(filter_line.line_no).await
+    }
+
+    // Move the content pane to a file line, centering it.
+    async fn goto_content_line(&mut self, line_no: usize) -> Result<()> {
+        self.content_state.view.set_current(line_no).await?;
         self.content_scroll_state = self.content_scroll_state.position(line_no);
 
         self.content_state.view.center_current_line().await?;
@@ -755,6 +4932,15 @@ impl Tui {
         Ok(())
     }
 
+    fn update_projection(&mut self) {
+        self.filter_state.projection = match (self.filter_projection, self.filter_spec.regex()) {
+            (Some(group), Some(regex)) if group < regex.captures_len() => {
+                Some((regex.clone(), group))
+            }
+            _ => None,
+        };
+    }
+
     async fn set_filter_spec(&mut self, filter_spec: FilterSpec) -> Result<()> {
         trace!(
             "TUI: Setting filter spec: {:?}, enabled: {}",
@@ -762,6 +4948,7 @@ impl Tui {
             self.filter_enabled
         );
         self.filter_spec = filter_spec;
+        self.update_projection();
 
         let filter_to_send = if self.filter_enabled {
             Some(self.filter_spec.clone())
@@ -773,9 +4960,12 @@ impl Tui {
             "TUI: Sending SetFilter request to FFile channel: filter_spec={:?}",
             filter_to_send
         );
-        self.ff_sender
+        self.file
+            .ff_sender
             .send(FFReq::SetFilter {
                 filter_spec: filter_to_send,
+                from: self.filter_start,
+                to: self.filter_end,
             })
             .await?;
         trace!("TUI: SetFilter request sent successfully");
@@ -798,20 +4988,65 @@ impl Tui {
         Ok(())
     }
 
+    // Put the content scrollbar where the current line is in the file's time span, so quiet periods
+    // take little of it and bursts a lot. The file is asked again when the line or its length
+    // changes, with the line's number used until it answers or if it has no timestamps.
+    fn place_by_time(&mut self, file_lines: usize) {
+        let current = self.content_state.view.current();
+        if self.time_fraction_requested != Some((current, file_lines)) {
+            self.time_fraction_requested = Some((current, file_lines));
+            spawn_time_fraction(
+                self.file.ifreq_sender.clone(),
+                current,
+                self.analysis_sender.clone(),
+            );
+        }
+
+        let position = match self.time_fraction {
+            Some((line_no, fraction)) if line_no == current => {
+                (fraction * file_lines.saturating_sub(1) as f64).round() as usize
+            }
+            _ => current,
+        };
+        self.content_scroll_state = self.content_scroll_state.position(position);
+    }
+
+    fn toggle_time_scrollbar(&mut self) {
+        self.time_scrollbar = !self.time_scrollbar;
+        self.time_fraction_requested = None;
+        self.time_fraction = None;
+        self.content_scroll_state = self
+            .content_scroll_state
+            .position(self.content_state.view.current());
+        self.show_notice(if self.time_scrollbar {
+            "Scrollbar by time".to_owned()
+        } else {
+            "Scrollbar by line".to_owned()
+        });
+    }
+
     async fn scroll(&mut self, delta: isize) -> Result<()> {
         let i = if self.current_window {
             clamped_add(
                 self.content_state.view.current(),
                 delta,
                 0,
-                self.content_state.view.get_stats().file_lines - 1,
+                self.content_state
+                    .view
+                    .get_stats()
+                    .file_lines
+                    .saturating_sub(1),
             )
         } else {
             clamped_add(
                 self.filter_state.view.current(),
                 delta,
                 0,
-                self.filter_state.view.get_stats().view_lines - 1,
+                self.filter_state
+                    .view
+                    .get_stats()
+                    .view_lines
+                    .saturating_sub(1),
             )
         };
 
@@ -828,16 +5063,22 @@ impl Tui {
     }
 
     async fn top(&mut self) -> Result<()> {
+        if self.current_window {
+            self.record_jump();
+        }
         self.place(0).await
     }
 
     async fn bottom(&mut self) -> Result<()> {
+        if self.current_window {
+            self.record_jump();
+        }
         let view_lines = if self.current_window {
             self.content_state.view.get_stats().view_lines
         } else {
             self.filter_state.view.get_stats().view_lines
         };
-        self.place(view_lines - 1).await
+        self.place(view_lines.saturating_sub(1)).await
     }
 
     async fn center(&mut self) -> Result<()> {
@@ -850,16 +5091,39 @@ impl Tui {
         Ok(())
     }
 
+    // Grow or shrink the current pane, keeping the split for next time.
     async fn resize(&mut self, delta: isize) {
-        let mut delta = delta;
+        let mut delta = delta * RESIZE_STEP_PERCENT;
 
         if !self.current_window {
             delta = -delta;
         }
-        self.content_fill = clamped_add(self.content_fill, delta, 1, 9);
+        self.content_percent = clamped_add(
+            self.content_percent,
+            delta,
+            MIN_PANE_PERCENT,
+            100 - MIN_PANE_PERCENT,
+        );
+
+        self.config.config.content_percent = Some(self.content_percent);
+        maybe_save_config(&self.config);
+    }
+
+    fn current_columns(&mut self) -> Option<&mut ColumnState> {
+        if self.current_window {
+            self.content_state.columns.as_mut()
+        } else {
+            self.filter_state.columns.as_mut()
+        }
     }
 
     async fn pan(&mut self, delta: isize) -> Result<()> {
+        // In column mode pan a column at a time.
+        if let Some(columns) = self.current_columns() {
+            columns.pan(delta.signum() * (delta.abs() / 20 * 4 + 1));
+            return Ok(());
+        }
+
         if self.current_window {
             self.content_state.view.pan(
                 delta,
@@ -876,6 +5140,11 @@ impl Tui {
     }
 
     async fn pan_start(&mut self) -> Result<()> {
+        if let Some(columns) = self.current_columns() {
+            columns.start_column = 0;
+            return Ok(());
+        }
+
         if self.current_window {
             self.content_state.view.pan_start();
         } else {
@@ -886,6 +5155,11 @@ impl Tui {
     }
 
     async fn pan_end(&mut self) -> Result<()> {
+        if let Some(columns) = self.current_columns() {
+            columns.start_column = columns.num_columns().saturating_sub(1);
+            return Ok(());
+        }
+
         if self.current_window {
             self.content_state
                 .view
@@ -922,6 +5196,8 @@ impl Tui {
             enabled: true,
             input: self.filter_spec.filter_pattern.clone().into(),
             filter_type: self.filter_spec.filter_type.clone(),
+            projection: self.filter_projection,
+            from: self.filter_from,
         });
     }
 
@@ -932,12 +5208,16 @@ impl Tui {
                 enabled: rule.enabled,
                 input: rule.filter_spec.filter_pattern.clone().into(),
                 filter_type: rule.filter_spec.filter_type.clone(),
+                projection: None,
+                from: FilterFrom::Start,
             }
         } else {
             FilterEditState {
                 enabled: true,
                 input: "".into(),
                 filter_type: FilterType::SimpleCaseInsensitive,
+                projection: None,
+                from: FilterFrom::Start,
             }
         };
 
@@ -1063,6 +5343,8 @@ impl Tui {
                     enabled: rule.enabled,
                     input: rule.filter_spec.filter_pattern.clone().into(),
                     filter_type: rule.filter_spec.filter_type.clone(),
+                    projection: None,
+                    from: FilterFrom::Start,
                 };
                 colouring_edit.selected_fg_color = rule.fg_colour.clone();
                 colouring_edit.selected_bg_color = rule.bg_colour.clone();
@@ -1076,11 +5358,18 @@ impl Tui {
                 colouring_edit.filter_edit_state.filter_type.clone(),
                 colouring_edit.filter_edit_state.input.value(),
             ) {
+                let scope = colouring_edit
+                    .spec
+                    .rules()
+                    .get(colouring_edit.selected_rule_index)
+                    .map(|rule| rule.scope)
+                    .unwrap_or_default();
                 let updated_rule = ColouringRule {
                     enabled: colouring_edit.filter_edit_state.enabled,
                     filter_spec,
                     fg_colour: colouring_edit.selected_fg_color.clone(),
                     bg_colour: colouring_edit.selected_bg_color.clone(),
+                    scope,
                 };
 
                 colouring_edit
@@ -1098,13 +5387,21 @@ impl Tui {
         if let Some(colouring_edit) = &self.colouring_edit {
             self.colouring = colouring_edit.spec.clone();
 
-            // Also update the colouring in both UI panes
-            self.content_state.colouring = colouring_edit.spec.clone();
-            self.filter_state.colouring = colouring_edit.spec.clone();
+            // Also update the colouring in both UI panes, with the rules for each.
+            self.content_state.colouring = colouring_edit.spec.for_pane(ColouringScope::Content);
+            self.filter_state.colouring = colouring_edit.spec.for_pane(ColouringScope::Filter);
         }
 
-        // Update the config and save it.
-        self.config.config.colouring = self.colouring.clone();
+        // Update the config and save it. Changes to a profile's colouring stay with the profile.
+        let profile = self
+            .profile
+            .as_ref()
+            .and_then(|name| self.config.config.profiles.get_mut(name))
+            .filter(|profile| profile.colouring.is_some());
+        match profile {
+            Some(profile) => profile.colouring = Some(self.colouring.clone()),
+            None => self.config.config.colouring = self.colouring.clone(),
+        }
         maybe_save_config(&self.config);
     }
 
@@ -1126,6 +5423,8 @@ impl Tui {
                 enabled: new_rule.enabled,
                 input: new_rule.filter_spec.filter_pattern.clone().into(),
                 filter_type: new_rule.filter_spec.filter_type.clone(),
+                projection: None,
+                from: FilterFrom::Start,
             };
             colouring_edit.selected_fg_color = new_rule.fg_colour.clone();
             colouring_edit.selected_bg_color = new_rule.bg_colour.clone();
@@ -1161,6 +5460,8 @@ impl Tui {
                             enabled: default_rule.enabled,
                             input: default_rule.filter_spec.filter_pattern.clone().into(),
                             filter_type: default_rule.filter_spec.filter_type.clone(),
+                            projection: None,
+                            from: FilterFrom::Start,
                         };
                         colouring_edit.selected_fg_color = None;
                         colouring_edit.selected_bg_color = None;
@@ -1231,52 +5532,96 @@ impl Tui {
     }
 
     fn draw(&mut self, frame: &mut Frame) {
-        let area = frame.area();
+        let mut area = frame.area();
+
+        // The bottom line reads out the current line and status in accessible mode, with the
+        // cursor on it for braille displays. Dialogs taking input move the cursor to it.
+        if accessible() {
+            let [main_area, status_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
+            frame.render_widget(Span::from(self.accessible_status()), status_area);
+            frame.set_cursor_position(status_area.as_position());
+            area = main_area;
+        }
 
         let [title_area, main_area] =
             Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(area);
-        let [file_area, controls_area, filter_area] = Layout::vertical([
-            Constraint::Fill(self.content_fill as u16),
-            Constraint::Length(1),
-            Constraint::Fill(10 - self.content_fill as u16),
-        ])
-        .areas(main_area);
 
-        let filename = Span::from(format!("File: {}", &self.path)).italic();
-        let tail_status = Tui::draw_checkbox("Tail", self.content_tail);
-        let file_stats = Line::from(self.compute_file_stats())
-            .reversed()
-            .alignment(Alignment::Right);
-        let title_layout = Layout::horizontal([
-            Constraint::Fill(4),
-            Constraint::Length(10),
-            Constraint::Length(30),
-        ]);
-        let [filename_area, tail_area, stats_area] = title_layout.areas(title_area);
+        // The tab bar, once there is more than one tab.
+        let main_area = match self.tabs.len() {
+            1 => main_area,
+            _ => {
+                let [tabs_area, main_area] =
+                    Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(main_area);
+                frame.render_widget(self.tab_bar(), tabs_area);
 
-        frame.render_widget(filename, filename_area);
-        frame.render_widget(tail_status, tail_area);
-        frame.render_widget(file_stats, stats_area);
+                main_area
+            }
+        };
 
-        let content = LazyList::new(self.content_state.view.get_start_point()).block(
-            Block::bordered()
-                .border_set(self.selected_border(self.current_window))
-                .title("Content"),
-        );
-        frame.render_stateful_widget(content, file_area, &mut self.content_state);
-        frame.render_stateful_widget(
-            Scrollbar::default()
-                .orientation(ScrollbarOrientation::VerticalRight)
-                .begin_symbol(None)
-                .end_symbol(None),
-            file_area.inner(Margin {
-                vertical: 1,
-                horizontal: 1,
-            }),
-            &mut self.content_scroll_state,
-        );
+        // The sidebar, if shown, takes the keys from the panes whilst it has focus.
+        let mut panes_focused = true;
+        let main_area = match (&self.sidebar, &self.file_list) {
+            (Some(sidebar), Some(file_list)) => {
+                let [sidebar_area, main_area] =
+                    Layout::horizontal([Constraint::Length(SIDEBAR_WIDTH), Constraint::Fill(1)])
+                        .areas(main_area);
+                Tui::draw_sidebar(sidebar, file_list, &self.file.path, sidebar_area, frame);
+                panes_focused = !sidebar.focused;
+
+                main_area
+            }
+            _ => main_area,
+        };
+        let [file_area, controls_area, filter_area] = self.pane_areas(main_area);
+
+        match self.config.config.title_format.clone() {
+            Some(title_format) => {
+                let title = fill_template(&title_format, |name| self.template_value(name));
+                frame.render_widget(Span::from(title), title_area);
+            }
+            None => self.draw_title(title_area, frame),
+        }
+
+        // A hidden pane isn't drawn, keeping its height for when it is shown again.
+        let content = LazyList::new(self.content_state.view.get_start_point())
+            .block(
+                Block::bordered()
+                    .border_set(self.selected_border(panes_focused && self.current_window))
+                    .title(self.pane_title("Content", &self.content_state.columns))
+                    .title(focus_marker(panes_focused && self.current_window))
+                    .title(self.sampling_badge())
+                    .title_bottom(self.source_legend())
+                    .title_bottom(self.highlight_legend()),
+            )
+            .rare_lines(self.rare_lines.as_ref());
+        if !file_area.is_empty() {
+            match &self.content_render {
+                Some(render) if render.area == file_area => frame.buffer_mut().merge(render),
+                _ => {
+                    frame.render_stateful_widget(content, file_area, &mut self.content_state);
+                    self.content_render = Some(copy_area(frame.buffer_mut(), file_area));
+                }
+            }
+        }
+        if !accessible() {
+            frame.render_stateful_widget(
+                Scrollbar::default()
+                    .orientation(ScrollbarOrientation::VerticalRight)
+                    .begin_symbol(None)
+                    .end_symbol(None),
+                file_area.inner(Margin {
+                    vertical: 1,
+                    horizontal: 1,
+                }),
+                &mut self.content_scroll_state,
+            );
+        }
 
-        let filter_control_filter = Span::from(format!("Filter: {}", self.render_filter_spec()));
+        let filter_control_filter = match &self.notice {
+            Some((notice, _)) => Span::from(notice.clone()).bold(),
+            None => Span::from(format!("Filter: {}", self.filter_status())),
+        };
         let filter_controls = Line::from(vec![
             Tui::draw_checkbox("Sync", self.sync_filter_to_content),
             Span::from("  "),
@@ -1293,27 +5638,52 @@ impl Tui {
         ]);
         let [filter_control_filter_area, filter_control_tail_area, filter_control_tail_matches] =
             filter_control_layout.areas(controls_area);
-        frame.render_widget(filter_control_filter, filter_control_filter_area);
-        frame.render_widget(filter_controls, filter_control_tail_area);
-        frame.render_widget(filter_control_stats, filter_control_tail_matches);
-
-        let filter_content = LazyList::new(self.filter_state.view.get_start_point()).block(
-            Block::bordered()
-                .border_set(self.selected_border(!self.current_window))
-                .title("Filtered"),
-        );
-        frame.render_stateful_widget(filter_content, filter_area, &mut self.filter_state);
-        frame.render_stateful_widget(
-            Scrollbar::default()
-                .orientation(ScrollbarOrientation::VerticalRight)
-                .begin_symbol(None)
-                .end_symbol(None),
-            filter_area.inner(Margin {
-                vertical: 1,
-                horizontal: 1,
-            }),
-            &mut self.filter_scroll_state,
-        );
+        match (&self.notice, &self.config.config.status_format) {
+            (None, Some(status_format)) => {
+                let status = fill_template(status_format, |name| self.template_value(name));
+                frame.render_widget(Span::from(status), controls_area);
+            }
+            _ => {
+                frame.render_widget(filter_control_filter, filter_control_filter_area);
+                frame.render_widget(filter_controls, filter_control_tail_area);
+                frame.render_widget(filter_control_stats, filter_control_tail_matches);
+            }
+        }
+
+        let filter_content = LazyList::new(self.filter_state.view.get_start_point())
+            .block(
+                Block::bordered()
+                    .border_set(self.selected_border(panes_focused && !self.current_window))
+                    .title(self.pane_title("Filtered", &self.filter_state.columns))
+                    .title(focus_marker(panes_focused && !self.current_window)),
+            )
+            .rare_lines(self.rare_lines.as_ref());
+        if !filter_area.is_empty() {
+            match &self.filter_render {
+                Some(render) if render.area == filter_area => frame.buffer_mut().merge(render),
+                _ => {
+                    frame.render_stateful_widget(
+                        filter_content,
+                        filter_area,
+                        &mut self.filter_state,
+                    );
+                    self.filter_render = Some(copy_area(frame.buffer_mut(), filter_area));
+                }
+            }
+        }
+        if !accessible() {
+            frame.render_stateful_widget(
+                Scrollbar::default()
+                    .orientation(ScrollbarOrientation::VerticalRight)
+                    .begin_symbol(None)
+                    .end_symbol(None),
+                filter_area.inner(Margin {
+                    vertical: 1,
+                    horizontal: 1,
+                }),
+                &mut self.filter_scroll_state,
+            );
+        }
 
         // Render the filter spec dialog if needed.
         if let Some(filter_edit) = &self.filter_edit {
@@ -1324,17 +5694,403 @@ impl Tui {
         if let Some(colouring_edit) = &mut self.colouring_edit {
             Tui::draw_colouring_dlg(colouring_edit, area, frame);
         }
+
+        if let Some(result_table) = &mut self.result_table {
+            Tui::draw_result_table(result_table, filter_area, frame);
+        }
+
+        if let Some(history_picker) = &self.history_picker {
+            Tui::draw_history_picker(history_picker, area, frame);
+        }
+
+        if let Some(prompt) = &self.prompt {
+            Tui::draw_prompt_dlg(prompt, area, frame);
+        }
+
+        if let Some(popup) = &self.popup {
+            Tui::draw_popup(popup, area, frame);
+        }
     }
 
-    fn draw_filter_dlg(filter_edit: &FilterEditState, area: Rect, frame: &mut Frame) {
+    // Render both panes afresh on the next draw.
+    fn damage_panes(&mut self) {
+        self.content_render = None;
+        self.filter_render = None;
+    }
+
+    // The title line: the file, time display, tailing and file stats.
+    fn draw_title(&mut self, title_area: Rect, frame: &mut Frame) {
+        // A command's output is labelled with how the command is getting on.
+        let mut filename = match &self.command_status {
+            Some(_) => format!("{}: {}", self.command_shown, &self.path),
+            None => format!("File: {}", &self.path),
+        };
+        if let Some(link_target) = &self.link_target {
+            filename.push_str(&format!(" → {}", link_target));
+        }
+        if let Some(time_window) = &self.time_window {
+            filename.push_str(&format!(" ({})", time_window));
+        }
+        if self.opener.ignoring() {
+            filename.push_str(" (ignoring)");
+        }
+        match self.stream_backlog {
+            (0, 0) => {}
+            (waiting, 0) => filename.push_str(&format!(
+                " ({} waiting)",
+                waiting.to_formatted_string(&Locale::en)
+            )),
+            (waiting, dropped) => filename.push_str(&format!(
+                " ({} waiting, dropped {})",
+                waiting.to_formatted_string(&Locale::en),
+                dropped.to_formatted_string(&Locale::en)
+            )),
+        }
+        if let Some(reader_trouble) = &self.reader_trouble {
+            filename.push_str(&format!(" ({})", reader_trouble));
+        }
+        if let Some(profile) = &self.profile {
+            filename.push_str(&format!(" [{}]", profile));
+        }
+        let filename = Span::from(filename).italic();
+        let time_display = match self.content_state.time_display {
+            TimeDisplay::Original => Span::from(""),
+            time_display => Span::from(format!("Time: {}", time_display.describe())),
+        };
+        let tail_status = Tui::draw_checkbox("Tail", self.content_tail);
+        let file_stats = Line::from(self.compute_file_stats())
+            .reversed()
+            .alignment(Alignment::Right);
+        let title_layout = Layout::horizontal([
+            Constraint::Fill(4),
+            Constraint::Length(12),
+            Constraint::Length(10),
+            Constraint::Length(42),
+        ]);
+        let [filename_area, time_area, tail_area, stats_area] = title_layout.areas(title_area);
+
+        frame.render_widget(filename, filename_area);
+        frame.render_widget(time_display, time_area);
+        frame.render_widget(tail_status, tail_area);
+        frame.render_widget(file_stats, stats_area);
+    }
+
+    // The value for a placeholder in the title and status formats.
+    fn template_value(&self, name: &str) -> Option<String> {
+        let content_stats = self.content_state.view.get_stats();
+        let value = match name {
+            "path" => self.path.clone(),
+            "target" => self.link_target.clone().unwrap_or_default(),
+            "lines" => content_stats.file_lines.to_formatted_string(&Locale::en),
+            "bytes" => content_stats.file_bytes.fmt_size(Conventional).to_string(),
+            "growth" => self.growth_shown.clone(),
+            "line" => self.content_state.view.current().to_string(),
+            "position" => match content_stats.file_lines {
+                0 => "-".to_owned(),
+                file_lines => {
+                    ((self.content_state.view.current() + 1) * 100 / file_lines).to_string()
+                }
+            },
+            "tail" => if self.content_tail { "tailing" } else { "" }.to_owned(),
+            "time" => self.content_state.time_display.describe().to_owned(),
+            "profile" => self.profile.clone().unwrap_or_default(),
+            "filter" => self.filter_status(),
+            "matches" => self
+                .filter_state
+                .view
+                .get_stats()
+                .view_lines
+                .to_formatted_string(&Locale::en),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    // The content pane, filter controls and filter pane areas. A maximized pane takes all the
+    // space, and when swapped the filter pane comes first. Side by side, the filter controls go
+    // below both panes.
+    fn pane_areas(&self, area: Rect) -> [Rect; 3] {
+        let (content, filter) = match (self.maximized, self.current_window) {
+            (true, true) => (Constraint::Fill(1), Constraint::Length(0)),
+            (true, false) => (Constraint::Length(0), Constraint::Fill(1)),
+            (false, _) => (
+                Constraint::Fill(self.content_percent as u16),
+                Constraint::Fill(100 - self.content_percent as u16),
+            ),
+        };
+
+        let [first, second] = if self.swapped {
+            [filter, content]
+        } else {
+            [content, filter]
+        };
+        let [first_area, controls_area, second_area] = match self.layout {
+            PaneLayout::Stacked => {
+                Layout::vertical([first, Constraint::Length(1), second]).areas(area)
+            }
+            PaneLayout::SideBySide => {
+                let [panes_area, controls_area] =
+                    Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
+                let [first_area, second_area] =
+                    Layout::horizontal([first, second]).areas(panes_area);
+                [first_area, controls_area, second_area]
+            }
+        };
+
+        if self.swapped {
+            [second_area, controls_area, first_area]
+        } else {
+            [first_area, controls_area, second_area]
+        }
+    }
+
+    fn draw_sidebar(
+        sidebar: &SidebarState,
+        file_list: &FileList,
+        current_path: &str,
+        area: Rect,
+        frame: &mut Frame,
+    ) {
+        let border_set = if sidebar.focused {
+            symbols::border::THICK
+        } else {
+            symbols::border::PLAIN
+        };
+        let block = Block::bordered()
+            .border_set(border(border_set))
+            .title(format!("Files ({})", file_list.files().len()));
+
+        let today = chrono::Local::now().date_naive();
+        let rows = file_list.files().iter().map(|file| {
+            let current = if file.path == current_path { ">" } else { " " };
+            let growing = if file.growing() { "▲" } else { " " };
+            let modified = file
+                .modified
+                .map(|modified| {
+                    let modified = chrono::DateTime::<chrono::Local>::from(modified);
+                    if modified.date_naive() == today {
+                        modified.format("%H:%M").to_string()
+                    } else {
+                        modified.format("%b %d").to_string()
+                    }
+                })
+                .unwrap_or_default();
+
+            Row::new(vec![
+                Cell::from(current),
+                Cell::from(file.name.as_str()),
+                Cell::from(
+                    Line::from(file.bytes.fmt_size(Conventional).to_string())
+                        .alignment(Alignment::Right),
+                ),
+                Cell::from(modified),
+                Cell::from(growing),
+            ])
+        });
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(1),
+                Constraint::Fill(1),
+                Constraint::Length(9),
+                Constraint::Length(6),
+                Constraint::Length(1),
+            ],
+        )
+        .block(block)
+        .row_highlight_style(if sidebar.focused {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        });
+
+        let mut table_state = TableState::default().with_selected(sidebar.selected);
+        frame.render_stateful_widget(table, area, &mut table_state);
+    }
+
+    fn draw_prompt_dlg(prompt: &PromptState, area: Rect, frame: &mut Frame) {
         let area = Tui::popup_area(area, 60, 20);
+        let [area] = Layout::vertical([Constraint::Length(3)]).areas(area);
+        frame.render_widget(Clear, area);
+
+        let input_widget =
+            Paragraph::new(prompt.input.value()).block(bordered().title(prompt.kind.title()));
+        frame.render_widget(input_widget, area);
+
+        let cursor_position = prompt.input.cursor() as u16;
+        frame.set_cursor_position(Position::new(area.x + cursor_position + 1, area.y + 1));
+    }
+
+    fn draw_history_picker(picker: &HistoryPickerState, area: Rect, frame: &mut Frame) {
+        let area = Tui::popup_area(area, 60, 40);
+        frame.render_widget(Clear, area);
+
+        let block = bordered().title(format!(
+            "History - {} (type to search, Enter to use, Esc to close)",
+            picker.matches.len()
+        ));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let [input_area, list_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(inner);
+        frame.render_widget(
+            Paragraph::new(format!("> {}", picker.input.value())),
+            input_area,
+        );
+        frame.set_cursor_position(Position::new(
+            input_area.x + picker.input.cursor() as u16 + 2,
+            input_area.y,
+        ));
+
+        let items = picker
+            .matches
+            .iter()
+            .map(|(_, label)| ListItem::new(label.as_str()))
+            .collect::<Vec<_>>();
+        let list =
+            List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut list_state = ListState::default().with_selected(Some(picker.selected));
+        frame.render_stateful_widget(list, list_area, &mut list_state);
+    }
+
+    fn draw_result_table(view: &mut ResultTableView, area: Rect, frame: &mut Frame) {
+        frame.render_widget(Clear, area);
+
+        let enter = if view.table.rows.iter().any(|r| r.filter.is_some()) {
+            "filter"
+        } else {
+            "go to line"
+        };
+        let expand = if view.groups.is_some() {
+            ", Space expand"
+        } else {
+            ""
+        };
+        let block = bordered().title(format!(
+            "{} - {} rows (j/k move and sync, Enter {}{}, Esc close)",
+            view.table.title,
+            view.table.rows.len().to_formatted_string(&Locale::en),
+            enter,
+            expand
+        ));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        // Only build the visible rows, results can be large. Leave a row for the header.
+        let height = (inner.height as usize).saturating_sub(1).max(1);
+        if view.selected < view.offset {
+            view.offset = view.selected;
+        } else if view.selected >= view.offset + height {
+            view.offset = view.selected + 1 - height;
+        }
+
+        let visible = view
+            .table
+            .rows
+            .iter()
+            .skip(view.offset)
+            .take(height)
+            .collect::<Vec<_>>();
+
+        // Size the columns to what is on screen.
+        let mut widths = vec![4];
+        widths.extend(view.table.header.iter().map(|h| h.chars().count()));
+        for row in &visible {
+            if let Some(line_no) = row.line_no {
+                widths[0] = widths[0].max(common::count_digits(line_no));
+            }
+            for (i, cell) in row.cells.iter().enumerate() {
+                if i + 1 >= widths.len() {
+                    widths.push(0);
+                }
+                widths[i + 1] = widths[i + 1].max(cell.chars().count().min(MAX_COLUMN_WIDTH));
+            }
+        }
+
+        let header = Row::new(
+            std::iter::once("Line".to_owned())
+                .chain(view.table.header.iter().cloned())
+                .map(Cell::from),
+        )
+        .style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED));
+
+        let rows = visible.iter().map(|row| {
+            Row::new(
+                std::iter::once(row.line_no.map(|l| l.to_string()).unwrap_or_default())
+                    .chain(row.cells.iter().cloned())
+                    .map(Cell::from),
+            )
+        });
+
+        // The last column takes the remaining width, e.g. for long templates.
+        let last = widths.len() - 1;
+        let table = Table::new(
+            rows,
+            widths
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    if i == last {
+                        Constraint::Min(*w as u16)
+                    } else {
+                        Constraint::Length(*w as u16)
+                    }
+                })
+                .collect::<Vec<_>>(),
+        )
+        .header(header)
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        let mut table_state = TableState::default().with_selected(view.selected - view.offset);
+        frame.render_stateful_widget(table, inner, &mut table_state);
+    }
+
+    fn draw_popup(popup: &PopupState, area: Rect, frame: &mut Frame) {
+        let area = Tui::popup_area(area, 60, 40);
+        frame.render_widget(Clear, area);
+
+        let lines = popup
+            .lines
+            .iter()
+            .map(|l| Line::from(l.as_str()))
+            .collect::<Vec<_>>();
+        let paragraph = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(bordered().title(format!("{} (Esc to close)", popup.title)));
+        frame.render_widget(paragraph, area);
+    }
+
+    fn draw_filter_dlg(filter_edit: &FilterEditState, area: Rect, frame: &mut Frame) {
+        let area = Tui::popup_area(area, 80, 20);
         frame.render_widget(Clear, area);
 
         let surrounding_block =
-            Block::bordered().title("Filter (Enter to apply, Esc to close, C-_ to toggle)");
+            bordered().title("Filter (Enter to apply, Esc to close, C-_ to toggle)");
         let inner_area = surrounding_block.inner(area);
 
         Tui::draw_filter_edit(filter_edit, inner_area, frame);
+
+        // The capture group to show goes on the same row as the enabled toggle.
+        let group = match filter_edit.projection {
+            Some(group) => group.to_string(),
+            None => "whole line".to_owned(),
+        };
+        let projection = Line::from(format!("Show [G]roup: {}   ", group)).right_aligned();
+        frame.render_widget(projection, inner_area.rows().next().unwrap_or_default());
+
+        let from = Line::from(vec![
+            Span::raw("[F]rom: "),
+            Tui::draw_radiobutton("start", filter_edit.from == FilterFrom::Start),
+            Span::raw("  "),
+            Tui::draw_radiobutton("current line", filter_edit.from == FilterFrom::CurrentLine),
+            Span::raw("  "),
+            Tui::draw_radiobutton("end", filter_edit.from == FilterFrom::End),
+        ])
+        .centered();
+        frame.render_widget(from, inner_area.rows().next().unwrap_or_default());
         frame.render_widget(surrounding_block, area);
     }
 
@@ -1342,7 +6098,7 @@ impl Tui {
         let area = Tui::popup_area(area, 80, 70);
         frame.render_widget(Clear, area);
 
-        let surrounding_block = Block::bordered().title("Colouring");
+        let surrounding_block = bordered().title("Colouring");
         let inner_area = surrounding_block.inner(area);
 
         let colouring_dlg_layout = Layout::vertical([Constraint::Fill(1), Constraint::Fill(1)]);
@@ -1372,12 +6128,12 @@ impl Tui {
         let rules_title = if colouring_edit.pending_deletion.is_some() {
             "⚠️ Press 'y' to DELETE rule, any other key to CANCEL"
         } else {
-            "Rules (Tab/Shift+Tab=focus, j/k/↑↓=nav, t=toggle, +/-=add/del, Shift+j/k/↑↓=move, Enter=apply, Esc=close)"
+            "Rules (Tab/Shift+Tab=focus, j/k/↑↓=nav, t=toggle, p=panes, +/-=add/del, Shift+j/k/↑↓=move, Enter=apply, Esc=close)"
         };
 
         let rules_block = Block::new()
             .borders(Borders::ALL)
-            .border_set(border_style)
+            .border_set(border(border_style))
             .title(rules_title);
         let inner_area = rules_block.inner(area);
 
@@ -1399,13 +6155,19 @@ impl Tui {
                     .map(|c| format!("{:?}", c))
                     .unwrap_or_else(|| "None".to_string());
 
+                let scope_str = match rule.scope {
+                    ColouringScope::Both => String::new(),
+                    scope => format!(" ({} only)", scope),
+                };
+
                 let text = format!(
-                    "{}. {} {} → fg:{}/bg:{}",
+                    "{}. {} {} → fg:{}/bg:{}{}",
                     index + 1,
                     enabled_str,
                     rule.filter_spec.render(),
                     fg_str,
-                    bg_str
+                    bg_str,
+                    scope_str
                 );
 
                 ListItem::new(text)
@@ -1438,17 +6200,19 @@ impl Tui {
         frame.render_stateful_widget(list, area, &mut colouring_edit.rules_list_state);
 
         // Add scrollbar
-        frame.render_stateful_widget(
-            Scrollbar::default()
-                .orientation(ScrollbarOrientation::VerticalRight)
-                .begin_symbol(None)
-                .end_symbol(None),
-            area.inner(Margin {
-                vertical: 1,
-                horizontal: 1,
-            }),
-            &mut colouring_edit.rules_scroll_state,
-        );
+        if !accessible() {
+            frame.render_stateful_widget(
+                Scrollbar::default()
+                    .orientation(ScrollbarOrientation::VerticalRight)
+                    .begin_symbol(None)
+                    .end_symbol(None),
+                area.inner(Margin {
+                    vertical: 1,
+                    horizontal: 1,
+                }),
+                &mut colouring_edit.rules_scroll_state,
+            );
+        }
     }
 
     fn draw_colouring_edit_section(
@@ -1470,7 +6234,7 @@ impl Tui {
 
         let pattern_block = Block::new()
             .borders(Borders::ALL)
-            .border_set(pattern_border_style)
+            .border_set(border(pattern_border_style))
             .title("Pattern");
         let pattern_inner_area = pattern_block.inner(pattern_area);
 
@@ -1491,7 +6255,7 @@ impl Tui {
 
         let color_block = Block::new()
             .borders(Borders::ALL)
-            .border_set(border_style)
+            .border_set(border(border_style))
             .title("Colours (letter=fg, Shift+letter=bg)");
         let inner_area = color_block.inner(area);
 
@@ -1570,21 +6334,34 @@ impl Tui {
         ]);
         frame.render_widget(enabled, enabled_area);
 
-        let filter_type = Line::from(vec![
+        let mut filter_type = vec![
             Span::raw("   "),
             Tui::draw_radiobutton(
                 "In[s]ensitive",
                 filter_edit.filter_type == FilterType::SimpleCaseInsensitive,
             ),
             Span::raw("  "),
+            Tui::draw_radiobutton(
+                "[S]mart case",
+                filter_edit.filter_type == FilterType::SmartCase,
+            ),
+            Span::raw("  "),
             Tui::draw_radiobutton(
                 "[C]ase sensitive",
                 filter_edit.filter_type == FilterType::SimpleCaseSensitive,
             ),
             Span::raw("  "),
             Tui::draw_radiobutton("[R]egex", filter_edit.filter_type == FilterType::Regex),
-        ]);
-        frame.render_widget(filter_type, filter_type_area);
+            Span::raw("  "),
+            Tui::draw_radiobutton("[L]evel", filter_edit.filter_type == FilterType::Level),
+            Span::raw("  "),
+            Tui::draw_radiobutton("[J]SON", filter_edit.filter_type == FilterType::Json),
+        ];
+        if filter_edit.filter_type == FilterType::Compound {
+            filter_type.push(Span::raw("  "));
+            filter_type.push(Tui::draw_radiobutton("File", true));
+        }
+        frame.render_widget(Line::from(filter_type), filter_type_area);
 
         let input_widget = Paragraph::new(filter_edit.input.value())
             .block(Block::default().borders(Borders::ALL).title("Expression"));
@@ -1606,11 +6383,36 @@ impl Tui {
         area
     }
 
+    fn pane_title(&self, name: &str, columns: &Option<ColumnState>) -> String {
+        let mut modes = Vec::new();
+        if let Some(columns) = columns {
+            modes.push(columns.mode.describe());
+        }
+        if self.rare_lines.is_some() {
+            modes.push(format!("rare < {}", self.rare_threshold));
+        }
+        if self.content_state.show_whitespace {
+            modes.push("whitespace".to_owned());
+        }
+        if self.transform.is_some() {
+            modes.push("transformed".to_owned());
+        }
+        if self.substitutions_enabled && !self.substitutions.is_empty() {
+            modes.push("substituted".to_owned());
+        }
+
+        if modes.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{} ({})", name, modes.join(", "))
+        }
+    }
+
     fn selected_border(&self, selected: bool) -> symbols::border::Set {
         if selected {
-            symbols::border::THICK
+            border(symbols::border::THICK)
         } else {
-            symbols::border::PLAIN
+            border(symbols::border::PLAIN)
         }
     }
 
@@ -1618,9 +6420,10 @@ impl Tui {
         let stats = self.content_state.view.get_stats();
 
         format!(
-            "{} L / {}",
+            "{} L / {} {}",
             stats.file_lines.to_formatted_string(&Locale::en),
-            (stats.file_bytes as u64).fmt_size(Conventional)
+            (stats.file_bytes as u64).fmt_size(Conventional),
+            self.growth_shown
         )
     }
 
@@ -1641,11 +6444,233 @@ impl Tui {
         )
     }
 
+    // Show the file, tailing and matches in the terminal title, to find this otail amongst many
+    // terminals.
+    fn update_title(&mut self) -> Result<()> {
+        let name = Path::new(&self.path)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.clone());
+
+        let mut status = Vec::new();
+        if self.content_tail || self.filter_tail {
+            status.push("tailing".to_owned());
+        }
+        if self.filter_enabled {
+            let matches = self.filter_state.view.get_stats().view_lines;
+            status.push(format!(
+                "{} matches",
+                matches.to_formatted_string(&Locale::en)
+            ));
+        }
+        let title = if status.is_empty() {
+            format!("{} - otail", name)
+        } else {
+            format!("{} ({}) - otail", name, status.join(", "))
+        };
+
+        if title != self.title {
+            trace!("TUI: Setting title: {}", title);
+            stdout().execute(SetTitle(&title))?;
+            self.title = title;
+        }
+
+        Ok(())
+    }
+
+    // How far the filter has got through the file and the matches so far, whilst it is catching
+    // up with the file.
+    fn filter_progress(&self) -> Option<(usize, usize)> {
+        let file_lines = self
+            .content_state
+            .view
+            .get_stats()
+            .file_lines
+            .min(self.filter_end.unwrap_or(usize::MAX));
+        let filter_stats = self.filter_state.view.get_stats();
+        // Lines before where the filter started are skipped.
+        let filtered = filter_stats.file_lines.max(self.filter_start);
+        if !self.filter_enabled || filtered + FILTER_SPOOLING_BATCH_SIZE >= file_lines {
+            return None;
+        }
+
+        Some((filtered * 100 / file_lines, filter_stats.view_lines))
+    }
+
+    // Stop a filter that is taking too long, turning it off.
+    async fn cancel_filter(&mut self) -> Result<()> {
+        debug!("{}: Cancelling filter", self.path);
+        self.filter_enabled = false;
+        self.set_filter_spec(self.filter_spec.clone()).await?;
+        self.show_notice("Filter cancelled, / to change it".to_owned());
+
+        Ok(())
+    }
+
+    // The filter and, whilst it is catching up, its progress.
+    // Each tab's number and file name, with the one shown picked out.
+    fn tab_bar(&self) -> Line<'static> {
+        let spans = self.tabs.iter().enumerate().map(|(i, tab)| {
+            let path = tab.as_ref().map_or(&self.path, |tab| &tab.path);
+            let name = Path::new(path)
+                .file_name()
+                .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+            let label = Span::from(format!(" {}:{} ", i + 1, name));
+            match i == self.tab {
+                true => label.reversed().bold(),
+                false => label,
+            }
+        });
+
+        Line::from(spans.collect::<Vec<_>>())
+    }
+
+    // The focused pane's current line and the status, as one line of plain text.
+    fn accessible_status(&self) -> String {
+        let mut status = vec![if self.current_window {
+            let line_no = self.content_state.view.current();
+            let line = self.content_state.view.get_line(line_no);
+            format!(
+                "Line {} of {}: {}",
+                line_no,
+                self.content_state.view.get_stats().file_lines,
+                line.as_ref()
+                    .map(common::replace_for_view)
+                    .unwrap_or_default()
+            )
+        } else {
+            let match_no = self.filter_state.view.current();
+            match self.filter_state.view.get_line(match_no) {
+                Some(line) => format!(
+                    "Match {} of {}, line {}: {}",
+                    match_no,
+                    self.filter_state.view.get_stats().view_lines,
+                    line.line_no,
+                    common::replace_for_view(&line.line)
+                ),
+                None => "No matches".to_owned(),
+            }
+        }];
+        if let Some((notice, _)) = &self.notice {
+            status.push(notice.clone());
+        }
+        status.push(format!("Filter: {}", self.filter_status()));
+        if self.content_tail || self.filter_tail {
+            status.push("tailing".to_owned());
+        }
+
+        status.join(" | ")
+    }
+
+    fn filter_status(&self) -> String {
+        match self.filter_progress() {
+            Some((percent, matches)) => format!(
+                "{} - filtering… {}%, {} matches",
+                self.render_filter_spec(),
+                percent,
+                matches.to_formatted_string(&Locale::en)
+            ),
+            None => self.render_filter_spec(),
+        }
+    }
+
     fn render_filter_spec(&self) -> String {
         if self.filter_enabled {
-            format!("{}", self.filter_spec.render())
+            let mut rendered = match &self.filter_state.projection {
+                Some((_, group)) => format!("{} group {}", self.filter_spec.render(), group),
+                None => format!("{}", self.filter_spec.render()),
+            };
+            match self.filter_end {
+                Some(end) => {
+                    rendered.push_str(&format!(" lines {}-{}", self.filter_start, end - 1));
+                }
+                None if self.filter_start > 0 => {
+                    rendered.push_str(&format!(" from line {}", self.filter_start));
+                }
+                None => {}
+            }
+            rendered
         } else {
             "(None)".to_owned()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::OtailConfig, timestamp::TimestampParser};
+
+    fn tab_paths(tui: &Tui) -> Vec<String> {
+        tui.tabs
+            .iter()
+            .enumerate()
+            .map(|(index, tab)| match tab {
+                Some(tab) => tab.path.clone(),
+                None => {
+                    assert_eq!(index, tui.tab, "Only the tab shown is empty");
+                    tui.path.clone()
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_close_first_tab() {
+        let dir = std::env::temp_dir().join(format!("otail-test-tabs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<String> = ["a.log", "b.log", "c.log"]
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                std::fs::write(&path, format!("{}\n", name)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+
+        let opener = FileOpener::new(TimestampParser::default());
+        let file = opener.open(&paths[0]).unwrap();
+        let config = LocatedConfig {
+            path: None,
+            config: OtailConfig::default(),
+        };
+        let mut tui = Tui::new(paths[0].clone(), opener, file, config);
+        tui.open_tab(&paths[1]).await.unwrap();
+        tui.open_tab(&paths[2]).await.unwrap();
+        assert_eq!(tab_paths(&tui), paths);
+
+        tui.switch_tab(0).await.unwrap();
+        tui.close_tab().await.unwrap();
+        assert_eq!(tui.tab, 0);
+        assert_eq!(tui.path, paths[1]);
+        assert_eq!(tab_paths(&tui), paths[1..]);
+
+        tui.step_tab(true).await.unwrap();
+        assert_eq!(tui.tab, 1);
+        assert_eq!(tui.path, paths[2]);
+        tui.step_tab(true).await.unwrap();
+        assert_eq!(tui.tab, 0);
+        assert_eq!(tui.path, paths[1]);
+        assert_eq!(tab_paths(&tui), paths[1..]);
+
+        // Closing the last tab shows the one before it.
+        tui.step_tab(false).await.unwrap();
+        tui.close_tab().await.unwrap();
+        assert_eq!(tui.tab, 0);
+        assert_eq!(tab_paths(&tui), paths[1..2]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_keys() {
+        let ctrl = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL));
+        assert_eq!(legacy_keys(ctrl('m')), Event::Key(KeyCode::Enter.into()));
+        assert_eq!(legacy_keys(ctrl('[')), Event::Key(KeyCode::Esc.into()));
+        assert_eq!(legacy_keys(ctrl('i')), ctrl('i'));
+        assert_eq!(
+            legacy_keys(Event::Key(KeyCode::Char('m').into())),
+            Event::Key(KeyCode::Char('m').into())
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use io_uring::{opcode, types, IoUring};
+use log::{debug, error};
+
+// How much is read at a time whilst reading through a file.
+const URING_BLOCK_BYTES: usize = 1024 * 1024;
+// How much is read on from a seek before reading ahead, so fetching a line doesn't.
+const READ_THROUGH_BYTES: u64 = URING_BLOCK_BYTES as u64;
+// The buffers read ahead into, one being read from whilst a cancelled read may finish in another.
+const BUFFERS: usize = 2;
+// The user data of a cancel, told apart from the reads which have their buffer's index.
+const CANCEL: u64 = u64::MAX;
+
+/// A file read through with io_uring a block at a time, with the next block read ahead by the
+/// kernel whilst one is being used, as when spooling a large file. Reads after a seek, as when
+/// fetching a line, are only as big as asked for until a block has been read on from there. Where
+/// io_uring isn't available, e.g. blocked in a container, the file is read as usual.
+pub struct UringFile {
+    file: File,
+    ring: Option<IoUring>,
+    // Where the next read is from.
+    pos: u64,
+    // How much has been read on from the last seek, to tell when the file is being read through.
+    read_through: u64,
+    // Allocated when first reading ahead, then reused.
+    bufs: Vec<Box<[u8]>>,
+    // Which buffers the kernel may still be reading into.
+    busy: [bool; BUFFERS],
+    block: Option<Block>,
+}
+
+// A block of the file from the offset, read or still being read by the kernel into a buffer.
+struct Block {
+    offset: u64,
+    buf: usize,
+    // What the read gave, the bytes read or an error, None until it completes.
+    result: Option<i32>,
+}
+
+impl Block {
+    fn len(&self) -> Option<usize> {
+        self.result.and_then(|result| usize::try_from(result).ok())
+    }
+}
+
+impl UringFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let ring = IoUring::new(4)
+            .inspect_err(|e| debug!("Reading without io_uring: {:?}", e))
+            .ok();
+
+        Ok(UringFile {
+            file,
+            ring,
+            pos: 0,
+            read_through: 0,
+            bufs: Vec::new(),
+            busy: [false; BUFFERS],
+            block: None,
+        })
+    }
+
+    // Whether the block has, or will have, what is at the offset.
+    fn block_has(&self, offset: u64) -> bool {
+        match &self.block {
+            Some(block) => match block.len() {
+                Some(len) => offset >= block.offset && offset < block.offset + len as u64,
+                None => block.result.is_none() && offset == block.offset,
+            },
+            None => false,
+        }
+    }
+
+    // Start reading the block at the offset, into a buffer the kernel is done with.
+    fn submit(&mut self, offset: u64) -> io::Result<()> {
+        if self.ring.is_none() {
+            return Ok(());
+        }
+        if self.bufs.is_empty() {
+            self.bufs = (0..BUFFERS)
+                .map(|_| vec![0; URING_BLOCK_BYTES].into_boxed_slice())
+                .collect();
+        }
+        self.reap(false)?;
+        while self.busy.iter().all(|busy| *busy) {
+            self.reap(true)?;
+        }
+        let Some(buf) = self.busy.iter().position(|busy| !busy) else {
+            return Ok(());
+        };
+
+        let Some(ring) = self.ring.as_mut() else {
+            return Ok(());
+        };
+        let entry = opcode::Read::new(
+            types::Fd(self.file.as_raw_fd()),
+            self.bufs[buf].as_mut_ptr(),
+            URING_BLOCK_BYTES as u32,
+        )
+        .offset(offset)
+        .build()
+        .user_data(buf as u64);
+        // Safety: the buffer is kept, and not read into again, until the read has completed, see
+        // `reap`.
+        unsafe { ring.submission().push(&entry) }.map_err(io::Error::other)?;
+        self.busy[buf] = true;
+        self.block = Some(Block {
+            offset,
+            buf,
+            result: None,
+        });
+        ring.submit()?;
+
+        Ok(())
+    }
+
+    // Take the reads completed, waiting for one if asked to, freeing their buffers.
+    fn reap(&mut self, wait: bool) -> io::Result<()> {
+        let Some(ring) = self.ring.as_mut() else {
+            return Ok(());
+        };
+        if wait {
+            match ring.submit_and_wait(1) {
+                Err(e) if e.kind() != io::ErrorKind::Interrupted => return Err(e),
+                _ => {}
+            }
+        }
+
+        for cqe in ring.completion() {
+            let buf = cqe.user_data() as usize;
+            // Cancels have nothing to free.
+            let Some(busy) = self.busy.get_mut(buf) else {
+                continue;
+            };
+            *busy = false;
+            if let Some(block) = self
+                .block
+                .as_mut()
+                .filter(|block| block.buf == buf && block.result.is_none())
+            {
+                block.result = Some(cqe.result());
+            }
+        }
+
+        Ok(())
+    }
+
+    // Wait for the block to be read, if it is still being read.
+    fn complete(&mut self) -> io::Result<()> {
+        while self
+            .block
+            .as_ref()
+            .is_some_and(|block| block.result.is_none())
+        {
+            self.reap(true)?;
+        }
+
+        match self.block.as_ref().and_then(|block| block.result) {
+            Some(result) if result < 0 => {
+                self.block = None;
+                Err(io::Error::from_raw_os_error(-result))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Drop the block, cancelling its read if it is still being read rather than waiting for it.
+    // Its buffer is reused once the read has completed.
+    fn discard(&mut self) -> io::Result<()> {
+        let (Some(ring), Some(block)) = (self.ring.as_mut(), self.block.take()) else {
+            return Ok(());
+        };
+        if block.result.is_some() {
+            return Ok(());
+        }
+
+        let entry = opcode::AsyncCancel::new(block.buf as u64)
+            .build()
+            .user_data(CANCEL);
+        // Safety: a cancel refers to no memory.
+        unsafe { ring.submission().push(&entry) }.map_err(io::Error::other)?;
+        ring.submit()?;
+
+        Ok(())
+    }
+}
+
+impl Read for UringFile {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.ring.is_none() || self.read_through < READ_THROUGH_BYTES {
+            let read = self.file.read_at(out, self.pos)?;
+            self.pos += read as u64;
+            self.read_through += read as u64;
+            return Ok(read);
+        }
+
+        if !self.block_has(self.pos) {
+            self.discard()?;
+            self.submit(self.pos)?;
+        }
+        self.complete()?;
+        let Some((offset, buf, len)) = self
+            .block
+            .as_ref()
+            .and_then(|block| Some((block.offset, block.buf, block.len()?)))
+        else {
+            return Ok(0);
+        };
+
+        let start = (self.pos - offset) as usize;
+        let read = (len - start).min(out.len());
+        out[..read].copy_from_slice(&self.bufs[buf][start..start + read]);
+        self.pos += read as u64;
+        self.read_through += read as u64;
+
+        // A whole block read suggests there is more, so the next is read ahead. A short one is the
+        // end of the file for now, read again from there once written to.
+        if start + read == len {
+            self.block = None;
+            if len == URING_BLOCK_BYTES {
+                self.submit(offset + len as u64)?;
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+impl Seek for UringFile {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let pos = match from {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.file.metadata()?.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start"))?;
+
+        // What was read ahead may be out of date by the time it's read from elsewhere.
+        if pos != self.pos {
+            self.discard()?;
+            self.read_through = 0;
+        }
+        self.pos = pos;
+
+        Ok(pos)
+    }
+}
+
+impl fmt::Debug for UringFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UringFile")
+            .field("file", &self.file)
+            .field("uring", &self.ring.is_some())
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
+
+// The kernel may still be reading into the buffers, so they can only be freed once the reads are
+// done.
+impl Drop for UringFile {
+    fn drop(&mut self) {
+        let mut result = self.discard();
+        while result.is_ok() && self.busy.iter().any(|busy| *busy) {
+            result = self.reap(true);
+        }
+        if let Err(e) = result {
+            error!("Failed to stop reading ahead, leaking its buffers: {:?}", e);
+            std::mem::forget(std::mem::take(&mut self.bufs));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+
+    #[test]
+    fn test_uring_file() {
+        let path = std::env::temp_dir().join(format!("otail-test-uring-{}", std::process::id()));
+        let lines = (0..300_000)
+            .map(|n| format!("line {}", n))
+            .collect::<Vec<_>>();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        // Read through, across blocks.
+        let mut reader = BufReader::new(UringFile::open(&path).unwrap());
+        let read = reader
+            .by_ref()
+            .lines()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(read, lines);
+
+        // Read on once written to.
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"more\n").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "more\n");
+
+        // Read at random.
+        reader.seek(SeekFrom::Start(7)).unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "line 1\n");
+        assert!(reader.get_ref().block.is_none());
+
+        // Read at random part way through, cancelling what is read ahead, then read through again.
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let read = reader
+            .by_ref()
+            .lines()
+            .take(200_000)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(read, lines[..200_000]);
+        reader.seek(SeekFrom::Start(7)).unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "line 1\n");
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let read = reader
+            .by_ref()
+            .lines()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(read[..lines.len()], lines);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -43,6 +43,9 @@ pub struct View<T, L> {
     line_cache: LineCache<L>,
 
     tailing: bool,
+
+    // Rows of context kept above and below the current line when scrolling.
+    scrolloff: usize,
 }
 
 impl LinesSlice {
@@ -129,7 +132,7 @@ impl<L: Clone + LineContent> LineCache<L> {
         self.lines.push(Some(line));
     }
 
-    pub fn get_line(&self, line_no: usize) -> Option<L> {
+    pub fn get_line(&self, line_no: usize) -> Option<&L> {
         if !self.range.range().contains(&line_no) {
             warn!(
                 "Requested line outside the current ViewPort: line: {}, viewport: {:?}",
@@ -138,9 +141,7 @@ impl<L: Clone + LineContent> LineCache<L> {
             return None;
         }
 
-        let s = self.lines[line_no - self.range.first_line].clone();
-
-        s
+        self.lines[line_no - self.range.first_line].as_ref()
     }
 }
 
@@ -166,9 +167,16 @@ impl<T: std::marker::Send + 'static, L: Clone + Default + LineContent> View<T, L
             line_cache: LineCache::default(),
 
             tailing: false,
+
+            scrolloff: 0,
         }
     }
 
+    pub fn with_scrolloff(mut self, scrolloff: usize) -> Self {
+        self.scrolloff = scrolloff;
+        self
+    }
+
     pub async fn init(&self) -> Result<()> {
         trace!("Sending RegisterClient request for id: {}", self.id);
         self.file_req_sender
@@ -204,6 +212,11 @@ impl<T: std::marker::Send + 'static, L: Clone + Default + LineContent> View<T, L
     // Sync methods... callable from the TUI render function.
     //
     pub fn get_line(&self, line_no: usize) -> Option<L> {
+        self.line_cache.get_line(line_no).cloned()
+    }
+
+    // Borrow the line, to avoid copying long lines when rendering.
+    pub fn get_line_ref(&self, line_no: usize) -> Option<&L> {
         self.line_cache.get_line(line_no)
     }
 
@@ -215,9 +228,10 @@ impl<T: std::marker::Send + 'static, L: Clone + Default + LineContent> View<T, L
         self.current
     }
 
+    // Lengths and the start point are in display columns.
     pub fn current_line_length(&self) -> usize {
         if let Some(line) = self.get_line(self.current) {
-            return line.len();
+            return line.width();
         }
 
         0
@@ -286,43 +300,42 @@ impl<T: std::marker::Send + 'static, L: Clone + Default + LineContent> View<T, L
         Ok(())
     }
 
+    /// Lines were added before those known, e.g. the start of a file opened at its last lines, so
+    /// the view moves down past them, staying on the same lines.
+    pub async fn prepended(&mut self, lines: usize) -> Result<()> {
+        self.stats.view_lines += lines;
+        self.stats.file_lines += lines;
+        self.current += lines;
+        self.viewport.first_line += lines;
+        self.line_cache.range.first_line += lines;
+
+        // Lines asked for before may have been dropped.
+        self.request_missing(self.line_cache.missing_lines()).await
+    }
+
     pub async fn set_current(&mut self, line_no: usize) -> Result<()> {
         self.current = line_no;
 
-        // Whilst the current line is in the viewport, do not scroll.
-        // Only scroll to keep the current in the viewport.
-
-        if self.viewport.range().contains(&line_no) {
+        // Whilst the current line is in the viewport, and away from its edges by the scrolloff,
+        // do not scroll. Only scroll to keep the current line and its context in the viewport.
+        let first_line = scroll_first_line(
+            &self.viewport,
+            line_no,
+            self.scrolloff,
+            self.stats.view_lines,
+        );
+        if first_line == self.viewport.first_line {
             return Ok(());
         }
 
-        let num_lines = self.viewport.num_lines;
-        if line_no < self.viewport.first_line {
-            trace!("Moving viewport up to keep the current line on screen");
-            return self
-                .set_viewport(LinesSlice {
-                    first_line: line_no,
-                    num_lines,
-                })
-                .await;
-        }
-
-        // Move the viewport so the current line is at the end. Be careful to avoid a negative
-        // first line.
-        if line_no < num_lines {
-            trace!("Moving to start to keep screen full");
-            return self
-                .set_viewport(LinesSlice {
-                    first_line: 0,
-                    num_lines,
-                })
-                .await;
-        }
-
-        trace!("Move viewport down to keep current line on screen");
+        trace!(
+            "Moving viewport from {} to {} to keep the current line on screen",
+            self.viewport.first_line,
+            first_line
+        );
         self.set_viewport(LinesSlice {
-            first_line: line_no - num_lines + 1,
-            num_lines,
+            first_line,
+            num_lines: self.viewport.num_lines,
         })
         .await
     }
@@ -378,7 +391,7 @@ impl<T: std::marker::Send + 'static, L: Clone + Default + LineContent> View<T, L
         self.longest_line_length = 0;
         for l in &self.line_cache.lines {
             if let Some(l) = l {
-                let len = l.len();
+                let len = l.width();
                 if len > self.longest_line_length {
                     self.longest_line_length = len;
                 }
@@ -429,7 +442,7 @@ impl<T: std::marker::Send + 'static, L: Clone + Default + LineContent> View<T, L
                     line_content.render(),
                 );
 
-                let len = line_content.len();
+                let len = line_content.width();
                 if self
                     .line_cache
                     .set_line(line_no, line_content, self.tailing)
@@ -462,3 +475,67 @@ impl<T: std::marker::Send + 'static, L: Clone + Default + LineContent> View<T, L
         }
     }
 }
+
+// The first line of the viewport after moving to line_no, keeping scrolloff lines either side where
+// there is room. Past the end of the view the context is dropped, so the screen stays full.
+fn scroll_first_line(
+    viewport: &LinesSlice,
+    line_no: usize,
+    scrolloff: usize,
+    view_lines: usize,
+) -> usize {
+    let num_lines = viewport.num_lines;
+    if num_lines == 0 {
+        return line_no;
+    }
+    // Leave room for the current line itself.
+    let scrolloff = min(scrolloff, (num_lines - 1) / 2);
+
+    if line_no < viewport.first_line + scrolloff {
+        return line_no.saturating_sub(scrolloff);
+    }
+
+    if line_no + scrolloff >= viewport.first_line + num_lines {
+        let last_wanted = min(line_no + scrolloff, max(view_lines, line_no + 1) - 1);
+        return max(
+            viewport.first_line,
+            (last_wanted + 1).saturating_sub(num_lines),
+        );
+    }
+
+    viewport.first_line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scroll_first_line() {
+        let viewport = LinesSlice {
+            first_line: 10,
+            num_lines: 10,
+        };
+        let first = |line_no, scrolloff| scroll_first_line(&viewport, line_no, scrolloff, 100);
+
+        // Without a scrolloff, only scroll once the line is off screen.
+        assert_eq!(first(10, 0), 10);
+        assert_eq!(first(19, 0), 10);
+        assert_eq!(first(20, 0), 11);
+        assert_eq!(first(9, 0), 9);
+
+        // With one, keep context either side.
+        assert_eq!(first(12, 2), 10);
+        assert_eq!(first(11, 2), 9);
+        assert_eq!(first(17, 2), 10);
+        assert_eq!(first(18, 2), 11);
+        assert_eq!(first(40, 2), 33);
+        assert_eq!(first(1, 2), 0);
+
+        // Large scrolloffs keep the line central.
+        assert_eq!(first(16, 50), 11);
+
+        // No context past the end of the view.
+        assert_eq!(scroll_first_line(&viewport, 99, 3, 100), 90);
+    }
+}
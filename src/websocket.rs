@@ -0,0 +1,307 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+use anyhow::{anyhow, bail, Result};
+use log::{debug, trace};
+
+// Messages bigger than this are refused rather than read into memory.
+const MAX_MESSAGE_BYTES: u64 = 64 * 1024 * 1024;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// A `ws://` or `wss://` URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebSocketUrl {
+    pub secure: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl WebSocketUrl {
+    pub fn parse(url: &str) -> Result<Self> {
+        let (secure, rest) = if let Some(rest) = url.strip_prefix("ws://") {
+            (false, rest)
+        } else if let Some(rest) = url.strip_prefix("wss://") {
+            (true, rest)
+        } else {
+            bail!("Not a WebSocket URL: {}", url);
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let default_port = if secure { 443 } else { 80 };
+        let (host, port) = match authority.rsplit_once(':') {
+            // An IPv6 address without a port, e.g. [::1].
+            Some((_, port)) if port.ends_with(']') => (authority, default_port),
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| anyhow!("Bad port in WebSocket URL: {}", url))?,
+            ),
+            None => (authority, default_port),
+        };
+        if host.is_empty() {
+            bail!("No host in WebSocket URL: {}", url);
+        }
+
+        Ok(WebSocketUrl {
+            secure,
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn is_websocket(url: &str) -> bool {
+        url.starts_with("ws://") || url.starts_with("wss://")
+    }
+}
+
+/// A WebSocket client connection (RFC 6455) that reads the messages sent to it. `wss://` is
+/// connected through `openssl s_client`, so no TLS library is needed.
+pub struct WebSocket {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+    tls: Option<Child>,
+}
+
+impl WebSocket {
+    pub fn connect(url: &WebSocketUrl) -> Result<Self> {
+        let address = format!("{}:{}", url.host, url.port);
+        let (reader, writer, tls): (Box<dyn Read + Send>, Box<dyn Write + Send>, _) = if url.secure
+        {
+            let mut child = Command::new("openssl")
+                .args(["s_client", "-quiet", "-verify_return_error", "-connect"])
+                .arg(&address)
+                .arg("-servername")
+                .arg(url.host.trim_start_matches('[').trim_end_matches(']'))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| anyhow!("Failed to run openssl for {}: {:?}", address, e))?;
+            let reader = child.stdout.take().ok_or(anyhow!("No openssl output"))?;
+            let writer = child.stdin.take().ok_or(anyhow!("No openssl input"))?;
+            (Box::new(reader), Box::new(writer), Some(child))
+        } else {
+            let stream = TcpStream::connect(&address)?;
+            (Box::new(stream.try_clone()?), Box::new(stream), None)
+        };
+
+        let mut socket = WebSocket {
+            reader: BufReader::new(reader),
+            writer,
+            tls,
+        };
+        socket.handshake(url)?;
+        debug!("Connected to {:?}", url);
+
+        Ok(socket)
+    }
+
+    fn handshake(&mut self, url: &WebSocketUrl) -> Result<()> {
+        let mut key = [0; 16];
+        key[..8].copy_from_slice(&random().to_be_bytes());
+        key[8..].copy_from_slice(&random().to_be_bytes());
+        write!(
+            self.writer,
+            "GET {} HTTP/1.1\r\nHost: {}:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            url.path,
+            url.host,
+            url.port,
+            base64(&key)
+        )?;
+        self.writer.flush()?;
+
+        let mut status = String::new();
+        self.reader.read_line(&mut status)?;
+        if status.split_whitespace().nth(1) != Some("101") {
+            bail!("WebSocket handshake refused: {}", status.trim_end());
+        }
+        // Skip the headers, up to the blank line.
+        loop {
+            let mut header = String::new();
+            if self.reader.read_line(&mut header)? == 0 {
+                bail!("Connection closed during the WebSocket handshake");
+            }
+            if header.trim_end().is_empty() {
+                break;
+            }
+            trace!("Handshake header: {}", header.trim_end());
+        }
+
+        Ok(())
+    }
+
+    /// The next text or binary message, or None once the connection is closed. Pings are
+    /// answered.
+    pub fn read_message(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut message = Vec::new();
+        loop {
+            let mut header = [0; 2];
+            match self.reader.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+            let fin = header[0] & 0x80 != 0;
+            let opcode = header[0] & 0x0f;
+            let masked = header[1] & 0x80 != 0;
+            let length = match header[1] & 0x7f {
+                126 => {
+                    let mut length = [0; 2];
+                    self.reader.read_exact(&mut length)?;
+                    u16::from_be_bytes(length) as u64
+                }
+                127 => {
+                    let mut length = [0; 8];
+                    self.reader.read_exact(&mut length)?;
+                    u64::from_be_bytes(length)
+                }
+                length => length as u64,
+            };
+            if message.len() as u64 + length > MAX_MESSAGE_BYTES {
+                bail!("WebSocket message too big: {} bytes", length);
+            }
+            let mut mask = [0; 4];
+            if masked {
+                self.reader.read_exact(&mut mask)?;
+            }
+            let mut payload = vec![0; length as usize];
+            self.reader.read_exact(&mut payload)?;
+            if masked {
+                apply_mask(&mut payload, mask);
+            }
+
+            match opcode {
+                OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                    message.extend_from_slice(&payload);
+                    if fin {
+                        return Ok(Some(message));
+                    }
+                }
+                OP_CLOSE => {
+                    debug!("WebSocket closed by the server");
+                    self.send(OP_CLOSE, &payload).ok();
+                    return Ok(None);
+                }
+                OP_PING => self.send(OP_PONG, &payload)?,
+                OP_PONG => {}
+                _ => bail!("Unknown WebSocket opcode: {}", opcode),
+            }
+        }
+    }
+
+    // Send a frame, masked as clients must.
+    fn send(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            length @ 0..=125 => frame.push(0x80 | length as u8),
+            length @ 126..=0xffff => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        let mask = (random() as u32).to_be_bytes();
+        frame.extend_from_slice(&mask);
+        let start = frame.len();
+        frame.extend_from_slice(payload);
+        apply_mask(&mut frame[start..], mask);
+
+        self.writer.write_all(&frame)?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl Drop for WebSocket {
+    fn drop(&mut self) {
+        if let Some(tls) = &mut self.tls {
+            tls.kill().ok();
+            tls.wait().ok();
+        }
+    }
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+}
+
+// Keys and masks need only be unpredictable, not cryptographically random.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let url = WebSocketUrl::parse("ws://localhost:8080/logs?level=debug").unwrap();
+        assert_eq!(
+            url,
+            WebSocketUrl {
+                secure: false,
+                host: "localhost".to_owned(),
+                port: 8080,
+                path: "/logs?level=debug".to_owned(),
+            }
+        );
+
+        let url = WebSocketUrl::parse("wss://relay.example.com").unwrap();
+        assert!(url.secure);
+        assert_eq!((url.port, url.path.as_str()), (443, "/"));
+        assert_eq!(WebSocketUrl::parse("ws://[::1]/").unwrap().port, 80);
+
+        assert!(WebSocketUrl::parse("http://localhost").is_err());
+        assert!(WebSocketUrl::parse("ws://:80/").is_err());
+        assert!(WebSocketUrl::parse("ws://host:port/").is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}